@@ -0,0 +1,86 @@
+// allocations and time for decoding many small lzfse images, the case the
+// shared DecodeContext is for. Run with `cargo bench --bench decode_context`
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use carutil_lib::coreui::csi;
+use carutil_lib::coreui::rendition::CompressionType;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const IMAGES: usize = 500;
+const ROUNDS: usize = 20;
+
+fn main() {
+    let mut spec = common::fixtures::CatalogSpec::new();
+    for index in 0..IMAGES {
+        let (width, height) = (16 + index as u32 % 48, 16 + index as u32 % 32);
+        let pixels: Vec<u8> = (0..width * height * 4).map(|i| (i % 7) as u8).collect();
+        let mut payload = vec![];
+        lzfse_rust::encode_bytes(&pixels, &mut payload).unwrap();
+        spec = spec.compressed_image(
+            &format!("Image{}", index),
+            (width, height),
+            csi::PixelFormat::ARGB,
+            CompressionType::LZFSE,
+            &payload,
+        );
+    }
+    let asset_storage = spec.open();
+    let headers: Vec<&csi::Header> = asset_storage.theme_store.store.imagedb.values().collect();
+    assert_eq!(headers.len(), IMAGES);
+
+    let decode_all = || {
+        for csi_header in &headers {
+            match csi_header.decode().unwrap() {
+                csi::DecodedRendition::Rgba { pixels, .. } => assert!(!pixels.is_empty()),
+                _ => unreachable!(),
+            }
+        }
+    };
+    // the first round sizes the thread's buffers
+    decode_all();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let allocated_bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    let started = Instant::now();
+    for _ in 0..ROUNDS {
+        decode_all();
+    }
+    let elapsed = started.elapsed();
+    let decodes = (IMAGES * ROUNDS) as u64;
+    println!(
+        "{} lzfse images x {} rounds: {:.1} allocations and {} bytes allocated per image, {:.1} us per image",
+        IMAGES,
+        ROUNDS,
+        (ALLOCATIONS.load(Ordering::Relaxed) - allocations) as f64 / decodes as f64,
+        (ALLOCATED_BYTES.load(Ordering::Relaxed) - allocated_bytes) / decodes,
+        elapsed.as_secs_f64() * 1e6 / decodes as f64
+    );
+}
@@ -0,0 +1,18 @@
+#![no_main]
+
+use carutil_lib::assetutil;
+use carutil_lib::coreui;
+use libfuzzer_sys::fuzz_target;
+
+// a whole catalog: the BOM, every tree, the entries assetutil would print and
+// decoding every rendition
+fuzz_target!(|data: &[u8]| {
+    let Ok(car) = coreui::CarUtilAssetStorage::from_bytes(data.to_vec()) else {
+        return;
+    };
+    let store = &car.theme_store.store;
+    let _ = assetutil::AssetUtilEntry::entries_from_asset_storage(store);
+    for csi_header in store.imagedb.values() {
+        let _ = csi_header.extracted_file();
+    }
+});
@@ -0,0 +1,21 @@
+#![no_main]
+
+use binrw::BinRead;
+use carutil_lib::assetutil;
+use carutil_lib::coreui::csi;
+use libfuzzer_sys::fuzz_target;
+
+// a single csi header and its payload, without a BOM around it
+fuzz_target!(|data: &[u8]| {
+    let Ok(csi_header) = csi::Header::read(&mut std::io::Cursor::new(data)) else {
+        return;
+    };
+    let _ = assetutil::AssetUtilEntry::from_csi_header(
+        &csi_header,
+        None,
+        vec![],
+        vec![],
+        &Default::default(),
+    );
+    let _ = csi_header.extracted_file();
+});
@@ -1,10 +1,11 @@
 use serde::Deserialize;
+use serde::Serialize;
 use std::collections::BTreeMap;
 
 use super::catalog_type;
+use super::common_type;
 use crate::common;
 use crate::coreui;
-use super::common_type;
 
 #[derive(Debug, Deserialize)]
 pub struct AssetIcon {
@@ -32,12 +33,12 @@ impl AssetIcon {
             csimetadata: coreui::csi::Metadata {
                 mod_time: 0,
                 layout: coreui::rendition::LayoutType32::Data,
-                name: common::str_to_sized_slice128(""),
+                name: [0; 128],
             },
             csibitmaplist: coreui::csi::BitmapList {
                 tlv_length: 0,
-                unknown: 1,
-                zero: 0,
+                bitmap_count: 1,
+                reserved: 0,
                 rendition_length: 0,
             },
             tlv_data: common::RawData(vec![]),
@@ -50,7 +51,7 @@ impl AssetIcon {
 pub struct AppIconImage {
     #[serde(default)]
     pub filename: Option<String>,
-    #[serde(default)]
+    #[serde(rename = "display-gamut", default)]
     pub display_gamut: Option<common_type::DisplayGamut>,
     #[serde(default)]
     pub idiom: common_type::Idiom,
@@ -107,7 +108,7 @@ pub enum Size {
     TenTwentyFour, // The App Store icon.
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub enum Scale {
     #[serde(rename = "1x")]
     OneX,
@@ -1,12 +1,27 @@
 use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
 
-#[derive(Debug, Deserialize)]
+// the Contents.json of the catalog and of the folders grouping its assets
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Catalog {
     pub info: Info,
+    // "provides-namespace" for groups whose name is part of their assets' names
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub properties: Option<BTreeMap<String, bool>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Info {
     pub author: String,
     pub version: u32,
 }
+
+impl Default for Info {
+    fn default() -> Self {
+        Info {
+            author: "xcode".to_string(),
+            version: 1,
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use serde::Deserialize;
+use serde::Serialize;
 
-
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub enum DisplayGamut {
     #[serde(rename = "sRGB")]
     SRGB,
@@ -9,10 +9,12 @@ pub enum DisplayGamut {
     DisplayP3,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub enum Idiom {
     #[serde(rename = "appLauncher")]
     AppLauncher,
+    #[serde(rename = "car")]
+    Car,
     #[serde(rename = "companionSettings")]
     CompanionSettings,
     #[serde(rename = "ios-marketing")]
@@ -30,15 +32,20 @@ pub enum Idiom {
     #[serde(rename = "tv")]
     Tv,
     #[serde(rename = "universal")]
+    #[default]
     Universal,
+    #[serde(rename = "vision")]
+    Vision,
     #[serde(rename = "watch")]
     Watch,
     #[serde(rename = "watch-marketing")]
     WatchMarketing,
 }
 
-impl Default for Idiom {
-    fn default() -> Self {
-        Idiom::Universal
-    }
+// an entry of "appearances", {"appearance": "luminosity", "value": "dark"} or
+// {"appearance": "contrast", "value": "high"}
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Appearance {
+    pub appearance: String,
+    pub value: String,
 }
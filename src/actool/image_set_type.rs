@@ -0,0 +1,35 @@
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use super::app_icon_type;
+use super::catalog_type;
+use super::common_type;
+
+// the Contents.json of a .imageset folder
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ImageSetType {
+    pub info: catalog_type::Info,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub properties: Option<BTreeMap<String, bool>>,
+    pub images: Vec<ImageSetImage>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ImageSetImage {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub appearances: Vec<common_type::Appearance>,
+    #[serde(
+        rename = "display-gamut",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub display_gamut: Option<common_type::DisplayGamut>,
+    #[serde(default)]
+    pub idiom: common_type::Idiom,
+    // absent for single scale (pdf and svg) images
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<app_icon_type::Scale>,
+}
@@ -1,21 +1,28 @@
-use std::collections::BTreeMap;
 use std::path::Path;
 
+use super::common;
 use super::coreui;
+use super::dataset;
+use super::versions::ToolVersions;
 use anyhow::Result;
-use anyhow::Context;
 use serde_json;
 use std::fs;
 
 pub mod app_icon_type;
 pub mod catalog_type;
 pub mod common_type;
+pub mod image_set_type;
 pub mod named_color_type;
 
-static COREUI_VERSION: u32 = 802;
-
-pub fn compile(document: &str, output_path: &str) -> Result<()> {
-    let catalog_path = Path::new(document).join("Contents.json");
+pub fn compile(
+    document: impl AsRef<Path>,
+    output_path: impl AsRef<Path>,
+    versions: &ToolVersions,
+    compress: bool,
+    truncate: bool,
+) -> Result<()> {
+    let document = document.as_ref();
+    let catalog_path = document.join("Contents.json");
     let catalog_str = fs::read(catalog_path)?;
     let catalog: catalog_type::Catalog = serde_json::from_slice(&catalog_str)?;
     dbg!(&catalog);
@@ -23,69 +30,73 @@ pub fn compile(document: &str, output_path: &str) -> Result<()> {
     let mut image_set_paths = vec![];
     let mut app_icon_set_paths = vec![];
     let mut color_set_paths = vec![];
+    let mut data_set_paths = vec![];
     for entry in fs::read_dir(document)? {
         let entry = entry?;
         let path = entry.path();
-        let path_str = path.to_str().context("Unable to get path for file")?;
-
-        if path.ends_with("Contents.json") {
-            // skip
-        } else if path_str.ends_with(".appiconset") {
-            app_icon_set_paths.push(path.to_owned());
-        } else if path_str.ends_with(".imageset") {
-            image_set_paths.push(path.to_owned());
-        } else if path_str.ends_with(".colorset") {
-            color_set_paths.push(path.to_owned());
-        } else {
-            eprintln!("Unhandled file: {}", path_str);
+        match common::path_extension(&path.to_string_lossy()) {
+            _ if path.file_name() == Some("Contents.json".as_ref()) => {}
+            Some("appiconset") => app_icon_set_paths.push(path),
+            Some("imageset") => image_set_paths.push(path),
+            Some("colorset") => color_set_paths.push(path),
+            Some("dataset") => data_set_paths.push(path),
+            _ => eprintln!("Unhandled file: {}", path.display()),
         }
     }
 
-    let imagedb = BTreeMap::new();
-
     for app_icon_set_path in app_icon_set_paths {
         let app_icon_set_path = app_icon_set_path.join("Contents.json");
-        let app_icon_set_str= fs::read(app_icon_set_path)?;
+        let app_icon_set_str = fs::read(app_icon_set_path)?;
         let app_icon_image: app_icon_type::AssetIcon = serde_json::from_slice(&app_icon_set_str)?;
         dbg!(&app_icon_image);
     }
 
     let header = coreui::CarHeader::new(
-        COREUI_VERSION,
+        versions.core_ui_version,
         17,
         0,
         0,
-        &format!("@(#)PROGRAM:CoreUI  PROJECT:CoreUI-{}\n", COREUI_VERSION),
+        &format!(
+            "@(#)PROGRAM:CoreUI  PROJECT:CoreUI-{}\n",
+            versions.core_ui_version
+        ),
         "Xcode 14.1 (14B47b) via ibtoold",
         [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
         0,
         5,
         0,
         0,
-    );
+    )?;
     let extended_metadata = coreui::CarExtendedMetadata::new(
         "",
         "12.0",
         "ios",
         "@(#)PROGRAM:CoreThemeDefinition  PROJECT:CoreThemeDefinition-556\n",
-    );
-    let renditionkeyfmt = coreui::rendition::KeyFormat::new(vec![]);
-    let store = coreui::CommonAssetStorage {
-        header,
-        extended_metadata,
-        renditionkeyfmt,
-        rendition_sha_digests: BTreeMap::new(),
-        imagedb,
-        facetkeysdb: Vec::new(),
-        bitmapkeydb: None,
-        appearancedb: None,
-    };
+    )?;
+    // the key format Xcode writes. Data renditions are told apart by
+    // Identifier and Idiom, an empty key format has no room for them and every
+    // dataset would share the same empty key in the RENDITIONS tree
+    let renditionkeyfmt = coreui::rendition::KeyFormat::standard();
+    let mut store = coreui::CommonAssetStorage::new(header, extended_metadata, renditionkeyfmt);
+    // read_dir order isn't stable, name identifiers are handed out by name
+    data_set_paths.sort();
+    let datasets = data_set_paths
+        .iter()
+        .map(|data_set_path| dataset::read_dataset(data_set_path))
+        .collect::<Result<Vec<dataset::DataSet>>>()?;
+    // the tlv the tags go in hasn't been checked against a catalog Xcode
+    // compiled, and a catalog without them wouldn't match its source
+    if let Some(dataset) = datasets.iter().find(|dataset| !dataset.tags.is_empty()) {
+        anyhow::bail!(
+            "{}.dataset has on-demand-resource tags {:?}, writing them isn't supported",
+            dataset.name,
+            dataset.tags
+        );
+    }
+    dataset::add_datasets(&mut store, &datasets, compress, truncate)?;
+
     let theme_store = coreui::StructuredThemeStore { store };
     let car = coreui::CarUtilAssetStorage { theme_store };
 
-    let car_output_path = Path::new(output_path).join("Assets.car");
-    let car_output_path = car_output_path
-        .to_str()
-        .context("Unable to create output path for Assets.car")?;
-    car.write_data(car_output_path)
+    car.write_data(output_path.as_ref().join("Assets.car"))
 }
@@ -1,33 +1,42 @@
 use std::collections::BTreeMap;
 
 use serde::Deserialize;
+use serde::Serialize;
 
 use super::catalog_type;
 use super::common_type;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct NamedColorType {
     pub info: catalog_type::Info,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub properties: Option<BTreeMap<String, bool>>,
     pub colors: Vec<NamedColor>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct NamedColor {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub appearances: Vec<common_type::Appearance>,
+    #[serde(
+        rename = "display-gamut",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
     pub display_gamut: Option<common_type::DisplayGamut>,
     #[serde(default)]
     pub idiom: common_type::Idiom,
     pub color: Color,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Color {
+    #[serde(rename = "color-space")]
     pub color_space: ColorSpace,
     pub components: Components,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub enum ColorSpace {
     #[serde(rename = "srgb")]
     SRGB,
@@ -35,7 +44,7 @@ pub enum ColorSpace {
     DisplayP3,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Components {
     pub red: f64,
     pub green: f64,
@@ -0,0 +1,181 @@
+use anyhow::Context;
+use anyhow::Result;
+use std::io::Write;
+
+const LOCAL_FILE_HEADER: u32 = 0x04034b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+// 2.0, the first version with deflate
+const VERSION: u16 = 20;
+// names are utf-8
+const FLAGS: u16 = 1 << 11;
+// every entry is dated 1980-01-01 00:00 so archives of the same catalog are identical
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZipCompression {
+    Stored,
+    Deflate,
+}
+
+impl ZipCompression {
+    fn method(self) -> u16 {
+        match self {
+            ZipCompression::Stored => 0,
+            ZipCompression::Deflate => 8,
+        }
+    }
+}
+
+struct CentralDirectoryEntry {
+    name: String,
+    name_length: u16,
+    method: u16,
+    crc32: u32,
+    compressed_size: u32,
+    uncompressed_size: u32,
+    offset: u32,
+}
+
+// writes a zip archive entry by entry, without zip64 so entries and the
+// archive itself are limited to 4GiB
+pub(crate) struct ZipWriter<W: Write> {
+    writer: W,
+    compression: ZipCompression,
+    offset: u64,
+    entries: Vec<CentralDirectoryEntry>,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(writer: W, compression: ZipCompression) -> ZipWriter<W> {
+        ZipWriter {
+            writer,
+            compression,
+            offset: 0,
+            entries: vec![],
+        }
+    }
+
+    pub fn add(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        let compressed = compress(self.compression, data)?;
+        let entry = CentralDirectoryEntry {
+            name: name.to_string(),
+            name_length: u16::try_from(name.len())
+                .ok()
+                .context(format!("{} is too long for a zip entry name", name))?,
+            method: self.compression.method(),
+            crc32: crc32fast::hash(data),
+            compressed_size: u32::try_from(compressed.len())
+                .ok()
+                .context(format!("{} is too large for a zip archive", name))?,
+            uncompressed_size: u32::try_from(data.len())
+                .ok()
+                .context(format!("{} is too large for a zip archive", name))?,
+            offset: self.offset()?,
+        };
+
+        let mut header = vec![];
+        header.extend(LOCAL_FILE_HEADER.to_le_bytes());
+        header.extend(VERSION.to_le_bytes());
+        header.extend(FLAGS.to_le_bytes());
+        header.extend(entry.method.to_le_bytes());
+        header.extend(DOS_TIME.to_le_bytes());
+        header.extend(DOS_DATE.to_le_bytes());
+        header.extend(entry.crc32.to_le_bytes());
+        header.extend(entry.compressed_size.to_le_bytes());
+        header.extend(entry.uncompressed_size.to_le_bytes());
+        header.extend(entry.name_length.to_le_bytes());
+        // extra field length
+        header.extend(0u16.to_le_bytes());
+        header.extend(name.as_bytes());
+        self.write(&header)?;
+        self.write(&compressed)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    // writes the central directory, returns the underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        let central_directory_offset = self.offset()?;
+        let mut central_directory = vec![];
+        for entry in &self.entries {
+            central_directory.extend(CENTRAL_DIRECTORY_HEADER.to_le_bytes());
+            // version made by, version needed to extract
+            central_directory.extend(VERSION.to_le_bytes());
+            central_directory.extend(VERSION.to_le_bytes());
+            central_directory.extend(FLAGS.to_le_bytes());
+            central_directory.extend(entry.method.to_le_bytes());
+            central_directory.extend(DOS_TIME.to_le_bytes());
+            central_directory.extend(DOS_DATE.to_le_bytes());
+            central_directory.extend(entry.crc32.to_le_bytes());
+            central_directory.extend(entry.compressed_size.to_le_bytes());
+            central_directory.extend(entry.uncompressed_size.to_le_bytes());
+            central_directory.extend(entry.name_length.to_le_bytes());
+            // extra field length, comment length, disk number, internal and external attributes
+            central_directory.extend([0u8; 12]);
+            central_directory.extend(entry.offset.to_le_bytes());
+            central_directory.extend(entry.name.as_bytes());
+        }
+        let entry_count = u16::try_from(self.entries.len())
+            .ok()
+            .context("too many entries for a zip archive")?;
+        self.write(&central_directory)?;
+
+        let mut end = vec![];
+        end.extend(END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        // this disk, disk with the central directory
+        end.extend([0u8; 4]);
+        end.extend(entry_count.to_le_bytes());
+        end.extend(entry_count.to_le_bytes());
+        end.extend((central_directory.len() as u32).to_le_bytes());
+        end.extend(central_directory_offset.to_le_bytes());
+        // comment length
+        end.extend(0u16.to_le_bytes());
+        self.write(&end)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn offset(&self) -> Result<u32> {
+        u32::try_from(self.offset)
+            .ok()
+            .context("zip archive is larger than 4GiB")
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.writer.write_all(data)?;
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+}
+
+fn compress(compression: ZipCompression, data: &[u8]) -> Result<Vec<u8>> {
+    match compression {
+        ZipCompression::Stored => Ok(data.to_vec()),
+        #[cfg(feature = "zlib")]
+        ZipCompression::Deflate => {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(vec![], flate2::Compression::default());
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?)
+        }
+        #[cfg(not(feature = "zlib"))]
+        ZipCompression::Deflate => anyhow::bail!("deflate needs the zlib feature"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_names_are_an_error() {
+        let mut zip = ZipWriter::new(vec![], ZipCompression::Stored);
+        let name = "a".repeat(u16::MAX as usize + 1);
+        let error = zip.add(&name, b"data").unwrap_err();
+        assert!(error.to_string().contains("too long for a zip entry name"));
+        // nothing was written for it, the archive is only the end of central directory
+        assert_eq!(zip.finish().unwrap().len(), 22);
+    }
+}
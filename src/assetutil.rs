@@ -1,21 +1,27 @@
 use std::collections::BTreeMap;
-use std::collections::HashMap;
+use std::collections::BTreeSet;
+use std::fmt::Display;
 
 use crate::common;
 use crate::coregraphics;
 use crate::coreui;
+use crate::extract;
+use crate::filter;
+use crate::versions::ToolVersions;
 use hex::ToHex;
 use num_traits::FromPrimitive;
 use serde::Serialize;
+use serde::Serializer;
 
-// version of the assetutil tool, this is hardcoded to match current version
-pub static VERSION: f64 = 804.3;
-
-#[derive(Debug, Serialize)]
+#[derive(Debug, Default, Serialize)]
 pub struct AssetUtilHeader {
     #[serde(rename(serialize = "Appearances"))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub appearances: Option<HashMap<String, u32>>,
+    pub appearances: Option<BTreeMap<String, u32>>,
+    // the part of Appearances at least one rendition uses
+    #[serde(rename(serialize = "AppearancesUsed"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub appearances_used: Option<BTreeMap<String, u32>>,
     #[serde(rename(serialize = "AssetStorageVersion"))]
     pub asset_storage_version: String,
     #[serde(rename(serialize = "Authoring Tool"))]
@@ -24,14 +30,26 @@ pub struct AssetUtilHeader {
     pub core_ui_version: u32,
     #[serde(rename(serialize = "DumpToolVersion"))]
     pub dump_tool_version: f64,
+    #[serde(rename(serialize = "FileTimestamp"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_timestamp: Option<i64>,
+    #[serde(rename(serialize = "IdiomsPresent"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idioms_present: Option<Vec<coreui::rendition::Idiom>>,
     #[serde(rename(serialize = "Key Format"))]
     pub key_format: Vec<coreui::rendition::AttributeType>,
+    #[serde(rename(serialize = "KeySemantics"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_semantics: Option<u32>,
     #[serde(rename(serialize = "MainVersion"))]
     pub main_version_string: String,
     #[serde(rename(serialize = "Platform"))]
     pub platform: String,
     #[serde(rename(serialize = "PlatformVersion"))]
     pub platform_version: String,
+    #[serde(rename(serialize = "ScalesPresent"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scales_present: Option<Vec<u32>>,
     #[serde(rename(serialize = "SchemaVersion"))]
     pub schema_version: u32,
     #[serde(rename(serialize = "StorageVersion"))]
@@ -40,35 +58,249 @@ pub struct AssetUtilHeader {
     #[serde(skip_serializing_if = "String::is_empty")]
     pub thinning_parameters: String,
     #[serde(rename(serialize = "Timestamp"))]
-    pub timestamp: u32,
+    pub timestamp: i64,
+}
+
+impl AssetUtilHeader {
+    // the idioms, scales and appearances the entries have, so nobody has to
+    // scan every entry to find out whether there are iPad or dark assets.
+    // Entries without an appearance use the one with identifier 0
+    pub fn add_variants_present(&mut self, entries: &[AssetUtilEntry]) {
+        let mut idioms = BTreeMap::new();
+        let mut scales = BTreeSet::new();
+        let mut appearances = BTreeSet::new();
+        for entry in entries {
+            let idiom = entry.idiom.unwrap_or(coreui::rendition::Idiom::Universal);
+            idioms.insert(idiom.value(), idiom);
+            scales.extend(entry.scale);
+            appearances.insert(entry.appearance.as_deref());
+        }
+        self.idioms_present = Some(idioms.into_values().collect());
+        self.scales_present = Some(scales.into_iter().collect());
+        self.appearances_used = self.appearances.as_ref().map(|appearancedb| {
+            appearancedb
+                .iter()
+                .filter(|(name, identifier)| {
+                    appearances.contains(&Some(name.as_str()))
+                        || (**identifier == 0 && appearances.contains(&None))
+                })
+                .map(|(name, identifier)| (name.clone(), *identifier))
+                .collect()
+        });
+    }
+}
+
+// AssetType as assetutil prints it, None for layouts it has no name for
+pub fn asset_type_name(layout: coreui::rendition::LayoutType32) -> Option<&'static str> {
+    match layout {
+        coreui::rendition::LayoutType32::Color => Some("Color"),
+        coreui::rendition::LayoutType32::Data => Some("Data"),
+        coreui::rendition::LayoutType32::Image => Some("Image"),
+        coreui::rendition::LayoutType32::LayerStack => Some("Layer Stack"),
+        coreui::rendition::LayoutType32::MultisizeImage => Some("MultiSized Image"),
+        coreui::rendition::LayoutType32::PackedImage => Some("PackedImage"),
+        coreui::rendition::LayoutType32::Vector => Some("Vector"),
+        coreui::rendition::LayoutType32::Texture => Some("Texture"),
+        coreui::rendition::LayoutType32::TextureImage => Some("Texture Image"),
+        _ => None,
+    }
+}
+
+// the AssetType of a rendition, layouts newer than this table are printed
+// with their value, "Unknown (0x3F7)"
+pub fn asset_type(layout: coreui::rendition::LayoutType32) -> Option<String> {
+    match layout {
+        coreui::rendition::LayoutType32::Unknown(value) => Some(format!("Unknown (0x{:X})", value)),
+        layout => asset_type_name(layout).map(|asset_type| asset_type.to_string()),
+    }
+}
+
+// what `assetutil --brief` prints: the header fields people look for and
+// how many entries of each asset type there are
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BriefInfo {
+    #[serde(rename(serialize = "Platform"))]
+    pub platform: String,
+    #[serde(rename(serialize = "PlatformVersion"))]
+    pub platform_version: String,
+    #[serde(rename(serialize = "CoreUIVersion"))]
+    pub core_ui_version: u32,
+    #[serde(rename(serialize = "StorageVersion"))]
+    pub storage_version: u32,
+    #[serde(rename(serialize = "Timestamp"))]
+    pub timestamp: i64,
+    #[serde(rename(serialize = "Appearances"))]
+    pub appearances: Vec<String>,
+    #[serde(rename(serialize = "KeyFormatLength"))]
+    pub key_format_length: usize,
+    #[serde(rename(serialize = "Entries"))]
+    pub entries: usize,
+    // renditions whose layout has no AssetType are counted as "Other"
+    #[serde(rename(serialize = "AssetTypes"))]
+    pub asset_types: BTreeMap<String, usize>,
+}
+
+impl BriefInfo {
+    pub fn from_storage(store: &coreui::CommonAssetStorage) -> BriefInfo {
+        let mut asset_types = BTreeMap::new();
+        for csi_header in store.imagedb.values() {
+            let asset_type =
+                asset_type(csi_header.csimetadata.layout).unwrap_or_else(|| "Other".to_string());
+            *asset_types.entry(asset_type).or_insert(0) += 1;
+        }
+        BriefInfo {
+            platform: store.deployment_platform(),
+            platform_version: store.deployment_platform_version(),
+            core_ui_version: store.header.core_ui_version,
+            storage_version: store.header.storage_version,
+            timestamp: store.timestamp(),
+            appearances: store
+                .appearance_names()
+                .unwrap_or_default()
+                .into_keys()
+                .collect(),
+            key_format_length: store.renditionkeyfmt.attribute_types.len(),
+            entries: store.imagedb.len(),
+            asset_types,
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let timestamp = chrono::DateTime::from_timestamp(self.timestamp, 0)
+            .map(|date| {
+                format!(
+                    "{} ({})",
+                    self.timestamp,
+                    date.format("%Y-%m-%d %H:%M:%S UTC")
+                )
+            })
+            .unwrap_or_else(|| self.timestamp.to_string());
+        let appearances = if self.appearances.is_empty() {
+            "none".to_string()
+        } else {
+            self.appearances.join(", ")
+        };
+        let mut lines = vec![
+            format!("Platform: {} {}", self.platform, self.platform_version),
+            format!("CoreUI version: {}", self.core_ui_version),
+            format!("Storage version: {}", self.storage_version),
+            format!("Timestamp: {}", timestamp),
+            format!("Appearances: {}", appearances),
+            format!("Key format: {} attributes", self.key_format_length),
+            format!("Entries: {}", self.entries),
+        ];
+        lines.extend(
+            self.asset_types
+                .iter()
+                .map(|(asset_type, count)| format!("  {}: {}", asset_type, count)),
+        );
+        lines
+    }
 }
 
 pub trait ToAssetUtilHeader {
-    fn asset_util_header(&self) -> AssetUtilHeader;
+    fn asset_util_header(&self) -> AssetUtilHeader {
+        self.asset_util_header_with_versions(&ToolVersions::default())
+    }
+
+    fn asset_util_header_with_versions(&self, versions: &ToolVersions) -> AssetUtilHeader;
 }
 
 impl ToAssetUtilHeader for coreui::CarUtilAssetStorage {
-    fn asset_util_header(&self) -> AssetUtilHeader {
+    fn asset_util_header_with_versions(&self, versions: &ToolVersions) -> AssetUtilHeader {
         AssetUtilHeader {
-            appearances: self.theme_store.store.appearences(),
+            appearances: self.theme_store.store.appearance_names(),
+            appearances_used: None,
             asset_storage_version: self.theme_store.store.version_string(),
             authoring_tool: self.theme_store.store.authoring_tool(),
             core_ui_version: self.theme_store.store.header.core_ui_version,
-            dump_tool_version: VERSION,
+            dump_tool_version: versions.dump_tool_version,
+            file_timestamp: None,
+            idioms_present: None,
             key_format: self.theme_store.rendition_key_format(),
+            key_semantics: None,
             main_version_string: self.theme_store.store.main_version_string(),
             platform: self.theme_store.store.deployment_platform(),
             platform_version: self.theme_store.store.deployment_platform_version(),
+            scales_present: None,
             schema_version: self.theme_store.store.header.schema_version,
             storage_version: self.theme_store.store.header.storage_version,
-            timestamp: self.theme_store.store.header.storage_timestamp,
+            timestamp: self.theme_store.store.timestamp(),
             thinning_parameters: self.theme_store.store.thinning_arguments(),
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+// fields beyond what assetutil prints
+#[derive(Debug, Default)]
+pub struct EntryOptions {
+    // entries whose facet or rendition name matches also get their raw bytes,
+    // no other entry's payload is included
+    pub debug_blobs: Option<String>,
+    // PixelDigest for every image entry
+    pub pixel_digests: bool,
+    // how group separators in Name are rendered, raw like assetutil by default
+    pub flatten_names: common::FlattenNames,
+    // threads building entries, 0 for one per core
+    pub jobs: usize,
+    // only renditions whose key matches get an entry
+    pub filter: filter::RenditionFilter,
+    // debug_blobs names match exactly instead of ignoring case
+    pub case_sensitive: bool,
+    // what entries of facets sharing a name identifier get
+    pub name_collisions: NameCollisions,
+}
+
+// renditions whose name identifier more than one facet uses, and whose key
+// doesn't tell which one it belongs to
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum NameCollisions {
+    // Name is the first candidate, NameCandidates lists all of them
+    #[default]
+    Report,
+    // Name is the first candidate, like assetutil output
+    First,
+    // refuse to dump the catalog
+    Error,
+}
+
+impl std::str::FromStr for NameCollisions {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "report" => Ok(NameCollisions::Report),
+            "first" => Ok(NameCollisions::First),
+            "error" => Ok(NameCollisions::Error),
+            _ => anyhow::bail!(
+                "unknown name collision handling {:?}, expected report, first or error",
+                s
+            ),
+        }
+    }
+}
+
+// the first rendition whose facet is ambiguous and its candidate names
+pub fn ambiguous_facet_names(
+    asset_storage: &coreui::CommonAssetStorage,
+) -> Option<(coreui::rendition::Key, Vec<String>)> {
+    let collisions = asset_storage.name_identifier_collisions();
+    if collisions.is_empty() {
+        return None;
+    }
+    asset_storage.imagedb.keys().find_map(|rendition_key| {
+        let names = asset_storage.facet_names_for_key(rendition_key);
+        (names.len() > 1).then_some((*rendition_key, names))
+    })
+}
+
+#[derive(Debug, Default, Serialize)]
 pub struct AssetUtilEntry {
+    // the frame of the stored pixels on the original canvas, for images CoreUI
+    // cropped the transparent borders of
+    #[serde(rename(serialize = "AlphaCroppedFrame"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpha_cropped_frame: Option<[u32; 4]>,
     #[serde(rename(serialize = "Appearance"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub appearance: Option<String>,
@@ -79,6 +311,7 @@ pub struct AssetUtilEntry {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub bits_per_component: Option<u32>,
     #[serde(rename(serialize = "Color components"))]
+    #[serde(serialize_with = "serialize_color_components")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub color_components: Option<Vec<f64>>,
     #[serde(rename(serialize = "ColorModel"))]
@@ -90,33 +323,96 @@ pub struct AssetUtilEntry {
     #[serde(rename(serialize = "Compression"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub compression: Option<coreui::rendition::CompressionType>,
+    #[serde(rename(serialize = "CSIHeader"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub csi_header: Option<serde_json::Value>,
+    #[serde(rename(serialize = "CursorHotspot"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor_hotspot: Option<(u16, u16)>,
+    // the payload of data assets once decompressed, SizeOnDisk is what the
+    // rendition takes up in the catalog and StoredDataLength the compressed
+    // payload when it is smaller
     #[serde(rename(serialize = "Data Length"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub data_length: Option<u32>,
+    #[serde(rename(serialize = "Direction"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direction: Option<coreui::rendition::NamedValue<coreui::rendition::Direction>>,
     #[serde(rename(serialize = "Encoding"))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub encoding: Option<coreui::csi::PixelFormat>,
+    pub encoding: Option<String>,
+    #[serde(rename(serialize = "FrameCount"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_count: Option<u32>,
+    #[serde(rename(serialize = "FrameHeight"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_height: Option<u32>,
+    // the variant of a vector glyph (symbol)
+    #[serde(rename(serialize = "GlyphSize"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub glyph_size: Option<coreui::rendition::NamedValue<coreui::rendition::GlyphSize>>,
+    #[serde(rename(serialize = "GlyphWeight"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub glyph_weight: Option<coreui::rendition::NamedValue<coreui::rendition::GlyphWeight>>,
     #[serde(rename(serialize = "Idiom"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub idiom: Option<coreui::rendition::Idiom>,
+    #[serde(rename(serialize = "Internal"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub internal: Option<bool>,
+    #[serde(rename(serialize = "Look"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub look: Option<coreui::rendition::NamedValue<coreui::rendition::Look>>,
     #[serde(rename(serialize = "Name"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    // every facet name the rendition could belong to when its name
+    // identifier is shared, Name is the first one
+    #[serde(rename(serialize = "NameCandidates"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_candidates: Option<Vec<String>>,
     #[serde(rename(serialize = "NameIdentifier"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name_identifier: Option<u16>,
     #[serde(rename(serialize = "Opaque"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub opaque: Option<bool>,
+    #[serde(rename(serialize = "OriginalSize"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_size: Option<[u32; 2]>,
+    #[serde(rename(serialize = "Packed"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packed: Option<bool>,
+    #[serde(rename(serialize = "PackedAsset"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packed_asset: Option<String>,
+    #[serde(rename(serialize = "PayloadHexPrefix"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload_hex_prefix: Option<String>,
+    // only filled in when asked for, null when the image can't be decoded
+    #[serde(rename(serialize = "PixelDigest"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pixel_digest: Option<Option<String>>,
     #[serde(rename(serialize = "PixelHeight"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub pixel_height: Option<u32>,
     #[serde(rename(serialize = "PixelWidth"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub pixel_width: Option<u32>,
+    #[serde(rename(serialize = "PresentationState"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presentation_state:
+        Option<coreui::rendition::NamedValue<coreui::rendition::PresentationState>>,
     #[serde(rename(serialize = "RenditionName"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rendition_name: Option<String>,
+    // payloads in a format we can't read yet, by their tag
+    #[serde(rename(serialize = "RenditionPayloadLength"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rendition_payload_length: Option<u32>,
+    #[serde(rename(serialize = "RenditionPayloadTag"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rendition_payload_tag: Option<String>,
     #[serde(rename(serialize = "Scale"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub scale: Option<u32>,
@@ -132,6 +428,22 @@ pub struct AssetUtilEntry {
     #[serde(rename(serialize = "State"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub state: Option<coreui::rendition::State>,
+    #[serde(rename(serialize = "StoredDataLength"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stored_data_length: Option<u32>,
+    #[serde(rename(serialize = "Subtype"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtype: Option<u16>,
+    #[serde(rename(serialize = "SubtypeName"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtype_name: Option<coreui::rendition::ImageSubtype>,
+    // RenditionName was made up because the stored one is blank
+    #[serde(rename(serialize = "SynthesizedRenditionName"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub synthesized_rendition_name: Option<bool>,
+    #[serde(rename(serialize = "TLVHex"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tlv_hex: Option<String>,
     #[serde(rename(serialize = "Template Mode"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub template_mode: Option<coreui::rendition::TemplateMode>,
@@ -141,60 +453,187 @@ pub struct AssetUtilEntry {
     #[serde(rename(serialize = "Value"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub value: Option<coreui::rendition::Value>,
+    #[serde(rename(serialize = "VectorBased"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vector_based: Option<bool>,
+    // "pdf", "svg", or "unknown" for payloads we can't read yet
+    #[serde(rename(serialize = "VectorFormat"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vector_format: Option<String>,
+}
+
+// the order assetutil prints entries in: by asset type, name, then rendition name
+pub fn sort_entries(entries: &mut [AssetUtilEntry]) {
+    entries.sort_by(|a, b| {
+        (&a.asset_type, &a.name, &a.rendition_name).cmp(&(
+            &b.asset_type,
+            &b.name,
+            &b.rendition_name,
+        ))
+    });
+}
+
+// the pretty printed array assetutil writes, the header followed by the entries.
+// Entries are serialized on `jobs` threads and spliced into the array, the
+// output is the same as serializing the whole array at once
+pub fn to_json_pretty(
+    header: &serde_json::Value,
+    entries: &[AssetUtilEntry],
+    jobs: usize,
+) -> anyhow::Result<String> {
+    let mut elements = vec![serde_json::to_string_pretty(header)?];
+    // entries go through Value like the header so object keys come out sorted
+    let serialized = common::parallel_map(entries, jobs, |entry| {
+        serde_json::to_value(entry).and_then(|value| serde_json::to_string_pretty(&value))
+    });
+    for element in serialized {
+        elements.push(element?);
+    }
+    let elements: Vec<String> = elements
+        .iter()
+        .map(|element| format!("  {}", element.replace('\n', "\n  ")))
+        .collect();
+    Ok(format!("[\n{}\n]", elements.join(",\n")))
 }
 
 impl AssetUtilEntry {
     pub fn entries_from_asset_storage(
         asset_storage: &coreui::CommonAssetStorage,
     ) -> Vec<AssetUtilEntry> {
-        let mut result = vec![];
+        AssetUtilEntry::entries_with_options(asset_storage, &EntryOptions::default())
+    }
+
+    pub fn entries_with_debug_blobs(
+        asset_storage: &coreui::CommonAssetStorage,
+        debug_blobs: Option<&str>,
+    ) -> Vec<AssetUtilEntry> {
+        let options = EntryOptions {
+            debug_blobs: debug_blobs.map(|name| name.to_string()),
+            ..Default::default()
+        };
+        AssetUtilEntry::entries_with_options(asset_storage, &options)
+    }
+
+    pub fn entries_with_options(
+        asset_storage: &coreui::CommonAssetStorage,
+        options: &EntryOptions,
+    ) -> Vec<AssetUtilEntry> {
+        let debug_blobs = options.debug_blobs.as_deref();
 
-        let name_identifer_to_facet_key = asset_storage
-            .facetkeysdb
+        let name_identifer_to_facet_key = asset_storage.name_identifier_to_facet_name();
+        let name_identifier_collisions = asset_storage.name_identifier_collisions();
+        let names_from_rendition_names =
+            !asset_storage.has_key_attribute(coreui::rendition::AttributeType::Identifier);
+        let appearances = if asset_storage.supports_appearances() {
+            asset_storage.appearance_names().unwrap_or_default()
+        } else {
+            BTreeMap::new()
+        };
+
+        // every input is read-only, so entries are built on `jobs` threads and
+        // joined in imagedb order
+        let renditions: Vec<_> = asset_storage
+            .imagedb
             .iter()
-            .map(|(name, key_token)| {
-                key_token
-                    .attributes
-                    .iter()
-                    .find(|attribute| {
-                        attribute.name == coreui::rendition::AttributeType16::Identifier
-                    })
-                    .and_then(|attribute| Some((attribute.value, name.to_string())))
+            .filter(|(rendition_key, _)| {
+                options
+                    .filter
+                    .matches_key(&asset_storage.key_values(rendition_key))
             })
-            .flatten()
-            .collect::<HashMap<u16, String>>();
-
-        for (rendition_key, csi_header) in &asset_storage.imagedb {
+            .collect();
+        common::parallel_map(&renditions, options.jobs, |(rendition_key, csi_header)| {
             let rendition_key_values: Vec<(coreui::rendition::AttributeType, u16)> =
-                asset_storage.renditionkeyfmt.map(rendition_key);
+                asset_storage.key_values(rendition_key);
             let name_identifier = rendition_key_values
                 .iter()
                 .find(|(attribute, _)| *attribute == coreui::rendition::AttributeType::Identifier)
                 .and_then(|(_, value)| Some(value));
-            let facet_key = if let Some(name_identifier) = name_identifier {
-                name_identifer_to_facet_key.get(&name_identifier).cloned()
-            } else {
-                None
+            // only shared identifiers need the whole facet token compared
+            let facet_names = match name_identifier {
+                Some(name_identifier)
+                    if name_identifier_collisions.contains_key(name_identifier) =>
+                {
+                    asset_storage.facet_names_for_key(rendition_key)
+                }
+                Some(name_identifier) => name_identifer_to_facet_key
+                    .get(name_identifier)
+                    .cloned()
+                    .into_iter()
+                    .collect(),
+                None if names_from_rendition_names => {
+                    facet_name_from_rendition_name(&csi_header.csimetadata.name())
+                        .into_iter()
+                        .collect()
+                }
+                None => vec![],
             };
+            let facet_key = facet_names.first().cloned();
+            let cursor_hotspot = facet_key
+                .as_ref()
+                .and_then(|facet_key| asset_storage.facetkeysdb.get(facet_key))
+                .map(|key_token| key_token.cursor_hotspot)
+                .filter(|cursor_hotspot| *cursor_hotspot != (0, 0));
             let sha_digest = asset_storage
                 .rendition_sha_digests
                 .get(rendition_key)
                 .cloned()
                 .unwrap_or_default();
-            let entry = AssetUtilEntry::from_csi_header(
+            let (rendition_name, synthesized) = rendition_name(
+                csi_header,
+                facet_key.as_deref(),
+                &rendition_key_values,
+                &appearances,
+            );
+            let mut entry = AssetUtilEntry::from_csi_header(
                 &csi_header,
                 facet_key,
                 rendition_key_values,
                 sha_digest,
-                asset_storage
-                    .appearancedb
-                    .as_ref()
-                    .unwrap_or(&BTreeMap::new()),
+                &appearances,
             );
-            result.push(entry);
-        }
+            entry.cursor_hotspot = cursor_hotspot;
+            if synthesized && entry.rendition_name.is_some() {
+                entry.rendition_name = Some(rendition_name);
+                entry.synthesized_rendition_name = Some(true);
+            }
+            if let Some(coreui::rendition::Rendition::InternalLink { .. }) =
+                csi_header.rendition_data
+            {
+                entry.packed = Some(true);
+                entry.packed_asset = asset_storage.packed_asset_name(csi_header);
+            }
+            if debug_blobs.is_some_and(|debug_blobs| {
+                [&entry.name, &entry.rendition_name]
+                    .into_iter()
+                    .flatten()
+                    .any(|name| coreui::names_match(name, debug_blobs, options.case_sensitive))
+            }) {
+                entry.add_debug_blobs(csi_header);
+            }
+            if facet_names.len() > 1 && options.name_collisions == NameCollisions::Report {
+                entry.name_candidates = Some(
+                    facet_names
+                        .iter()
+                        .map(|name| options.flatten_names.name(name))
+                        .collect(),
+                );
+            }
+            entry.name = entry.name.map(|name| options.flatten_names.name(&name));
+            if options.pixel_digests && entry.asset_type.as_deref() == Some("Image") {
+                entry.pixel_digest = Some(csi_header.pixel_digest().ok());
+            }
+            entry
+        })
+    }
 
-        result
+    // bytes to attach to format bug reports: the tlv region, the start of the
+    // stored payload and every csi header field
+    pub fn add_debug_blobs(&mut self, csi_header: &coreui::csi::Header) {
+        let (compression, payload) =
+            extract::raw_payload(csi_header).unwrap_or_else(|_| ("unknown".to_string(), vec![]));
+        self.tlv_hex = Some(common::hex_prefix(&csi_header.tlv_data.0, usize::MAX));
+        self.payload_hex_prefix = Some(common::hex_prefix(&payload, 256));
+        self.csi_header = Some(extract::raw_sidecar(csi_header, &compression));
     }
 
     pub fn from_csi_header(
@@ -204,275 +643,679 @@ impl AssetUtilEntry {
         sha_digest: Vec<u8>,
         appearancedb: &BTreeMap<String, u32>,
     ) -> AssetUtilEntry {
-        let layout = csi_header.csimetadata.layout;
+        let context = EntryContext {
+            csi_header,
+            facet_key,
+            key_values: rendition_key_values,
+            sha_digest,
+            appearancedb,
+        };
+        let mut entry = context.base_entry();
+        if let Some(builder) = entry_builder(csi_header.csimetadata.layout) {
+            builder.add_fields(&context, &mut entry);
+        }
 
-        let appearance: Option<String> =
-            rendition_key_values
-                .iter()
-                .find_map(|(attribute, attribute_value)| {
-                    if *attribute == coreui::rendition::AttributeType::Appearance {
-                        appearancedb
-                            .iter()
-                            .find_map(|(appearance_string, appearance_index)| {
-                                if *attribute_value > 0
-                                    && *appearance_index == *attribute_value as u32
-                                {
-                                    Some(appearance_string.to_owned())
-                                } else {
-                                    None
-                                }
-                            })
-                    } else {
-                        None
-                    }
-                });
+        if entry.name.is_none() {
+            entry.name = entry.rendition_name.clone();
+        }
+        // vector renditions have no pixel size or slices, assetutil reports the
+        // PDF bounds in points instead
+        if entry.vector_based.is_some() && matches!(entry.pixel_width, None | Some(0)) {
+            if let Some((width, height)) = csi_header.vector_bounds() {
+                entry.pixel_width = Some(width);
+                entry.pixel_height = Some(height);
+            }
+        }
+        entry
+    }
+}
 
-        let asset_type = match layout {
-            coreui::rendition::LayoutType32::Color => Some("Color".to_string()),
-            coreui::rendition::LayoutType32::Data => Some("Data".to_string()),
-            coreui::rendition::LayoutType32::Image => Some("Image".to_string()),
-            coreui::rendition::LayoutType32::MultisizeImage => Some("MultiSized Image".to_string()),
-            coreui::rendition::LayoutType32::PackedImage => Some("PackedImage".to_string()),
-            _ => None,
-        };
+// a rendition and what its key decodes to, shared by every entry builder
+pub struct EntryContext<'a> {
+    pub csi_header: &'a coreui::csi::Header,
+    pub facet_key: Option<String>,
+    pub key_values: Vec<(coreui::rendition::AttributeType, u16)>,
+    pub sha_digest: Vec<u8>,
+    pub appearancedb: &'a BTreeMap<String, u32>,
+}
 
-        // TODO: fix
-        let bits_per_component = match layout {
-            coreui::rendition::LayoutType32::PackedImage
-            | coreui::rendition::LayoutType32::Image => Some(8),
-            _ => None,
-        };
+impl EntryContext<'_> {
+    // only set in keys of variants, zero is the default
+    pub fn key_value(&self, attribute: coreui::rendition::AttributeType) -> Option<u16> {
+        self.key_values
+            .iter()
+            .find(|(key_attribute, value)| *key_attribute == attribute && *value > 0)
+            .map(|(_, value)| *value)
+    }
 
-        let color_components = match &csi_header.rendition_data {
-            Some(coreui::rendition::Rendition::Color { components, .. }) => {
-                Some(components.to_owned())
-            }
-            _ => None,
+    // the fields every layout has: what the key says, digests and sizes, and
+    // what can be told from the payload alone
+    pub fn base_entry(&self) -> AssetUtilEntry {
+        let csi_header = self.csi_header;
+        let value_of = |attribute| {
+            self.key_values
+                .iter()
+                .find(|(key_attribute, _)| *key_attribute == attribute)
+                .map(|(_, value)| *value)
         };
 
-        let color_model = match layout {
-            coreui::rendition::LayoutType32::PackedImage
-            | coreui::rendition::LayoutType32::Image => csi_header.color_space.color_model(),
-            _ => None,
-        };
+        let appearance = self
+            .key_value(coreui::rendition::AttributeType::Appearance)
+            .and_then(|appearance| {
+                self.appearancedb
+                    .iter()
+                    .find(|(_, identifier)| **identifier == appearance as u32)
+                    .map(|(appearance_name, _)| appearance_name.to_owned())
+            });
 
-        // TODO: fix
+        let theme_payload = csi_header
+            .rendition_data
+            .as_ref()
+            .and_then(|rendition_data| rendition_data.theme_payload());
+
+        // payloads stored in a CELM container get a color space, the image
+        // builder picks gray for monochrome ones
         let colorspace = match &csi_header.rendition_data {
             Some(coreui::rendition::Rendition::Theme { .. })
-            | Some(coreui::rendition::Rendition::ThemeCBCK { .. })
-            | Some(coreui::rendition::Rendition::Color { .. }) => match color_model {
-                Some(coregraphics::ColorModel::Monochrome) => {
-                    Some(coregraphics::ColorSpace::GrayGamma2_2)
-                }
-                _ => Some(coregraphics::ColorSpace::SRGB),
-            },
+            | Some(coreui::rendition::Rendition::ThemeCBCK { .. }) => {
+                Some(coregraphics::ColorSpace::SRGB)
+            }
             _ => None,
         };
 
-        let compression = match &csi_header.rendition_data {
-            Some(coreui::rendition::Rendition::Theme {
-                compression_type, ..
-            }) => Some(*compression_type),
-            Some(coreui::rendition::Rendition::ThemeCBCK {
-                compression_type, ..
-            }) => Some(*compression_type),
-            Some(coreui::rendition::Rendition::RawData { .. }) => match layout {
-                coreui::rendition::LayoutType32::Data => {
-                    Some(coreui::rendition::CompressionType::Uncompressed)
-                }
-                _ => None,
-            },
-            _ => None,
-        };
+        let unknown_payload = csi_header
+            .rendition_data
+            .as_ref()
+            .and_then(|rendition_data| rendition_data.unknown_payload());
 
-        let data_length = match &csi_header.rendition_data {
-            Some(coreui::rendition::Rendition::RawData {
-                _raw_data_length, ..
-            }) => match layout {
-                coreui::rendition::LayoutType32::Data => Some(*_raw_data_length),
-                _ => None,
-            },
-            _ => None,
-        };
+        let subtype = self.key_value(coreui::rendition::AttributeType::Subtype);
+        let subtype_name: Option<coreui::rendition::ImageSubtype> =
+            subtype.and_then(FromPrimitive::from_u16);
 
-        let encoding = match layout {
-            coreui::rendition::LayoutType32::Image
-            | coreui::rendition::LayoutType32::PackedImage => Some(csi_header.pixel_format),
+        // filmstrip frames are stacked vertically, each one slice high
+        let frame_height = match subtype_name {
+            Some(coreui::rendition::ImageSubtype::AnimationFilmstrip) => csi_header.frame_height(),
             _ => None,
         };
 
-        let idiom: Option<coreui::rendition::Idiom> = rendition_key_values
-            .iter()
-            .find(|(attribute, _)| *attribute == coreui::rendition::AttributeType::Idiom)
-            .and_then(|(_, value)| FromPrimitive::from_u16(*value));
-
-        let name_identifier = rendition_key_values
-            .iter()
-            .find(|(attribute, value)| {
-                *attribute == coreui::rendition::AttributeType::Identifier && *value > 0
-            })
-            .and_then(|(_, value)| Some(*value));
+        AssetUtilEntry {
+            appearance,
+            asset_type: asset_type(csi_header.csimetadata.layout),
+            colorspace,
+            compression: theme_payload.map(|(compression_type, _)| compression_type),
+            direction: self
+                .key_value(coreui::rendition::AttributeType::Direction)
+                .map(coreui::rendition::NamedValue::from_u16),
+            frame_count: frame_height.map(|frame_height| csi_header.height / frame_height),
+            frame_height,
+            idiom: value_of(coreui::rendition::AttributeType::Idiom)
+                .and_then(FromPrimitive::from_u16),
+            look: self
+                .key_value(coreui::rendition::AttributeType::Look)
+                .map(coreui::rendition::NamedValue::from_u16),
+            name: self.facet_key.clone(),
+            name_identifier: self.key_value(coreui::rendition::AttributeType::Identifier),
+            presentation_state: self
+                .key_value(coreui::rendition::AttributeType::PresentationState)
+                .map(coreui::rendition::NamedValue::from_u16),
+            rendition_payload_length: unknown_payload.map(|(_, payload)| payload.len() as u32),
+            rendition_payload_tag: unknown_payload.map(|(tag, _)| common::fourcc_label(tag)),
+            scale: if csi_header.scale_factor == 0 {
+                Some(1)
+            } else {
+                Some(csi_header.scale_factor / 100)
+            },
+            sha1_digest: Some(self.sha_digest.encode_hex_upper()),
+            size_on_disk: Some(csi_header.size_on_disk() as u32),
+            state: value_of(coreui::rendition::AttributeType::State)
+                .and_then(FromPrimitive::from_u16),
+            subtype,
+            subtype_name,
+            value: value_of(coreui::rendition::AttributeType::Value)
+                .and_then(FromPrimitive::from_u16),
+            vector_based: csi_header.is_vector().then_some(true),
+            ..Default::default()
+        }
+    }
+}
 
-        let opaque = match layout {
-            coreui::rendition::LayoutType32::Image
-            | coreui::rendition::LayoutType32::PackedImage => Some(csi_header.is_opaque()),
-            _ => None,
-        };
+// adds the fields only renditions of some layouts have to an entry built by
+// EntryContext::base_entry
+pub trait EntryBuilder {
+    fn add_fields(&self, context: &EntryContext, entry: &mut AssetUtilEntry);
+}
 
-        let mut pixel_height = match layout {
-            coreui::rendition::LayoutType32::PackedImage
-            | coreui::rendition::LayoutType32::Image => Some(csi_header.height),
-            _ => None,
-        };
-        if pixel_height == Some(0) {
-            pixel_height = csi_header
-                .properties()
-                .into_iter()
-                .find_map(|attribute_type| match attribute_type {
-                    coreui::tlv::RenditionType::Slices { height, .. } => Some(height),
-                    _ => None,
-                })
+// the builder for renditions of layout, None for layouts with nothing to add
+pub fn entry_builder(layout: coreui::rendition::LayoutType32) -> Option<&'static dyn EntryBuilder> {
+    match layout {
+        coreui::rendition::LayoutType32::Image | coreui::rendition::LayoutType32::PackedImage => {
+            Some(&ImageEntryBuilder)
         }
+        coreui::rendition::LayoutType32::Color => Some(&ColorEntryBuilder),
+        coreui::rendition::LayoutType32::Data => Some(&DataEntryBuilder),
+        coreui::rendition::LayoutType32::MultisizeImage => Some(&MultisizeEntryBuilder),
+        coreui::rendition::LayoutType32::Vector => Some(&VectorEntryBuilder),
+        _ => None,
+    }
+}
 
-        let mut pixel_width = match layout {
-            coreui::rendition::LayoutType32::PackedImage
-            | coreui::rendition::LayoutType32::Image => Some(csi_header.width),
-            _ => None,
-        };
-        if pixel_width == Some(0) {
-            pixel_width = csi_header
+// bitmaps, including the packed images (atlases) other renditions point into
+pub struct ImageEntryBuilder;
+
+impl EntryBuilder for ImageEntryBuilder {
+    fn add_fields(&self, context: &EntryContext, entry: &mut AssetUtilEntry) {
+        let csi_header = context.csi_header;
+        let layout = csi_header.csimetadata.layout;
+        let slices = || {
+            csi_header
                 .properties()
                 .into_iter()
                 .find_map(|attribute_type| match attribute_type {
-                    coreui::tlv::RenditionType::Slices { width, .. } => Some(width),
+                    coreui::tlv::RenditionType::Slices { width, height, .. } => {
+                        Some((width, height))
+                    }
                     _ => None,
                 })
-        }
+        };
 
-        let rendition_name = match layout {
-            coreui::rendition::LayoutType32::Image => Some(csi_header.csimetadata.name()),
-            coreui::rendition::LayoutType32::PackedImage => Some(csi_header.csimetadata.name()),
-            _ => None,
+        entry.bits_per_component = Some(csi_header.pixel_format.bits_per_component());
+        entry.color_model = csi_header.color_model();
+        if entry.colorspace.is_some()
+            && matches!(
+                entry.color_model,
+                Some(coregraphics::ColorModel::Monochrome)
+            )
+        {
+            entry.colorspace = Some(coregraphics::ColorSpace::GrayGamma2_2);
+        }
+        // a whole png or jpeg file is reported as such, not as the pixel
+        // format the header claims
+        entry.encoding = Some(match csi_header.container_format() {
+            Some(coreui::csi::OutputFormat::Png) => "PNG".to_string(),
+            Some(coreui::csi::OutputFormat::Jpeg) => "JPEG".to_string(),
+            _ => csi_header.pixel_format.name(),
+        });
+        entry.opaque = Some(csi_header.is_opaque());
+        // renditions packed into an atlas have the size of their frame in it
+        let frame = csi_header
+            .rendition_data
+            .as_ref()
+            .and_then(|rendition_data| rendition_data.internal_link_frame());
+        entry.pixel_height = match csi_header.height {
+            0 => frame
+                .map(|(_, _, _, height)| height)
+                .or_else(|| slices().map(|(_, height)| height)),
+            height => Some(height),
         };
-        let name = if facet_key.is_some() {
-            facet_key
-        } else {
-            rendition_name.clone()
+        entry.pixel_width = match csi_header.width {
+            0 => frame
+                .map(|(_, _, width, _)| width)
+                .or_else(|| slices().map(|(width, _)| width)),
+            width => Some(width),
         };
+        entry.rendition_name = Some(csi_header.csimetadata.name());
+        // svg images have no pixels of their own, the UTI says what they are
+        if let Some(coreui::rendition::Rendition::Svg { .. }) = &csi_header.rendition_data {
+            entry.uti = csi_header.uti();
+        }
 
-        let scale = if csi_header.scale_factor == 0 {
-            Some(1)
-        } else {
-            Some(csi_header.scale_factor / 100)
-        };
+        match layout {
+            coreui::rendition::LayoutType32::Image => {
+                if let Some(crop) = csi_header.alpha_crop() {
+                    entry.original_size = Some([crop.original_width, crop.original_height]);
+                    entry.alpha_cropped_frame = Some([crop.x, crop.y, crop.width, crop.height]);
+                }
+                // palette images always have a template mode, other images
+                // only when they're opaque
+                let palette =
+                    entry.compression == Some(coreui::rendition::CompressionType::PaletteImg);
+                if palette || entry.opaque == Some(true) {
+                    entry.template_mode = csi_header.rendition_flags.template_rendering_mode();
+                }
+            }
+            // packed images (atlases) only exist to back other renditions
+            _ => entry.internal = Some(true),
+        }
+    }
+}
 
-        let sha1_digest = Some(sha_digest.encode_hex_upper());
-        let size_on_disk = Some(
-            // 184 is the size of the csi header struct
-            184 + csi_header.csibitmaplist.tlv_length + csi_header.csibitmaplist.rendition_length,
-        );
+pub struct ColorEntryBuilder;
+
+impl EntryBuilder for ColorEntryBuilder {
+    fn add_fields(&self, context: &EntryContext, entry: &mut AssetUtilEntry) {
+        if let Some(coreui::rendition::Rendition::Color { components, .. }) =
+            &context.csi_header.rendition_data
+        {
+            entry.color_components = Some(components.to_owned());
+            entry.colorspace = Some(coregraphics::ColorSpace::SRGB);
+        }
+    }
+}
 
-        let sizes = match &csi_header.rendition_data {
-            Some(coreui::rendition::Rendition::MultisizeImageSet { entries, .. }) => Some(
+pub struct DataEntryBuilder;
+
+impl EntryBuilder for DataEntryBuilder {
+    fn add_fields(&self, context: &EntryContext, entry: &mut AssetUtilEntry) {
+        let csi_header = context.csi_header;
+        match &csi_header.rendition_data {
+            Some(coreui::rendition::Rendition::RawData {
+                _raw_data_length, ..
+            }) => {
+                entry.compression = Some(coreui::rendition::CompressionType::Uncompressed);
+                entry.data_length = Some(*_raw_data_length);
+            }
+            // compressed data, assetutil reports the decompressed length
+            // (SizeOnDisk still has the stored size). StoredDataLength shows
+            // the compressed one when there was something to decompress
+            Some(rendition_data) => {
+                if let Some((_, raw_data)) = rendition_data.theme_payload() {
+                    let stored_length = raw_data.len() as u32;
+                    entry.data_length = match csi_header.data_payload() {
+                        Ok(Some(data)) => Some(data.len() as u32),
+                        _ => Some(stored_length),
+                    };
+                    entry.stored_data_length =
+                        Some(stored_length).filter(|length| Some(*length) != entry.data_length);
+                }
+            }
+            None => {}
+        }
+        // only fall back when the tlv is missing, an empty UTI stays empty
+        entry.uti = Some(csi_header.uti().unwrap_or("UTI-Unknown".to_string()));
+        entry.vector_based = None;
+    }
+}
+
+// vector glyphs (symbols), one rendition per weight and size
+pub struct VectorEntryBuilder;
+
+impl EntryBuilder for VectorEntryBuilder {
+    fn add_fields(&self, context: &EntryContext, entry: &mut AssetUtilEntry) {
+        let csi_header = context.csi_header;
+        entry.glyph_size = context
+            .key_value(coreui::rendition::AttributeType::GlyphSize)
+            .map(coreui::rendition::NamedValue::from_u16);
+        entry.glyph_weight = context
+            .key_value(coreui::rendition::AttributeType::GlyphWeight)
+            .map(coreui::rendition::NamedValue::from_u16);
+        entry.rendition_name = Some(csi_header.csimetadata.name());
+        entry.vector_format = csi_header.data_payload().ok().flatten().map(|payload| {
+            match coreui::csi::OutputFormat::sniff(&payload, csi_header.pixel_format) {
+                coreui::csi::OutputFormat::Pdf => "pdf".to_string(),
+                coreui::csi::OutputFormat::Svg => "svg".to_string(),
+                _ => "unknown".to_string(),
+            }
+        });
+    }
+}
+
+pub struct MultisizeEntryBuilder;
+
+impl EntryBuilder for MultisizeEntryBuilder {
+    fn add_fields(&self, context: &EntryContext, entry: &mut AssetUtilEntry) {
+        if let Some(coreui::rendition::Rendition::MultisizeImageSet { entries, .. }) =
+            &context.csi_header.rendition_data
+        {
+            entry.sizes = Some(
                 entries
                     .iter()
                     .map(|entry| {
                         format!(
-                            "{}x{} index:{} idiom:{:?}",
-                            entry.width, entry.height, entry.index, entry.idiom
+                            "{}x{} index:{} idiom:{}",
+                            entry.width,
+                            entry.height,
+                            entry.index,
+                            entry.idiom.name()
                         )
                     })
                     .collect(),
-            ),
-            _ => None,
+            );
+        }
+    }
+}
+
+// assetutil prints components with %.15g, so 128/255 is 0.501960784313725
+// and whole numbers like 0 and 1 have no fraction
+pub fn color_component(value: f64) -> serde_json::Number {
+    if value.fract() == 0.0 && value.abs() < (1u64 << 53) as f64 {
+        return serde_json::Number::from(value as i64);
+    }
+    // any 15 digit decimal survives the round trip through f64, so the
+    // shortest representation of the rounded value has at most 15 digits
+    let rounded: f64 = format!("{:.14e}", value).parse().unwrap_or(value);
+    serde_json::Number::from_f64(rounded).unwrap_or_else(|| serde_json::Number::from(0))
+}
+
+pub(crate) fn serialize_color_components<S>(
+    components: &Option<Vec<f64>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match components {
+        Some(components) => serializer.collect_seq(
+            components
+                .iter()
+                .map(|component| color_component(*component)),
+        ),
+        None => serializer.serialize_none(),
+    }
+}
+
+// appearances vary along two axes, luminosity and the Increase Contrast
+// accessibility setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Luminosity {
+    Any,
+    Light,
+    Dark,
+}
+
+// ordered by contrast first, so the high contrast variants come last
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AppearanceVariant {
+    pub high_contrast: bool,
+    pub luminosity: Luminosity,
+}
+
+impl AppearanceVariant {
+    pub const ALL: [AppearanceVariant; 6] = [
+        AppearanceVariant {
+            high_contrast: false,
+            luminosity: Luminosity::Any,
+        },
+        AppearanceVariant {
+            high_contrast: false,
+            luminosity: Luminosity::Light,
+        },
+        AppearanceVariant {
+            high_contrast: false,
+            luminosity: Luminosity::Dark,
+        },
+        AppearanceVariant {
+            high_contrast: true,
+            luminosity: Luminosity::Any,
+        },
+        AppearanceVariant {
+            high_contrast: true,
+            luminosity: Luminosity::Light,
+        },
+        AppearanceVariant {
+            high_contrast: true,
+            luminosity: Luminosity::Dark,
+        },
+    ];
+
+    // "any", "dark", "highcontrast", "dark-highcontrast", ...
+    pub fn label(&self) -> String {
+        let luminosity = match self.luminosity {
+            Luminosity::Any => None,
+            Luminosity::Light => Some("light"),
+            Luminosity::Dark => Some("dark"),
         };
+        match (luminosity, self.high_contrast) {
+            (None, false) => "any".to_string(),
+            (Some(luminosity), false) => luminosity.to_string(),
+            (None, true) => "highcontrast".to_string(),
+            (Some(luminosity), true) => format!("{}-highcontrast", luminosity),
+        }
+    }
+}
 
-        let state = rendition_key_values.iter().find_map(|(attribute, value)| {
-            if *attribute == coreui::rendition::AttributeType::State {
-                FromPrimitive::from_u16(*value)
-            } else {
-                None
-            }
-        });
+// where an appearance sits on the two axes, None for appearances on neither
+// (vibrant ones, names we don't know)
+pub fn appearance_variant(appearance: Option<&str>) -> Option<AppearanceVariant> {
+    let (luminosity, high_contrast) = match appearance {
+        None | Some("UIAppearanceAny") | Some("NSAppearanceNameSystem") => (Luminosity::Any, false),
+        Some("UIAppearanceDark") | Some("NSAppearanceNameDarkAqua") => (Luminosity::Dark, false),
+        Some("UIAppearanceLight") | Some("NSAppearanceNameAqua") => (Luminosity::Light, false),
+        Some("UIAppearanceHighContrastAny") | Some("NSAppearanceNameAccessibilitySystem") => {
+            (Luminosity::Any, true)
+        }
+        Some("UIAppearanceHighContrastDark")
+        | Some("NSAppearanceNameAccessibilityHighContrastDarkAqua") => (Luminosity::Dark, true),
+        Some("UIAppearanceHighContrastLight")
+        | Some("NSAppearanceNameAccessibilityHighContrastAqua") => (Luminosity::Light, true),
+        Some(_) => return None,
+    };
+    Some(AppearanceVariant {
+        high_contrast,
+        luminosity,
+    })
+}
 
-        let template_mode = match layout {
-            coreui::rendition::LayoutType32::Image => match &csi_header.rendition_data {
-                Some(coreui::rendition::Rendition::Theme {
-                    compression_type, ..
-                })
-                | Some(coreui::rendition::Rendition::ThemeCBCK {
-                    compression_type, ..
-                }) => {
-                    if *compression_type == coreui::rendition::CompressionType::PaletteImg {
-                        csi_header.rendition_flags.template_rendering_mode()
-                    } else {
-                        if opaque == Some(true) {
-                            csi_header.rendition_flags.template_rendering_mode()
-                        } else {
-                            None
-                        }
-                    }
+// short label used in list output, e.g. "UIAppearanceDark" -> "dark",
+// "UIAppearanceHighContrastDark" -> "dark-highcontrast"
+pub fn appearance_label(appearance: Option<&str>) -> String {
+    match appearance_variant(appearance) {
+        Some(variant) => variant.label(),
+        None => appearance.unwrap_or_default().to_string(),
+    }
+}
+
+// a name for a rendition stored with a blank metadata name, from its facet
+// name and Xcode's file name suffixes: "-dark" for appearances, "@2x" for
+// scales above 1 and "~ipad" for idioms other than universal
+// ("MyIcon-dark@2x~ipad"). Only the last group of namespaced names is used
+pub fn synthesized_rendition_name(
+    facet_name: &str,
+    key_values: &[(coreui::rendition::AttributeType, u16)],
+    appearancedb: &BTreeMap<String, u32>,
+) -> String {
+    let value = |attribute| {
+        key_values
+            .iter()
+            .find(|(key_attribute, _)| *key_attribute == attribute)
+            .map(|(_, value)| *value)
+            .unwrap_or(0)
+    };
+    let mut name = facet_name
+        .rsplit('/')
+        .next()
+        .unwrap_or(facet_name)
+        .to_string();
+    let appearance = value(coreui::rendition::AttributeType::Appearance);
+    if appearance > 0 {
+        let appearance_name = appearancedb
+            .iter()
+            .find(|(_, identifier)| **identifier == appearance as u32)
+            .map(|(appearance_name, _)| appearance_name.to_string())
+            .unwrap_or_else(|| coreui::unknown_appearance_name(appearance as u32));
+        name.push_str(&format!("-{}", appearance_label(Some(&appearance_name))));
+    }
+    let scale = value(coreui::rendition::AttributeType::Scale);
+    if scale > 1 {
+        name.push_str(&format!("@{}x", scale));
+    }
+    let idiom = coreui::rendition::Idiom::from(value(coreui::rendition::AttributeType::Idiom));
+    if idiom != coreui::rendition::Idiom::Universal {
+        name.push_str(&format!("~{}", idiom_label(Some(&idiom))));
+    }
+    name
+}
+
+// the metadata name of a rendition, or a synthesized one when that's blank
+// and the facet is known. The flag is true for synthesized names
+pub fn rendition_name(
+    csi_header: &coreui::csi::Header,
+    facet_name: Option<&str>,
+    key_values: &[(coreui::rendition::AttributeType, u16)],
+    appearancedb: &BTreeMap<String, u32>,
+) -> (String, bool) {
+    let name = csi_header.csimetadata.name();
+    match facet_name {
+        Some(facet_name) if name.trim().is_empty() => (
+            synthesized_rendition_name(facet_name, key_values, appearancedb),
+            true,
+        ),
+        _ => (name, false),
+    }
+}
+
+// the facet name a rendition name like "Icon@2x~ipad.png" was made from, for
+// catalogs whose key format has no Identifier to look facets up by
+pub fn facet_name_from_rendition_name(rendition_name: &str) -> Option<String> {
+    let stem = match rendition_name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => rendition_name,
+    };
+    let stem = stem.split('~').next().unwrap_or_default();
+    let stem = match stem.rsplit_once('@') {
+        Some((name, scale))
+            if scale.len() > 1
+                && scale.ends_with('x')
+                && scale[..scale.len() - 1].bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            name
+        }
+        _ => stem,
+    };
+    let name = stem.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+pub fn idiom_label(idiom: Option<&coreui::rendition::Idiom>) -> String {
+    match idiom {
+        None | Some(coreui::rendition::Idiom::Universal) => "universal".to_string(),
+        Some(coreui::rendition::Idiom::Phone) => "iphone".to_string(),
+        Some(coreui::rendition::Idiom::Pad) => "ipad".to_string(),
+        Some(coreui::rendition::Idiom::TV) => "tv".to_string(),
+        Some(coreui::rendition::Idiom::Car) => "carplay".to_string(),
+        Some(coreui::rendition::Idiom::Watch) => "watch".to_string(),
+        Some(coreui::rendition::Idiom::Marketing) => "marketing".to_string(),
+        Some(coreui::rendition::Idiom::Mac) => "mac".to_string(),
+        Some(coreui::rendition::Idiom::Vision) => "vision".to_string(),
+        Some(coreui::rendition::Idiom::Unknown(value)) => value.to_string(),
+    }
+}
+
+// "regular", "bold", ... or the number for weights we have no name for
+pub fn glyph_weight_label(weight: u16) -> String {
+    match coreui::rendition::NamedValue::<coreui::rendition::GlyphWeight>::from_u16(weight) {
+        coreui::rendition::NamedValue::Named(weight) => format!("{:?}", weight).to_lowercase(),
+        coreui::rendition::NamedValue::Number(weight) => weight.to_string(),
+    }
+}
+
+// variants of a single facet aggregated over all of its renditions
+#[derive(Debug)]
+pub struct FacetSummary {
+    pub name: String,
+    pub name_identifier: Option<u16>,
+    pub asset_type: String,
+    pub scales: BTreeSet<u32>,
+    pub appearances: BTreeSet<String>,
+    pub idioms: BTreeSet<String>,
+    // weights of vector glyphs, and how many renditions (variants) they have
+    pub glyph_weights: BTreeSet<u16>,
+    pub variants: usize,
+}
+
+impl FacetSummary {
+    // groups entries by name identifier (falling back to the name), sorted by name
+    pub fn from_entries(entries: &[AssetUtilEntry]) -> Vec<FacetSummary> {
+        let mut groups: BTreeMap<(String, Option<u16>), FacetSummary> = BTreeMap::new();
+        for entry in entries {
+            let name = entry.name.clone().unwrap_or_default();
+            let summary = groups
+                .entry((name.clone(), entry.name_identifier))
+                .or_insert_with(|| FacetSummary {
+                    name,
+                    name_identifier: entry.name_identifier,
+                    asset_type: entry
+                        .asset_type
+                        .as_deref()
+                        .unwrap_or("unknown")
+                        .to_lowercase(),
+                    scales: BTreeSet::new(),
+                    appearances: BTreeSet::new(),
+                    idioms: BTreeSet::new(),
+                    glyph_weights: BTreeSet::new(),
+                    variants: 0,
+                });
+            summary.variants += 1;
+            match &entry.glyph_weight {
+                Some(coreui::rendition::NamedValue::Named(weight)) => {
+                    summary.glyph_weights.insert(*weight as u16);
                 }
-                _ => {
-                    if opaque == Some(true) {
-                        csi_header.rendition_flags.template_rendering_mode()
-                    } else {
-                        None
-                    }
+                Some(coreui::rendition::NamedValue::Number(weight)) => {
+                    summary.glyph_weights.insert(*weight);
                 }
-            },
-            _ => None,
-        };
-
-        let value = rendition_key_values.iter().find_map(|(attribute, value)| {
-            if *attribute == coreui::rendition::AttributeType::Value {
-                FromPrimitive::from_u16(*value)
-            } else {
-                None
+                None => {}
             }
-        });
-
-        let uti: Option<String> = match layout {
-            coreui::rendition::LayoutType32::Data => {
-                let uti =
-                    csi_header.properties().iter().find_map(
-                        |rendition_type| match &rendition_type {
-                            coreui::tlv::RenditionType::UTI { string, .. } => {
-                                Some(common::parse_padded_string(string))
-                            }
-                            _ => None,
-                        },
-                    );
-                Some(uti.unwrap_or("UTI-Unknown".to_string()))
+            if let Some(scale) = entry.scale {
+                summary.scales.insert(scale);
             }
-            _ => None,
-        };
+            summary
+                .appearances
+                .insert(appearance_label(entry.appearance.as_deref()));
+            summary.idioms.insert(idiom_label(entry.idiom.as_ref()));
+        }
+        groups.into_values().collect()
+    }
 
-        AssetUtilEntry {
-            appearance,
-            asset_type,
-            bits_per_component,
-            color_components,
-            color_model,
-            colorspace,
-            compression,
-            data_length,
-            encoding,
-            idiom,
-            name,
-            name_identifier,
-            opaque,
-            pixel_height,
-            pixel_width,
-            rendition_name,
-            scale,
-            sha1_digest,
-            size_on_disk,
-            sizes,
-            state,
-            template_mode,
-            uti,
-            value,
+    // one line per rendition instead of one per facet
+    pub fn long_lines(entries: &[AssetUtilEntry]) -> Vec<String> {
+        let mut lines: Vec<String> = entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{}  {}  {}x  {}  {}  {}",
+                    entry.name.as_deref().unwrap_or_default(),
+                    entry
+                        .asset_type
+                        .as_deref()
+                        .unwrap_or("unknown")
+                        .to_lowercase(),
+                    entry.scale.unwrap_or(1),
+                    appearance_label(entry.appearance.as_deref()),
+                    idiom_label(entry.idiom.as_ref()),
+                    entry.rendition_name.as_deref().unwrap_or("-"),
+                )
+            })
+            .collect();
+        lines.sort();
+        lines
+    }
+}
+
+impl Display for FacetSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let scales: Vec<String> = self
+            .scales
+            .iter()
+            .map(|scale| format!("{}x", scale))
+            .collect();
+        // luminosities then their high contrast variants, other appearances last
+        let mut appearances: Vec<&str> = self.appearances.iter().map(|a| a.as_str()).collect();
+        appearances.sort_by_key(|label| {
+            let variant = AppearanceVariant::ALL
+                .into_iter()
+                .find(|variant| variant.label() == *label);
+            (variant.is_none(), variant)
+        });
+        let idioms: Vec<&str> = self.idioms.iter().map(|i| i.as_str()).collect();
+        write!(
+            f,
+            "{}  {}  {}  {}  {}",
+            self.name,
+            self.asset_type,
+            scales.join(","),
+            appearances.join("+"),
+            idioms.join(",")
+        )?;
+        if self.asset_type == "vector" {
+            let weights: Vec<String> = self
+                .glyph_weights
+                .iter()
+                .map(|weight| glyph_weight_label(*weight))
+                .collect();
+            if !weights.is_empty() {
+                write!(f, "  weights:{}", weights.join(","))?;
+            }
+            write!(f, "  {} variants", self.variants)?;
         }
+        Ok(())
     }
 }
@@ -1,5 +1,9 @@
+use std::collections::HashSet;
 use std::fmt::Debug;
-use std::io::Cursor;
+use std::fmt::Display;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
 
 use anyhow::Context;
 use anyhow::Result;
@@ -10,7 +14,8 @@ use binrw::meta::ReadEndian;
 use binrw::BinRead;
 use binrw::BinWrite;
 use binrw::FilePtr;
-use memmap::Mmap;
+
+use crate::coreui::CarError;
 
 type BlockID = u32;
 
@@ -26,32 +31,78 @@ pub struct Storage {
 }
 
 impl Storage {
+    // items of the tree stored under this var, see Tree::items
+    pub fn get_named_tree_items(
+        &self,
+        name: &str,
+        reader: &mut (impl Read + Seek),
+    ) -> Result<Vec<(u32, u32)>> {
+        let tree = self.get_named_typed_block::<Tree>(name, reader, ())?;
+        tree.walk(self, reader, name)
+    }
+
+    pub fn get_named_tree_items_typed<T, U>(
+        &self,
+        name: &str,
+        reader: &mut (impl Read + Seek),
+    ) -> Result<Vec<(T, U)>>
+    where
+        T: BinRead + ReadEndian,
+        U: BinRead + ReadEndian,
+        for<'a> <T as BinRead>::Args<'a>: Default,
+        for<'a> <U as BinRead>::Args<'a>: Default,
+    {
+        let items = self.get_named_tree_items(name, reader)?;
+        read_typed_items(self, reader, items)
+    }
+
+    pub fn has_var(&self, name: &str) -> bool {
+        self.var_storage.vars.iter().any(|var| var.name() == name)
+    }
+
     pub fn get_named_block_id(&self, name: &str) -> Result<BlockID> {
-        (*self.var_storage)
+        self.var_storage
             .vars
             .iter()
             .find(|var| var.name() == name)
             .map(|v| v.block_id)
-            .context(format!("unable to find {:?}", name))
+            .with_context(|| format!("unable to find {:?}", name))
     }
 
     pub fn get_named_block(&self, name: &str) -> Result<BlockRange> {
         let block_id = self.get_named_block_id(name)?;
-        Ok(self.block_storage.items[block_id as usize])
+        self.block_storage
+            .items
+            .get(block_id as usize)
+            .copied()
+            .with_context(|| format!("{:?} points at missing block {}", name, block_id))
+    }
+
+    // "FACETKEYS (block 3 at 0x1A90..0x1AA5)", for error messages
+    pub fn describe_named_block(&self, name: &str) -> String {
+        let block_id = match self.get_named_block_id(name) {
+            Ok(block_id) => block_id,
+            Err(_) => return name.to_string(),
+        };
+        match self.block_storage.items.get(block_id as usize) {
+            Some(range) => format!("{} (block {} at {})", name, block_id, range),
+            None => format!("{} (block {})", name, block_id),
+        }
     }
 
     pub fn get_named_typed_block<'a, T>(
         &self,
         name: &str,
-        reader: &mut Cursor<Mmap>,
+        reader: &mut (impl Read + Seek),
         args: T::Args<'a>,
     ) -> Result<T>
     where
         T: BinRead + ReadEndian,
     {
         let block_range = self.get_named_block(name)?;
-        reader.set_position(block_range.address as u64);
-        let type_ = T::read_args(reader, args)?;
+        reader.seek(SeekFrom::Start(block_range.address as u64))?;
+        let type_ = T::read_args(reader, args)
+            .with_context(|| format!("unable to read {}", self.describe_named_block(name)))?;
         Ok(type_)
     }
 }
@@ -86,6 +137,14 @@ impl BlockStorage {
         }
     }
 
+    // block ids come from the file, so a missing block is an error rather than a panic
+    pub fn item(&self, block_id: BlockID) -> Result<BlockRange> {
+        self.items
+            .get(block_id as usize)
+            .copied()
+            .with_context(|| format!("block {} does not exist", block_id))
+    }
+
     pub fn add_item(&mut self, address: u32, end_address: u32) -> BlockID {
         let length = end_address - address;
         self.items.push(BlockRange { address, length });
@@ -102,25 +161,43 @@ pub struct BlockRange {
 }
 
 impl BlockRange {
-    pub fn read(&self, cursor: &mut Cursor<Mmap>) -> binrw::BinResult<Vec<u8>> {
-        cursor.set_position(self.address as u64);
+    pub fn read(&self, cursor: &mut (impl Read + Seek)) -> binrw::BinResult<Vec<u8>> {
+        cursor.seek(SeekFrom::Start(self.address as u64))?;
         helpers::count(self.length as usize)(cursor, binrw::Endian::Little, ())
     }
 
     pub fn read_type<'a, T>(
         &self,
-        cursor: &mut Cursor<Mmap>,
+        cursor: &mut (impl Read + Seek),
         args: T::Args<'a>,
     ) -> binrw::BinResult<T>
     where
         T: BinRead + ReadEndian,
     {
-        cursor.set_position(self.address as u64);
+        cursor.seek(SeekFrom::Start(self.address as u64))?;
         let mut range_reader = cursor.take_seek(self.length as u64);
         T::read_args(&mut range_reader, args)
     }
 }
 
+/// Start and end address in hex.
+///
+/// ```
+/// use carutil_lib::bom::BlockRange;
+///
+/// let range = BlockRange { address: 0x1A90, length: 21 };
+/// assert_eq!(range.to_string(), "0x1A90..0x1AA5");
+/// ```
+impl Display for BlockRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "0x{:X}..0x{:X}",
+            self.address,
+            self.address as u64 + self.length as u64
+        ))
+    }
+}
+
 impl Debug for BlockRange {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!(
@@ -182,21 +259,23 @@ pub struct Tree {
 }
 
 impl Tree {
-    pub fn items(&self, storage: &Storage, reader: &mut Cursor<Mmap>) -> Result<Vec<(u32, u32)>> {
-        let path_range = storage.block_storage.items[self.path_block_id as usize];
-        reader.set_position(path_range.address as u64);
-        let path = Paths::read(reader)?;
-        Ok(path
-            .indices
-            .into_iter()
-            .map(|indices| (indices.index1, indices.index0)) // key is index1
-            .collect())
+    // (key, value) block ids of every leaf, in order
+    pub fn items(
+        &self,
+        storage: &Storage,
+        reader: &mut (impl Read + Seek),
+    ) -> Result<Vec<(u32, u32)>> {
+        self.walk(
+            storage,
+            reader,
+            &format!("tree at block {}", self.path_block_id),
+        )
     }
 
     pub fn items_typed<T, U>(
         &self,
         storage: &Storage,
-        reader: &mut Cursor<Mmap>,
+        reader: &mut (impl Read + Seek),
     ) -> Result<Vec<(T, U)>>
     where
         T: BinRead + ReadEndian,
@@ -205,22 +284,127 @@ impl Tree {
         for<'a> <U as BinRead>::Args<'a>: Default,
     {
         let items = self.items(storage, reader)?;
-        items
-            .into_iter()
-            .map(|(key, value)| {
-                let key_range = storage.block_storage.items[key as usize];
-                reader.set_position(key_range.address as u64);
-                let key = T::read(reader)?;
-
-                let value_range = storage.block_storage.items[value as usize];
-                reader.set_position(value_range.address as u64);
-                let value = U::read(reader)?;
-
-                Ok((key, value))
-            })
-            .into_iter()
-            .collect()
+        read_typed_items(storage, reader, items)
+    }
+
+    // every block the tree is made of: the path blocks of all branches and
+    // leaves, and the key and value blocks they point at. keys_are_blocks is
+    // false for trees like BITMAPKEYS whose keys are plain numbers
+    pub fn blocks(
+        &self,
+        storage: &Storage,
+        reader: &mut (impl Read + Seek),
+        keys_are_blocks: bool,
+    ) -> Result<Vec<BlockID>> {
+        let var = format!("tree at block {}", self.path_block_id);
+        let mut visited = HashSet::new();
+        let mut blocks = vec![];
+        let mut pending = vec![self.path_block_id];
+        while let Some(block_id) = pending.pop() {
+            if !visited.insert(block_id) {
+                return Err(CarError::CyclicTree { var }.into());
+            }
+            let path_range = storage
+                .block_storage
+                .items
+                .get(block_id as usize)
+                .with_context(|| format!("{} points at missing block {}", var, block_id))?;
+            reader.seek(SeekFrom::Start(path_range.address as u64))?;
+            let paths = Paths::read(reader)?;
+            blocks.push(block_id);
+            for indices in &paths.indices {
+                if paths.is_leaf == 0 {
+                    pending.push(indices.index0);
+                } else {
+                    blocks.push(indices.index0);
+                }
+                if keys_are_blocks {
+                    blocks.push(indices.index1);
+                }
+            }
+        }
+        Ok(blocks)
     }
+
+    // follows the first child down to the leftmost leaf, then the forward
+    // pointers from leaf to leaf. Malformed trees can point back at a block
+    // already seen, so revisits are errors rather than endless loops
+    fn walk(
+        &self,
+        storage: &Storage,
+        reader: &mut (impl Read + Seek),
+        var: &str,
+    ) -> Result<Vec<(u32, u32)>> {
+        let mut visited = HashSet::new();
+        let mut read_paths = |block_id: BlockID| -> Result<Paths> {
+            if !visited.insert(block_id) {
+                return Err(CarError::CyclicTree {
+                    var: var.to_string(),
+                }
+                .into());
+            }
+            let path_range = storage
+                .block_storage
+                .items
+                .get(block_id as usize)
+                .with_context(|| format!("{} points at missing block {}", var, block_id))?;
+            reader.seek(SeekFrom::Start(path_range.address as u64))?;
+            Ok(Paths::read(reader)?)
+        };
+
+        let mut paths = read_paths(self.path_block_id)?;
+        while paths.is_leaf == 0 {
+            let child = paths
+                .indices
+                .first()
+                .with_context(|| format!("{} has an empty branch", var))?
+                .index0;
+            paths = read_paths(child)?;
+        }
+
+        let mut items = vec![];
+        loop {
+            // key is index1
+            items.extend(
+                paths
+                    .indices
+                    .iter()
+                    .map(|indices| (indices.index1, indices.index0)),
+            );
+            if paths.forward == 0 {
+                break;
+            }
+            paths = read_paths(paths.forward)?;
+        }
+        Ok(items)
+    }
+}
+
+fn read_typed_items<T, U>(
+    storage: &Storage,
+    reader: &mut (impl Read + Seek),
+    items: Vec<(u32, u32)>,
+) -> Result<Vec<(T, U)>>
+where
+    T: BinRead + ReadEndian,
+    U: BinRead + ReadEndian,
+    for<'a> <T as BinRead>::Args<'a>: Default,
+    for<'a> <U as BinRead>::Args<'a>: Default,
+{
+    items
+        .into_iter()
+        .map(|(key, value)| {
+            let key_range = storage.block_storage.item(key)?;
+            reader.seek(SeekFrom::Start(key_range.address as u64))?;
+            let key = T::read(reader)?;
+
+            let value_range = storage.block_storage.item(value)?;
+            reader.seek(SeekFrom::Start(value_range.address as u64))?;
+            let value = U::read(reader)?;
+
+            Ok((key, value))
+        })
+        .collect()
 }
 
 #[derive(Debug, BinRead, BinWrite)]
@@ -0,0 +1,169 @@
+use anyhow::Context;
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+use crate::assetutil::AssetUtilEntry;
+use crate::coreui;
+
+// "500KB", "1.5 MB", "2MiB" or a plain byte count. KB and MB are powers of
+// 1000 like App Store Connect reports sizes, KiB and MiB powers of 1024
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" | "k" => 1000,
+        "mb" | "m" => 1000 * 1000,
+        "gb" | "g" => 1000 * 1000 * 1000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        _ => anyhow::bail!("unknown size unit in {:?}", size),
+    };
+    let number: f64 = number
+        .parse()
+        .ok()
+        .context(format!("invalid size {:?}", size))?;
+    Ok((number * multiplier as f64).round() as u64)
+}
+
+#[derive(Debug, Default)]
+pub struct Budget {
+    // limit for the SizeOnDisk of a single rendition
+    pub max_asset_size: Option<u64>,
+    // limit for the sum of every rendition's SizeOnDisk
+    pub max_total: Option<u64>,
+    // per facet or rendition name limits that replace max_asset_size
+    pub overrides: BTreeMap<String, u64>,
+    // override names match exactly instead of ignoring case
+    pub case_sensitive: bool,
+}
+
+impl Budget {
+    // a JSON object of names to sizes, {"AppIcon": "1MB", "Splash": 2000000}
+    pub fn overrides_from_json(json: &str) -> Result<BTreeMap<String, u64>> {
+        let config: BTreeMap<String, serde_json::Value> =
+            serde_json::from_str(json).context("budget config must be a JSON object")?;
+        config
+            .into_iter()
+            .map(|(name, size)| {
+                let size = match &size {
+                    serde_json::Value::String(size) => parse_size(size)?,
+                    serde_json::Value::Number(size) => size
+                        .as_u64()
+                        .context(format!("invalid size {} for {:?}", size, name))?,
+                    _ => anyhow::bail!("invalid size {} for {:?}", size, name),
+                };
+                Ok((name, size))
+            })
+            .collect()
+    }
+
+    fn limit(&self, entry: &AssetUtilEntry) -> Option<u64> {
+        let names = || [&entry.name, &entry.rendition_name].into_iter().flatten();
+        names()
+            .find_map(|name| self.overrides.get(name).copied())
+            // then ignoring case, like CoreUI looks names up
+            .or_else(|| {
+                if self.case_sensitive {
+                    return None;
+                }
+                names().find_map(|name| {
+                    let name = coreui::FacetName::new(name);
+                    self.overrides
+                        .iter()
+                        .find(|(override_name, _)| name.matches(override_name))
+                        .map(|(_, limit)| *limit)
+                })
+            })
+            .or(self.max_asset_size)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct BudgetViolation {
+    // "Name (RenditionName)"
+    pub name: String,
+    pub size: u64,
+    pub limit: u64,
+}
+
+#[derive(Debug)]
+pub struct BudgetReport {
+    // ("Name (RenditionName)", SizeOnDisk) of every rendition, largest first
+    pub largest: Vec<(String, u64)>,
+    pub total: u64,
+    pub violations: Vec<BudgetViolation>,
+    pub total_limit_exceeded: Option<u64>,
+}
+
+impl BudgetReport {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty() && self.total_limit_exceeded.is_none()
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .violations
+            .iter()
+            .map(|violation| {
+                format!(
+                    "{}: {} bytes, over the {} byte budget",
+                    violation.name, violation.size, violation.limit
+                )
+            })
+            .collect();
+        if let Some(limit) = self.total_limit_exceeded {
+            lines.push(format!(
+                "total: {} bytes, over the {} byte budget",
+                self.total, limit
+            ));
+        }
+        lines
+    }
+}
+
+fn entry_label(entry: &AssetUtilEntry) -> String {
+    match (&entry.name, &entry.rendition_name) {
+        (Some(name), Some(rendition_name)) => format!("{} ({})", name, rendition_name),
+        (Some(name), None) => name.to_string(),
+        (None, Some(rendition_name)) => rendition_name.to_string(),
+        (None, None) => "(unnamed)".to_string(),
+    }
+}
+
+pub fn check_budget(store: &coreui::CommonAssetStorage, budget: &Budget) -> BudgetReport {
+    let entries = AssetUtilEntry::entries_from_asset_storage(store);
+    let mut largest = vec![];
+    let mut violations = vec![];
+    let mut total = 0;
+    for entry in &entries {
+        let size = entry.size_on_disk.unwrap_or(0) as u64;
+        total += size;
+        let name = entry_label(entry);
+        if let Some(limit) = budget.limit(entry) {
+            if size > limit {
+                violations.push(BudgetViolation {
+                    name: name.clone(),
+                    size,
+                    limit,
+                });
+            }
+        }
+        largest.push((name, size));
+    }
+    largest.sort_by(|(a_name, a_size), (b_name, b_size)| {
+        b_size.cmp(a_size).then_with(|| a_name.cmp(b_name))
+    });
+    violations.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+    let total_limit_exceeded = budget.max_total.filter(|limit| total > *limit);
+    BudgetReport {
+        largest,
+        total,
+        violations,
+        total_limit_exceeded,
+    }
+}
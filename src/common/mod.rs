@@ -16,6 +16,20 @@ impl BinRead for RawData {
         endian: binrw::Endian,
         args: Self::Args<'_>,
     ) -> binrw::BinResult<Self> {
+        // the length comes from the file, don't reserve more than is left to read
+        let position = reader.stream_position()?;
+        let end = reader.seek(std::io::SeekFrom::End(0))?;
+        reader.seek(std::io::SeekFrom::Start(position))?;
+        if args.count as u64 > end.saturating_sub(position) {
+            return Err(binrw::Error::AssertFail {
+                pos: position,
+                message: format!(
+                    "{} bytes of data but only {} left",
+                    args.count,
+                    end.saturating_sub(position)
+                ),
+            });
+        }
         let r = count_with(args.count, u8::read_options)(reader, endian, ())?;
         Ok(RawData(r))
     }
@@ -34,6 +48,22 @@ impl BinWrite for RawData {
     }
 }
 
+// Debug for parsed structs whose field by field impl is behind the
+// debug-impls feature, without it they only print their name
+macro_rules! compact_debug {
+    ($($type:ident),+) => {
+        $(
+            #[cfg(not(feature = "debug-impls"))]
+            impl std::fmt::Debug for $type {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    f.debug_struct(stringify!($type)).finish_non_exhaustive()
+                }
+            }
+        )+
+    };
+}
+pub(crate) use compact_debug;
+
 impl Debug for RawData {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let data_length = self.0.len();
@@ -54,18 +84,340 @@ pub fn parse_padded_string(buffer: &[u8]) -> String {
     String::from_utf8_lossy(&buffer[..string_length]).to_string()
 }
 
-pub fn str_to_sized_slice128(string: &str) -> [u8; 128] {
-    let mut slice: [u8; 128] = [0; 128];
-    for (i, c) in string.as_bytes().into_iter().enumerate() {
-        slice[i] = *c;
+// a name for bytes that aren't UTF-8: non-ASCII, control characters and '%'
+// become %XX so different byte strings never share a fallback name. It can
+// still equal a UTF-8 name that's spelled the same, callers check for that
+pub fn percent_encode(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| match b {
+            0x20..=0x7e if *b != b'%' => (*b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// string in a NUL padded fixed-width field, strings that don't fit are an
+// error naming the field. A string can fill the whole field without a NUL
+pub fn str_to_sized_slice<const N: usize>(field: &str, string: &str) -> anyhow::Result<[u8; N]> {
+    if string.len() > N {
+        anyhow::bail!(
+            "{} is {} bytes, at most {} fit: {:?}",
+            field,
+            string.len(),
+            N,
+            string
+        );
+    }
+    let mut slice = [0; N];
+    slice[..string.len()].copy_from_slice(string.as_bytes());
+    Ok(slice)
+}
+
+// the longest prefix of string that is at most max_bytes long, multi-byte
+// characters are never split
+pub fn truncate_str(string: &str, max_bytes: usize) -> &str {
+    if string.len() <= max_bytes {
+        return string;
+    }
+    let mut end = max_bytes;
+    while !string.is_char_boundary(end) {
+        end -= 1;
+    }
+    &string[..end]
+}
+
+// parses a hex dump of little endian u16s, e.g. a key from `debug` output
+pub fn u16s_from_hex<const N: usize>(string: &str) -> anyhow::Result<[u16; N]> {
+    let bytes = hex::decode(string.trim())
+        .map_err(|error| anyhow::anyhow!("invalid hex string {:?}: {}", string, error))?;
+    if bytes.len() != N * 2 {
+        anyhow::bail!(
+            "expected {} hex characters, got {}",
+            N * 4,
+            string.trim().len()
+        );
+    }
+    let mut raw = [0u16; N];
+    for (value, chunk) in raw.iter_mut().zip(bytes.chunks_exact(2)) {
+        *value = u16::from_le_bytes([chunk[0], chunk[1]]);
+    }
+    Ok(raw)
+}
+
+pub fn u16s_to_hex(raw: &[u16]) -> String {
+    hex::encode(
+        raw.iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<u8>>(),
+    )
+}
+
+// hex of at most the first max_bytes of data
+pub fn hex_prefix(data: &[u8], max_bytes: usize) -> String {
+    hex::encode(&data[..data.len().min(max_bytes)])
+}
+
+// characters Windows doesn't allow in file names
+const RESERVED_CHARACTERS: [char; 8] = ['<', '>', ':', '"', '\\', '|', '?', '*'];
+
+// device names Windows reserves whatever the extension ("nul.png" too)
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// a file name relative to the output directory ("Buttons/Icon.png") that can
+// be written on Windows too: reserved characters become '_', names Windows
+// would trim or open as a device are changed, and "." and ".." components
+// can't leave the output directory
+pub fn portable_file_name(file_name: &str) -> String {
+    file_name
+        .split('/')
+        .map(portable_component)
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+fn portable_component(component: &str) -> String {
+    if component.is_empty() || component == "." || component == ".." {
+        return "_".repeat(component.len().max(1));
+    }
+    let mut component: String = component
+        .chars()
+        .map(|c| {
+            if c.is_control() || RESERVED_CHARACTERS.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    // Windows drops trailing dots and spaces
+    if component.ends_with(['.', ' ']) {
+        component.push('_');
     }
-    slice
+    let stem = component.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(stem.trim_end()))
+    {
+        component.insert(0, '_');
+    }
+    component
+}
+
+// the last component of a path written with either separator, trailing ones
+// ignored: C:\Assets.xcassets\AppIcon.appiconset\ is "AppIcon.appiconset"
+pub fn last_path_component(path: &str) -> &str {
+    path.trim_end_matches(['/', '\\'])
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
 }
 
-pub fn str_to_sized_slice256(string: &str) -> [u8; 256] {
-    let mut slice: [u8; 256] = [0; 256];
-    for (i, c) in string.as_bytes().into_iter().enumerate() {
-        slice[i] = *c;
+// the extension of the last component ("imageset" for an Icon.imageset
+// folder), None for dot files like ".xcassets"
+pub fn path_extension(path: &str) -> Option<&str> {
+    match last_path_component(path).rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => Some(extension),
+        _ => None,
+    }
+}
+
+// canonical Windows paths are verbatim (\\?\C:\...), messages show them
+// the way they are typed
+pub fn strip_verbatim_prefix(path: &str) -> String {
+    if let Some(share) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", share)
+    } else {
+        path.strip_prefix(r"\\?\").unwrap_or(path).to_string()
+    }
+}
+
+// a fourcc as its four characters ("GA16") when they are all printable ASCII,
+// as hex otherwise
+pub fn fourcc_label(value: u32) -> String {
+    let bytes = value.to_be_bytes();
+    if bytes.iter().all(|b| (0x20..0x7f).contains(b)) {
+        String::from_utf8_lossy(&bytes).to_string()
+    } else {
+        format!("0x{:08X}", value)
+    }
+}
+
+// maps items on up to `jobs` scoped threads (0 for one per core), the results
+// are in the order of the items whatever the number of threads
+pub fn parallel_map<T, U, F>(items: &[T], jobs: usize, f: F) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T) -> U + Sync,
+{
+    let jobs = match jobs {
+        0 => std::thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+        jobs => jobs,
+    };
+    if jobs <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let chunk_size = items.len().div_ceil(jobs);
+    let f = &f;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<U>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("worker thread panicked"))
+            .collect()
+    })
+}
+
+// how the group separators of namespaced asset names ("Buttons/Primary/Background")
+// are rendered
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum FlattenNames {
+    // the raw name, what assetutil prints
+    #[default]
+    Slash,
+    // separators replaced with "-"
+    Dash,
+    // groups become directories when extracting, the raw name otherwise
+    Keep,
+}
+
+impl FlattenNames {
+    pub fn name(self, name: &str) -> String {
+        match self {
+            FlattenNames::Dash => name.replace('/', "-"),
+            FlattenNames::Slash | FlattenNames::Keep => name.to_string(),
+        }
+    }
+
+    // what goes in front of an extracted file name for the groups of
+    // facet_name, groups that would leave the output directory are dropped
+    pub fn path_prefix(self, facet_name: &str) -> String {
+        let groups: Vec<&str> = match facet_name.rsplit_once('/') {
+            Some((groups, _)) => groups
+                .split('/')
+                .filter(|group| !group.is_empty() && *group != "." && *group != "..")
+                .collect(),
+            None => vec![],
+        };
+        if groups.is_empty() {
+            return String::new();
+        }
+        match self {
+            FlattenNames::Slash => String::new(),
+            FlattenNames::Dash => format!("{}-", groups.join("-")),
+            FlattenNames::Keep => format!("{}/", groups.join("/")),
+        }
+    }
+}
+
+impl std::str::FromStr for FlattenNames {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "slash" => Ok(FlattenNames::Slash),
+            "dash" => Ok(FlattenNames::Dash),
+            "keep" => Ok(FlattenNames::Keep),
+            _ => anyhow::bail!(
+                "unknown name flattening {:?}, expected slash, dash or keep",
+                s
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_width_strings() {
+        let exact = "a".repeat(256);
+        let field: [u8; 256] = str_to_sized_slice("authoring tool", &exact).unwrap();
+        assert_eq!(parse_padded_string(&field), exact);
+        let field: [u8; 8] = str_to_sized_slice("name", "ab").unwrap();
+        assert_eq!(field, *b"ab\0\0\0\0\0\0");
+
+        assert_eq!(truncate_str("abc", 8), "abc");
+        assert_eq!(truncate_str("abc", 2), "ab");
+        assert_eq!(truncate_str("aé", 2), "a");
+        assert_eq!(truncate_str(&"é".repeat(127), 128), "é".repeat(64));
+    }
+
+    #[test]
+    fn hex_prefixes() {
+        assert_eq!(hex_prefix(&[0x00, 0xab, 0x10], 256), "00ab10");
+        assert_eq!(hex_prefix(&[0x00, 0xab, 0x10], 2), "00ab");
+        assert_eq!(hex_prefix(&[], 2), "");
+    }
+
+    #[test]
+    fn portable_file_names() {
+        assert_eq!(portable_file_name("Icon@2x.png"), "Icon@2x.png");
+        // groups of namespaced assets stay directories
+        assert_eq!(
+            portable_file_name("Buttons/Primary.png"),
+            "Buttons/Primary.png"
+        );
+        assert_eq!(portable_file_name("Clock 12:30.png"), "Clock 12_30.png");
+        assert_eq!(
+            portable_file_name(r#"a<b>c"d\e|f?g*h.png"#),
+            "a_b_c_d_e_f_g_h.png"
+        );
+        assert_eq!(portable_file_name("tab\there.png"), "tab_here.png");
+        // Windows would drop the trailing dot and space
+        assert_eq!(portable_file_name("Icon."), "Icon._");
+        assert_eq!(portable_file_name("Icon "), "Icon _");
+        // device names, with or without an extension
+        assert_eq!(portable_file_name("nul.png"), "_nul.png");
+        assert_eq!(portable_file_name("Com1"), "_Com1");
+        assert_eq!(portable_file_name("console.png"), "console.png");
+        // nothing can leave the output directory
+        assert_eq!(portable_file_name("../Icon.png"), "__/Icon.png");
+        assert_eq!(portable_file_name("/Icon.png"), "_/Icon.png");
+    }
+
+    #[test]
+    fn windows_path_components() {
+        assert_eq!(
+            last_path_component(r"C:\Assets.xcassets\AppIcon.appiconset\"),
+            "AppIcon.appiconset"
+        );
+        assert_eq!(
+            last_path_component("Assets.xcassets/Icon.imageset//"),
+            "Icon.imageset"
+        );
+        assert_eq!(last_path_component("Assets.car"), "Assets.car");
+        assert_eq!(
+            path_extension(r"..\Assets.xcassets\AppIcon.appiconset\"),
+            Some("appiconset")
+        );
+        assert_eq!(
+            path_extension("Assets.xcassets/Config.dataset/"),
+            Some("dataset")
+        );
+        // the folder of the catalog, not of an asset set
+        assert_eq!(path_extension(r"C:\Users\me\.xcassets\"), None);
+        assert_eq!(path_extension("Contents"), None);
+    }
+
+    #[test]
+    fn verbatim_prefix_stripped() {
+        assert_eq!(
+            strip_verbatim_prefix(r"\\?\C:\Users\me\Assets.car"),
+            r"C:\Users\me\Assets.car"
+        );
+        assert_eq!(
+            strip_verbatim_prefix(r"\\?\UNC\server\share\Assets.car"),
+            r"\\server\share\Assets.car"
+        );
+        assert_eq!(strip_verbatim_prefix("/tmp/Assets.car"), "/tmp/Assets.car");
     }
-    slice
 }
@@ -30,7 +30,7 @@ pub struct Color {
     // pattern: u32,
 }
 
-#[derive(Debug, FromPrimitive, BinRead, Clone, Serialize)]
+#[derive(Debug, FromPrimitive, BinRead, Clone, Copy, PartialEq, Serialize)]
 #[br(repr(u32))]
 pub enum ColorSpace {
     #[serde(rename = "srgb")]
@@ -1,8 +1,11 @@
 use binrw::BinRead;
 use binrw::BinWrite;
 use std::fmt::Debug;
+use std::fmt::Display;
 
-#[derive(BinRead, BinWrite)]
+use crate::common;
+
+#[derive(BinRead, BinWrite, Clone, Copy, PartialEq, Eq, Hash)]
 #[brw(little)]
 pub struct Key {
     pub raw: [u16; 11],
@@ -26,3 +29,32 @@ impl Debug for Key {
         ))
     }
 }
+
+// the raw values as hex, the same string from_hex reads
+impl Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl Key {
+    // the layout actool writes, only the last field differs between facets:
+    // a bit for every scale factor the facet has renditions at
+    pub fn for_scales(scales: impl IntoIterator<Item = u16>) -> Self {
+        let mut raw = [1, 0, 0, 0, 76, 0, 18, 0, 0xFFFF, 0xFFFF, 0];
+        for scale in scales {
+            raw[10] |= 1u16.checked_shl(scale as u32).unwrap_or(0);
+        }
+        Key { raw }
+    }
+
+    pub fn from_hex(string: &str) -> anyhow::Result<Self> {
+        Ok(Key {
+            raw: common::u16s_from_hex(string)?,
+        })
+    }
+
+    pub fn to_hex(self) -> String {
+        common::u16s_to_hex(&self.raw)
+    }
+}
@@ -1,147 +1,265 @@
 use super::bitmap;
 use super::csi;
 use super::rendition;
+use super::CarError;
+use super::FacetName;
+use super::RenditionQuery;
+use anyhow::Context;
 use anyhow::Result;
 use binrw::BinRead;
 use binrw::BinWrite;
 use binrw::NullString;
+use hex::ToHex;
 use memmap::Mmap;
 use sha2::Digest;
 use sha2::Sha256;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt::Debug;
 use std::fs;
 use std::io::Cursor;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
 use std::time::UNIX_EPOCH;
 
 use crate::bom;
 use crate::common;
+use crate::coregraphics;
+use crate::input;
 
 pub type NameIdentifier = u32;
 
+// renditions by the Identifier in their key, each list in key order
+pub type IdentifierIndex<'a> = BTreeMap<u16, Vec<(&'a rendition::Key, &'a csi::Header)>>;
+
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    // renditions whose SizeOnDisk is above this are not decoded
+    pub max_payload_bytes: Option<u64>,
+    // SHA1Digest of every rendition, skipping it leaves rendition_sha_digests empty
+    pub compute_digests: bool,
+    // map the file rather than reading it into memory, reading is the fallback
+    // when mapping fails anyway
+    pub use_mmap: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            max_payload_bytes: None,
+            compute_digests: true,
+            use_mmap: true,
+        }
+    }
+}
+
+// the bytes of an Assets.car, mapped or read into memory
+pub enum CarData {
+    Mapped(Mmap),
+    Read(Vec<u8>),
+}
+
+impl CarData {
+    // files locked by another process (Xcode on Windows) or on filesystems
+    // without mmap support are read instead
+    pub fn open(path: impl AsRef<Path>, use_mmap: bool) -> Result<CarData> {
+        let path = path.as_ref();
+        let file = open_shared(path)
+            .with_context(|| format!("Unable to open {}", input::display_path(path)))?;
+        if file.metadata()?.len() == 0 {
+            // empty files can't be mapped
+            return Ok(CarData::Read(vec![]));
+        }
+        if use_mmap {
+            match unsafe { Mmap::map(&file) } {
+                Ok(mmap) => return Ok(CarData::Mapped(mmap)),
+                Err(err) => {
+                    return fs::read(path).map(CarData::Read).with_context(|| {
+                        format!(
+                            "Unable to map ({}) or read {}",
+                            err,
+                            input::display_path(path)
+                        )
+                    })
+                }
+            }
+        }
+        fs::read(path)
+            .map(CarData::Read)
+            .with_context(|| format!("Unable to read {}", input::display_path(path)))
+    }
+}
+
+impl std::ops::Deref for CarData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            CarData::Mapped(mmap) => mmap,
+            CarData::Read(data) => data,
+        }
+    }
+}
+
+impl AsRef<[u8]> for CarData {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+// lets other processes keep reading, writing and even deleting the file, so
+// catalogs Xcode still has open can be read on Windows
+fn open_shared(path: &Path) -> std::io::Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.read(true);
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        // FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE
+        options.share_mode(0x1 | 0x2 | 0x4);
+    }
+    options.open(path)
+}
+
 pub struct CarUtilAssetStorage {
     pub theme_store: StructuredThemeStore,
 }
 
 impl CarUtilAssetStorage {
-    pub fn from(path: &str, _for_writing: bool) -> Result<CarUtilAssetStorage> {
-        let file = fs::File::open(path)?;
-        let file_timestamp: u32;
-        {
-            let file_metadata = file.metadata()?;
-            let modified = file_metadata.modified()?;
-            let duration = modified.duration_since(UNIX_EPOCH)?;
-            file_timestamp = duration.as_secs().try_into()?;
-        }
-        let mmap = unsafe { Mmap::map(&file).expect(&format!("Error mapping file {}", path)) };
-        let mut reader = Cursor::new(mmap);
+    pub fn from(path: impl AsRef<Path>, _for_writing: bool) -> Result<CarUtilAssetStorage> {
+        CarUtilAssetStorage::from_with_options(path, &ParseOptions::default())
+    }
 
-        // read items from bom storage
-        let bom_storage = bom::Storage::read(&mut reader)?;
-        let mut car_header =
-            bom_storage.get_named_typed_block::<CarHeader>("CARHEADER", &mut reader, ())?;
+    pub fn from_with_options(
+        path: impl AsRef<Path>,
+        options: &ParseOptions,
+    ) -> Result<CarUtilAssetStorage> {
+        let streaming = CarUtilAssetStorage::open_streaming_with_options(path, options)?;
+        CarUtilAssetStorage::from_streaming(streaming, options)
+    }
 
-        if car_header.storage_timestamp == 0 {
-            // default to file timestamp if the Assets.car file doesn't have a timestamp
-            car_header.storage_timestamp = file_timestamp;
-        }
+    // a catalog already in memory, e.g. one read out of an app bundle archive
+    pub fn from_bytes(data: Vec<u8>) -> Result<CarUtilAssetStorage> {
+        CarUtilAssetStorage::from_bytes_with_options(data, &ParseOptions::default())
+    }
 
-        let extended_metadata = bom_storage.get_named_typed_block::<CarExtendedMetadata>(
-            "EXTENDED_METADATA",
-            &mut reader,
-            (),
-        )?;
-        let renditionkeyfmt = bom_storage.get_named_typed_block::<rendition::KeyFormat>(
-            "KEYFORMAT",
-            &mut reader,
-            (),
-        )?;
+    pub fn from_bytes_with_options(
+        data: Vec<u8>,
+        options: &ParseOptions,
+    ) -> Result<CarUtilAssetStorage> {
+        CarUtilAssetStorage::check_bounds(&data)?;
+        let streaming = StreamingAssetStorage::from_reader(Cursor::new(CarData::Read(data)), None)?;
+        CarUtilAssetStorage::from_streaming(streaming, options)
+    }
+
+    fn from_streaming(
+        mut streaming: StreamingAssetStorage<Cursor<CarData>>,
+        options: &ParseOptions,
+    ) -> Result<CarUtilAssetStorage> {
+        streaming.renditions.max_payload_bytes = options.max_payload_bytes;
+        let imagedb = streaming
+            .renditions
+            .by_ref()
+            .collect::<Result<BTreeMap<rendition::Key, csi::Header>>>()?;
+        let StreamingAssetStorage {
+            header: car_header,
+            extended_metadata,
+            renditionkeyfmt,
+            file_timestamp,
+            renditions,
+        } = streaming;
+        let (mut reader, bom_storage) = renditions.into_inner();
 
-        let facetkeys_tree =
-            bom_storage.get_named_typed_block::<bom::Tree>("FACETKEYS", &mut reader, ())?;
-        let facetkeys = facetkeys_tree
-            .items_typed::<NullString, rendition::KeyToken>(&bom_storage, &mut reader)?;
+        let facetkeys = bom_storage
+            .get_named_tree_items_typed::<NullString, rendition::KeyToken>("FACETKEYS", &mut reader)
+            .with_context(|| {
+                format!(
+                    "unable to read the entries of {}",
+                    bom_storage.describe_named_block("FACETKEYS")
+                )
+            })?;
+        // names are decoded strictly, the ones that aren't UTF-8 are kept as
+        // read and go by a percent-encoded fallback. A UTF-8 name can spell
+        // the same fallback ("a%FF" and the bytes a\xFF), so taken fallbacks
+        // get a "-2", "-3", ... suffix
+        let facetkeys: Vec<(Result<String, Vec<u8>>, rendition::KeyToken)> = facetkeys
+            .into_iter()
+            .map(|(name, token)| {
+                let name = String::from_utf8(name.0).map_err(|err| err.into_bytes());
+                (name, token)
+            })
+            .collect();
+        let mut taken: HashSet<String> = facetkeys
+            .iter()
+            .filter_map(|(name, _)| name.as_ref().ok().cloned())
+            .collect();
+        let mut raw_facet_names = BTreeMap::new();
         let facetkeysdb = facetkeys
             .into_iter()
-            .map(|(name, token)| (name.to_string(), token))
+            .map(|(name, token)| match name {
+                Ok(name) => (name, token),
+                Err(bytes) => {
+                    let fallback = common::percent_encode(&bytes);
+                    let mut name = fallback.clone();
+                    let mut suffix = 2;
+                    while !taken.insert(name.clone()) {
+                        name = format!("{}-{}", fallback, suffix);
+                        suffix += 1;
+                    }
+                    raw_facet_names.insert(name.clone(), bytes);
+                    (name, token)
+                }
+            })
             .collect();
 
         let bitmapkeys: Option<Vec<(NameIdentifier, bitmap::Key)>> = bom_storage
-            .get_named_typed_block::<bom::Tree>("BITMAPKEYS", &mut reader, ())
-            .and_then(|tree| {
-                let path_range = bom_storage.block_storage.items[tree.path_block_id as usize];
-                let path = path_range.read_type::<bom::Paths>(&mut reader, ())?;
-
-                path.indices
+            .get_named_tree_items("BITMAPKEYS", &mut reader)
+            .and_then(|items| {
+                items
                     .into_iter()
-                    .map(|indices| {
-                        let key: NameIdentifier = indices.index1;
-                        let value_pointer =
-                            &bom_storage.block_storage.items[indices.index0 as usize];
+                    // the key is the name identifier itself rather than a block id
+                    .map(|(name_identifier, value)| {
+                        let value_pointer = bom_storage.block_storage.item(value)?;
                         reader.set_position((value_pointer.address) as u64);
                         let value = bitmap::Key::read(&mut reader)?;
-                        Ok((key, value))
+                        Ok((name_identifier, value))
                     })
-                    .into_iter()
                     .collect()
             })
             .ok();
 
-        let rendition_sha_digests: BTreeMap<rendition::Key, Vec<u8>> = bom_storage
-            .get_named_typed_block::<bom::Tree>("RENDITIONS", &mut reader, ())
-            .and_then(|tree| {
-                let path_range = bom_storage.block_storage.items[tree.path_block_id as usize];
-                let path = path_range.read_type::<bom::Paths>(&mut reader, ())?;
-
-                path.indices
-                    .into_iter()
-                    .map(|indices| {
-                        let mut key_range =
-                            bom_storage.block_storage.items[indices.index1 as usize];
-                        key_range.length = 36; // sometimes this is less? rendition key needs exactly 36 bytes
-                        let key = key_range
-                            .read_type::<rendition::Key>(&mut reader, ())
-                            .unwrap();
-                        let value_range = &bom_storage.block_storage.items[indices.index0 as usize];
-                        let value = value_range.read(&mut reader)?;
-                        let mut hasher = Sha256::new();
-                        hasher.update(value);
-                        Ok((key, hasher.finalize().to_vec()))
-                    })
-                    .into_iter()
-                    .collect()
-            })
-            .expect("Unable to find required RENDITIONS var in BOMTree.");
-
-        let imagedb: BTreeMap<rendition::Key, csi::Header> = bom_storage
-            .get_named_typed_block::<bom::Tree>("RENDITIONS", &mut reader, ())
-            .and_then(|tree| {
-                tree.items_typed::<rendition::Key, csi::Header>(&bom_storage, &mut reader)
-            })
-            .expect("Unable to find required RENDITIONS var in BOMTree.")
-            .into_iter()
-            .collect();
+        let rendition_sha_digests = if options.compute_digests {
+            CarUtilAssetStorage::rendition_sha_digests(&bom_storage, reader.get_ref())
+                .with_context(|| {
+                    format!(
+                        "unable to hash the renditions in {}",
+                        bom_storage.describe_named_block("RENDITIONS")
+                    )
+                })?
+        } else {
+            BTreeMap::new()
+        };
 
         let appearancedb: Option<BTreeMap<String, u32>> = bom_storage
-            .get_named_typed_block::<bom::Tree>("APPEARANCEKEYS", &mut reader, ())
-            .and_then(|tree| {
-                let path_range = bom_storage.block_storage.items[tree.path_block_id as usize];
-                let path = path_range.read_type::<bom::Paths>(&mut reader, ())?;
-
-                path.indices
+            .get_named_tree_items("APPEARANCEKEYS", &mut reader)
+            .and_then(|items| {
+                items
                     .into_iter()
-                    .map(|indices| {
-                        let key_range = &bom_storage.block_storage.items[indices.index0 as usize];
+                    .map(|(name, id)| {
+                        let key_range = bom_storage.block_storage.item(id)?;
                         reader.set_position((key_range.address) as u64);
                         let key = <u32>::read_le(&mut reader)?;
 
-                        let value_range = &bom_storage.block_storage.items[indices.index1 as usize];
+                        let value_range = bom_storage.block_storage.item(name)?;
                         let value = value_range.read(&mut reader)?;
                         let value_string = String::from_utf8(value)?;
                         Ok((value_string, key))
                     })
-                    .into_iter()
                     .collect()
             })
             .ok();
@@ -154,14 +272,127 @@ impl CarUtilAssetStorage {
             rendition_sha_digests,
             appearancedb,
             facetkeysdb,
+            raw_facet_names,
             bitmapkeydb,
             imagedb,
+            file_timestamp,
         };
         let theme_store = StructuredThemeStore { store };
         Ok(CarUtilAssetStorage { theme_store })
     }
 
-    pub fn write_data(&self, path: &str) -> Result<()> {
+    // sha256 of every csi header, hashed straight out of the mapped file
+    fn rendition_sha_digests(
+        bom_storage: &bom::Storage,
+        data: &[u8],
+    ) -> Result<BTreeMap<rendition::Key, Vec<u8>>> {
+        Ok(CarUtilAssetStorage::rendition_blocks(bom_storage, data)?
+            .into_iter()
+            .map(|(key, value)| (key, Sha256::digest(value).to_vec()))
+            .collect())
+    }
+
+    // the key and stored csi header bytes of every RENDITIONS entry, in tree
+    // order and with any entries sharing a key
+    pub fn rendition_blocks<'a>(
+        bom_storage: &bom::Storage,
+        data: &'a [u8],
+    ) -> Result<Vec<(rendition::Key, &'a [u8])>> {
+        let mut reader = Cursor::new(data);
+        if !bom_storage.has_var("RENDITIONS") {
+            return Ok(vec![]);
+        }
+        let items = bom_storage.get_named_tree_items("RENDITIONS", &mut reader)?;
+
+        items
+            .into_iter()
+            .enumerate()
+            .map(|(index, (key_block_id, value_block_id))| {
+                let mut key_range = bom_storage.block_storage.item(key_block_id)?;
+                key_range.length = 36; // sometimes this is less? rendition key needs exactly 36 bytes
+                let key = key_range
+                    .read_type::<rendition::Key>(&mut reader, ())
+                    .with_context(|| {
+                        format!(
+                            "unable to read the key of RENDITIONS entry {} (block {} at 0x{:X})",
+                            index, key_block_id, key_range.address
+                        )
+                    })?;
+                let value_range = bom_storage.block_storage.item(value_block_id)?;
+                let start = value_range.address as usize;
+                let value = data
+                    .get(start..start + value_range.length as usize)
+                    .context(format!("RENDITIONS entry {} is out of bounds", index))?;
+                Ok((key, value))
+            })
+            .collect()
+    }
+
+    // reads the header right away, renditions are parsed as they're iterated
+    pub fn open_streaming(
+        path: impl AsRef<Path>,
+    ) -> Result<StreamingAssetStorage<Cursor<CarData>>> {
+        CarUtilAssetStorage::open_streaming_with_options(path, &ParseOptions::default())
+    }
+
+    pub fn open_streaming_with_options(
+        path: impl AsRef<Path>,
+        options: &ParseOptions,
+    ) -> Result<StreamingAssetStorage<Cursor<CarData>>> {
+        let path = path.as_ref();
+        input::check_car_input(path)?;
+        let file_timestamp: i64;
+        {
+            let file_metadata = fs::metadata(path)?;
+            let modified = file_metadata.modified()?;
+            let duration = modified.duration_since(UNIX_EPOCH)?;
+            file_timestamp = duration.as_secs().try_into()?;
+        }
+        let data = CarData::open(path, options.use_mmap)?;
+        CarUtilAssetStorage::check_bounds(&data)?;
+        let mut streaming =
+            StreamingAssetStorage::from_reader(Cursor::new(data), Some(file_timestamp))?;
+        streaming.renditions.max_payload_bytes = options.max_payload_bytes;
+        Ok(streaming)
+    }
+
+    // catches truncated files before binrw fails with an EOF somewhere deep in
+    // the BOM: the header, the block and var storage, and every block range
+    // must lie within the file
+    pub fn check_bounds(data: &[u8]) -> Result<()> {
+        // magic, version, block count, then offset and length of both storages
+        static BOM_HEADER_SIZE: u64 = 32;
+
+        let actual = data.len() as u64;
+        let check = |expected: u64| -> Result<()> {
+            if expected > actual {
+                Err(CarError::Truncated { expected, actual }.into())
+            } else {
+                Ok(())
+            }
+        };
+        let read_u32 = |offset: u64| -> Result<u64> {
+            check(offset + 4)?;
+            let offset = offset as usize;
+            Ok(u32::from_be_bytes(data[offset..offset + 4].try_into()?) as u64)
+        };
+
+        check(BOM_HEADER_SIZE)?;
+        let block_storage_address = read_u32(16)?;
+        check(block_storage_address + read_u32(20)?)?;
+        let var_storage_address = read_u32(24)?;
+        check(var_storage_address + read_u32(28)?)?;
+
+        let block_count = read_u32(block_storage_address)?;
+        check(block_storage_address + 4 + block_count * 8)?;
+        for index in 0..block_count {
+            let item_address = block_storage_address + 4 + index * 8;
+            check(read_u32(item_address)? + read_u32(item_address + 4)?)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_data(&self, path: impl AsRef<Path>) -> Result<()> {
         let mut buffer: Vec<u8> = vec![];
         let mut writer = Cursor::new(&mut buffer);
         let mut block_storage = bom::BlockStorage::new();
@@ -208,20 +439,322 @@ impl CarUtilAssetStorage {
             });
         }
 
-        // path for renditions
+        let renditions_tree_block_id =
+            Self::write_tree(&mut writer, &mut block_storage, rendition_path_indices)?;
+
+        // facet names to key tokens
+        let mut facet_key_path_indices = vec![];
+        for (name, key_token) in &self.theme_store.store.facetkeysdb {
+            let next_address = block_storage.next_item_address();
+            writer.set_position(next_address as u64);
+            let name_bytes = match self.theme_store.store.raw_facet_names.get(name) {
+                Some(bytes) => bytes.clone(),
+                None => name.as_bytes().to_vec(),
+            };
+            NullString(name_bytes).write(&mut writer)?;
+            let key_block_id = block_storage.add_item(next_address, writer.position() as u32);
+
+            let next_address = block_storage.next_item_address();
+            writer.set_position(next_address as u64);
+            key_token.write(&mut writer)?;
+            let value_block_id = block_storage.add_item(next_address, writer.position() as u32);
+
+            facet_key_path_indices.push(bom::PathIndices {
+                index0: value_block_id,
+                index1: key_block_id,
+            });
+        }
+        let facet_keys_tree_block_id =
+            Self::write_tree(&mut writer, &mut block_storage, facet_key_path_indices)?;
+
+        // bitmap keys, index1 is the name identifier itself rather than a block id
+        let mut bitmap_key_path_indices = vec![];
+        for (name_identifier, bitmap_key) in self.theme_store.store.bitmap_keys() {
+            let next_address = block_storage.next_item_address();
+            writer.set_position(next_address as u64);
+            bitmap_key.write(&mut writer)?;
+            let value_block_id = block_storage.add_item(next_address, writer.position() as u32);
+
+            bitmap_key_path_indices.push(bom::PathIndices {
+                index0: value_block_id,
+                index1: name_identifier,
+            });
+        }
+        let bitmap_keys_tree_block_id =
+            Self::write_tree(&mut writer, &mut block_storage, bitmap_key_path_indices)?;
+
+        // appearance ids keyed by the unterminated appearance name
+        let mut appearance_keys_tree_block_id = None;
+        if let Some(appearancedb) = &self.theme_store.store.appearancedb {
+            let mut appearance_key_path_indices = vec![];
+            for (name, id) in appearancedb {
+                let next_address = block_storage.next_item_address();
+                writer.set_position(next_address as u64);
+                name.as_bytes().write(&mut writer)?;
+                let key_block_id = block_storage.add_item(next_address, writer.position() as u32);
+
+                let next_address = block_storage.next_item_address();
+                writer.set_position(next_address as u64);
+                id.write_le(&mut writer)?;
+                let value_block_id = block_storage.add_item(next_address, writer.position() as u32);
+
+                appearance_key_path_indices.push(bom::PathIndices {
+                    index0: value_block_id,
+                    index1: key_block_id,
+                });
+            }
+            appearance_keys_tree_block_id = Some(Self::write_tree(
+                &mut writer,
+                &mut block_storage,
+                appearance_key_path_indices,
+            )?);
+        }
+
+        // BOM VarStorage
+        let mut vars = vec![
+            bom::Var::from("CARHEADER", header_block_id),
+            bom::Var::from("EXTENDED_METADATA", extended_header_block_id),
+            bom::Var::from("KEYFORMAT", rendition_key_format_block_id),
+            bom::Var::from("RENDITIONS", renditions_tree_block_id),
+            bom::Var::from("FACETKEYS", facet_keys_tree_block_id),
+            bom::Var::from("BITMAPKEYS", bitmap_keys_tree_block_id),
+        ];
+        if let Some(block_id) = appearance_keys_tree_block_id {
+            vars.push(bom::Var::from("APPEARANCEKEYS", block_id));
+        }
+        let var_storage = bom::VarStorage {
+            count: vars.len() as u32,
+            vars,
+        };
+        // the var and block tables go after the last block, so catalogs of
+        // any size keep them clear of the blocks
+        let var_storage_address = block_storage.next_item_address() as u64;
+        writer.set_position(var_storage_address);
+        var_storage.write(&mut writer)?;
+        let var_storage_length = (writer.position() - var_storage_address) as u32;
+
+        // BOM BlockStorage
+        let block_storage_address = (writer.position() & !0xf) + 0x10;
+        writer.set_position(block_storage_address);
+        block_storage.write(&mut writer)?;
+
+        // BOM Storage (Header)
+        writer.set_position(0);
+        b"BOMStore".write(&mut writer)?; // magic
+        1u32.write_be(&mut writer)?; // version
+        block_storage.count.write_be(&mut writer)?;
+        (block_storage_address as u32).write_be(&mut writer)?;
+        (block_storage.count * 8 + 4).write_be(&mut writer)?; // size of BlockStorage struct
+        (var_storage_address as u32).write_be(&mut writer)?;
+        var_storage_length.write_be(&mut writer)?; // not sure if right
+
+        fs::write(path, buffer)?;
+        Ok(())
+    }
+
+    // replaces or adds renditions without rewriting the catalog: the new key
+    // and csi header blocks, a new RENDITIONS tree, a CARHEADER with the new
+    // rendition count, the block table and the var table are appended to the
+    // file and synced, then the BOM header is pointed at the new tables. The
+    // file is mapped rather than read, existing blocks are left untouched and
+    // the ones replaced stay in the file unreferenced
+    pub fn update_in_place(
+        path: impl AsRef<Path>,
+        edits: &[(rendition::Key, csi::Header)],
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let data = CarData::open(path, true)?;
+        CarUtilAssetStorage::check_bounds(&data)?;
+        let original_length = data.len();
+        let mut reader = Cursor::new(&data[..]);
+        let storage = bom::Storage::read(&mut reader)?;
+
+        // (key block, value block) of every rendition, by key
+        let mut renditions = BTreeMap::new();
+        let mut tree = bom::Tree {
+            version: 1,
+            path_block_id: 0,
+            block_size: 1024,
+            path_count: 0,
+            unknown3: 0,
+        };
+        if storage.has_var("RENDITIONS") {
+            tree = storage.get_named_typed_block::<bom::Tree>("RENDITIONS", &mut reader, ())?;
+            for (key_block_id, value_block_id) in
+                storage.get_named_tree_items("RENDITIONS", &mut reader)?
+            {
+                let mut key_range = storage.block_storage.item(key_block_id)?;
+                key_range.length = 36;
+                let key = key_range.read_type::<rendition::Key>(&mut reader, ())?;
+                renditions.insert(key, (key_block_id, value_block_id));
+            }
+        }
+        let mut car_header =
+            storage.get_named_typed_block::<CarHeader>("CARHEADER", &mut reader, ())?;
+        // nothing is read from the mapping past here, and it's closed before
+        // the file is written
+        drop(data);
+
+        let mut block_storage = bom::BlockStorage {
+            count: storage.block_storage.count,
+            items: storage.block_storage.items.clone(),
+        };
+        // new blocks go after everything already in the file, 16 byte aligned
+        let base = (original_length as u32 + 0xf) & !0xf;
+        let mut tail: Vec<u8> = vec![];
+        let mut append = |block_storage: &mut bom::BlockStorage,
+                          write: &dyn Fn(&mut Cursor<Vec<u8>>) -> binrw::BinResult<()>|
+         -> Result<u32> {
+            let mut writer = Cursor::new(vec![]);
+            write(&mut writer)?;
+            tail.resize((tail.len() + 0xf) & !0xf, 0);
+            let address = base + tail.len() as u32;
+            tail.extend(writer.into_inner());
+            Ok(block_storage.add_item(address, base + tail.len() as u32))
+        };
+
+        for (key, csi_header) in edits {
+            let key_block_id = match renditions.get(key) {
+                Some((key_block_id, _)) => *key_block_id,
+                None => append(&mut block_storage, &|writer| key.write(writer))?,
+            };
+            let value_block_id = append(&mut block_storage, &|writer| csi_header.write(writer))?;
+            renditions.insert(*key, (key_block_id, value_block_id));
+        }
+
+        // leaves as large as the original tree's block_size allows, linked
+        // forward and backward, then branches over them up to a single root.
+        // Each branch entry is a child and the key of the last item under it
+        let capacity = ((tree.block_size as usize).saturating_sub(12) / 8).clamp(1, 0xFFFF);
+        let mut entries: Vec<(u32, u32)> = renditions
+            .values()
+            .map(|(key_block_id, value_block_id)| (*value_block_id, *key_block_id))
+            .collect();
+        let mut is_leaf = 1;
+        let root_block_id = loop {
+            let nodes: Vec<&[(u32, u32)]> = if entries.is_empty() {
+                vec![&[]]
+            } else {
+                entries.chunks(capacity).collect()
+            };
+            // the nodes of a level get consecutive block ids
+            let first_block_id = block_storage.count;
+            let mut parents = vec![];
+            for (index, node) in nodes.iter().enumerate() {
+                let block_id = first_block_id + index as u32;
+                let linked = is_leaf == 1;
+                let paths = bom::Paths {
+                    is_leaf,
+                    count: node.len() as u16,
+                    forward: if linked && index + 1 < nodes.len() {
+                        block_id + 1
+                    } else {
+                        0
+                    },
+                    backward: if linked && index > 0 { block_id - 1 } else { 0 },
+                    indices: node
+                        .iter()
+                        .map(|(index0, index1)| bom::PathIndices {
+                            index0: *index0,
+                            index1: *index1,
+                        })
+                        .collect(),
+                };
+                let appended = append(&mut block_storage, &|writer| paths.write(writer))?;
+                debug_assert_eq!(appended, block_id);
+                let last_key = node.last().map(|(_, key_block_id)| *key_block_id);
+                parents.push((block_id, last_key.unwrap_or(0)));
+            }
+            if parents.len() == 1 {
+                break parents[0].0;
+            }
+            entries = parents;
+            is_leaf = 0;
+        };
+        let tree = bom::Tree {
+            path_block_id: root_block_id,
+            path_count: renditions.len() as u32,
+            ..tree
+        };
+        let tree_block_id = append(&mut block_storage, &|writer| tree.write(writer))?;
+
+        // a new CARHEADER block rather than a patched one, so the count only
+        // changes with the tree it counts
+        car_header.rendition_count = renditions.len() as u32;
+        let header_block_id = append(&mut block_storage, &|writer| car_header.write(writer))?;
+
+        let mut vars: Vec<bom::Var> = storage
+            .var_storage
+            .vars
+            .iter()
+            .map(|var| bom::Var::from(&var.name(), var.block_id))
+            .collect();
+        if !storage.has_var("RENDITIONS") {
+            vars.push(bom::Var::from("RENDITIONS", 0));
+        }
+        for var in &mut vars {
+            match var.name().as_str() {
+                "RENDITIONS" => var.block_id = tree_block_id,
+                "CARHEADER" => var.block_id = header_block_id,
+                _ => {}
+            }
+        }
+        let var_storage = bom::VarStorage {
+            count: vars.len() as u32,
+            vars,
+        };
+
+        // the tables aren't blocks themselves
+        tail.resize((tail.len() + 0xf) & !0xf, 0);
+        let block_storage_address = base + tail.len() as u32;
+        let mut writer = Cursor::new(vec![]);
+        block_storage.write(&mut writer)?;
+        tail.extend(writer.into_inner());
+        let var_storage_address = base + tail.len() as u32;
+        let mut writer = Cursor::new(vec![]);
+        var_storage.write(&mut writer)?;
+        let var_storage_length = writer.get_ref().len() as u32;
+        tail.extend(writer.into_inner());
+
+        let mut bom_header = vec![];
+        bom_header.extend(block_storage.count.to_be_bytes());
+        bom_header.extend(block_storage_address.to_be_bytes());
+        bom_header.extend((block_storage.count * 8 + 4).to_be_bytes());
+        bom_header.extend(var_storage_address.to_be_bytes());
+        bom_header.extend(var_storage_length.to_be_bytes());
+
+        // the appended blocks and tables are on disk before the BOM header
+        // points at them, and the header is the only existing byte written,
+        // so an interrupted update leaves the previous catalog
+        let mut file = fs::OpenOptions::new().write(true).open(path)?;
+        file.seek(SeekFrom::Start(original_length as u64))?;
+        file.write_all(&vec![0; base as usize - original_length])?;
+        file.write_all(&tail)?;
+        file.sync_data()?;
+        file.seek(SeekFrom::Start(12))?;
+        file.write_all(&bom_header)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    // writes a single leaf Paths block and the Tree pointing at it, returns the tree block id
+    fn write_tree(
+        writer: &mut Cursor<&mut Vec<u8>>,
+        block_storage: &mut bom::BlockStorage,
+        indices: Vec<bom::PathIndices>,
+    ) -> Result<u32> {
         let next_address = block_storage.next_item_address();
         writer.set_position(next_address as u64);
         let paths = bom::Paths {
             is_leaf: 1,
-            count: rendition_path_indices.len() as u16,
+            count: indices.len() as u16,
             forward: 0,
             backward: 0,
-            indices: rendition_path_indices,
+            indices,
         };
-        paths.write(&mut writer)?;
+        paths.write(writer)?;
         let paths_block_id = block_storage.add_item(next_address, writer.position() as u32);
 
-        // tree for renditions
         let next_address = block_storage.next_item_address();
         writer.set_position(next_address as u64);
         let tree = bom::Tree {
@@ -231,45 +764,134 @@ impl CarUtilAssetStorage {
             path_count: paths.count as u32,
             unknown3: 0,
         };
-        tree.write(&mut writer)?;
-        let renditions_tree_block_id =
-            block_storage.add_item(next_address, writer.position() as u32);
+        tree.write(writer)?;
+        Ok(block_storage.add_item(next_address, writer.position() as u32))
+    }
+}
 
-        // BOM BlockStorage
-        let block_storage_address = 0x8000; // arbitrary, TODO: fix
-        writer.set_position(block_storage_address);
-        block_storage.write(&mut writer)?;
+pub struct StreamingAssetStorage<R> {
+    pub header: CarHeader,                      // CARHEADER
+    pub extended_metadata: CarExtendedMetadata, // EXTENDED_METADATA
+    pub renditionkeyfmt: rendition::KeyFormat,  // KEYFORMAT
+    pub file_timestamp: Option<i64>,
+    pub renditions: Renditions<R>, // RENDITIONS
+}
 
-        // BOM VarStorage
-        let var_storage = bom::VarStorage {
-            count: 4,
-            vars: vec![
-                bom::Var::from("CARHEADER", header_block_id),
-                bom::Var::from("EXTENDED_METADATA", extended_header_block_id),
-                bom::Var::from("KEYFORMAT", rendition_key_format_block_id),
-                bom::Var::from("RENDITIONS", renditions_tree_block_id),
-            ],
+impl<R: Read + Seek> StreamingAssetStorage<R> {
+    pub fn from_reader(mut reader: R, file_timestamp: Option<i64>) -> Result<Self> {
+        // read items from bom storage
+        let bom_storage = bom::Storage::read(&mut reader)?;
+        let header =
+            bom_storage.get_named_typed_block::<CarHeader>("CARHEADER", &mut reader, ())?;
+
+        let extended_metadata = bom_storage.get_named_typed_block::<CarExtendedMetadata>(
+            "EXTENDED_METADATA",
+            &mut reader,
+            (),
+        )?;
+        let renditionkeyfmt = bom_storage.get_named_typed_block::<rendition::KeyFormat>(
+            "KEYFORMAT",
+            &mut reader,
+            (),
+        )?;
+
+        // catalogs holding only named colors, or stripped ones, have no
+        // RENDITIONS var at all
+        let items = if bom_storage.has_var("RENDITIONS") {
+            bom_storage
+                .get_named_tree_items("RENDITIONS", &mut reader)
+                .with_context(|| {
+                    format!(
+                        "unable to read the paths of {}",
+                        bom_storage.describe_named_block("RENDITIONS")
+                    )
+                })?
+        } else {
+            vec![]
         };
-        let var_storage_address = 0x7000; // arbitrary, TODO: fix
-        writer.set_position(var_storage_address);
-        var_storage.write(&mut writer)?;
-        let var_storage_length = (writer.position() - var_storage_address) as u32;
+        Ok(StreamingAssetStorage {
+            header,
+            extended_metadata,
+            renditionkeyfmt,
+            file_timestamp,
+            renditions: Renditions {
+                reader,
+                bom_storage,
+                items: items.into_iter().enumerate(),
+                max_payload_bytes: None,
+            },
+        })
+    }
+}
 
-        // BOM Storage (Header)
-        writer.set_position(0);
-        b"BOMStore".write(&mut writer)?; // magic
-        1u32.write_be(&mut writer)?; // version
-        block_storage.count.write_be(&mut writer)?;
-        (block_storage_address as u32).write_be(&mut writer)?;
-        (block_storage.count * 8 + 4).write_be(&mut writer)?; // size of BlockStorage struct
-        (var_storage_address as u32).write_be(&mut writer)?;
-        var_storage_length.write_be(&mut writer)?; // not sure if right
+// walks the RENDITIONS tree, reading each key and csi header only when asked for
+pub struct Renditions<R> {
+    reader: R,
+    bom_storage: bom::Storage,
+    items: std::iter::Enumerate<std::vec::IntoIter<(u32, u32)>>,
+    // renditions over it are read without their tlvs and payload
+    max_payload_bytes: Option<u64>,
+}
 
-        fs::write(path, buffer)?;
-        Ok(())
+impl<R: Read + Seek> Renditions<R> {
+    // the reader and bom storage, for reading the other trees afterwards
+    pub fn into_inner(self) -> (R, bom::Storage) {
+        (self.reader, self.bom_storage)
+    }
+
+    fn read_item(&mut self, key: u32, value: u32) -> Result<(rendition::Key, csi::Header)> {
+        let block_range = |block_id: u32| {
+            self.bom_storage
+                .block_storage
+                .items
+                .get(block_id as usize)
+                .copied()
+                .context(format!("rendition block {} does not exist", block_id))
+        };
+        let key_range = block_range(key)?;
+        let value_range = block_range(value)?;
+
+        self.reader
+            .seek(SeekFrom::Start(key_range.address as u64))?;
+        let key = rendition::Key::read(&mut self.reader).with_context(|| {
+            format!(
+                "unable to read the key (block {} at 0x{:X})",
+                key, key_range.address
+            )
+        })?;
+        self.reader
+            .seek(SeekFrom::Start(value_range.address as u64))?;
+        let csi_header = csi::Header::read_args(&mut self.reader, (self.max_payload_bytes,))
+            .with_context(|| {
+                format!(
+                    "unable to read the csi header (block {} at 0x{:X})",
+                    value, value_range.address
+                )
+            })?;
+        Ok((key, csi_header))
+    }
+}
+
+impl<R: Read + Seek> Iterator for Renditions<R> {
+    type Item = Result<(rendition::Key, csi::Header)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (index, (key, value)) = self.items.next()?;
+        Some(
+            self.read_item(key, value)
+                .with_context(|| format!("RENDITIONS entry {}", index)),
+        )
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.items.size_hint()
     }
 }
 
+pub fn unknown_appearance_name(id: u32) -> String {
+    format!("UnknownAppearance{}", id)
+}
+
 // CUIStructuredThemeStore
 pub struct StructuredThemeStore {
     pub store: CommonAssetStorage,
@@ -294,6 +916,29 @@ impl StructuredThemeStore {
     pub fn rendition_key_format(&self) -> Vec<rendition::AttributeType> {
         self.store.renditionkeyfmt.attribute_types.clone()
     }
+
+    // the rendition CoreUI would pick for the facet `name`, see RenditionQuery::score
+    pub fn best_rendition(&self, name: &str, query: &RenditionQuery) -> Option<&csi::Header> {
+        let identifier = self
+            .store
+            .facet_with_case(name, query.case_sensitive)?
+            .find_attribute(rendition::AttributeType16::Identifier)?;
+        self.store
+            .imagedb
+            .iter()
+            .filter_map(|(rendition_key, csi_header)| {
+                let key_values = self.store.key_values(rendition_key);
+                let same_facet = key_values.iter().any(|(attribute, value)| {
+                    *attribute == rendition::AttributeType::Identifier && *value == identifier
+                });
+                if !same_facet {
+                    return None;
+                }
+                query.score(&key_values).map(|score| (score, csi_header))
+            })
+            .min_by_key(|(score, _)| *score)
+            .map(|(_, csi_header)| csi_header)
+    }
 }
 
 pub struct CommonAssetStorage {
@@ -308,12 +953,37 @@ pub struct CommonAssetStorage {
     // pub fontsizedb: Option<Vec<FontSize>>,
     // pub _zcglyphdb: Option<Vec<Glyph>>, // zero code glyphs
     // pub _zcbezeldb: Option<Vec<Bezel>>, // zero code bezels
-    pub facetkeysdb: Vec<(String, rendition::KeyToken)>, // FACETKEYS
+    pub facetkeysdb: BTreeMap<String, rendition::KeyToken>, // FACETKEYS
+    // the bytes of facet names that aren't UTF-8, by their name in facetkeysdb
+    pub raw_facet_names: BTreeMap<String, Vec<u8>>,
     pub bitmapkeydb: Option<Vec<(NameIdentifier, bitmap::Key)>>, // BITMAPKEYS
-    pub appearancedb: Option<BTreeMap<String, u32>>,     // APPEARANCEKEYS
+    pub appearancedb: Option<BTreeMap<String, u32>>,             // APPEARANCEKEYS
+
+    // modification time of the file the storage was read from
+    pub file_timestamp: Option<i64>,
 }
 
 impl CommonAssetStorage {
+    // a store without facets or renditions, to build a catalog in memory and
+    // write it with CarUtilAssetStorage::write_data
+    pub fn new(
+        header: CarHeader,
+        extended_metadata: CarExtendedMetadata,
+        renditionkeyfmt: rendition::KeyFormat,
+    ) -> CommonAssetStorage {
+        CommonAssetStorage {
+            header,
+            extended_metadata,
+            renditionkeyfmt,
+            rendition_sha_digests: BTreeMap::new(),
+            imagedb: BTreeMap::new(),
+            facetkeysdb: BTreeMap::new(),
+            raw_facet_names: BTreeMap::new(),
+            bitmapkeydb: None,
+            appearancedb: None,
+            file_timestamp: None,
+        }
+    }
     pub fn thinning_arguments(&self) -> String {
         common::parse_padded_string(&self.extended_metadata.thinning_arguments)
     }
@@ -332,10 +1002,335 @@ impl CommonAssetStorage {
     pub fn main_version_string(&self) -> String {
         common::parse_padded_string(&self.header.main_version_string)
     }
-    pub fn appearences(&self) -> Option<HashMap<String, u32>> {
-        self.appearancedb
-            .clone()
-            .and_then(|appearances| Some(appearances.into_iter().collect()))
+    // attribute values of a rendition key, every consumer decodes keys through
+    // here so differences between key semantics only need handling once
+    pub fn key_values(&self, key: &rendition::Key) -> Vec<(rendition::AttributeType, u16)> {
+        self.renditionkeyfmt.map(key)
+    }
+    // catalogs seen so far use key semantics 1 or 2, both store every attribute
+    // of the key format explicitly
+    pub fn key_semantics_warning(&self) -> Option<String> {
+        match self.header.key_semantics {
+            1 | 2 => None,
+            key_semantics => Some(format!(
+                "key semantics {} is not known, attributes may be decoded wrongly",
+                key_semantics
+            )),
+        }
+    }
+    pub fn has_key_attribute(&self, attribute: rendition::AttributeType) -> bool {
+        self.renditionkeyfmt.attribute_types.contains(&attribute)
+    }
+    // minimal third-party catalogs leave attributes out of the key format that
+    // entries are built from, say what is used instead
+    pub fn key_format_warnings(&self) -> Vec<String> {
+        [
+            (
+                rendition::AttributeType::Identifier,
+                "names will be derived from rendition names",
+            ),
+            (
+                rendition::AttributeType::Scale,
+                "scales are taken from the csi headers",
+            ),
+            (
+                rendition::AttributeType::Idiom,
+                "entries will have no Idiom",
+            ),
+        ]
+        .into_iter()
+        .filter(|(attribute, _)| !self.has_key_attribute(*attribute))
+        .map(|(attribute, fallback)| {
+            format!("key format has no {:?} attribute; {}", attribute, fallback)
+        })
+        .collect()
+    }
+    pub fn facet_name_warnings(&self) -> Vec<String> {
+        self.raw_facet_names
+            .iter()
+            .map(|(name, bytes)| {
+                format!(
+                    "facet name {} is not UTF-8, using {:?}",
+                    bytes.encode_hex::<String>(),
+                    name
+                )
+            })
+            .chain(
+                self.name_identifier_collisions()
+                    .into_iter()
+                    .map(|(identifier, names)| {
+                        format!("facets {:?} share name identifier {}", names, identifier)
+                    }),
+            )
+            .collect()
+    }
+    // an APPEARANCEKEYS tree, or renditions keyed by a nonzero appearance.
+    // Catalogs built before dark mode have neither
+    pub fn supports_appearances(&self) -> bool {
+        self.appearancedb.is_some()
+            || self.imagedb.keys().any(|key| {
+                self.key_values(key).into_iter().any(|(attribute, value)| {
+                    attribute == rendition::AttributeType::Appearance && value > 0
+                })
+            })
+    }
+    // app thinning records its arguments ("idiom=phone scale=3 ...") in the
+    // extended metadata, unthinned catalogs leave them empty
+    pub fn is_thinned(&self) -> bool {
+        !self.thinning_arguments().trim().is_empty()
+    }
+    // color_space_id counts from 1 in the order of coregraphics::ColorSpace
+    // (1 is sRGB), 0 and unknown ids are treated as sRGB
+    pub fn document_colorspace(&self) -> coregraphics::ColorSpace {
+        self.header
+            .color_space_id
+            .checked_sub(1)
+            .and_then(num_traits::FromPrimitive::from_u32)
+            .unwrap_or(coregraphics::ColorSpace::SRGB)
+    }
+    // the stored timestamp, or the file timestamp if the Assets.car file doesn't have one
+    pub fn timestamp(&self) -> i64 {
+        match self.header.storage_timestamp {
+            0 => self.file_timestamp.unwrap_or(0),
+            storage_timestamp => storage_timestamp as i64,
+        }
+    }
+    // problems with the stored timestamp, `now` is in seconds since the epoch
+    pub fn timestamp_warnings(&self, now: i64) -> Vec<String> {
+        // 2007-01-01T00:00:00Z, older than any asset catalog
+        static EARLIEST_TIMESTAMP: i64 = 1167609600;
+
+        let storage_timestamp = self.header.storage_timestamp as i64;
+        let mut warnings = vec![];
+        if storage_timestamp == 0 {
+            return warnings;
+        }
+        if storage_timestamp > now {
+            warnings.push(format!(
+                "stored timestamp {} is in the future",
+                storage_timestamp
+            ));
+        }
+        if storage_timestamp < EARLIEST_TIMESTAMP {
+            warnings.push(format!(
+                "stored timestamp {} is before 2007",
+                storage_timestamp
+            ));
+        }
+        if storage_timestamp > i32::MAX as i64 {
+            warnings.push(format!(
+                "stored timestamp {} is past the 2038 rollover of a signed 32 bit field",
+                storage_timestamp
+            ));
+        }
+        warnings
+    }
+    // name of the packed image (atlas) an internal link rendition is stored in
+    pub fn packed_asset_name(&self, csi_header: &csi::Header) -> Option<String> {
+        self.packed_image(csi_header)
+            .map(|(_, packed_image)| packed_image.csimetadata.name())
+    }
+    // looked up once for a catalog, instead of scanning imagedb for the
+    // renditions of each image set
+    pub fn renditions_by_identifier(&self) -> IdentifierIndex<'_> {
+        let mut by_identifier: IdentifierIndex = BTreeMap::new();
+        for (rendition_key, csi_header) in &self.imagedb {
+            if let Some((_, identifier)) = self
+                .key_values(rendition_key)
+                .into_iter()
+                .find(|(attribute, _)| *attribute == rendition::AttributeType::Identifier)
+            {
+                by_identifier
+                    .entry(identifier)
+                    .or_default()
+                    .push((rendition_key, csi_header));
+            }
+        }
+        by_identifier
+    }
+    // key and header of the packed image an internal link rendition is stored in
+    pub fn packed_image(
+        &self,
+        csi_header: &csi::Header,
+    ) -> Option<(&rendition::Key, &csi::Header)> {
+        match &csi_header.rendition_data {
+            Some(rendition::Rendition::InternalLink { key, .. }) => {
+                self.imagedb.get_key_value(&self.renditionkeyfmt.key(key))
+            }
+            _ => None,
+        }
+    }
+    // decodes a rendition, internal links are cropped out of their packed
+    // image. The packed image is decoded on every call, extract keeps them
+    // around for the renditions it links
+    pub fn decode_rendition(
+        &self,
+        csi_header: &csi::Header,
+        options: &ParseOptions,
+    ) -> Result<csi::DecodedRendition> {
+        let Some(frame) = csi_header
+            .rendition_data
+            .as_ref()
+            .and_then(|rendition_data| rendition_data.internal_link_frame())
+        else {
+            return csi_header.decode_with_options(options);
+        };
+        let (_, packed_image) = self
+            .packed_image(csi_header)
+            .with_context(|| missing_packed_image(csi_header))?;
+        packed_image
+            .decode_with_options(options)?
+            .cropped(frame)
+            .with_context(|| format!("Unable to unpack {:?}", csi_header.csimetadata.name()))
+    }
+    // looked up ignoring case like CoreUI does, an exact match wins
+    pub fn facet(&self, name: &str) -> Option<&rendition::KeyToken> {
+        self.facet_with_case(name, false)
+    }
+    pub fn facet_with_case(
+        &self,
+        name: &str,
+        case_sensitive: bool,
+    ) -> Option<&rendition::KeyToken> {
+        if let Some(key_token) = self.facetkeysdb.get(name) {
+            return Some(key_token);
+        }
+        if case_sensitive {
+            return None;
+        }
+        let name = FacetName::new(name);
+        self.facetkeysdb
+            .iter()
+            .find(|(facet_name, _)| name.matches(facet_name))
+            .map(|(_, key_token)| key_token)
+    }
+    pub fn facet_names(&self) -> Vec<&str> {
+        self.facetkeysdb.keys().map(|name| name.as_str()).collect()
+    }
+    // facets sharing a name identifier keep the first name in FACETKEYS order,
+    // facet_names_for_key tells them apart
+    pub fn name_identifier_to_facet_name(&self) -> HashMap<u16, String> {
+        let mut names = HashMap::new();
+        for (name, key_token) in &self.facetkeysdb {
+            if let Some(identifier) =
+                key_token.find_attribute(rendition::AttributeType16::Identifier)
+            {
+                names.entry(identifier).or_insert_with(|| name.to_string());
+            }
+        }
+        names
+    }
+    // name identifiers more than one facet uses, with the names in FACETKEYS
+    // order. Real catalogs have them, the identifiers are only 16 bits
+    pub fn name_identifier_collisions(&self) -> BTreeMap<u16, Vec<String>> {
+        let mut names: BTreeMap<u16, Vec<String>> = BTreeMap::new();
+        for (name, key_token) in &self.facetkeysdb {
+            if let Some(identifier) =
+                key_token.find_attribute(rendition::AttributeType16::Identifier)
+            {
+                names.entry(identifier).or_default().push(name.to_string());
+            }
+        }
+        names.retain(|_, names| names.len() > 1);
+        names
+    }
+    // the facets a rendition can belong to: the ones with its name identifier,
+    // narrowed to those whose other token attributes (element, part, ...) the
+    // key has too. More than one name when that doesn't settle it
+    pub fn facet_names_for_key(&self, key: &rendition::Key) -> Vec<String> {
+        let key_values = self.key_values(key);
+        let Some(identifier) = key_values
+            .iter()
+            .find(|(attribute, _)| *attribute == rendition::AttributeType::Identifier)
+            .map(|(_, value)| *value)
+        else {
+            return vec![];
+        };
+        let candidates: Vec<(&String, &rendition::KeyToken)> = self
+            .facetkeysdb
+            .iter()
+            .filter(|(_, key_token)| {
+                key_token.find_attribute(rendition::AttributeType16::Identifier) == Some(identifier)
+            })
+            .collect();
+        // attributes the key format doesn't have can't rule a facet out
+        let matching: Vec<&String> = candidates
+            .iter()
+            .filter(|(_, key_token)| {
+                key_token.attributes.iter().all(|token_attribute| {
+                    key_values
+                        .iter()
+                        .find(|(attribute, _)| {
+                            *attribute == rendition::AttributeType::from(token_attribute.name)
+                        })
+                        .is_none_or(|(_, value)| *value == token_attribute.value)
+                })
+            })
+            .map(|(name, _)| *name)
+            .collect();
+        if matching.is_empty() {
+            candidates
+                .into_iter()
+                .map(|(name, _)| name.clone())
+                .collect()
+        } else {
+            matching.into_iter().cloned().collect()
+        }
+    }
+    // BITMAPKEYS as read, or one key per facet derived from the scales of
+    // its renditions for stores that were built in memory
+    pub fn bitmap_keys(&self) -> Vec<(NameIdentifier, bitmap::Key)> {
+        if let Some(bitmapkeydb) = &self.bitmapkeydb {
+            return bitmapkeydb.clone();
+        }
+        let mut scales: BTreeMap<NameIdentifier, Vec<u16>> = self
+            .facetkeysdb
+            .values()
+            .filter_map(|key_token| {
+                key_token.find_attribute(rendition::AttributeType16::Identifier)
+            })
+            .map(|identifier| (identifier as NameIdentifier, vec![]))
+            .collect();
+        for key in self.imagedb.keys() {
+            let key_values = self.key_values(key);
+            let value = |attribute| {
+                key_values
+                    .iter()
+                    .find(|(key_attribute, _)| *key_attribute == attribute)
+                    .map(|(_, value)| *value)
+            };
+            if let Some(identifier) = value(rendition::AttributeType::Identifier) {
+                if let Some(scales) = scales.get_mut(&(identifier as NameIdentifier)) {
+                    scales.push(value(rendition::AttributeType::Scale).unwrap_or(0));
+                }
+            }
+        }
+        scales
+            .into_iter()
+            .map(|(identifier, scales)| (identifier, bitmap::Key::for_scales(scales)))
+            .collect()
+    }
+    // APPEARANCEKEYS plus a placeholder name for every nonzero appearance id
+    // used by a rendition key but missing from it, so each entry's Appearance
+    // is listed in the header
+    pub fn appearance_names(&self) -> Option<BTreeMap<String, u32>> {
+        let mut appearances = self.appearancedb.clone();
+        for key in self.imagedb.keys() {
+            let id = self
+                .key_values(key)
+                .into_iter()
+                .find(|(attribute, _)| *attribute == rendition::AttributeType::Appearance)
+                .map(|(_, value)| value as u32);
+            let id = match id {
+                Some(id) if id > 0 => id,
+                _ => continue,
+            };
+            let appearances = appearances.get_or_insert_with(BTreeMap::new);
+            if !appearances.values().any(|value| *value == id) {
+                appearances.insert(unknown_appearance_name(id), id);
+            }
+        }
+        appearances
     }
 }
 
@@ -369,24 +1364,30 @@ impl CarHeader {
         schema_version: u32,
         color_space_id: u32,
         key_semantics: u32,
-    ) -> Self {
-        CarHeader {
+    ) -> Result<Self> {
+        Ok(CarHeader {
             magic: 0x43544152,
             core_ui_version,
             storage_version,
             storage_timestamp,
             rendition_count,
-            main_version_string: common::str_to_sized_slice128(main_version_string),
-            version_string: common::str_to_sized_slice256(version_string),
+            main_version_string: common::str_to_sized_slice(
+                "main version string",
+                main_version_string,
+            )?,
+            version_string: common::str_to_sized_slice("version string", version_string)?,
             uuid,
             associated_checksum,
             schema_version,
             color_space_id,
             key_semantics,
-        }
+        })
     }
 }
 
+common::compact_debug!(CarHeader, CarExtendedMetadata);
+
+#[cfg(feature = "debug-impls")]
 impl Debug for CarHeader {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("CarHeader")
@@ -428,17 +1429,27 @@ impl CarExtendedMetadata {
         deployment_platform_version: &str,
         deployment_platform: &str,
         authoring_tool: &str,
-    ) -> Self {
-        CarExtendedMetadata {
+    ) -> Result<Self> {
+        Ok(CarExtendedMetadata {
             magic: 0x4154454D,
-            thinning_arguments: common::str_to_sized_slice256(thinning_arguments),
-            deployment_platform_version: common::str_to_sized_slice256(deployment_platform_version),
-            deployment_platform: common::str_to_sized_slice256(deployment_platform),
-            authoring_tool: common::str_to_sized_slice256(authoring_tool),
-        }
+            thinning_arguments: common::str_to_sized_slice(
+                "thinning arguments",
+                thinning_arguments,
+            )?,
+            deployment_platform_version: common::str_to_sized_slice(
+                "deployment platform version",
+                deployment_platform_version,
+            )?,
+            deployment_platform: common::str_to_sized_slice(
+                "deployment platform",
+                deployment_platform,
+            )?,
+            authoring_tool: common::str_to_sized_slice("authoring tool", authoring_tool)?,
+        })
     }
 }
 
+#[cfg(feature = "debug-impls")]
 impl Debug for CarExtendedMetadata {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("CarExtendedMetadata")
@@ -462,3 +1473,11 @@ impl Debug for CarExtendedMetadata {
             .finish()
     }
 }
+
+// the error for an internal link whose packed image isn't in the catalog
+pub(crate) fn missing_packed_image(csi_header: &csi::Header) -> String {
+    format!(
+        "{:?} links to a packed image that isn't in the catalog",
+        csi_header.csimetadata.name()
+    )
+}
@@ -0,0 +1,292 @@
+use anyhow::Context;
+use anyhow::Result;
+use binrw::BinRead;
+use std::cell::RefCell;
+use std::io::Cursor;
+
+use super::rendition;
+use super::rendition::CompressionType;
+
+// scratch state kept between decodes on one thread: the lzfse decoder's
+// tables and the buffer decoders write their output into. Buffers that grew
+// past max_retained_bytes are dropped once the output is no longer borrowed,
+// so one huge asset doesn't keep its memory for the rest of a run
+pub struct DecodeContext {
+    lzfse: lzfse_rust::LzfseDecoder,
+    scratch: Vec<u8>,
+    max_retained_bytes: usize,
+}
+
+impl Default for DecodeContext {
+    fn default() -> Self {
+        Self::with_max_retained_bytes(Self::MAX_RETAINED_BYTES)
+    }
+}
+
+thread_local! {
+    static THREAD_CONTEXT: RefCell<DecodeContext> = RefCell::new(DecodeContext::default());
+}
+
+impl DecodeContext {
+    pub const MAX_RETAINED_BYTES: usize = 64 * 1024 * 1024;
+
+    pub fn with_max_retained_bytes(max_retained_bytes: usize) -> Self {
+        DecodeContext {
+            lzfse: lzfse_rust::LzfseDecoder::default(),
+            scratch: Vec::new(),
+            max_retained_bytes,
+        }
+    }
+
+    // runs f with this thread's context, what Codec::decode uses
+    pub(crate) fn with_thread_context<T>(f: impl FnOnce(&mut DecodeContext) -> T) -> T {
+        THREAD_CONTEXT.with(|context| {
+            let mut context = context.borrow_mut();
+            let result = f(&mut context);
+            context.release();
+            result
+        })
+    }
+
+    // bytes of scratch space held for the next decode
+    pub fn retained_capacity(&self) -> usize {
+        self.scratch.capacity()
+    }
+
+    // lzfse decodes input into the scratch buffer, replacing its contents
+    fn decode_lzfse(&mut self, input: &[u8], capacity: usize) -> Result<()> {
+        self.scratch.clear();
+        self.scratch.reserve(capacity);
+        if let Err(err) = self.lzfse.decode_bytes(input, &mut self.scratch) {
+            self.release();
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    pub fn release(&mut self) {
+        if self.scratch.capacity() > self.max_retained_bytes {
+            self.scratch = Vec::new();
+        }
+    }
+}
+
+// output of Codec::decode_with, borrowed from the context's buffer or, when
+// there was nothing to decode, from the input. Dropping it applies the
+// context's retention bound
+pub struct Decoded<'a> {
+    source: Source<'a>,
+}
+
+enum Source<'a> {
+    Input(&'a [u8]),
+    Context(&'a mut DecodeContext),
+}
+
+impl std::ops::Deref for Decoded<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.source {
+            Source::Input(input) => input,
+            Source::Context(context) => &context.scratch,
+        }
+    }
+}
+
+impl Drop for Decoded<'_> {
+    fn drop(&mut self) {
+        if let Source::Context(context) = &mut self.source {
+            context.release();
+        }
+    }
+}
+
+pub trait Codec: Sync {
+    // expected_len is the decoded size when the caller knows it (e.g. from
+    // the rendition's width and height), decoders use it to validate output
+    fn decode_with<'a>(
+        &self,
+        context: &'a mut DecodeContext,
+        input: &'a [u8],
+        expected_len: Option<usize>,
+    ) -> Result<Decoded<'a>>;
+
+    // an owned copy of the output, decoded with this thread's context
+    fn decode(&self, input: &[u8], expected_len: Option<usize>) -> Result<Vec<u8>> {
+        DecodeContext::with_thread_context(|context| {
+            Ok(self.decode_with(context, input, expected_len)?.to_vec())
+        })
+    }
+}
+
+pub fn codec_for(compression_type: CompressionType) -> Option<&'static dyn Codec> {
+    match compression_type {
+        CompressionType::Uncompressed => Some(&UncompressedCodec),
+        // lzvn payloads are "bvxn" blocks in the same container as lzfse
+        CompressionType::LZFSE | CompressionType::LZVN | CompressionType::JPEGLZFSE => {
+            Some(&LzfseCodec)
+        }
+        #[cfg(feature = "zlib")]
+        CompressionType::ZIP => Some(&ZipCodec),
+        CompressionType::ASTC => Some(&AstcCodec),
+        CompressionType::PaletteImg => Some(&PaletteImgCodec),
+        _ => None,
+    }
+}
+
+// which compression types this build is able to decode
+pub fn capabilities() -> Vec<(CompressionType, bool)> {
+    CompressionType::ALL
+        .iter()
+        .map(|compression_type| (*compression_type, codec_for(*compression_type).is_some()))
+        .collect()
+}
+
+// room to reserve before decoding. expected_len comes from the file, so it
+// only counts up to a generous compression ratio and the buffer grows past
+// that if the data really is that compressible
+fn initial_capacity(input: &[u8], expected_len: Option<usize>) -> usize {
+    const MAX_RATIO: usize = 64;
+    let bound = input.len().saturating_mul(MAX_RATIO);
+    expected_len.unwrap_or(input.len() * 4).min(bound)
+}
+
+fn check_length(output: Decoded, expected_len: Option<usize>) -> Result<Decoded> {
+    match expected_len {
+        Some(expected_len) if expected_len != output.len() => None.context(format!(
+            "decoded {} bytes, expected {} bytes",
+            output.len(),
+            expected_len
+        )),
+        _ => Ok(output),
+    }
+}
+
+pub struct UncompressedCodec;
+
+impl Codec for UncompressedCodec {
+    fn decode_with<'a>(
+        &self,
+        _context: &'a mut DecodeContext,
+        input: &'a [u8],
+        expected_len: Option<usize>,
+    ) -> Result<Decoded<'a>> {
+        let output = Decoded {
+            source: Source::Input(input),
+        };
+        check_length(output, expected_len)
+    }
+}
+
+pub struct LzfseCodec;
+
+impl Codec for LzfseCodec {
+    fn decode_with<'a>(
+        &self,
+        context: &'a mut DecodeContext,
+        input: &'a [u8],
+        expected_len: Option<usize>,
+    ) -> Result<Decoded<'a>> {
+        context.decode_lzfse(input, initial_capacity(input, expected_len))?;
+        let output = Decoded {
+            source: Source::Context(context),
+        };
+        check_length(output, expected_len)
+    }
+}
+
+#[cfg(feature = "zlib")]
+pub struct ZipCodec;
+
+#[cfg(feature = "zlib")]
+impl Codec for ZipCodec {
+    fn decode_with<'a>(
+        &self,
+        context: &'a mut DecodeContext,
+        input: &'a [u8],
+        expected_len: Option<usize>,
+    ) -> Result<Decoded<'a>> {
+        use std::io::Read;
+
+        context.scratch.clear();
+        context
+            .scratch
+            .reserve(initial_capacity(input, expected_len));
+        // libcompression writes raw deflate, but accept zlib framed data too
+        let result = if input.first() == Some(&0x78) {
+            flate2::read::ZlibDecoder::new(input).read_to_end(&mut context.scratch)
+        } else {
+            flate2::read::DeflateDecoder::new(input).read_to_end(&mut context.scratch)
+        };
+        let output = Decoded {
+            source: Source::Context(context),
+        };
+        result?;
+        check_length(output, expected_len)
+    }
+}
+
+pub struct AstcCodec;
+
+impl Codec for AstcCodec {
+    fn decode_with<'a>(
+        &self,
+        context: &'a mut DecodeContext,
+        input: &'a [u8],
+        expected_len: Option<usize>,
+    ) -> Result<Decoded<'a>> {
+        // first 12 bytes are a header??
+        let payload = input
+            .get(12..)
+            .context("ASTC payload is missing its header")?;
+        LzfseCodec.decode_with(context, payload, expected_len)
+    }
+}
+
+// decodes to RGBA, so expected_len (width * height * 4) is required
+pub struct PaletteImgCodec;
+
+impl PaletteImgCodec {
+    // the lzfse wrapped color table and indices, before expanding to RGBA
+    pub fn quantized_image(input: &[u8], pixel_count: u32) -> Result<rendition::QuantizedImage> {
+        DecodeContext::with_thread_context(|context| {
+            Self::quantized_image_with(context, input, pixel_count)
+        })
+    }
+
+    fn quantized_image_with(
+        context: &mut DecodeContext,
+        input: &[u8],
+        pixel_count: u32,
+    ) -> Result<rendition::QuantizedImage> {
+        context.decode_lzfse(input, initial_capacity(input, None))?;
+        let mut reader = Cursor::new(&context.scratch);
+        let quantized_image = rendition::QuantizedImage::read_args(&mut reader, (pixel_count, 1))?;
+        Ok(quantized_image)
+    }
+}
+
+impl Codec for PaletteImgCodec {
+    fn decode_with<'a>(
+        &self,
+        context: &'a mut DecodeContext,
+        input: &'a [u8],
+        expected_len: Option<usize>,
+    ) -> Result<Decoded<'a>> {
+        let expected_len = expected_len.context("palette-img needs the decoded image size")?;
+        let pixel_count = u32::try_from(expected_len / 4)
+            .with_context(|| format!("{} bytes is too big for a palette-img", expected_len))?;
+        let quantized_image = PaletteImgCodec::quantized_image_with(context, input, pixel_count)?;
+        // the indices are parsed out, so the buffer takes the RGBA pixels
+        context.scratch.clear();
+        context.scratch.resize(expected_len, 0);
+        let result = quantized_image.extract(&mut context.scratch);
+        // wrapped before returning errors so the retention bound applies
+        let output = Decoded {
+            source: Source::Context(context),
+        };
+        result?;
+        Ok(output)
+    }
+}
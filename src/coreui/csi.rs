@@ -3,24 +3,31 @@ use anyhow::Result;
 use binrw::BinRead;
 use binrw::BinWrite;
 use chrono::NaiveDateTime;
-use num_derive::FromPrimitive;
+use hex::ToHex;
 use num_traits::FromPrimitive;
 use serde::Serialize;
+use serde::Serializer;
+use sha2::Digest;
+use sha2::Sha256;
+use std::borrow::Cow;
 use std::fmt::Debug;
 use std::fs;
-use std::fs::File;
-use std::io::BufWriter;
 use std::io::Cursor;
 use std::path::Path;
+use std::str::FromStr;
 
 use crate::common;
 use crate::coregraphics;
 
+use super::codec;
 use super::csi;
+use super::deep_color;
 use super::rendition;
 use super::rendition::CompressionType;
 use super::rendition::TemplateMode;
 use super::tlv;
+use super::CarError;
+use super::ParseOptions;
 
 #[derive(BinRead, BinWrite, Clone)]
 #[brw(little)]
@@ -31,11 +38,24 @@ pub struct Metadata {
 }
 
 impl Metadata {
+    pub const NAME_LENGTH: usize = 128;
+
+    pub fn new(mod_time: u32, layout: rendition::LayoutType32, name: &str) -> Result<Self> {
+        Ok(Metadata {
+            mod_time,
+            layout,
+            name: common::str_to_sized_slice("rendition name", name)?,
+        })
+    }
+
     pub fn name(&self) -> String {
         common::parse_padded_string(&self.name)
     }
 }
 
+common::compact_debug!(Metadata);
+
+#[cfg(feature = "debug-impls")]
 impl Debug for Metadata {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Metadata")
@@ -57,14 +77,33 @@ pub struct Bitmap {
     pub data: common::RawData,
 }
 
+// CoreUI's _csibitmaplist preceded by the tlv length. Every catalog seen so far
+// has one bitmap and a zero reserved field, other values are kept as read
 #[derive(BinRead, BinWrite, Debug, Clone)]
 pub struct BitmapList {
     pub tlv_length: u32,
-    pub unknown: u32, // usually 1?
-    pub zero: u32,    // usually 0?
+    pub bitmap_count: u32,
+    pub reserved: u32,
     pub rendition_length: u32,
 }
 
+impl BitmapList {
+    pub const EXPECTED_BITMAP_COUNT: u32 = 1;
+    pub const EXPECTED_RESERVED: u32 = 0;
+
+    pub fn is_expected(&self) -> bool {
+        self.bitmap_count == BitmapList::EXPECTED_BITMAP_COUNT
+            && self.reserved == BitmapList::EXPECTED_RESERVED
+    }
+
+    // whether a rendition's SizeOnDisk, known before its tlvs and payload
+    // are read, is above max_payload_bytes
+    pub fn over_limit(&self, max_payload_bytes: Option<u64>) -> bool {
+        let size = Header::HEADER_SIZE + self.tlv_length as u64 + self.rendition_length as u64;
+        max_payload_bytes.is_some_and(|limit| size > limit)
+    }
+}
+
 /*
 struct cuithemerenditionrenditionflags {
   isVectorBased x0;
@@ -118,14 +157,168 @@ impl RenditionFlags {
     }
 }
 
-#[derive(BinRead, BinWrite, Debug, Clone, Copy, Serialize, FromPrimitive)]
-#[brw(repr(u32))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PixelFormat {
-    None = 0,
-    ARGB = 0x41524742,
-    Data = 0x44415441,
-    Gray = 0x47413820,
-    JPEG = 0x4A504547,
+    None,
+    ARGB,
+    Data,
+    Gray,
+    JPEG,
+    // deep color, 16 bit integer ('RGBW') or half float ('RGBH') components
+    ARGB16,
+    ARGBHalf,
+    // 16 bit integer gray and alpha ('GA16')
+    Gray16,
+    // fourcc we don't have a name for yet
+    Unknown(u32),
+}
+
+impl PixelFormat {
+    pub fn from_fourcc(value: u32) -> PixelFormat {
+        match value {
+            0 => PixelFormat::None,
+            0x41524742 => PixelFormat::ARGB,
+            0x44415441 => PixelFormat::Data,
+            0x47413820 => PixelFormat::Gray,
+            0x4A504547 => PixelFormat::JPEG,
+            0x52474257 => PixelFormat::ARGB16,
+            0x52474248 => PixelFormat::ARGBHalf,
+            0x47413136 => PixelFormat::Gray16,
+            _ => PixelFormat::Unknown(value),
+        }
+    }
+
+    pub fn fourcc(&self) -> u32 {
+        match self {
+            PixelFormat::None => 0,
+            PixelFormat::ARGB => 0x41524742,
+            PixelFormat::Data => 0x44415441,
+            PixelFormat::Gray => 0x47413820,
+            PixelFormat::JPEG => 0x4A504547,
+            PixelFormat::ARGB16 => 0x52474257,
+            PixelFormat::ARGBHalf => 0x52474248,
+            PixelFormat::Gray16 => 0x47413136,
+            PixelFormat::Unknown(value) => *value,
+        }
+    }
+
+    // BitsPerComponent of the decoded image
+    pub fn bits_per_component(&self) -> u32 {
+        match self.deep_component_type() {
+            Some(_) => 16,
+            None => 8,
+        }
+    }
+
+    pub fn deep_component_type(&self) -> Option<deep_color::ComponentType> {
+        match self {
+            PixelFormat::ARGB16 | PixelFormat::Gray16 => {
+                Some(deep_color::ComponentType::Unsigned16)
+            }
+            PixelFormat::ARGBHalf => Some(deep_color::ComponentType::Half),
+            _ => None,
+        }
+    }
+
+    // components per pixel, alpha is always the last one
+    pub fn channels(&self) -> usize {
+        match self {
+            PixelFormat::Gray | PixelFormat::Gray16 => 2,
+            _ => 4,
+        }
+    }
+
+    // how Encoding prints it, the fourcc for formats without a name
+    pub fn name(&self) -> String {
+        match self {
+            PixelFormat::Unknown(value) => common::fourcc_label(*value),
+            _ => format!("{:?}", self),
+        }
+    }
+
+    // fourcc as ASCII if every byte is printable, e.g. "GA16", otherwise None
+    pub fn fourcc_string(&self) -> Option<String> {
+        let bytes = self.fourcc().to_be_bytes();
+        if bytes.iter().all(|b| (0x20..0x7f).contains(b)) {
+            Some(String::from_utf8_lossy(&bytes).to_string())
+        } else {
+            None
+        }
+    }
+}
+
+impl BinRead for PixelFormat {
+    type Args<'a> = ();
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let value = u32::read_options(reader, endian, args)?;
+        Ok(PixelFormat::from_fourcc(value))
+    }
+}
+
+impl BinWrite for PixelFormat {
+    type Args<'a> = ();
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.fourcc().write_options(writer, endian, args)
+    }
+}
+
+impl Serialize for PixelFormat {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.name())
+    }
+}
+
+impl FromStr for PixelFormat {
+    type Err = anyhow::Error;
+
+    // accepts the names used in the JSON output ("ARGB", "Gray"), a raw
+    // fourcc ("GA8 ", "RGB5") or a hex value ("0x47413136")
+    fn from_str(s: &str) -> Result<Self> {
+        let named = [
+            PixelFormat::None,
+            PixelFormat::ARGB,
+            PixelFormat::Data,
+            PixelFormat::Gray,
+            PixelFormat::JPEG,
+            PixelFormat::ARGB16,
+            PixelFormat::ARGBHalf,
+            PixelFormat::Gray16,
+        ]
+        .into_iter()
+        .find(|pixel_format| format!("{:?}", pixel_format).eq_ignore_ascii_case(s));
+        if let Some(pixel_format) = named {
+            return Ok(pixel_format);
+        }
+
+        if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            let value = u32::from_str_radix(hex, 16)
+                .context(format!("invalid pixel format value {:?}", s))?;
+            return Ok(PixelFormat::from_fourcc(value));
+        }
+
+        // short fourccs like "GA8" are space padded
+        if !s.is_empty() && s.len() <= 4 && s.bytes().all(|b| (0x20..0x7f).contains(&b)) {
+            let mut bytes = [b' '; 4];
+            bytes[..s.len()].copy_from_slice(s.as_bytes());
+            return Ok(PixelFormat::from_fourcc(u32::from_be_bytes(bytes)));
+        }
+
+        None.context(format!("unknown pixel format {:?}", s))
+    }
 }
 
 #[derive(BinRead, BinWrite, Debug, Clone)]
@@ -139,8 +332,263 @@ impl ColorModel {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Pdf,
+    Svg,
+    // only written for --preserve-extended
+    Exr,
+    Data,
+    // a decoded payload that isn't what its compression promised, always
+    // written as .bin
+    Bin,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 7] = [
+        OutputFormat::Png,
+        OutputFormat::Jpeg,
+        OutputFormat::Pdf,
+        OutputFormat::Svg,
+        OutputFormat::Exr,
+        OutputFormat::Data,
+        OutputFormat::Bin,
+    ];
+
+    // looks at the payload's signature first, then the declared pixel format
+    pub fn sniff(data: &[u8], pixel_format: PixelFormat) -> OutputFormat {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            OutputFormat::Png
+        } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            OutputFormat::Jpeg
+        } else if data.starts_with(b"%PDF") {
+            OutputFormat::Pdf
+        } else if is_svg(data) {
+            OutputFormat::Svg
+        } else if pixel_format == PixelFormat::JPEG {
+            OutputFormat::Jpeg
+        } else {
+            OutputFormat::Data
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Pdf => "pdf",
+            OutputFormat::Svg => "svg",
+            OutputFormat::Exr => "exr",
+            OutputFormat::Data => "dat",
+            OutputFormat::Bin => "bin",
+        }
+    }
+
+    // rendition names without an extension get one for the detected format
+    pub fn file_name(&self, rendition_name: &str) -> String {
+        // "a.png" converted to exr is "a.exr"
+        if *self == OutputFormat::Exr || *self == OutputFormat::Bin {
+            return Path::new(rendition_name)
+                .with_extension(self.extension())
+                .to_string_lossy()
+                .to_string();
+        }
+        if Path::new(rendition_name).extension().is_some() {
+            rendition_name.to_string()
+        } else {
+            format!("{}.{}", rendition_name, self.extension())
+        }
+    }
+}
+
+// svg text, possibly after a byte order mark, whitespace, an xml
+// declaration or comments
+fn is_svg(data: &[u8]) -> bool {
+    let start = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+    let start = match start.iter().position(|byte| !byte.is_ascii_whitespace()) {
+        Some(index) => &start[index..],
+        None => return false,
+    };
+    if start.starts_with(b"<svg") {
+        return true;
+    }
+    // the root element has to show up early in the file
+    start.starts_with(b"<?xml")
+        && start[..start.len().min(1024)]
+            .windows(4)
+            .any(|window| window == b"<svg")
+}
+
+pub struct ExtractedFile {
+    pub format: OutputFormat,
+    pub data: Vec<u8>,
+    // written, but not exactly as stored
+    pub warning: Option<String>,
+}
+
+// an image rendition decoded in memory, what extract encodes into a file.
+// Pixels are as stored, premultiplied and without a transfer function applied
+pub enum DecodedRendition {
+    Rgba {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+    // GA8 images, gray and alpha
+    GrayAlpha {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+    Deep(deep_color::DeepPixels),
+    Jpeg(Vec<u8>),
+    // a file stored whole: png, pdf, svg, or data without a known signature
+    File {
+        format: OutputFormat,
+        data: Vec<u8>,
+    },
+    // a jpeg-lzfse payload that didn't decode to a jpeg
+    Raw(Vec<u8>),
+}
+
+impl DecodedRendition {
+    // straight alpha pixels for viewers that don't expect premultiplied ones,
+    // renditions that aren't pixels are returned as they are
+    pub fn unpremultiplied(mut self) -> Self {
+        match &mut self {
+            DecodedRendition::Rgba { pixels, .. } => unpremultiply(pixels, 4),
+            DecodedRendition::GrayAlpha { pixels, .. } => unpremultiply(pixels, 2),
+            DecodedRendition::Deep(pixels) => pixels.unpremultiply(),
+            _ => {}
+        }
+        self
+    }
+
+    // the (x, y, width, height) region of decoded pixels, what a packed image
+    // (atlas) holds for each rendition linked into it
+    pub fn cropped(&self, frame: (u32, u32, u32, u32)) -> Result<Self> {
+        let (x, y, width, height) = frame;
+        let within = |image_width: u32, image_height: u32| {
+            if x as u64 + width as u64 > image_width as u64
+                || y as u64 + height as u64 > image_height as u64
+            {
+                anyhow::bail!(
+                    "{}x{} frame at ({}, {}) is outside the {}x{} image",
+                    width,
+                    height,
+                    x,
+                    y,
+                    image_width,
+                    image_height
+                );
+            }
+            Ok(image_width as usize)
+        };
+        fn crop<T: Copy>(
+            pixels: &[T],
+            row_length: usize,
+            channels: usize,
+            (x, y, width, height): (u32, u32, u32, u32),
+        ) -> Vec<T> {
+            pixels
+                .chunks_exact(row_length * channels)
+                .skip(y as usize)
+                .take(height as usize)
+                .flat_map(|row| &row[x as usize * channels..(x + width) as usize * channels])
+                .copied()
+                .collect()
+        }
+        Ok(match self {
+            DecodedRendition::Rgba {
+                width: image_width,
+                height: image_height,
+                pixels,
+            } => DecodedRendition::Rgba {
+                width,
+                height,
+                pixels: crop(pixels, within(*image_width, *image_height)?, 4, frame),
+            },
+            DecodedRendition::GrayAlpha {
+                width: image_width,
+                height: image_height,
+                pixels,
+            } => DecodedRendition::GrayAlpha {
+                width,
+                height,
+                pixels: crop(pixels, within(*image_width, *image_height)?, 2, frame),
+            },
+            DecodedRendition::Deep(pixels) => DecodedRendition::Deep(deep_color::DeepPixels {
+                width,
+                height,
+                channels: pixels.channels,
+                components: crop(
+                    &pixels.components,
+                    within(pixels.width, pixels.height)?,
+                    pixels.channels,
+                    frame,
+                ),
+            }),
+            _ => anyhow::bail!("only bitmaps can be cropped"),
+        })
+    }
+
+    // the file extract writes, warnings are about the rendition called name
+    pub fn into_extracted_file(self, name: &str) -> Result<ExtractedFile> {
+        let file = |format, data| ExtractedFile {
+            format,
+            data,
+            warning: None,
+        };
+        Ok(match self {
+            DecodedRendition::Rgba {
+                width,
+                height,
+                pixels,
+            } => file(OutputFormat::Png, encode_png(width, height, &pixels)?),
+            DecodedRendition::GrayAlpha {
+                width,
+                height,
+                pixels,
+            } => file(
+                OutputFormat::Png,
+                encode_png_as(width, height, png::ColorType::GrayscaleAlpha, &pixels)?,
+            ),
+            DecodedRendition::Deep(pixels) => {
+                let extended = pixels.extended_count();
+                ExtractedFile {
+                    format: OutputFormat::Png,
+                    data: pixels.encode_png()?,
+                    warning: (extended > 0).then(|| {
+                        format!(
+                            "{}: clamped {} extended range components to 16 bit, --preserve-extended writes an exr",
+                            name, extended
+                        )
+                    }),
+                }
+            }
+            DecodedRendition::Jpeg(data) => file(OutputFormat::Jpeg, data),
+            DecodedRendition::File { format, data } => file(format, data),
+            DecodedRendition::Raw(data) => ExtractedFile {
+                warning: Some(format!(
+                    "{}: jpeg-lzfse payload isn't a jpeg (starts with {}), wrote the decoded bytes",
+                    name,
+                    (&data[..data.len().min(8)]).encode_hex::<String>()
+                )),
+                format: OutputFormat::Bin,
+                data,
+            },
+        })
+    }
+}
+
+// headers read with a max_payload_bytes limit skip the tlvs and payload of
+// renditions over it, they are never allocated. Decoding those fails with
+// PayloadTooLarge like it would have anyway
 #[derive(BinRead, BinWrite, Debug, Clone)]
 #[brw(little, magic = b"ISTC")]
+#[br(import(max_payload_bytes: Option<u64>))]
 pub struct Header {
     pub version: u32,
     pub rendition_flags: RenditionFlags,
@@ -151,9 +599,10 @@ pub struct Header {
     pub color_space: ColorModel,
     pub csimetadata: Metadata,
     pub csibitmaplist: BitmapList,
-    #[br(count = csibitmaplist.tlv_length)]
+    #[br(count = if csibitmaplist.over_limit(max_payload_bytes) { 0 } else { csibitmaplist.tlv_length })]
     pub tlv_data: common::RawData,
-    #[brw(if(csibitmaplist.rendition_length > 0))]
+    #[br(if(csibitmaplist.rendition_length > 0 && !csibitmaplist.over_limit(max_payload_bytes)))]
+    #[bw(if(csibitmaplist.rendition_length > 0))]
     pub rendition_data: Option<rendition::Rendition>,
 }
 
@@ -167,113 +616,656 @@ impl Header {
         result
     }
 
-    pub fn extract(&self, path: &str) -> Result<Option<String>> {
+    pub fn extract(&self, path: impl AsRef<Path>) -> Result<Option<String>> {
         let name = self.csimetadata.name();
-        let output_path = Path::new(path).join(&name);
+        let extracted_file = match self.extracted_file()? {
+            Some(extracted_file) => extracted_file,
+            None => return Ok(None),
+        };
+        let file_name = common::portable_file_name(&extracted_file.format.file_name(&name));
+        let output_path = path.as_ref().join(&file_name);
         let output_path_str = output_path
             .to_str()
             .context(format!("Unable to get output path for {:?}", name))?;
-        match self.csimetadata.layout {
-            rendition::LayoutType32::Image => match &self.rendition_data {
-                Some(rendition::Rendition::RawData { raw_data, .. }) => {
-                    fs::write(&output_path, raw_data.0.to_owned())?;
-                    Ok(Some(output_path_str.to_string()))
-                }
-                Some(rendition::Rendition::Theme {
+        fs::write(&output_path, &extracted_file.data)?;
+        Ok(Some(output_path_str.to_string()))
+    }
+
+    // the size of the csi header struct, before the tlvs
+    pub const HEADER_SIZE: u64 = 184;
+
+    // what assetutil reports as SizeOnDisk for every layout: the header, the
+    // tlvs and the rendition as stored, compressed payloads included
+    pub fn size_on_disk(&self) -> u64 {
+        Self::HEADER_SIZE
+            + self.csibitmaplist.tlv_length as u64
+            + self.csibitmaplist.rendition_length as u64
+    }
+
+    // the bytes of a data asset, decompressed when it's stored in a CELM
+    // payload. None when this build has no codec for the compression type
+    pub fn data_payload(&self) -> Result<Option<Vec<u8>>> {
+        let rendition_data = match &self.rendition_data {
+            Some(rendition::Rendition::RawData { raw_data, .. })
+            | Some(rendition::Rendition::Svg { raw_data, .. }) => {
+                return Ok(Some(raw_data.0.to_owned()))
+            }
+            Some(rendition_data) => rendition_data,
+            None => return Ok(Some(vec![])),
+        };
+        let (compression_type, raw_data) = rendition_data.theme_payload().with_context(|| {
+            format!(
+                "unhandled data type {:?}, rendition={:?}",
+                self.csimetadata.name(),
+                rendition_data
+            )
+        })?;
+        codec::codec_for(compression_type)
+            .map(|codec| {
+                codec.decode(raw_data, None).context(format!(
+                    "Unable to decode {:?} data {:?}",
                     compression_type,
-                    raw_data,
-                    ..
+                    self.csimetadata.name()
+                ))
+            })
+            .transpose()
+    }
+
+    // PDF backed renditions: the vector layout, or images with the vector
+    // based flag (preserved vector data)
+    pub fn is_vector(&self) -> bool {
+        self.rendition_flags.is_vector_based()
+            || matches!(self.csimetadata.layout, rendition::LayoutType32::Vector)
+    }
+
+    // (width, height) in points at 1x of a vector rendition from the MediaBox
+    // of its PDF, vector renditions store no pixel size of their own
+    pub fn vector_bounds(&self) -> Option<(u32, u32)> {
+        if !self.is_vector() {
+            return None;
+        }
+        let payload = self.data_payload().ok().flatten()?;
+        let (width, height) = pdf_media_box(&payload)?;
+        Some((width.ceil() as u32, height.ceil() as u32))
+    }
+
+    // UTI of data renditions, trimmed at the first NUL
+    // the UTI tlv, CoreSVG renditions are svg images without one
+    pub fn uti(&self) -> Option<String> {
+        self.properties()
+            .iter()
+            .find_map(|rendition_type| rendition_type.uti())
+            .or_else(|| {
+                self.rendition_data
+                    .as_ref()?
+                    .svg_document()
+                    .map(|_| "public.svg-image".to_string())
+            })
+    }
+
+    // the color model of the color space, GA8 and GA16 images are monochrome
+    // whatever their color space says
+    pub fn color_model(&self) -> Option<coregraphics::ColorModel> {
+        match self.pixel_format {
+            PixelFormat::Gray | PixelFormat::Gray16 => Some(coregraphics::ColorModel::Monochrome),
+            _ => self.color_space.color_model(),
+        }
+    }
+
+    // contents of the file extract would write, without touching the filesystem
+    pub fn extracted_file(&self) -> Result<Option<ExtractedFile>> {
+        self.extracted_file_with_options(&ParseOptions::default())
+    }
+
+    pub fn extracted_file_with_options(
+        &self,
+        options: &ParseOptions,
+    ) -> Result<Option<ExtractedFile>> {
+        // only images are decoded, so only they are held to the limit
+        if !matches!(self.csimetadata.layout, rendition::LayoutType32::Image) {
+            return Ok(None);
+        }
+        self.decode_with_options(options)?
+            .into_extracted_file(&self.csimetadata.name())
+            .map(Some)
+    }
+
+    // the image in memory, for library users that don't want files
+    pub fn decode(&self) -> Result<DecodedRendition> {
+        self.decode_with_options(&ParseOptions::default())
+    }
+
+    pub fn decode_with_options(&self, options: &ParseOptions) -> Result<DecodedRendition> {
+        let name = self.csimetadata.name();
+        if let Some(limit) = options.max_payload_bytes {
+            let size = self.size_on_disk();
+            if size > limit {
+                return Err(CarError::PayloadTooLarge { name, size, limit }.into());
+            }
+        }
+        if let Some(pixels) = self.deep_pixels()? {
+            return Ok(DecodedRendition::Deep(pixels));
+        }
+        if let Some(rendition::Rendition::RawData { raw_data, .. }) = &self.rendition_data {
+            let data = raw_data.0.to_owned();
+            return Ok(match OutputFormat::sniff(&data, self.pixel_format) {
+                // uncompressed pixels rather than a whole file
+                OutputFormat::Data if self.bitmap_bytes_per_pixel().is_some() => {
+                    self.decoded_bitmap(Cow::Owned(self.raw_bitmap(&data)?))
+                }
+                OutputFormat::Jpeg => DecodedRendition::Jpeg(data),
+                format => DecodedRendition::File { format, data },
+            });
+        }
+        if let Some(document) = self
+            .rendition_data
+            .as_ref()
+            .and_then(|rendition_data| rendition_data.svg_document())
+        {
+            return Ok(DecodedRendition::File {
+                format: OutputFormat::Svg,
+                data: document.to_vec(),
+            });
+        }
+        if let Some((tag, payload)) = self
+            .rendition_data
+            .as_ref()
+            .and_then(|rendition_data| rendition_data.unknown_payload())
+        {
+            anyhow::bail!(
+                "unsupported rendition payload tag {} ({} bytes) for image {:?}, extract it with --raw",
+                common::fourcc_label(tag),
+                payload.len(),
+                name
+            );
+        }
+        let (compression_type, raw_data) = self
+            .rendition_data
+            .as_ref()
+            .and_then(|rendition_data| rendition_data.theme_payload())
+            .context(format!(
+                "unhandled image type {:?}, layout={:?}, rendition={:?}",
+                name, self.csimetadata.layout, &self.rendition_data
+            ))?;
+        match compression_type {
+            CompressionType::PaletteImg => {
+                let codec = codec::codec_for(CompressionType::PaletteImg)
+                    .context("no codec for PaletteImg")?;
+                let image_size = self.image_size(4)?;
+                Ok(DecodedRendition::Rgba {
+                    width: self.width,
+                    height: self.height,
+                    pixels: codec.decode(raw_data, Some(image_size))?,
                 })
-                | Some(rendition::Rendition::ThemeCBCK {
-                    compression_type,
-                    raw_data,
-                    ..
-                }) => match compression_type {
-                    CompressionType::ASTC => {
-                        let mut uncompressed_rendition_data = vec![];
-                        // first 12 bytes are a header??
-                        lzfse_rust::decode_bytes(
-                            &raw_data.0[12..],
-                            &mut uncompressed_rendition_data,
-                        )?;
-                        fs::write(&output_path, &uncompressed_rendition_data)?;
-                        Ok(Some(output_path_str.to_string()))
-                    }
-                    CompressionType::PaletteImg => {
-                        let mut uncompressed_rendition_data = vec![];
-                        lzfse_rust::decode_bytes(&raw_data.0, &mut uncompressed_rendition_data)?;
-                        let mut reader = Cursor::new(&mut uncompressed_rendition_data);
-                        let quantized_image = rendition::QuantizedImage::read_args(
-                            &mut reader,
-                            (self.width, self.height),
-                        )?;
-                        let image_size = self.width * self.height * 4;
-                        let mut image_buffer = vec![0u8; image_size as usize];
-                        quantized_image.extract(&mut image_buffer);
-
-                        let file = File::create(&output_path)?;
-                        let ref mut w = BufWriter::new(file);
-                        let mut encoder = png::Encoder::new(w, self.width, self.height);
-                        encoder.set_color(png::ColorType::Rgba);
-                        encoder.set_depth(png::BitDepth::Eight);
-                        encoder.set_source_gamma(png::ScaledFloat::from_scaled(45455));
-                        encoder.set_source_gamma(png::ScaledFloat::new(1.0 / 2.2));
-                        let source_chromaticities = png::SourceChromaticities::new(
-                            (0.31270, 0.32900),
-                            (0.64000, 0.33000),
-                            (0.30000, 0.60000),
-                            (0.15000, 0.06000),
-                        );
-                        encoder.set_source_chromaticities(source_chromaticities);
-                        let mut writer = encoder.write_header()?;
-                        writer.write_image_data(&image_buffer)?;
-                        Ok(Some(output_path_str.to_string()))
-                    }
-                    CompressionType::HEVC => {
-                        // first 8 bytes are a header??
-                        fs::write(&output_path, &raw_data.0[8..])?;
-                        Ok(Some(output_path_str.to_string()))
-                    }
-                    _ => None.context(format!(
-                        "unhandled compression type \"{:?}\" for image {:?}",
-                        compression_type, name
-                    )),
-                },
-                _ => None.context(format!(
-                    "unhandled image type {:?}, layout={:?}, rendition={:?}",
-                    name, self.csimetadata.layout, &self.rendition_data
-                )),
+            }
+            CompressionType::LZFSE | CompressionType::LZVN => {
+                self.compressed_bitmap(compression_type, raw_data)
+            }
+            CompressionType::JPEGLZFSE => {
+                let codec = codec::codec_for(compression_type)
+                    .context(format!("no codec for {:?}", compression_type))?;
+                let data = codec.decode(raw_data, None)?;
+                // kept as decoded, without re-encoding
+                if data.starts_with(&[0xFF, 0xD8]) {
+                    return Ok(DecodedRendition::Jpeg(data));
+                }
+                Ok(DecodedRendition::Raw(data))
+            }
+            // not implemented: there's no DeepMapImage parser or decoder, the
+            // deepmap containers haven't been worked out from a real catalog
+            CompressionType::DeepMapLZFSE | CompressionType::DeepMap2 => {
+                anyhow::bail!(
+                    "no decoder for {} compressed image {:?}, extract it with --raw",
+                    compression_type.name(),
+                    name
+                )
+            }
+            // not implemented: there's no hevc decoder and no cargo feature
+            // for one yet
+            CompressionType::HEVC => {
+                anyhow::bail!(
+                    "no decoder for {} compressed image {:?}, extract it with --raw",
+                    compression_type.name(),
+                    name
+                )
+            }
+            CompressionType::ASTC => {
+                let codec = codec::codec_for(compression_type)
+                    .context(format!("no codec for {:?}", compression_type))?;
+                Ok(DecodedRendition::File {
+                    format: OutputFormat::Data,
+                    data: codec.decode(raw_data, None)?,
+                })
+            }
+            _ => None.context(format!(
+                "unhandled compression type \"{:?}\" for image {:?}",
+                compression_type, name
+            )),
+        }
+    }
+
+    // 16 bit components of a deep color image, None for other pixel formats
+    // and for deep color images stored as png
+    pub fn deep_pixels(&self) -> Result<Option<deep_color::DeepPixels>> {
+        let Some(component_type) = self.pixel_format.deep_component_type() else {
+            return Ok(None);
+        };
+        if let Some(rendition::Rendition::RawData { raw_data, .. }) = &self.rendition_data {
+            if OutputFormat::sniff(&raw_data.0, self.pixel_format) != OutputFormat::Data {
+                return Ok(None);
+            }
+        }
+        let data = deep_color::payload(self)?;
+        let channels = self.pixel_format.channels();
+        deep_color::DeepPixels::decode(self.width, self.height, channels, component_type, &data)
+            .map(Some)
+            .context(format!("Unable to decode {:?}", self.csimetadata.name()))
+    }
+
+    // the file format of RawData payloads that are a whole PNG or JPEG file
+    // rather than pixels, whatever the pixel format says
+    pub fn container_format(&self) -> Option<OutputFormat> {
+        let Some(rendition::Rendition::RawData { raw_data, .. }) = &self.rendition_data else {
+            return None;
+        };
+        match OutputFormat::sniff(&raw_data.0, PixelFormat::None) {
+            format @ (OutputFormat::Png | OutputFormat::Jpeg) => Some(format),
+            _ => None,
+        }
+    }
+
+    // decoded RGBA pixels, bitmaps, palette-img payloads and embedded PNGs
+    // can be decoded for now
+    pub fn rgba_pixels(&self) -> Result<Vec<u8>> {
+        self.decoded_rgba(self.decode()?)
+    }
+
+    // rgba_pixels with straight alpha, embedded PNGs already have it
+    pub fn unpremultiplied_rgba_pixels(&self) -> Result<Vec<u8>> {
+        self.decoded_rgba(self.decode()?.unpremultiplied())
+    }
+
+    fn decoded_rgba(&self, decoded: DecodedRendition) -> Result<Vec<u8>> {
+        match decoded {
+            DecodedRendition::Rgba { pixels, .. } => Ok(pixels),
+            DecodedRendition::GrayAlpha { pixels, .. } => Ok(pixels
+                .chunks_exact(2)
+                .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+                .collect()),
+            DecodedRendition::File {
+                format: OutputFormat::Png,
+                data,
+            } => {
+                let (width, height, rgba) = decode_png(&data)?;
+                if (width, height) != (self.width, self.height) {
+                    return None.context(format!(
+                        "{:?} is {}x{} but its png is {}x{}",
+                        self.csimetadata.name(),
+                        self.width,
+                        self.height,
+                        width,
+                        height
+                    ));
+                }
+                Ok(rgba)
+            }
+            DecodedRendition::Jpeg(_) => anyhow::bail!(
+                "{:?} is a jpeg, there is no jpeg decoder yet",
+                self.csimetadata.name()
+            ),
+            _ => None.context(format!(
+                "no pixel decoder for {:?}, rendition={:?}",
+                self.csimetadata.name(),
+                &self.rendition_data
+            )),
+        }
+    }
+
+    // bitmaps are premultiplied BGRA rows for ARGB and gray and alpha rows
+    // for GA8, None for pixel formats stored some other way
+    fn bitmap_bytes_per_pixel(&self) -> Option<usize> {
+        match self.pixel_format {
+            PixelFormat::ARGB => Some(4),
+            PixelFormat::Gray => Some(2),
+            _ => None,
+        }
+    }
+
+    fn bitmap_rgba(&self, pixels: &[u8]) -> Vec<u8> {
+        match self.pixel_format {
+            PixelFormat::Gray => pixels
+                .chunks_exact(2)
+                .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+                .collect(),
+            _ => pixels
+                .chunks_exact(4)
+                .flat_map(|bgra| [bgra[2], bgra[1], bgra[0], bgra[3]])
+                .collect(),
+        }
+    }
+
+    // gray images keep their color type
+    fn decoded_bitmap(&self, pixels: Cow<[u8]>) -> DecodedRendition {
+        let (width, height) = (self.width, self.height);
+        match self.pixel_format {
+            PixelFormat::Gray => DecodedRendition::GrayAlpha {
+                width,
+                height,
+                pixels: pixels.into_owned(),
+            },
+            _ => DecodedRendition::Rgba {
+                width,
+                height,
+                pixels: self.bitmap_rgba(&pixels),
             },
-            _ => Ok(None),
         }
     }
 
-    pub fn is_opaque(&self) -> bool {
-        // it seems like this actually has to check if the image has any transparent pixels
-        match &self.rendition_data {
-            Some(rendition::Rendition::Theme {
+    // pixels of an uncompressed bitmap without row padding. Rows are padded
+    // out to the same length when the payload holds more than the pixels
+    fn raw_bitmap(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let name = self.csimetadata.name();
+        let bytes_per_pixel = self.bitmap_bytes_per_pixel().with_context(|| {
+            format!(
+                "{} image {:?} isn't a bitmap",
+                self.pixel_format.name(),
+                name
+            )
+        })?;
+        let row_length = self.width as usize * bytes_per_pixel;
+        let height = self.height as usize;
+        let rowbytes = match height {
+            0 => 0,
+            _ => data.len() / height,
+        };
+        if rowbytes < row_length || rowbytes * height != data.len() {
+            anyhow::bail!(
+                "{:?} is a {}x{} {} image but has {} bytes of pixels",
+                name,
+                self.width,
+                self.height,
+                self.pixel_format.name(),
+                data.len()
+            );
+        }
+        if rowbytes == row_length {
+            return Ok(data.to_vec());
+        }
+        Ok(data
+            .chunks_exact(rowbytes)
+            .flat_map(|row| &row[..row_length])
+            .copied()
+            .collect())
+    }
+
+    // pixels of an lzfse compressed bitmap as stored, without row padding
+    // bytes of width x height pixels, the dimensions come from the file
+    fn image_size(&self, bytes_per_pixel: usize) -> Result<usize> {
+        (self.width as usize)
+            .checked_mul(self.height as usize)
+            .and_then(|pixels| pixels.checked_mul(bytes_per_pixel))
+            .with_context(|| {
+                format!(
+                    "{:?} is too big at {}x{}",
+                    self.csimetadata.name(),
+                    self.width,
+                    self.height
+                )
+            })
+    }
+
+    fn compressed_bitmap(
+        &self,
+        compression_type: CompressionType,
+        raw_data: &[u8],
+    ) -> Result<DecodedRendition> {
+        let name = self.csimetadata.name();
+        let Some(bytes_per_pixel) = self.bitmap_bytes_per_pixel() else {
+            anyhow::bail!(
+                "no decoder for {} compressed {} image {:?}",
+                compression_type.name(),
+                self.pixel_format.name(),
+                name
+            );
+        };
+        let expected = self.image_size(bytes_per_pixel)?;
+        let codec = codec::codec_for(compression_type)
+            .context(format!("no codec for {:?}", compression_type))?;
+        // pixels are converted straight out of the decoder's buffer
+        codec::DecodeContext::with_thread_context(|context| {
+            let pixels = codec
+                .decode_with(context, raw_data, Some(expected))
+                .with_context(|| {
+                    format!(
+                        "Unable to decode {:?}, a {}x{} {} image is {} bytes",
+                        name,
+                        self.width,
+                        self.height,
+                        self.pixel_format.name(),
+                        expected
+                    )
+                })?;
+            Ok(self.decoded_bitmap(Cow::Borrowed(&pixels)))
+        })
+    }
+
+    // SHA256 of the dimensions and decoded RGBA pixels, unlike SHA1Digest it
+    // stays the same when only the compression changes
+    pub fn pixel_digest(&self) -> Result<String> {
+        let rgba = self.rgba_pixels()?;
+        let mut hasher = Sha256::new();
+        hasher.update(self.width.to_le_bytes());
+        hasher.update(self.height.to_le_bytes());
+        hasher.update(rgba);
+        Ok(hasher.finalize().encode_hex_upper())
+    }
+
+    // height of one frame from the slices tlv, animation filmstrips stack
+    // their frames vertically
+    pub fn frame_height(&self) -> Option<u32> {
+        self.properties()
+            .into_iter()
+            .find_map(|rendition_type| match rendition_type {
+                tlv::RenditionType::Slices { height, .. } if height > 0 => Some(height),
+                _ => None,
+            })
+    }
+
+    // where the stored pixels sit on the canvas they had before CoreUI cropped
+    // their transparent borders, None when nothing was cropped. Renditions
+    // without a size in their header (JPEGs) can't have been cropped. Taking
+    // idk1 and idk2 of the metrics tlv as the left and top insets is a guess,
+    // every catalog we have stores zero insets
+    pub fn alpha_crop(&self) -> Option<AlphaCrop> {
+        if self.width == 0 || self.height == 0 {
+            return None;
+        }
+        self.properties()
+            .into_iter()
+            .find_map(|rendition_type| match rendition_type {
+                tlv::RenditionType::Metrics {
+                    idk1,
+                    idk2,
+                    width,
+                    height,
+                    ..
+                } if (width, height) != (self.width, self.height) || (idk1, idk2) != (0, 0) => {
+                    Some(AlphaCrop {
+                        original_width: width,
+                        original_height: height,
+                        x: idk1,
+                        y: idk2,
+                        width: self.width,
+                        height: self.height,
+                    })
+                }
+                _ => None,
+            })
+    }
+
+    // the decoded image padded back to its original canvas with transparent
+    // pixels, None when it wasn't cropped. Pixels stay premultiplied unless
+    // unpremultiplied is set. The canvas size comes from the file, so it's
+    // held to max_payload_bytes like the payload
+    pub fn restored_canvas_png(
+        &self,
+        unpremultiplied: bool,
+        options: &ParseOptions,
+    ) -> Result<Option<Vec<u8>>> {
+        let Some(crop) = self.alpha_crop() else {
+            return Ok(None);
+        };
+        let fits = |offset: u32, length: u32, canvas: u32| {
+            offset.checked_add(length).is_some_and(|end| end <= canvas)
+        };
+        if !fits(crop.x, crop.width, crop.original_width)
+            || !fits(crop.y, crop.height, crop.original_height)
+        {
+            anyhow::bail!(
+                "{:?} has a {}x{} frame at ({}, {}) outside its {}x{} canvas",
+                self.csimetadata.name(),
+                crop.width,
+                crop.height,
+                crop.x,
+                crop.y,
+                crop.original_width,
+                crop.original_height
+            );
+        }
+        let name = self.csimetadata.name();
+        let size = (crop.original_width as u64 * crop.original_height as u64).saturating_mul(4);
+        if let Some(limit) = options.max_payload_bytes {
+            if size > limit {
+                return Err(CarError::PayloadTooLarge { name, size, limit }.into());
+            }
+        }
+        let rgba = if unpremultiplied {
+            self.unpremultiplied_rgba_pixels()?
+        } else {
+            self.rgba_pixels()?
+        };
+        let mut canvas = vec![];
+        canvas
+            .try_reserve_exact(usize::try_from(size).unwrap_or(usize::MAX))
+            .with_context(|| {
+                format!(
+                    "{:?} has a {}x{} canvas that doesn't fit in memory",
+                    name, crop.original_width, crop.original_height
+                )
+            })?;
+        canvas.resize(size as usize, 0);
+        let row_length = crop.width as usize * 4;
+        let canvas_row_length = crop.original_width as usize * 4;
+        for (row, pixels) in rgba.chunks_exact(row_length).enumerate() {
+            let start = (crop.y as usize + row) * canvas_row_length + crop.x as usize * 4;
+            canvas[start..start + row_length].copy_from_slice(pixels);
+        }
+        Ok(Some(encode_png(
+            crop.original_width,
+            crop.original_height,
+            &canvas,
+        )?))
+    }
+
+    // replaces the stored pixel payload, the tlv region is carried through
+    // verbatim so slices, metrics and tags we don't parse survive a rewrite
+    pub fn set_payload(&mut self, data: &[u8], compression_type: CompressionType) -> Result<()> {
+        let raw_data = common::RawData(data.to_vec());
+        let raw_data_length = data.len() as u32;
+        let rendition_data = match self.rendition_data.take() {
+            Some(rendition::Rendition::RawData { version, .. })
+                if compression_type == CompressionType::Uncompressed =>
+            {
+                rendition::Rendition::RawData {
+                    version,
+                    _raw_data_length: raw_data_length,
+                    raw_data,
+                }
+            }
+            Some(rendition::Rendition::ThemeCBCK {
+                version,
+                idk,
+                a,
+                b,
+                c,
+                ..
+            }) => rendition::Rendition::ThemeCBCK {
+                version,
                 compression_type,
+                idk,
+                a,
+                b,
+                c,
+                _raw_data_length: raw_data_length,
                 raw_data,
-                ..
-            }) => {
-                match compression_type {
-                    CompressionType::PaletteImg => {
-                        let mut uncompressed_rendition_data = vec![];
-                        lzfse_rust::decode_bytes(&raw_data.0, &mut uncompressed_rendition_data)
-                            .unwrap();
-                        let mut reader = Cursor::new(&mut uncompressed_rendition_data);
-                        let quantized_image = rendition::QuantizedImage::read_args(
-                            &mut reader,
-                            (self.width, self.height),
-                        )
-                        .unwrap();
-                        // any non 0xff values for the alpha channel?
-                        !quantized_image
-                            .color_table
-                            .iter()
-                            .any(|pixel| (*pixel & 0xff) != 0xff)
-                    }
-                    _ => self.rendition_flags.is_opaque(),
+            },
+            Some(rendition::Rendition::Theme { version, .. }) => rendition::Rendition::Theme {
+                version,
+                compression_type,
+                _raw_data_length: raw_data_length,
+                raw_data,
+            },
+            None | Some(rendition::Rendition::RawData { .. }) => rendition::Rendition::Theme {
+                version: 1,
+                compression_type,
+                _raw_data_length: raw_data_length,
+                raw_data,
+            },
+            Some(other) => {
+                let description = format!("{:?}", other);
+                self.rendition_data = Some(other);
+                return None.context(format!(
+                    "can't set a pixel payload on rendition {:?}",
+                    description
+                ));
+            }
+        };
+
+        let mut writer = Cursor::new(vec![]);
+        rendition_data.write_le(&mut writer)?;
+        self.csibitmaplist.rendition_length = writer.into_inner().len() as u32;
+        self.csibitmaplist.tlv_length = self.tlv_data.0.len() as u32;
+        self.rendition_data = Some(rendition_data);
+        Ok(())
+    }
+
+    // stores a png as the rendition's payload (a DWAR blob, like the images
+    // actool keeps as they are) and takes its size
+    pub fn set_png(&mut self, png: &[u8]) -> Result<()> {
+        let (width, height, _) = decode_png(png)?;
+        let rendition_data = rendition::Rendition::RawData {
+            version: 1,
+            _raw_data_length: png.len() as u32,
+            raw_data: common::RawData(png.to_vec()),
+        };
+        let mut writer = Cursor::new(vec![]);
+        rendition_data.write_le(&mut writer)?;
+        self.csibitmaplist.rendition_length = writer.into_inner().len() as u32;
+        self.rendition_data = Some(rendition_data);
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        // it seems like this actually has to check if the image has any transparent pixels
+        match self
+            .rendition_data
+            .as_ref()
+            .and_then(|rendition_data| rendition_data.theme_payload())
+        {
+            Some((CompressionType::PaletteImg, raw_data)) => {
+                // payloads we can't read fall back to the flags
+                match codec::PaletteImgCodec::quantized_image(
+                    raw_data,
+                    self.width.saturating_mul(self.height),
+                ) {
+                    // any non 0xff values for the alpha channel?
+                    Ok(quantized_image) => !quantized_image
+                        .color_table
+                        .iter()
+                        .any(|pixel| (*pixel & 0xff) != 0xff),
+                    Err(_) => self.rendition_flags.is_opaque(),
                 }
             }
             _ => self.rendition_flags.is_opaque(),
@@ -281,6 +1273,125 @@ impl Header {
     }
 }
 
+// (width, height) of the first /MediaBox [llx lly urx ury] in a PDF, a plain
+// scan so compressed object streams hiding it aren't supported
+pub fn pdf_media_box(data: &[u8]) -> Option<(f64, f64)> {
+    const MEDIA_BOX: &[u8] = b"/MediaBox";
+    let start = data
+        .windows(MEDIA_BOX.len())
+        .position(|window| window == MEDIA_BOX)?
+        + MEDIA_BOX.len();
+    let rest = &data[start..];
+    let open = rest.iter().position(|b| !b.is_ascii_whitespace())?;
+    if rest[open] != b'[' {
+        return None;
+    }
+    let close = rest.iter().position(|b| *b == b']')?;
+    let values: Vec<f64> = std::str::from_utf8(rest.get(open + 1..close)?)
+        .ok()?
+        .split_ascii_whitespace()
+        .map(|value| value.parse())
+        .collect::<std::result::Result<_, _>>()
+        .ok()?;
+    match values[..] {
+        [llx, lly, urx, ury] => Some(((urx - llx).abs(), (ury - lly).abs())),
+        _ => None,
+    }
+}
+
+// divides the color components of premultiplied pixels by their alpha, the
+// last of each pixel's channels. Fully transparent pixels become all zero
+pub fn unpremultiply(pixels: &mut [u8], channels: usize) {
+    for pixel in pixels.chunks_exact_mut(channels) {
+        let (color, alpha) = pixel.split_at_mut(channels - 1);
+        let alpha = alpha[0] as u32;
+        for component in color {
+            *component = match alpha {
+                0 => 0,
+                _ => ((*component as u32 * 255 + alpha / 2) / alpha).min(255) as u8,
+            };
+        }
+    }
+}
+
+// 8 bit RGBA png tagged as sRGB like the images assetutil extracts
+pub fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Result<Vec<u8>> {
+    encode_png_as(width, height, png::ColorType::Rgba, rgba)
+}
+
+// 8 bit png of pixels already laid out for color_type
+pub fn encode_png_as(
+    width: u32,
+    height: u32,
+    color_type: png::ColorType,
+    data: &[u8],
+) -> Result<Vec<u8>> {
+    let mut png_data = vec![];
+    {
+        let mut encoder = png_encoder(&mut png_data, width, height);
+        encoder.set_color(color_type);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(data)?;
+    }
+    Ok(png_data)
+}
+
+// (width, height, 8 bit RGBA pixels) of any png
+pub fn decode_png(data: &[u8]) -> Result<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    buffer.truncate(info.buffer_size());
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buffer,
+        png::ColorType::Rgb => buffer
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 0xFF])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+            .collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|g| [*g, *g, *g, 0xFF]).collect(),
+        // expanded by normalize_to_color8
+        png::ColorType::Indexed => None.context("png palette was not expanded")?,
+    };
+    Ok((info.width, info.height, rgba))
+}
+
+pub fn png_encoder<W: std::io::Write>(
+    writer: W,
+    width: u32,
+    height: u32,
+) -> png::Encoder<'static, W> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_gamma(png::ScaledFloat::from_scaled(45455));
+    encoder.set_source_gamma(png::ScaledFloat::new(1.0 / 2.2));
+    let source_chromaticities = png::SourceChromaticities::new(
+        (0.31270, 0.32900),
+        (0.64000, 0.33000),
+        (0.30000, 0.60000),
+        (0.15000, 0.06000),
+    );
+    encoder.set_source_chromaticities(source_chromaticities);
+    encoder
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlphaCrop {
+    pub original_width: u32,
+    pub original_height: u32,
+    // the frame of the stored pixels, from the top left of the original canvas
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
 #[derive(Debug, Default)]
 pub struct Generator {
     pub size: Option<coregraphics::Size>,
@@ -403,7 +1514,7 @@ impl Generator {
             _ => unimplemented!("Unhandled layout type"),
         }
 
-        header.csibitmaplist.zero = 0;
+        header.csibitmaplist.reserved = 0;
         header.csibitmaplist.rendition_length = 0;
     }
 }
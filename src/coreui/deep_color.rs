@@ -0,0 +1,231 @@
+use anyhow::Context;
+use anyhow::Result;
+
+use super::codec;
+use super::csi;
+use super::rendition;
+
+// deep color renditions store 4 little endian 16 bit components per pixel in
+// RGBA order, or 2 in gray and alpha order for GA16 (inferred from wide gamut
+// catalogs, not documented anywhere).
+// Integer components are 0..=65535, half float ones can go below 0 and above
+// 1 for colors outside the sRGB gamut
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComponentType {
+    Unsigned16,
+    Half,
+}
+
+// decoded pixels of a deep color rendition, the components are kept as stored
+// (premultiplied, no transfer function applied) and widened to f32 so integer
+// and half float formats can share the encoders
+pub struct DeepPixels {
+    pub width: u32,
+    pub height: u32,
+    // 4 for RGBA, 2 for gray and alpha
+    pub channels: usize,
+    pub components: Vec<f32>,
+}
+
+impl DeepPixels {
+    pub fn decode(
+        width: u32,
+        height: u32,
+        channels: usize,
+        component_type: ComponentType,
+        data: &[u8],
+    ) -> Result<DeepPixels> {
+        // u128 so hostile dimensions can't overflow
+        let expected = width as u128 * height as u128 * channels as u128 * 2;
+        if data.len() as u128 != expected {
+            anyhow::bail!(
+                "{}x{} deep color image needs {} bytes but has {}",
+                width,
+                height,
+                expected,
+                data.len()
+            );
+        }
+        let components = data
+            .chunks_exact(2)
+            .map(|bytes| {
+                let value = u16::from_le_bytes([bytes[0], bytes[1]]);
+                match component_type {
+                    ComponentType::Unsigned16 => value as f32 / 65535.0,
+                    ComponentType::Half => half_to_f32(value),
+                }
+            })
+            .collect();
+        Ok(DeepPixels {
+            width,
+            height,
+            channels,
+            components,
+        })
+    }
+
+    // divides the color components by alpha, fully transparent pixels become
+    // all zero
+    pub fn unpremultiply(&mut self) {
+        for pixel in self.components.chunks_exact_mut(self.channels) {
+            let (color, alpha) = pixel.split_at_mut(self.channels - 1);
+            let alpha = alpha[0];
+            for component in color {
+                *component = if alpha == 0.0 {
+                    0.0
+                } else {
+                    *component / alpha
+                };
+            }
+        }
+    }
+
+    // components below 0 or above 1, which a 16 bit png can't hold
+    pub fn extended_count(&self) -> usize {
+        self.components
+            .iter()
+            .filter(|value| !(0.0..=1.0).contains(*value))
+            .count()
+    }
+
+    // components scaled to 0..=65535, extended range values are clamped
+    pub fn to_u16(&self) -> Vec<u16> {
+        self.components
+            .iter()
+            .map(|value| {
+                let value = if value.is_nan() { 0.0 } else { *value };
+                (value.clamp(0.0, 1.0) * 65535.0).round() as u16
+            })
+            .collect()
+    }
+
+    // 16 bit RGBA or gray and alpha png with the same tags as the 8 bit ones
+    pub fn encode_png(&self) -> Result<Vec<u8>> {
+        // png samples are big endian
+        let samples: Vec<u8> = self
+            .to_u16()
+            .into_iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+        let mut png_data = vec![];
+        {
+            let mut encoder = csi::png_encoder(&mut png_data, self.width, self.height);
+            encoder.set_depth(png::BitDepth::Sixteen);
+            if self.channels == 2 {
+                encoder.set_color(png::ColorType::GrayscaleAlpha);
+            }
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(&samples)?;
+        }
+        Ok(png_data)
+    }
+
+    // single part scanline OpenEXR with 32 bit float channels and no
+    // compression, extended range values are written as they are
+    #[cfg(feature = "exr")]
+    pub fn encode_exr(&self) -> Result<Vec<u8>> {
+        fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+            header.extend_from_slice(name.as_bytes());
+            header.push(0);
+            header.extend_from_slice(kind.as_bytes());
+            header.push(0);
+            header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+            header.extend_from_slice(value);
+        }
+
+        let (width, height) = (self.width as usize, self.height as usize);
+        if width == 0 || height == 0 {
+            anyhow::bail!("can't write an empty exr");
+        }
+        // channels are sorted by name, A B G R or A Y for gray
+        let channels: &[(&str, usize)] = match self.channels {
+            2 => &[("A", 1), ("Y", 0)],
+            _ => &[("A", 3), ("B", 2), ("G", 1), ("R", 0)],
+        };
+        let mut channel_list = vec![];
+        for (name, _) in channels {
+            channel_list.extend_from_slice(name.as_bytes());
+            channel_list.push(0);
+            // FLOAT pixels, not perceptually linear, 3 reserved bytes, no subsampling
+            channel_list.extend_from_slice(&2i32.to_le_bytes());
+            channel_list.extend_from_slice(&[0, 0, 0, 0]);
+            channel_list.extend_from_slice(&1i32.to_le_bytes());
+            channel_list.extend_from_slice(&1i32.to_le_bytes());
+        }
+        channel_list.push(0);
+        let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+
+        let mut exr = vec![0x76, 0x2f, 0x31, 0x01];
+        exr.extend_from_slice(&2u32.to_le_bytes());
+        attribute(&mut exr, "channels", "chlist", &channel_list);
+        attribute(&mut exr, "compression", "compression", &[0]);
+        attribute(&mut exr, "dataWindow", "box2i", &window);
+        attribute(&mut exr, "displayWindow", "box2i", &window);
+        attribute(&mut exr, "lineOrder", "lineOrder", &[0]);
+        attribute(&mut exr, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+        attribute(&mut exr, "screenWindowCenter", "v2f", &[0; 8]);
+        attribute(&mut exr, "screenWindowWidth", "float", &1f32.to_le_bytes());
+        exr.push(0);
+
+        // one scanline per block: y, data length, then each channel's row
+        let block_length = 8 + width * 4 * channels.len();
+        let table_end = exr.len() + height * 8;
+        for y in 0..height {
+            exr.extend_from_slice(&((table_end + y * block_length) as u64).to_le_bytes());
+        }
+        for (y, row) in self
+            .components
+            .chunks_exact(width * self.channels)
+            .enumerate()
+        {
+            exr.extend_from_slice(&(y as i32).to_le_bytes());
+            exr.extend_from_slice(&((block_length - 8) as i32).to_le_bytes());
+            for (_, component) in channels {
+                for pixel in row.chunks_exact(self.channels) {
+                    exr.extend_from_slice(&pixel[*component].to_le_bytes());
+                }
+            }
+        }
+        Ok(exr)
+    }
+}
+
+// IEEE 754 binary16 to f32
+pub(crate) fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+// the decompressed components of a deep color rendition
+pub fn payload(csi_header: &csi::Header) -> Result<Vec<u8>> {
+    let name = csi_header.csimetadata.name();
+    let expected = csi_header.width as usize
+        * csi_header.height as usize
+        * csi_header.pixel_format.channels()
+        * 2;
+    if let Some(rendition::Rendition::RawData { raw_data, .. }) = &csi_header.rendition_data {
+        return Ok(raw_data.0.to_owned());
+    }
+    let (compression_type, raw_data) = csi_header
+        .rendition_data
+        .as_ref()
+        .and_then(|rendition_data| rendition_data.theme_payload())
+        .with_context(|| format!("deep color image {:?} has no payload", name))?;
+    let codec = codec::codec_for(compression_type).with_context(|| {
+        format!(
+            "no codec for {:?} deep color image {:?}",
+            compression_type, name
+        )
+    })?;
+    codec.decode(raw_data, Some(expected))
+}
@@ -0,0 +1,32 @@
+use std::fmt::Display;
+
+// errors callers may want to tell apart, returned wrapped in anyhow::Error
+#[derive(Debug, Clone, PartialEq)]
+pub enum CarError {
+    PayloadTooLarge { name: String, size: u64, limit: u64 },
+    Truncated { expected: u64, actual: u64 },
+    // a tree's child or forward pointers lead back to a block already read
+    CyclicTree { var: String },
+}
+
+impl Display for CarError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CarError::PayloadTooLarge { name, size, limit } => write!(
+                f,
+                "rendition {:?} is {} bytes, larger than the {} byte limit",
+                name, size, limit
+            ),
+            CarError::Truncated { expected, actual } => write!(
+                f,
+                "file is {} bytes but needs at least {}, it may be incomplete (truncated download or copy?)",
+                actual, expected
+            ),
+            CarError::CyclicTree { var } => {
+                write!(f, "{} has a cycle in its paths, the file is malformed", var)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CarError {}
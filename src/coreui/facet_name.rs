@@ -0,0 +1,85 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+// a facet or rendition name that compares, orders and hashes the way CoreUI
+// looks names up: ignoring case. The case folding is the same everywhere,
+// it doesn't depend on a locale. The original spelling is kept for display
+#[derive(Debug, Clone)]
+pub struct FacetName {
+    name: String,
+    folded: String,
+}
+
+impl FacetName {
+    pub fn new(name: &str) -> FacetName {
+        FacetName {
+            name: name.to_string(),
+            folded: fold(name),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.folded == fold(name)
+    }
+
+    pub fn contains(&self, query: &str) -> bool {
+        self.folded.contains(&fold(query))
+    }
+}
+
+fn fold(name: &str) -> String {
+    name.chars().flat_map(char::to_lowercase).collect()
+}
+
+// compares a and b case-insensitively like CoreUI, or byte for byte
+pub fn names_match(a: &str, b: &str, case_sensitive: bool) -> bool {
+    if case_sensitive {
+        a == b
+    } else {
+        FacetName::new(a).matches(b)
+    }
+}
+
+impl PartialEq for FacetName {
+    fn eq(&self, other: &Self) -> bool {
+        self.folded == other.folded
+    }
+}
+
+impl Eq for FacetName {}
+
+impl Hash for FacetName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.folded.hash(state)
+    }
+}
+
+impl PartialOrd for FacetName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FacetName {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.folded.cmp(&other.folded)
+    }
+}
+
+impl Display for FacetName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+impl From<&str> for FacetName {
+    fn from(name: &str) -> Self {
+        FacetName::new(name)
+    }
+}
@@ -1,9 +1,17 @@
 pub mod bitmap;
 mod car_util_asset_storage;
+pub mod codec;
 mod color;
 pub mod csi;
+pub mod deep_color;
+mod error;
+mod facet_name;
+mod query;
 pub mod rendition;
 pub mod tlv;
 
 pub use self::car_util_asset_storage::*;
 pub use self::color::*;
+pub use self::error::*;
+pub use self::facet_name::*;
+pub use self::query::*;
@@ -0,0 +1,60 @@
+use super::rendition::AttributeType;
+
+// what a rendition lookup asks for, unset attributes prefer the universal value
+#[derive(Debug, Default, Clone)]
+pub struct RenditionQuery {
+    pub scale: Option<u16>,
+    pub idiom: Option<u16>,
+    pub appearance: Option<u16>,
+    pub display_gamut: Option<u16>,
+    // the asset name has to match exactly instead of ignoring case
+    pub case_sensitive: bool,
+}
+
+impl RenditionQuery {
+    // None if the rendition can't be used for the query, otherwise lower is a
+    // better match: attributes that only matched through the universal value (0)
+    // count first, then how far the scale is from the one asked for, falling
+    // back to lower scales before higher ones
+    pub fn score(&self, key_values: &[(AttributeType, u16)]) -> Option<(u32, u32)> {
+        // attributes missing from the key format are implicitly 0
+        let value = |attribute: AttributeType| {
+            key_values
+                .iter()
+                .find(|(key_attribute, _)| *key_attribute == attribute)
+                .map(|(_, value)| *value)
+                .unwrap_or(0)
+        };
+
+        let mut fallbacks = 0;
+        for (attribute, wanted) in [
+            (AttributeType::Appearance, self.appearance),
+            (AttributeType::Idiom, self.idiom),
+            (AttributeType::DisplayGamut, self.display_gamut),
+        ] {
+            match (wanted, value(attribute)) {
+                (Some(wanted), value) if wanted == value => {}
+                // universal rendition for a specific query
+                (Some(_), 0) => fallbacks += 1,
+                (Some(_), _) => return None,
+                (None, 0) => {}
+                // specific rendition, usable when nothing universal exists
+                (None, _) => fallbacks += 1,
+            }
+        }
+
+        // scale isn't optional for images, without a query scale assume 1x
+        let wanted = self.scale.unwrap_or(1) as u32;
+        let scale = value(AttributeType::Scale) as u32;
+        let scale_rank = if scale == wanted {
+            0
+        } else if scale == 0 {
+            1
+        } else if scale < wanted {
+            1 + wanted - scale
+        } else {
+            100 + scale - wanted
+        };
+        Some((fallbacks, scale_rank))
+    }
+}
@@ -4,10 +4,12 @@ use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 use serde::Serialize;
 use serde::Serializer;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::fmt::Display;
 use std::iter::zip;
 
+use crate::common;
 use crate::common::RawData;
 use crate::coregraphics;
 
@@ -29,12 +31,72 @@ impl KeyFormat {
         }
     }
 
+    // the attribute order catalogs built by Xcode 14 use
+    pub fn standard() -> Self {
+        KeyFormat::new(vec![
+            AttributeType::Appearance,
+            AttributeType::Scale,
+            AttributeType::Idiom,
+            AttributeType::Subtype,
+            AttributeType::DeploymentTarget,
+            AttributeType::GraphicsClass,
+            AttributeType::MemoryClass,
+            AttributeType::DisplayGamut,
+            AttributeType::Direction,
+            AttributeType::SizeClassHorizontal,
+            AttributeType::SizeClassVertical,
+            AttributeType::Identifier,
+            AttributeType::Element,
+            AttributeType::Part,
+            AttributeType::State,
+            AttributeType::Value,
+            AttributeType::Dimension1,
+            AttributeType::Dimension2,
+        ])
+    }
+
     pub fn map(&self, key: &Key) -> Vec<(AttributeType, u16)> {
         zip(self.attribute_types.clone(), key.raw).collect()
     }
+
+    // builds the rendition key for a list of attributes, missing attributes are 0
+    pub fn key(&self, attributes: &[Attribute]) -> Key {
+        let mut raw = [0u16; 18];
+        for (value, attribute_type) in raw.iter_mut().zip(&self.attribute_types) {
+            if let Some(attribute) = attributes
+                .iter()
+                .find(|attribute| AttributeType::from(attribute.name) == *attribute_type)
+            {
+                *value = attribute.value;
+            }
+        }
+        Key { raw }
+    }
+
+    // the same attribute values laid out for the target key format, attributes
+    // the target adds are 0 and dropping a nonzero value is an error
+    pub fn reencode(&self, key: &Key, target: &KeyFormat) -> anyhow::Result<Key> {
+        let mut raw = [0u16; 18];
+        for (attribute_type, value) in self.map(key) {
+            match target
+                .attribute_types
+                .iter()
+                .position(|target_type| *target_type == attribute_type)
+            {
+                Some(index) if index < raw.len() => raw[index] = value,
+                _ if value == 0 => {}
+                _ => anyhow::bail!(
+                    "key format has no {:?} attribute for value {}",
+                    attribute_type,
+                    value
+                ),
+            }
+        }
+        Ok(Key { raw })
+    }
 }
 
-#[derive(BinRead, BinWrite, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(BinRead, BinWrite, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
 #[brw(little)]
 pub struct Key {
     pub raw: [u16; 18],
@@ -66,6 +128,60 @@ impl Debug for Key {
 }
 
 impl Key {
+    pub fn from_hex(string: &str) -> anyhow::Result<Self> {
+        Ok(Key {
+            raw: common::u16s_from_hex(string)?,
+        })
+    }
+
+    pub fn to_hex(self) -> String {
+        common::u16s_to_hex(&self.raw)
+    }
+
+    /// Every attribute of the key format with its value, keyed by the
+    /// lowercase attribute name.
+    ///
+    /// ```
+    /// use carutil_lib::coreui::rendition::{AttributeType, Key, KeyFormat};
+    ///
+    /// let key_format = KeyFormat::new(vec![AttributeType::Scale, AttributeType::Idiom]);
+    /// let key = Key { raw: [2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0] };
+    /// let map = key.to_map(&key_format);
+    /// assert_eq!(map["scale"], 2);
+    /// assert_eq!(map["idiom"], 1);
+    /// ```
+    pub fn to_map(self, key_format: &KeyFormat) -> BTreeMap<String, u16> {
+        key_format
+            .map(&self)
+            .into_iter()
+            .map(|(attribute_type, value)| (attribute_name(&attribute_type), value))
+            .collect()
+    }
+
+    /// The nonzero attributes in key format order, idioms by name.
+    ///
+    /// ```
+    /// use carutil_lib::coreui::rendition::{AttributeType, Key, KeyFormat};
+    ///
+    /// let key_format = KeyFormat::new(vec![
+    ///     AttributeType::Appearance,
+    ///     AttributeType::Scale,
+    ///     AttributeType::Idiom,
+    ///     AttributeType::Identifier,
+    /// ]);
+    /// let key = Key { raw: [0, 2, 2, 32625, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0] };
+    /// assert_eq!(
+    ///     key.display_with(&key_format).to_string(),
+    ///     "scale=2 idiom=pad identifier=32625"
+    /// );
+    /// ```
+    pub fn display_with<'a>(&'a self, key_format: &'a KeyFormat) -> KeyDisplay<'a> {
+        KeyDisplay {
+            key: self,
+            key_format,
+        }
+    }
+
     pub fn find_attribute(&self, key_format: KeyFormat, attribute: AttributeType) -> Option<u16> {
         key_format
             .map(self)
@@ -78,87 +194,298 @@ impl Key {
 #[derive(BinRead, BinWrite)]
 #[brw(little)]
 pub struct KeyToken {
-    _cursor_hotspot: (u16, u16),
+    // the click point of cursors in macOS cursor theme catalogs, zero elsewhere
+    pub cursor_hotspot: (u16, u16),
     _number_of_attributes: u16,
     #[br(count = _number_of_attributes)]
     pub attributes: Vec<Attribute>,
 }
 
+impl KeyToken {
+    pub fn new(attributes: Vec<Attribute>) -> Self {
+        Self::with_cursor_hotspot(attributes, (0, 0))
+    }
+
+    pub fn with_cursor_hotspot(attributes: Vec<Attribute>, cursor_hotspot: (u16, u16)) -> Self {
+        KeyToken {
+            cursor_hotspot,
+            _number_of_attributes: attributes.len() as u16,
+            attributes,
+        }
+    }
+
+    pub fn find_attribute(&self, attribute: AttributeType16) -> Option<u16> {
+        self.attributes
+            .iter()
+            .find(|token_attribute| token_attribute.name == attribute)
+            .map(|token_attribute| token_attribute.value)
+    }
+}
+
+/// A key's attributes, see [`Key::display_with`].
+pub struct KeyDisplay<'a> {
+    key: &'a Key,
+    key_format: &'a KeyFormat,
+}
+
+impl Display for KeyDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let attributes: Vec<String> = self
+            .key_format
+            .map(self.key)
+            .into_iter()
+            .filter(|(_, value)| *value != 0)
+            .map(|(attribute_type, value)| format_attribute(&attribute_type, value))
+            .collect();
+        f.write_str(&attributes.join(" "))
+    }
+}
+
+/// The raw values as hex, the same string [`Key::from_hex`] reads.
+///
+/// ```
+/// use carutil_lib::coreui::rendition::Key;
+///
+/// let mut raw = [0u16; 18];
+/// raw[0] = 1;
+/// let key = Key { raw };
+/// assert_eq!(key.to_string(), format!("0100{}", "0000".repeat(17)));
+/// assert_eq!(Key::from_hex(&key.to_string()).unwrap(), key);
+/// ```
+impl Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+// lowercase Debug name, "identifier" rather than the Display "NameIdentifier".
+// Unknown(30) becomes "unknown30", next to the known "unknown13"
+fn attribute_name(attribute_type: &impl Debug) -> String {
+    format!("{:?}", attribute_type)
+        .to_lowercase()
+        .replace(['(', ')'], "")
+}
+
+// "idiom=pad", idioms by name and every other attribute as a number
+fn format_attribute(attribute_type: &impl Debug, value: u16) -> String {
+    let name = attribute_name(attribute_type);
+    match Idiom::from(value) {
+        idiom if name == "idiom" && !matches!(idiom, Idiom::Unknown(_)) => {
+            format!("{}={}", name, format!("{:?}", idiom).to_lowercase())
+        }
+        _ => format!("{}={}", name, value),
+    }
+}
+
+/// Every attribute in token order, including zeros.
+///
+/// ```
+/// use carutil_lib::coreui::rendition::{Attribute, AttributeType16, KeyToken};
+///
+/// let key_token = KeyToken::new(vec![
+///     Attribute { name: AttributeType16::Element, value: 85 },
+///     Attribute { name: AttributeType16::Idiom, value: 1 },
+///     Attribute { name: AttributeType16::Identifier, value: 48301 },
+/// ]);
+/// assert_eq!(key_token.to_string(), "element=85 idiom=phone identifier=48301");
+/// ```
+impl Display for KeyToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let attributes: Vec<String> = self
+            .attributes
+            .iter()
+            .map(|attribute| format_attribute(&attribute.name, attribute.value))
+            .collect();
+        f.write_str(&attributes.join(" "))
+    }
+}
+
 impl Debug for KeyToken {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!(
             "KeyToken {{ cursor_hotspot: ({}, {}),  attributes: {:?} }}",
-            self._cursor_hotspot.0, self._cursor_hotspot.1, self.attributes
+            self.cursor_hotspot.0, self.cursor_hotspot.1, self.attributes
         ))
     }
 }
 
-#[derive(BinRead, BinWrite, Debug)]
+#[derive(BinRead, BinWrite, Debug, Clone, PartialEq, PartialOrd)]
 pub struct Attribute {
     pub name: AttributeType16,
     pub value: u16,
 }
 
-#[derive(Debug, BinRead, BinWrite, PartialEq, FromPrimitive, Clone, Copy)]
-#[brw(repr(u16))]
-pub enum AttributeType16 {
+// newer CoreUI versions keep adding layouts and key attributes, values we
+// don't know are kept as Unknown so the rest of the catalog still parses
+macro_rules! open_enum {
+    ($name:ident, $repr:ty, { $($variant:ident = $value:literal,)+ }) => {
+        #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+        pub enum $name {
+            $($variant,)+
+            Unknown($repr),
+        }
+
+        impl From<$name> for $repr {
+            fn from(value: $name) -> Self {
+                match value {
+                    $($name::$variant => $value,)+
+                    $name::Unknown(value) => value,
+                }
+            }
+        }
+
+        impl From<$repr> for $name {
+            fn from(value: $repr) -> Self {
+                match value {
+                    $($value => $name::$variant,)+
+                    _ => $name::Unknown(value),
+                }
+            }
+        }
+
+        impl BinRead for $name {
+            type Args<'a> = ();
+
+            fn read_options<R: std::io::Read + std::io::Seek>(
+                reader: &mut R,
+                endian: binrw::Endian,
+                args: Self::Args<'_>,
+            ) -> binrw::BinResult<Self> {
+                Ok($name::from(<$repr>::read_options(reader, endian, args)?))
+            }
+        }
+
+        impl BinWrite for $name {
+            type Args<'a> = ();
+
+            fn write_options<W: std::io::Write + std::io::Seek>(
+                &self,
+                writer: &mut W,
+                endian: binrw::Endian,
+                args: Self::Args<'_>,
+            ) -> binrw::BinResult<()> {
+                <$repr>::from(*self).write_options(writer, endian, args)
+            }
+        }
+    };
+}
+
+open_enum!(AttributeType16, u16, {
     Look = 0,
-    Element,
-    Part,
-    Size,
-    Direction,
-    PlaceHolder,
-    Value,
-    Appearance,
-    Dimension1,
-    Dimension2,
-    State,
-    Layer,
-    Scale,
-    Unknown13,
-    PresentationState,
-    Idiom,
-    Subtype,
-    Identifier,
-    PreviousValue,
-    PreviousState,
-    SizeClassHorizontal,
-    SizeClassVertical,
-    MemoryClass,
-    GraphicsClass,
-    DisplayGamut,
-    DeploymentTarget,
-}
-
-#[derive(Debug, BinRead, BinWrite, PartialEq, FromPrimitive, Clone, Copy)]
-#[brw(repr(u32))]
-pub enum AttributeType {
+    Element = 1,
+    Part = 2,
+    Size = 3,
+    Direction = 4,
+    PlaceHolder = 5,
+    Value = 6,
+    Appearance = 7,
+    Dimension1 = 8,
+    Dimension2 = 9,
+    State = 10,
+    Layer = 11,
+    Scale = 12,
+    Unknown13 = 13,
+    PresentationState = 14,
+    Idiom = 15,
+    Subtype = 16,
+    Identifier = 17,
+    PreviousValue = 18,
+    PreviousState = 19,
+    SizeClassHorizontal = 20,
+    SizeClassVertical = 21,
+    MemoryClass = 22,
+    GraphicsClass = 23,
+    DisplayGamut = 24,
+    DeploymentTarget = 25,
+    GlyphWeight = 26,
+    GlyphSize = 27,
+});
+
+// 32 bit version of above, as stored in the key format
+open_enum!(AttributeType, u32, {
     Look = 0,
-    Element,
-    Part,
-    Size,
-    Direction,
-    PlaceHolder,
-    Value,
-    Appearance,
-    Dimension1,
-    Dimension2,
-    State,
-    Layer,
-    Scale,
-    Unknown13,
-    PresentationState,
-    Idiom,
-    Subtype,
-    Identifier,
-    PreviousValue,
-    PreviousState,
-    SizeClassHorizontal,
-    SizeClassVertical,
-    MemoryClass,
-    GraphicsClass,
-    DisplayGamut,
-    DeploymentTarget,
+    Element = 1,
+    Part = 2,
+    Size = 3,
+    Direction = 4,
+    PlaceHolder = 5,
+    Value = 6,
+    Appearance = 7,
+    Dimension1 = 8,
+    Dimension2 = 9,
+    State = 10,
+    Layer = 11,
+    Scale = 12,
+    Unknown13 = 13,
+    PresentationState = 14,
+    Idiom = 15,
+    Subtype = 16,
+    Identifier = 17,
+    PreviousValue = 18,
+    PreviousState = 19,
+    SizeClassHorizontal = 20,
+    SizeClassVertical = 21,
+    MemoryClass = 22,
+    GraphicsClass = 23,
+    DisplayGamut = 24,
+    DeploymentTarget = 25,
+    GlyphWeight = 26,
+    GlyphSize = 27,
+});
+
+impl From<AttributeType16> for AttributeType {
+    fn from(attribute: AttributeType16) -> Self {
+        AttributeType::from(u16::from(attribute) as u32)
+    }
+}
+
+impl AttributeType {
+    pub const ALL: [AttributeType; 28] = [
+        AttributeType::Look,
+        AttributeType::Element,
+        AttributeType::Part,
+        AttributeType::Size,
+        AttributeType::Direction,
+        AttributeType::PlaceHolder,
+        AttributeType::Value,
+        AttributeType::Appearance,
+        AttributeType::Dimension1,
+        AttributeType::Dimension2,
+        AttributeType::State,
+        AttributeType::Layer,
+        AttributeType::Scale,
+        AttributeType::Unknown13,
+        AttributeType::PresentationState,
+        AttributeType::Idiom,
+        AttributeType::Subtype,
+        AttributeType::Identifier,
+        AttributeType::PreviousValue,
+        AttributeType::PreviousState,
+        AttributeType::SizeClassHorizontal,
+        AttributeType::SizeClassVertical,
+        AttributeType::MemoryClass,
+        AttributeType::GraphicsClass,
+        AttributeType::DisplayGamut,
+        AttributeType::DeploymentTarget,
+        AttributeType::GlyphWeight,
+        AttributeType::GlyphSize,
+    ];
+
+    // the variant name, ids we don't know are numbered like Unknown13
+    fn name(&self) -> String {
+        match self {
+            AttributeType::Unknown(value) => format!("Unknown{}", value),
+            _ => format!("{:?}", self),
+        }
+    }
+
+    // case-insensitive, accepts both the Debug and Display names ("Identifier", "NameIdentifier")
+    pub fn from_name(name: &str) -> Option<AttributeType> {
+        AttributeType::ALL.into_iter().find(|attribute| {
+            format!("{:?}", attribute).eq_ignore_ascii_case(name)
+                || attribute.to_string().eq_ignore_ascii_case(name)
+        })
+    }
 }
 
 impl Serialize for AttributeType {
@@ -166,7 +493,7 @@ impl Serialize for AttributeType {
     where
         S: Serializer,
     {
-        serializer.serialize_str(&format!("kCRTheme{:?}Name", self))
+        serializer.serialize_str(&format!("kCRTheme{}Name", self.name()))
     }
 }
 
@@ -174,7 +501,7 @@ impl Display for AttributeType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             AttributeType::Identifier => f.serialize_str("NameIdentifier"),
-            _ => f.serialize_str(&format!("{:?}", self)),
+            _ => f.serialize_str(&self.name()),
         }
     }
 }
@@ -183,10 +510,10 @@ impl Display for AttributeType {
 pub struct ColorFlags(pub u32);
 
 impl ColorFlags {
-    pub fn color_space(&self) -> coregraphics::ColorSpace {
+    // None for values that aren't a known color space
+    pub fn color_space(&self) -> Option<coregraphics::ColorSpace> {
         let value = self.0 & 0xff; // last byte?
-                                   // coregraphics::ColorSpace::SRGB
-        FromPrimitive::from_u32(value).unwrap()
+        FromPrimitive::from_u32(value)
     }
 }
 
@@ -237,6 +564,27 @@ pub enum Rendition {
         #[br(count = sizes_count)]
         entries: Vec<MultisizeImageSetEntry>,
     },
+    // INLK, points at the part of a packed image (atlas) holding this rendition
+    #[brw(magic = b"KNLI")]
+    InternalLink {
+        version: u32,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        layout: u16,
+        _key_count: u16,
+        #[br(count = _key_count)]
+        key: Vec<Attribute>,
+    },
+    // CSVG, the CoreSVG document an svg added to an image set is stored as
+    #[brw(magic = b"GVSC")]
+    Svg {
+        version: u32,
+        _raw_data_length: u32,
+        #[br(count = _raw_data_length)]
+        raw_data: RawData,
+    },
     Unknown {
         tag: u32,
         version: u32,
@@ -246,6 +594,56 @@ pub enum Rendition {
     },
 }
 
+impl Rendition {
+    // compression type and stored bytes of a theme pixel payload, whether or not
+    // it's wrapped in the KCBC container
+    pub fn theme_payload(&self) -> Option<(CompressionType, &[u8])> {
+        match self {
+            Rendition::Theme {
+                compression_type,
+                raw_data,
+                ..
+            }
+            | Rendition::ThemeCBCK {
+                compression_type,
+                raw_data,
+                ..
+            } => Some((*compression_type, &raw_data.0)),
+            _ => None,
+        }
+    }
+
+    // (x, y, width, height) of an internal link's rendition in its atlas
+    pub fn internal_link_frame(&self) -> Option<(u32, u32, u32, u32)> {
+        match self {
+            Rendition::InternalLink {
+                x,
+                y,
+                width,
+                height,
+                ..
+            } => Some((*x, *y, *width, *height)),
+            _ => None,
+        }
+    }
+
+    // the svg document bytes of a CoreSVG rendition
+    pub fn svg_document(&self) -> Option<&[u8]> {
+        match self {
+            Rendition::Svg { raw_data, .. } => Some(&raw_data.0),
+            _ => None,
+        }
+    }
+
+    // the tag and bytes of a payload in a format we can't read yet
+    pub fn unknown_payload(&self) -> Option<(u32, &[u8])> {
+        match self {
+            Rendition::Unknown { tag, raw_data, .. } => Some((*tag, &raw_data.0)),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, BinRead, BinWrite, Clone, PartialEq, PartialOrd)]
 pub struct MultisizeImageSetEntry {
     pub width: u32,
@@ -254,17 +652,108 @@ pub struct MultisizeImageSetEntry {
     pub idiom: Idiom,
 }
 
-#[derive(Debug, BinRead, BinWrite, Clone, FromPrimitive, Serialize, PartialEq, PartialOrd)]
-#[brw(repr = u16)]
-#[serde(rename_all = "lowercase")]
+// newer platforms keep adding idioms, values we don't know are kept as is
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub enum Idiom {
-    Universal = 0,
+    Universal,
     Phone,
     Pad,
     TV,
     Car,
     Watch,
     Marketing,
+    Mac,
+    Vision,
+    Unknown(u16),
+}
+
+impl Idiom {
+    // how assetutil prints it, lowercase, the value for idioms without a name
+    pub fn name(&self) -> String {
+        match self {
+            Idiom::Unknown(value) => value.to_string(),
+            _ => format!("{:?}", self).to_lowercase(),
+        }
+    }
+
+    pub fn value(&self) -> u16 {
+        match self {
+            Idiom::Universal => 0,
+            Idiom::Phone => 1,
+            Idiom::Pad => 2,
+            Idiom::TV => 3,
+            Idiom::Car => 4,
+            Idiom::Watch => 5,
+            Idiom::Marketing => 6,
+            Idiom::Mac => 7,
+            Idiom::Vision => 8,
+            Idiom::Unknown(value) => *value,
+        }
+    }
+}
+
+impl From<u16> for Idiom {
+    fn from(value: u16) -> Self {
+        match value {
+            0 => Idiom::Universal,
+            1 => Idiom::Phone,
+            2 => Idiom::Pad,
+            3 => Idiom::TV,
+            4 => Idiom::Car,
+            5 => Idiom::Watch,
+            6 => Idiom::Marketing,
+            7 => Idiom::Mac,
+            8 => Idiom::Vision,
+            _ => Idiom::Unknown(value),
+        }
+    }
+}
+
+impl FromPrimitive for Idiom {
+    fn from_i64(n: i64) -> Option<Self> {
+        u16::try_from(n).ok().map(Idiom::from)
+    }
+
+    fn from_u64(n: u64) -> Option<Self> {
+        u16::try_from(n).ok().map(Idiom::from)
+    }
+}
+
+impl BinRead for Idiom {
+    type Args<'a> = ();
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(Idiom::from(u16::read_options(reader, endian, args)?))
+    }
+}
+
+impl BinWrite for Idiom {
+    type Args<'a> = ();
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.value().write_options(writer, endian, args)
+    }
+}
+
+impl Serialize for Idiom {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Idiom::Unknown(value) => serializer.serialize_u16(*value),
+            _ => serializer.serialize_str(&self.name()),
+        }
+    }
 }
 
 #[derive(Debug, BinRead, BinWrite, Clone, Copy, Serialize, PartialEq, PartialOrd)]
@@ -289,6 +778,31 @@ pub enum CompressionType {
     DeepMap2,
 }
 
+impl CompressionType {
+    pub const ALL: [CompressionType; 12] = [
+        CompressionType::Uncompressed,
+        CompressionType::RLE,
+        CompressionType::ZIP,
+        CompressionType::LZVN,
+        CompressionType::LZFSE,
+        CompressionType::JPEGLZFSE,
+        CompressionType::Blurred,
+        CompressionType::ASTC,
+        CompressionType::PaletteImg,
+        CompressionType::HEVC,
+        CompressionType::DeepMapLZFSE,
+        CompressionType::DeepMap2,
+    ];
+
+    // how Compression prints it ("lzfse", "palette-img", "deepmap2", ...)
+    pub fn name(&self) -> String {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|value| value.as_str().map(|name| name.to_string()))
+            .unwrap_or_else(|| format!("{:?}", self))
+    }
+}
+
 #[derive(Debug, Serialize, FromPrimitive)]
 pub enum State {
     Normal,
@@ -303,12 +817,159 @@ pub enum TemplateMode {
     Template,
 }
 
+// CoreThemeImageSubtype, how theme artwork is stretched, used as the Subtype
+// of system theme catalogs
+#[derive(Debug, Clone, Copy, Serialize, FromPrimitive, PartialEq)]
+pub enum ImageSubtype {
+    #[serde(rename = "one-part fixed size")]
+    OnePartFixedSize = 10,
+    #[serde(rename = "one-part tile")]
+    OnePartTile = 11,
+    #[serde(rename = "one-part scale")]
+    OnePartScale = 12,
+    #[serde(rename = "three-part horizontal tile")]
+    ThreePartHTile = 20,
+    #[serde(rename = "three-part horizontal scale")]
+    ThreePartHScale = 21,
+    #[serde(rename = "three-part horizontal uniform")]
+    ThreePartHUniform = 22,
+    #[serde(rename = "three-part vertical tile")]
+    ThreePartVTile = 23,
+    #[serde(rename = "three-part vertical scale")]
+    ThreePartVScale = 24,
+    #[serde(rename = "three-part vertical uniform")]
+    ThreePartVUniform = 25,
+    #[serde(rename = "nine-part tile")]
+    NinePartTile = 30,
+    #[serde(rename = "nine-part scale")]
+    NinePartScale = 31,
+    #[serde(rename = "nine-part horizontal uniform vertical scale")]
+    NinePartHorizontalUniformVerticalScale = 32,
+    #[serde(rename = "nine-part horizontal scale vertical uniform")]
+    NinePartHorizontalScaleVerticalUniform = 33,
+    #[serde(rename = "nine-part edges only")]
+    NinePartEdgesOnly = 34,
+    #[serde(rename = "six-part")]
+    SixPart = 40,
+    #[serde(rename = "animation filmstrip")]
+    AnimationFilmstrip = 50,
+}
+
 #[derive(Debug, Serialize, FromPrimitive)]
 pub enum Value {
     Off = 0,
     On = 1,
 }
 
+// which way artwork faces, images flipped for right-to-left languages are
+// stored as separate rightToLeft renditions
+#[derive(Debug, Serialize, FromPrimitive, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Direction {
+    Horizontal = 0,
+    Vertical,
+    PointingUp,
+    PointingDown,
+    LeftToRight,
+    RightToLeft,
+}
+
+// whether the window showing the artwork is active
+#[derive(Debug, Serialize, FromPrimitive, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum PresentationState {
+    Active = 0,
+    Inactive,
+    ActiveMain,
+}
+
+// the UI era of system theme artwork: before and after the flat redesign
+#[derive(Debug, Serialize, FromPrimitive, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Look {
+    Default = 0,
+    PreFlat,
+    Flat,
+}
+
+// weight of a vector glyph (symbol), the same scale as the font weights
+#[derive(Debug, Serialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum GlyphWeight {
+    UltraLight = 1,
+    Thin,
+    Light,
+    Regular,
+    Medium,
+    Semibold,
+    Bold,
+    Heavy,
+    Black,
+}
+
+// the small, medium and large scales symbols come in
+#[derive(Debug, Serialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum GlyphSize {
+    Small = 1,
+    Medium,
+    Large,
+}
+
+impl FromPrimitive for GlyphWeight {
+    fn from_i64(n: i64) -> Option<Self> {
+        u64::try_from(n).ok().and_then(GlyphWeight::from_u64)
+    }
+
+    fn from_u64(n: u64) -> Option<Self> {
+        match n {
+            1 => Some(GlyphWeight::UltraLight),
+            2 => Some(GlyphWeight::Thin),
+            3 => Some(GlyphWeight::Light),
+            4 => Some(GlyphWeight::Regular),
+            5 => Some(GlyphWeight::Medium),
+            6 => Some(GlyphWeight::Semibold),
+            7 => Some(GlyphWeight::Bold),
+            8 => Some(GlyphWeight::Heavy),
+            9 => Some(GlyphWeight::Black),
+            _ => None,
+        }
+    }
+}
+
+impl FromPrimitive for GlyphSize {
+    fn from_i64(n: i64) -> Option<Self> {
+        u64::try_from(n).ok().and_then(GlyphSize::from_u64)
+    }
+
+    fn from_u64(n: u64) -> Option<Self> {
+        match n {
+            1 => Some(GlyphSize::Small),
+            2 => Some(GlyphSize::Medium),
+            3 => Some(GlyphSize::Large),
+            _ => None,
+        }
+    }
+}
+
+// a key attribute value by name when we know what it means, as the number
+// in the key otherwise
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum NamedValue<T> {
+    Named(T),
+    Number(u16),
+}
+
+impl<T: FromPrimitive> NamedValue<T> {
+    pub fn from_u16(value: u16) -> Self {
+        match T::from_u16(value) {
+            Some(named) => NamedValue::Named(named),
+            None => NamedValue::Number(value),
+        }
+    }
+}
+
 type BGRAColor = u32;
 
 #[derive(Debug, BinRead, Clone)]
@@ -319,30 +980,39 @@ pub struct QuantizedImage {
     pub color_count: u16,
     #[br(count = color_count)]
     pub color_table: Vec<BGRAColor>,
-    #[br(count = width * height / 2)]
+    #[br(count = width as usize * height as usize / 2)]
     pub data: Vec<u16>, // little endian u16, two u8 indices per value
 }
 
 impl QuantizedImage {
-    pub fn extract(&self, buffer: &mut [u8]) {
-        for i in 0..self.data.len() {
-            let a = (self.data[i] >> 8) as usize;
-            let b = (self.data[i] & 0xff) as usize;
-            buffer[8 * i + 0] = ((self.color_table[a] >> 8) & 0xff) as u8;
-            buffer[8 * i + 1] = ((self.color_table[a] >> 16) & 0xff) as u8;
-            buffer[8 * i + 2] = ((self.color_table[a] >> 24) & 0xff) as u8;
-            buffer[8 * i + 3] = ((self.color_table[a] >> 0) & 0xff) as u8;
-            buffer[8 * i + 4] = ((self.color_table[b] >> 8) & 0xff) as u8;
-            buffer[8 * i + 5] = ((self.color_table[b] >> 16) & 0xff) as u8;
-            buffer[8 * i + 6] = ((self.color_table[b] >> 24) & 0xff) as u8;
-            buffer[8 * i + 7] = ((self.color_table[b] >> 0) & 0xff) as u8;
+    // RGBA of two pixels per index pair into buffer, which holds 4 bytes per
+    // pixel. Pairs past the end of buffer are dropped, indices past the end
+    // of the color table are an error
+    pub fn extract(&self, buffer: &mut [u8]) -> anyhow::Result<()> {
+        let color = |index: usize| {
+            self.color_table.get(index).copied().ok_or_else(|| {
+                anyhow::anyhow!(
+                    "color index {} is outside the {} color table",
+                    index,
+                    self.color_table.len()
+                )
+            })
+        };
+        for (pair, pixels) in zip(&self.data, buffer.chunks_exact_mut(8)) {
+            let a = color((pair >> 8) as usize)?;
+            let b = color((pair & 0xff) as usize)?;
+            for (pixel, color) in zip(pixels.chunks_exact_mut(4), [a, b]) {
+                pixel[0] = ((color >> 8) & 0xff) as u8;
+                pixel[1] = ((color >> 16) & 0xff) as u8;
+                pixel[2] = ((color >> 24) & 0xff) as u8;
+                pixel[3] = (color & 0xff) as u8;
+            }
         }
+        Ok(())
     }
 }
 
-#[derive(BinRead, Debug)]
-#[br(repr(u16))]
-pub enum LayoutType {
+open_enum!(LayoutType, u16, {
     TextEffect = 0x007,
     Vector = 0x009,
     Image = 0x00C, // ???
@@ -360,12 +1030,10 @@ pub enum LayoutType {
     LayerReference = 0x3F4,
     ContentRendition = 0x3F5,
     RecognitionObject = 0x3F6,
-}
+});
 
 // 32 bit version of above
-#[derive(BinRead, BinWrite, Debug, Clone, Copy)]
-#[brw(repr(u32))]
-pub enum LayoutType32 {
+open_enum!(LayoutType32, u32, {
     TextEffect = 0x007,
     Vector = 0x009,
     Image = 0x00C, // ???
@@ -383,4 +1051,4 @@ pub enum LayoutType32 {
     LayerReference = 0x3F4,
     ContentRendition = 0x3F5,
     RecognitionObject = 0x3F6,
-}
+});
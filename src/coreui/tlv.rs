@@ -50,8 +50,9 @@ pub enum RenditionType {
         _length: u32,
         string_length: u32,
         _padding: u32,
-        #[br(count = string_length)]
-        string: Vec<u8>,
+        // read the whole tlv, string_length may or may not include padding
+        #[br(count = _length.saturating_sub(8))]
+        string: common::RawData,
     },
     #[brw(magic = 0x3EEu32)]
     EXIFOrientation {
@@ -72,6 +73,26 @@ pub enum RenditionType {
     },
 }
 
+impl RenditionType {
+    // UTI string trimmed at the first NUL, None if this isn't a UTI tlv
+    pub fn uti(&self) -> Option<String> {
+        match self {
+            Self::UTI {
+                string_length,
+                string,
+                ..
+            } => {
+                let length = (*string_length as usize).min(string.0.len());
+                Some(common::parse_padded_string(&string.0[..length]))
+            }
+            _ => None,
+        }
+    }
+}
+
+common::compact_debug!(RenditionType);
+
+#[cfg(feature = "debug-impls")]
 impl Debug for RenditionType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -87,9 +108,9 @@ impl Debug for RenditionType {
                 "BlendModeAndOpacity {{ blend: {}, opacity: {} }}",
                 blend, opacity
             )),
-            Self::UTI { string, .. } => f.write_fmt(format_args!(
+            Self::UTI { .. } => f.write_fmt(format_args!(
                 "UTI {{ string: {} }}",
-                String::from_utf8_lossy(&string)
+                self.uti().unwrap_or_default()
             )),
             Self::EXIFOrientation { orientation, .. } => f.write_fmt(format_args!(
                 "EXIFOrientation {{ orientation: {:?} }}",
@@ -0,0 +1,48 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use crate::coreui;
+use crate::coreui::rendition::AttributeType;
+use crate::coreui::rendition::LayoutType32;
+use crate::filter::RenditionFilter;
+
+#[derive(Debug)]
+pub struct CoverageReport {
+    // number of image facets that were checked
+    pub checked: usize,
+    // facet names without any rendition matching the filter, sorted
+    pub missing: Vec<String>,
+}
+
+// for every facet with at least one image rendition, checks that some rendition
+// of that facet matches all of the filter's attributes
+pub fn coverage(store: &coreui::CommonAssetStorage, filter: &RenditionFilter) -> CoverageReport {
+    let names = store.name_identifier_to_facet_name();
+
+    let mut facets: BTreeMap<String, bool> = BTreeMap::new();
+    let mut image_facets: BTreeSet<String> = BTreeSet::new();
+    for (rendition_key, csi_header) in &store.imagedb {
+        let key_values = store.key_values(rendition_key);
+        let name = key_values
+            .iter()
+            .find(|(attribute, _)| *attribute == AttributeType::Identifier)
+            .and_then(|(_, identifier)| names.get(identifier).cloned())
+            .unwrap_or_else(|| csi_header.csimetadata.name());
+
+        if matches!(csi_header.csimetadata.layout, LayoutType32::Image) {
+            image_facets.insert(name.clone());
+        }
+        let covered = facets.entry(name).or_insert(false);
+        *covered |= filter.matches_key(&key_values);
+    }
+
+    let missing = image_facets
+        .iter()
+        .filter(|name| !facets.get(*name).copied().unwrap_or(false))
+        .cloned()
+        .collect();
+    CoverageReport {
+        checked: image_facets.len(),
+        missing,
+    }
+}
@@ -0,0 +1,253 @@
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+use crate::actool::catalog_type;
+use crate::common;
+use crate::coreui;
+use crate::coreui::csi;
+use crate::coreui::rendition;
+use crate::coreui::rendition::CompressionType;
+use crate::filter;
+
+// the Contents.json of a .dataset folder
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DataSetContents {
+    pub data: Vec<DataSetItem>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub info: Option<catalog_type::Info>,
+    #[serde(default, skip_serializing_if = "DataSetProperties::is_empty")]
+    pub properties: DataSetProperties,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DataSetItem {
+    pub filename: String,
+    pub idiom: Option<String>,
+    #[serde(rename = "universal-type-identifier")]
+    pub universal_type_identifier: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct DataSetProperties {
+    #[serde(
+        rename = "on-demand-resource-tags",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub on_demand_resource_tags: Vec<String>,
+}
+
+impl DataSetProperties {
+    fn is_empty(&self) -> bool {
+        self.on_demand_resource_tags.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct DataSetFile {
+    pub file_name: String,
+    pub idiom: rendition::Idiom,
+    pub uti: String,
+    pub data: Vec<u8>,
+}
+
+// a .dataset folder with the contents of every file it lists
+#[derive(Debug)]
+pub struct DataSet {
+    // the folder name without .dataset
+    pub name: String,
+    // on-demand-resource tags, read but not written: the tlv Xcode stores
+    // them in isn't known, so actool refuses datasets that have them
+    pub tags: Vec<String>,
+    // written to the facet's key token, only cursors have one
+    pub cursor_hotspot: Option<(u16, u16)>,
+    pub files: Vec<DataSetFile>,
+}
+
+// Xcode falls back to this for files without a universal-type-identifier
+const DEFAULT_UTI: &str = "public.data";
+
+// element and part Xcode gives every facet in a catalog
+const FACET_ELEMENT: u16 = 85;
+const FACET_PART: u16 = 181;
+
+pub fn read_dataset(path: &Path) -> Result<DataSet> {
+    let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .context(format!("invalid dataset path {}", path.display()))?
+        .to_string();
+    let contents_path = path.join("Contents.json");
+    let contents: DataSetContents = serde_json::from_slice(
+        &fs::read(&contents_path).context(format!("unable to read {}", contents_path.display()))?,
+    )
+    .context(format!("invalid {}", contents_path.display()))?;
+    let files = contents
+        .data
+        .into_iter()
+        .map(|item| {
+            let idiom = match &item.idiom {
+                Some(idiom) => rendition::Idiom::from(filter::parse_idiom(idiom)?),
+                None => rendition::Idiom::Universal,
+            };
+            let file_path = path.join(&item.filename);
+            Ok(DataSetFile {
+                data: fs::read(&file_path)
+                    .context(format!("unable to read {}", file_path.display()))?,
+                file_name: item.filename,
+                idiom,
+                uti: item
+                    .universal_type_identifier
+                    .unwrap_or_else(|| DEFAULT_UTI.to_string()),
+            })
+        })
+        .collect::<Result<Vec<DataSetFile>>>()?;
+    Ok(DataSet {
+        name,
+        tags: contents.properties.on_demand_resource_tags,
+        cursor_hotspot: None,
+        files,
+    })
+}
+
+fn tlv(tag: u32, value: &[u8]) -> Vec<u8> {
+    let mut tlv = vec![];
+    tlv.extend(tag.to_le_bytes());
+    tlv.extend((value.len() as u32).to_le_bytes());
+    tlv.extend(value);
+    tlv
+}
+
+// the tlvs Xcode writes for data renditions (blend mode, UTI and EXIF
+// orientation)
+fn data_tlvs(uti: &str) -> Vec<u8> {
+    let mut blend_mode = vec![];
+    blend_mode.extend(0f32.to_le_bytes());
+    blend_mode.extend(1f32.to_le_bytes());
+
+    let mut uti_string = uti.as_bytes().to_vec();
+    uti_string.push(0);
+    let mut uti_value = vec![];
+    uti_value.extend((uti_string.len() as u32).to_le_bytes());
+    uti_value.extend(0u32.to_le_bytes());
+    uti_value.extend(uti_string);
+
+    let mut tlvs = tlv(0x3EC, &blend_mode);
+    tlvs.extend(tlv(0x3ED, &uti_value));
+    tlvs.extend(tlv(0x3EE, &1u32.to_le_bytes()));
+    tlvs
+}
+
+// a Data layout rendition holding data, LZFSE compressed when compress is set
+pub fn data_rendition(name: &str, uti: &str, data: &[u8], compress: bool) -> Result<csi::Header> {
+    let mut csi_header = csi::Header {
+        version: 1,
+        rendition_flags: csi::RenditionFlags(0),
+        width: 0,
+        height: 0,
+        scale_factor: 100,
+        pixel_format: csi::PixelFormat::Data,
+        color_space: csi::ColorModel(0),
+        csimetadata: csi::Metadata::new(0, rendition::LayoutType32::Data, name)?,
+        csibitmaplist: csi::BitmapList {
+            tlv_length: 0,
+            bitmap_count: csi::BitmapList::EXPECTED_BITMAP_COUNT,
+            reserved: csi::BitmapList::EXPECTED_RESERVED,
+            rendition_length: 0,
+        },
+        tlv_data: common::RawData(data_tlvs(uti)),
+        rendition_data: Some(rendition::Rendition::RawData {
+            version: 1,
+            _raw_data_length: 0,
+            raw_data: common::RawData(vec![]),
+        }),
+    };
+    if compress {
+        let mut compressed = vec![];
+        lzfse_rust::encode_bytes(data, &mut compressed)?;
+        csi_header.set_payload(&compressed, CompressionType::LZFSE)?;
+    } else {
+        csi_header.set_payload(data, CompressionType::Uncompressed)?;
+    }
+    Ok(csi_header)
+}
+
+// a facet and one rendition per file for each dataset. Facets get name
+// identifiers above the ones the store already uses. File names too long for
+// a rendition name are an error, or cut to fit with truncate
+pub fn add_datasets(
+    store: &mut coreui::CommonAssetStorage,
+    datasets: &[DataSet],
+    compress: bool,
+    truncate: bool,
+) -> Result<()> {
+    let first_identifier = store
+        .facetkeysdb
+        .values()
+        .filter_map(|key_token| key_token.find_attribute(rendition::AttributeType16::Identifier))
+        .max()
+        .unwrap_or(0) as u32
+        + 1;
+    for (index, dataset) in datasets.iter().enumerate() {
+        if store.facetkeysdb.contains_key(&dataset.name) {
+            anyhow::bail!("duplicate asset name {:?}", dataset.name);
+        }
+        let identifier = u16::try_from(first_identifier + index as u32)
+            .context("ran out of name identifiers")?;
+        let facet_attributes = vec![
+            rendition::Attribute {
+                name: rendition::AttributeType16::Element,
+                value: FACET_ELEMENT,
+            },
+            rendition::Attribute {
+                name: rendition::AttributeType16::Part,
+                value: FACET_PART,
+            },
+            rendition::Attribute {
+                name: rendition::AttributeType16::Identifier,
+                value: identifier,
+            },
+        ];
+        for file in &dataset.files {
+            let mut attributes = facet_attributes.clone();
+            attributes.extend([
+                rendition::Attribute {
+                    name: rendition::AttributeType16::Scale,
+                    value: 1,
+                },
+                rendition::Attribute {
+                    name: rendition::AttributeType16::Idiom,
+                    value: file.idiom.value(),
+                },
+            ]);
+            let key = store.renditionkeyfmt.key(&attributes);
+            if store.imagedb.contains_key(&key) {
+                anyhow::bail!(
+                    "{:?} has more than one file for idiom {:?}",
+                    dataset.name,
+                    file.idiom
+                );
+            }
+            let name = if truncate {
+                common::truncate_str(&file.file_name, csi::Metadata::NAME_LENGTH)
+            } else {
+                &file.file_name
+            };
+            let csi_header = data_rendition(name, &file.uti, &file.data, compress)?;
+            store.imagedb.insert(key, csi_header);
+        }
+        store.facetkeysdb.insert(
+            dataset.name.clone(),
+            rendition::KeyToken::with_cursor_hotspot(
+                facet_attributes,
+                dataset.cursor_hotspot.unwrap_or_default(),
+            ),
+        );
+    }
+    store.header.rendition_count = store.imagedb.len() as u32;
+    Ok(())
+}
@@ -0,0 +1,379 @@
+// rebuilds an .xcassets folder from a catalog: an .imageset, .colorset or
+// .dataset per facet, with a Contents.json made from the rendition keys.
+// Payloads are decoded the way extract decodes them, and the groups of
+// namespaced names become folders that provide a namespace. App icons come
+// back as image sets, the multisize rendition listing their sizes is left out
+
+use anyhow::Context;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::actool::app_icon_type;
+use crate::actool::catalog_type;
+use crate::actool::common_type;
+use crate::actool::image_set_type;
+use crate::actool::named_color_type;
+use crate::assetutil;
+use crate::common;
+use crate::coreui;
+use crate::coreui::csi::OutputFormat;
+use crate::coreui::rendition;
+use crate::dataset;
+use crate::extract;
+
+// the renditions of one facet, by the kind of folder they go in
+enum AssetSet {
+    Images(Vec<image_set_type::ImageSetImage>),
+    Colors(Vec<named_color_type::NamedColor>),
+    Data(Vec<dataset::DataSetItem>),
+}
+
+impl AssetSet {
+    fn extension(&self) -> &'static str {
+        match self {
+            AssetSet::Images(_) => "imageset",
+            AssetSet::Colors(_) => "colorset",
+            AssetSet::Data(_) => "dataset",
+        }
+    }
+
+    fn contents(self) -> Result<Vec<u8>> {
+        let info = catalog_type::Info::default();
+        Ok(match self {
+            AssetSet::Images(images) => serde_json::to_vec_pretty(&image_set_type::ImageSetType {
+                info,
+                properties: None,
+                images,
+            })?,
+            AssetSet::Colors(colors) => {
+                serde_json::to_vec_pretty(&named_color_type::NamedColorType {
+                    info,
+                    properties: None,
+                    colors,
+                })?
+            }
+            AssetSet::Data(data) => serde_json::to_vec_pretty(&dataset::DataSetContents {
+                data,
+                info: Some(info),
+                properties: dataset::DataSetProperties::default(),
+            })?,
+        })
+    }
+}
+
+struct Folder {
+    set: AssetSet,
+    // file name and contents, next to Contents.json
+    files: Vec<(String, Vec<u8>)>,
+    used: extract::OutputNames,
+}
+
+// the rendition key attributes an xcassets entry is told apart by
+struct Variant {
+    appearances: Vec<common_type::Appearance>,
+    display_gamut: Option<common_type::DisplayGamut>,
+    idiom: common_type::Idiom,
+    scale: u16,
+}
+
+impl Variant {
+    fn new(
+        key_values: &[(rendition::AttributeType, u16)],
+        appearancedb: &BTreeMap<String, u32>,
+    ) -> Result<Variant> {
+        let value = |attribute| {
+            key_values
+                .iter()
+                .find(|(key_attribute, _)| *key_attribute == attribute)
+                .map(|(_, value)| *value)
+                .unwrap_or(0)
+        };
+        let appearance = value(rendition::AttributeType::Appearance) as u32;
+        let appearance_name = match appearance {
+            0 => None,
+            _ => Some(
+                appearancedb
+                    .iter()
+                    .find(|(_, identifier)| **identifier == appearance)
+                    .map(|(appearance_name, _)| appearance_name.to_string())
+                    .unwrap_or_else(|| coreui::unknown_appearance_name(appearance)),
+            ),
+        };
+        let variant =
+            assetutil::appearance_variant(appearance_name.as_deref()).with_context(|| {
+                format!(
+                    "appearance {:?} has no xcassets equivalent",
+                    appearance_name
+                )
+            })?;
+        let mut appearances = vec![];
+        match variant.luminosity {
+            assetutil::Luminosity::Any => {}
+            assetutil::Luminosity::Light => {
+                appearances.push(appearance_value("luminosity", "light"))
+            }
+            assetutil::Luminosity::Dark => appearances.push(appearance_value("luminosity", "dark")),
+        }
+        if variant.high_contrast {
+            appearances.push(appearance_value("contrast", "high"));
+        }
+        Ok(Variant {
+            appearances,
+            display_gamut: match value(rendition::AttributeType::DisplayGamut) {
+                1 => Some(common_type::DisplayGamut::DisplayP3),
+                _ => None,
+            },
+            idiom: xcassets_idiom(rendition::Idiom::from(value(
+                rendition::AttributeType::Idiom,
+            ))),
+            scale: value(rendition::AttributeType::Scale),
+        })
+    }
+
+    fn scale(&self) -> Option<app_icon_type::Scale> {
+        match self.scale {
+            1 => Some(app_icon_type::Scale::OneX),
+            2 => Some(app_icon_type::Scale::TwoX),
+            3 => Some(app_icon_type::Scale::ThreeX),
+            _ => None,
+        }
+    }
+}
+
+fn appearance_value(appearance: &str, value: &str) -> common_type::Appearance {
+    common_type::Appearance {
+        appearance: appearance.to_string(),
+        value: value.to_string(),
+    }
+}
+
+// idioms without an xcassets name are written as universal
+fn xcassets_idiom(idiom: rendition::Idiom) -> common_type::Idiom {
+    match idiom {
+        rendition::Idiom::Phone => common_type::Idiom::Iphone,
+        rendition::Idiom::Pad => common_type::Idiom::Ipad,
+        rendition::Idiom::TV => common_type::Idiom::Tv,
+        rendition::Idiom::Car => common_type::Idiom::Car,
+        rendition::Idiom::Watch => common_type::Idiom::Watch,
+        rendition::Idiom::Marketing => common_type::Idiom::IosMarketing,
+        rendition::Idiom::Mac => common_type::Idiom::Mac,
+        rendition::Idiom::Vision => common_type::Idiom::Vision,
+        rendition::Idiom::Universal | rendition::Idiom::Unknown(_) => common_type::Idiom::Universal,
+    }
+}
+
+// the name an enum is written with in Contents.json
+fn serialized_name(value: impl Serialize) -> Option<String> {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+}
+
+// writes an .xcassets folder at output_path, the summary lists paths relative
+// to it
+pub fn decompile(
+    car: &coreui::CarUtilAssetStorage,
+    output_path: impl AsRef<Path>,
+    options: &coreui::ParseOptions,
+) -> Result<extract::ExtractSummary> {
+    let output_path = output_path.as_ref();
+    let store = &car.theme_store.store;
+    let facet_names = store.name_identifier_to_facet_name();
+    let name_identifier_collisions = store.name_identifier_collisions();
+    let appearancedb = store.appearance_names().unwrap_or_default();
+    let mut summary = extract::ExtractSummary::default();
+    let mut folders: BTreeMap<String, Folder> = BTreeMap::new();
+
+    for (rendition_key, csi_header) in &store.imagedb {
+        let name = csi_header.csimetadata.name();
+        let Some(facet_name) = extract::rendition_facet_name(
+            store,
+            rendition_key,
+            &facet_names,
+            &name_identifier_collisions,
+        )
+        .or_else(|| assetutil::facet_name_from_rendition_name(&name)) else {
+            summary.skipped.push(format!("{}: no facet name", name));
+            continue;
+        };
+        let key_values = store.key_values(rendition_key);
+        let variant = match Variant::new(&key_values, &appearancedb) {
+            Ok(variant) => variant,
+            Err(err) => {
+                summary.skipped.push(format!("{}: {}", name, err));
+                continue;
+            }
+        };
+        let layout = csi_header.csimetadata.layout;
+        let set = match layout {
+            rendition::LayoutType32::Color => AssetSet::Colors(vec![]),
+            rendition::LayoutType32::Data => AssetSet::Data(vec![]),
+            rendition::LayoutType32::Image => AssetSet::Images(vec![]),
+            rendition::LayoutType32::Vector => {
+                summary
+                    .skipped
+                    .push(format!("{}: symbol glyphs aren't decompiled", name));
+                continue;
+            }
+            // multisize images and the like carry nothing to write
+            _ => continue,
+        };
+        let folder = folders.entry(facet_name.clone()).or_insert_with(|| Folder {
+            set,
+            files: vec![],
+            used: extract::OutputNames::default(),
+        });
+
+        match (&mut folder.set, &csi_header.rendition_data) {
+            (AssetSet::Colors(colors), Some(rendition::Rendition::Color { components, .. })) => {
+                // gray and alpha, or red, green, blue and alpha
+                let (red, green, blue, alpha) = match components[..] {
+                    [gray, alpha] => (gray, gray, gray, alpha),
+                    [red, green, blue, alpha] => (red, green, blue, alpha),
+                    _ => {
+                        summary.skipped.push(format!(
+                            "{}: a color with {} components",
+                            facet_name,
+                            components.len()
+                        ));
+                        continue;
+                    }
+                };
+                colors.push(named_color_type::NamedColor {
+                    appearances: variant.appearances,
+                    display_gamut: variant.display_gamut,
+                    idiom: variant.idiom,
+                    color: named_color_type::Color {
+                        color_space: named_color_type::ColorSpace::SRGB,
+                        components: named_color_type::Components {
+                            red,
+                            green,
+                            blue,
+                            alpha,
+                        },
+                    },
+                });
+            }
+            (AssetSet::Data(data), _) if matches!(layout, rendition::LayoutType32::Data) => {
+                let (file_name, payload, warning) = match extract::data_file(csi_header, &name) {
+                    Ok(data_file) => data_file,
+                    Err(err) => {
+                        summary.failed.push(err.to_string());
+                        continue;
+                    }
+                };
+                summary.warnings.extend(warning);
+                let file_name = extract::output_file_name(
+                    &file_name,
+                    OutputFormat::sniff(&payload, csi_header.pixel_format),
+                    None,
+                    &mut folder.used,
+                );
+                data.push(dataset::DataSetItem {
+                    filename: file_name.clone(),
+                    idiom: serialized_name(&variant.idiom),
+                    universal_type_identifier: csi_header.uti(),
+                });
+                folder.files.push((file_name, payload));
+            }
+            (AssetSet::Images(images), _) if matches!(layout, rendition::LayoutType32::Image) => {
+                // renditions packed into an atlas are cropped out of it
+                let extracted_file = match store
+                    .decode_rendition(csi_header, options)
+                    .and_then(|decoded| decoded.into_extracted_file(&csi_header.csimetadata.name()))
+                {
+                    Ok(extracted_file) => extracted_file,
+                    Err(err) => {
+                        summary.failed.push(err.to_string());
+                        continue;
+                    }
+                };
+                summary.warnings.extend(extracted_file.warning);
+                let file_name = extract::output_file_name(
+                    &assetutil::synthesized_rendition_name(&facet_name, &key_values, &appearancedb),
+                    extracted_file.format,
+                    None,
+                    &mut folder.used,
+                );
+                // pdfs and svgs are for every scale
+                let scale = match extracted_file.format {
+                    OutputFormat::Pdf | OutputFormat::Svg => None,
+                    _ => variant.scale(),
+                };
+                images.push(image_set_type::ImageSetImage {
+                    filename: Some(file_name.clone()),
+                    appearances: variant.appearances,
+                    display_gamut: variant.display_gamut,
+                    idiom: variant.idiom,
+                    scale,
+                });
+                folder.files.push((file_name, extracted_file.data));
+            }
+            (set, _) => summary.skipped.push(format!(
+                "{}: a {:?} rendition of the {} {:?}",
+                name,
+                layout,
+                set.extension(),
+                facet_name
+            )),
+        }
+    }
+
+    let write = |relative_path: &str, data: &[u8], summary: &mut extract::ExtractSummary| {
+        let path = output_path.join(relative_path);
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)
+                .with_context(|| format!("unable to create {}", directory.display()))?;
+        }
+        fs::write(&path, data).with_context(|| format!("unable to write {}", path.display()))?;
+        summary.written.push(relative_path.to_string());
+        Ok::<(), anyhow::Error>(())
+    };
+    let catalog = |properties| {
+        serde_json::to_vec_pretty(&catalog_type::Catalog {
+            info: catalog_type::Info::default(),
+            properties,
+        })
+    };
+    write("Contents.json", &catalog(None)?, &mut summary)?;
+    let mut groups = BTreeSet::new();
+    for (facet_name, folder) in folders {
+        // every group of a namespaced name is a folder providing a namespace.
+        // Each component is made portable, so names with "..", empty or
+        // leading "/" components stay under the output path
+        let mut parts: Vec<String> = facet_name
+            .split('/')
+            .map(common::portable_file_name)
+            .collect();
+        let base_name = parts.pop().unwrap_or_default();
+        for depth in 1..=parts.len() {
+            let group = parts[..depth].join("/");
+            if groups.insert(group.clone()) {
+                let properties = BTreeMap::from([("provides-namespace".to_string(), true)]);
+                write(
+                    &format!("{}/Contents.json", group),
+                    &catalog(Some(properties))?,
+                    &mut summary,
+                )?;
+            }
+        }
+        let directory = parts
+            .iter()
+            .map(|group| format!("{}/", group))
+            .collect::<String>()
+            + &format!("{}.{}", base_name, folder.set.extension());
+        for (file_name, data) in &folder.files {
+            write(&format!("{}/{}", directory, file_name), data, &mut summary)?;
+        }
+        write(
+            &format!("{}/Contents.json", directory),
+            &folder.set.contents()?,
+            &mut summary,
+        )?;
+    }
+    Ok(summary.sort())
+}
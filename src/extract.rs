@@ -0,0 +1,1836 @@
+use anyhow::Context;
+use anyhow::Result;
+use binrw::BinWrite;
+use hex::ToHex;
+use serde::Serialize;
+use serde_json::json;
+use sha2::Digest;
+use sha2::Sha256;
+use std::collections::hash_map::Entry;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs;
+use std::io::BufWriter;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::OnceLock;
+
+use crate::archive;
+use crate::assetutil;
+use crate::common;
+use crate::coregraphics;
+use crate::coreui;
+use crate::coreui::csi;
+use crate::coreui::csi::OutputFormat;
+use crate::coreui::rendition;
+use crate::filter;
+
+// the file names an extraction run has planned so far. On case-insensitive
+// filesystems (the macOS default) "Icon.png" and "icon.png" are the same
+// file, so names are compared case-folded unless the output is known to be
+// case-sensitive
+#[derive(Debug, Default)]
+pub struct OutputNames {
+    case_sensitive: bool,
+    // folded name to the name it was planned as
+    names: HashMap<String, String>,
+    // one per name that was only taken case-folded
+    warnings: Vec<String>,
+}
+
+impl OutputNames {
+    pub fn new(case_sensitive: bool) -> Self {
+        OutputNames {
+            case_sensitive,
+            ..Default::default()
+        }
+    }
+
+    // planned names are portable already, names looked up may not be
+    fn fold(&self, name: &str) -> String {
+        let name = common::portable_file_name(name);
+        if self.case_sensitive {
+            name
+        } else {
+            name.to_lowercase()
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains_key(&self.fold(name))
+    }
+
+    // false when the name, or one differing only in case, is taken
+    pub fn insert(&mut self, name: &str) -> bool {
+        match self.names.entry(self.fold(name)) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(name.to_string());
+                true
+            }
+        }
+    }
+
+    // the planned name that differs from name only in case
+    fn case_collision(&self, name: &str) -> Option<&String> {
+        self.names
+            .get(&self.fold(name))
+            .filter(|planned| planned.as_str() != name)
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+}
+
+// picks the file name for an extracted rendition: names without an extension
+// get one for the detected format, and a name that was already written (or
+// differs from one only in case) gets the name identifier appended
+// ("gradient-44959.png")
+pub fn output_file_name(
+    rendition_name: &str,
+    format: OutputFormat,
+    name_identifier: Option<u16>,
+    used: &mut OutputNames,
+) -> String {
+    let file_name = common::portable_file_name(&format.file_name(rendition_name));
+    if used.insert(&file_name) {
+        return file_name;
+    }
+    let case_collision = used.case_collision(&file_name).cloned();
+
+    // the groups of namespaced assets stay in front
+    let (directory, base_name) = match file_name.rsplit_once('/') {
+        Some((directory, base_name)) => (format!("{}/", directory), base_name),
+        None => (String::new(), file_name.as_str()),
+    };
+    let path = Path::new(base_name);
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(base_name)
+        .to_string();
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or(format.extension())
+        .to_string();
+
+    let base = match name_identifier {
+        Some(name_identifier) => format!("{}{}-{}", directory, stem, name_identifier),
+        None => format!("{}{}", directory, stem),
+    };
+    let mut candidate = format!("{}.{}", base, extension);
+    let mut suffix = 2;
+    while !used.insert(&candidate) {
+        candidate = format!("{}-{}.{}", base, suffix, extension);
+        suffix += 1;
+    }
+    if let Some(planned) = case_collision {
+        used.warnings.push(format!(
+            "{} and {} differ only in case, wrote {} instead",
+            planned, file_name, candidate
+        ));
+    }
+    candidate
+}
+
+// last entry of archives written by extract_zip
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+// what an extraction run did, every list is sorted so runs over the same
+// catalog report identically
+#[derive(Debug, Default, PartialEq)]
+pub struct ExtractSummary {
+    // file names relative to the output directory
+    pub written: Vec<String>,
+    // "name (size bytes): reason"
+    pub skipped: Vec<String>,
+    pub failed: Vec<String>,
+    // written, but not the way they were asked for
+    pub warnings: Vec<String>,
+    // one per written file and per skipped or failed rendition, in catalog order
+    pub records: Vec<ManifestRecord>,
+    // renditions the --filter pattern was tried on, None without one
+    pub name_filter: Option<NameFilterCounts>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct NameFilterCounts {
+    pub matched: usize,
+    pub excluded: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestStatus {
+    Written,
+    Skipped,
+    Failed,
+}
+
+// an entry of the --manifest file. Path is relative to the output directory
+// (or the zip archive) and null unless Status is "written", Reason is only set
+// for skipped and failed renditions. Compression is how the payload is stored
+// in the catalog, the SHA256Digest is of the file that was written
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ManifestRecord {
+    #[serde(rename(serialize = "Path"))]
+    pub path: Option<String>,
+    #[serde(rename(serialize = "FacetName"))]
+    pub facet_name: Option<String>,
+    #[serde(rename(serialize = "RenditionName"))]
+    pub rendition_name: String,
+    #[serde(rename(serialize = "Scale"))]
+    pub scale: u16,
+    #[serde(rename(serialize = "Idiom"))]
+    pub idiom: String,
+    #[serde(rename(serialize = "Appearance"))]
+    pub appearance: String,
+    #[serde(rename(serialize = "PixelWidth"))]
+    pub pixel_width: u32,
+    #[serde(rename(serialize = "PixelHeight"))]
+    pub pixel_height: u32,
+    #[serde(rename(serialize = "Compression"))]
+    pub compression: String,
+    #[serde(rename(serialize = "SHA256Digest"))]
+    pub sha256_digest: Option<String>,
+    #[serde(rename(serialize = "Status"))]
+    pub status: ManifestStatus,
+    #[serde(rename(serialize = "Reason"))]
+    pub reason: Option<String>,
+}
+
+impl ManifestRecord {
+    fn new(
+        store: &coreui::CommonAssetStorage,
+        rendition_key: &rendition::Key,
+        csi_header: &csi::Header,
+        facet_name: Option<&String>,
+        rendition_name: &str,
+        appearances: &BTreeMap<String, u32>,
+    ) -> ManifestRecord {
+        let key_values = store.key_values(rendition_key);
+        let value = |attribute| {
+            key_values
+                .iter()
+                .find(|(key_attribute, _)| *key_attribute == attribute)
+                .map(|(_, value)| *value)
+                .unwrap_or(0)
+        };
+        let appearance = value(rendition::AttributeType::Appearance) as u32;
+        let appearance_name = match appearance {
+            0 => None,
+            _ => Some(
+                appearances
+                    .iter()
+                    .find(|(_, identifier)| **identifier == appearance)
+                    .map(|(appearance_name, _)| appearance_name.to_string())
+                    .unwrap_or_else(|| coreui::unknown_appearance_name(appearance)),
+            ),
+        };
+        let idiom = rendition::Idiom::from(value(rendition::AttributeType::Idiom));
+        let (pixel_width, pixel_height) = match csi_header.vector_bounds() {
+            Some(bounds) if csi_header.width == 0 => bounds,
+            _ => (csi_header.width, csi_header.height),
+        };
+        ManifestRecord {
+            path: None,
+            facet_name: facet_name.cloned(),
+            rendition_name: rendition_name.to_string(),
+            scale: value(rendition::AttributeType::Scale),
+            idiom: assetutil::idiom_label(Some(&idiom)),
+            appearance: assetutil::appearance_label(appearance_name.as_deref()),
+            pixel_width,
+            pixel_height,
+            compression: payload_label(csi_header),
+            sha256_digest: None,
+            status: ManifestStatus::Written,
+            reason: None,
+        }
+    }
+
+    fn written(&self, file_name: &str, data: &[u8]) -> ManifestRecord {
+        ManifestRecord {
+            path: Some(file_name.to_string()),
+            sha256_digest: Some(Sha256::digest(data).encode_hex::<String>()),
+            ..self.clone()
+        }
+    }
+
+    fn not_written(&self, status: ManifestStatus, reason: &str) -> ManifestRecord {
+        ManifestRecord {
+            status,
+            reason: Some(reason.to_string()),
+            ..self.clone()
+        }
+    }
+}
+
+// writes the records of an extraction run as a JSON array
+pub fn write_manifest(summary: &ExtractSummary, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    fs::write(path, serde_json::to_string_pretty(&summary.records)?)
+        .with_context(|| format!("unable to write manifest {}", path.display()))
+}
+
+impl ExtractSummary {
+    pub(crate) fn sort(mut self) -> ExtractSummary {
+        self.written.sort();
+        self.skipped.sort();
+        self.failed.sort();
+        self.warnings.sort();
+        self
+    }
+
+    pub fn manifest(&self) -> serde_json::Value {
+        json!({
+            "Written": self.written,
+            "Skipped": self.skipped,
+            "Failed": self.failed,
+            "Warnings": self.warnings,
+        })
+    }
+
+    pub fn summary_line(&self) -> String {
+        let line = format!(
+            "{} files written, {} skipped, {} failed",
+            self.written.len(),
+            self.skipped.len(),
+            self.failed.len()
+        );
+        match self.name_filter {
+            Some(counts) => format!(
+                "{} renditions matched the filter, {} excluded, {}",
+                counts.matched, counts.excluded, line
+            ),
+            None => line,
+        }
+    }
+
+    pub fn log(&self, output_path: impl AsRef<Path>) {
+        for file_name in &self.written {
+            eprintln!(
+                "Extracted: {}",
+                output_path.as_ref().join(file_name).display()
+            );
+        }
+        for err in &self.failed {
+            eprintln!("Unable to extract: {}", err);
+        }
+        for warning in &self.warnings {
+            eprintln!("warning: {}", warning);
+        }
+        if !self.skipped.is_empty() {
+            eprintln!("Skipped {} renditions:", self.skipped.len());
+            for skipped in &self.skipped {
+                eprintln!("  {}", skipped);
+            }
+        }
+        eprintln!("{}", self.summary_line());
+    }
+}
+
+// the variants of an asset often share a rendition name. Renditions with an
+// appearance other than any get its label before Xcode's scale and idiom
+// suffixes ("Icon~dark@2x.png", "Icon~dark-highcontrast.png") whenever
+// label_appearance is set, names from a template or --best are left to the
+// template. When the name is taken in any output format "@2x" for scales
+// above 1 and "~ipad" for idioms other than universal are added too
+// ("Icon~dark@2x~ipad.png"), so the variants stay apart before name
+// identifiers are used. Suffixes the name already ends with for this
+// rendition's scale and idiom aren't repeated
+pub fn variant_file_name(
+    name: &str,
+    record: &ManifestRecord,
+    used: &OutputNames,
+    label_appearance: bool,
+) -> String {
+    let taken = OutputFormat::ALL
+        .iter()
+        .any(|format| used.contains(&format.file_name(name)));
+    let appearance = match record.appearance.as_str() {
+        "any" => String::new(),
+        _ if !label_appearance && !taken => String::new(),
+        appearance => format!("~{}", appearance),
+    };
+    if !taken && appearance.is_empty() {
+        return name.to_string();
+    }
+    let (directory, base_name) = match name.rsplit_once('/') {
+        Some((directory, base_name)) => (format!("{}/", directory), base_name),
+        None => (String::new(), name),
+    };
+    let (stem, extension) = match base_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (base_name, String::new()),
+    };
+    let idiom = match record.idiom.as_str() {
+        "universal" => String::new(),
+        idiom => format!("~{}", idiom),
+    };
+    let scale = match record.scale {
+        0 | 1 => String::new(),
+        scale => format!("@{}x", scale),
+    };
+    // an untaken name keeps only the suffixes it already had
+    let (stem, idiom) = match stem.strip_suffix(&idiom) {
+        Some(stem) => (stem, idiom),
+        None if taken => (stem, idiom),
+        None => (stem, String::new()),
+    };
+    let (stem, scale) = match stem.strip_suffix(&scale) {
+        Some(stem) => (stem, scale),
+        None if taken => (stem, scale),
+        None => (stem, String::new()),
+    };
+    format!(
+        "{}{}{}{}{}{}",
+        directory, stem, appearance, scale, idiom, extension
+    )
+}
+
+fn name_identifier(
+    store: &coreui::CommonAssetStorage,
+    rendition_key: &rendition::Key,
+) -> Option<u16> {
+    store
+        .key_values(rendition_key)
+        .into_iter()
+        .find(|(attribute, _)| *attribute == rendition::AttributeType::Identifier)
+        .map(|(_, value)| value)
+}
+
+// how animation filmstrips are written
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Filmstrip {
+    // one tall png with every frame, like any other image
+    #[default]
+    Strip,
+    // <name>_frame000.png, <name>_frame001.png, ...
+    Frames,
+    // a single animated png
+    Apng,
+}
+
+// frames are 30 fps, the catalog doesn't store a frame rate
+const APNG_FRAME_DELAY: (u16, u16) = (1, 30);
+
+// the RGBA pixels of each frame of a filmstrip, extra rows past the last
+// whole frame are dropped
+pub fn filmstrip_frames(
+    csi_header: &csi::Header,
+    frame_height: u32,
+    unpremultiplied: bool,
+) -> Result<Vec<Vec<u8>>> {
+    let pixels = if unpremultiplied {
+        csi_header.unpremultiplied_rgba_pixels()?
+    } else {
+        csi_header.rgba_pixels()?
+    };
+    let frame_length = (csi_header.width as usize)
+        .saturating_mul(frame_height as usize)
+        .saturating_mul(4);
+    if frame_length == 0 {
+        anyhow::bail!("{:?} has empty frames", csi_header.csimetadata.name());
+    }
+    Ok(pixels
+        .chunks_exact(frame_length)
+        .map(|frame| frame.to_vec())
+        .collect())
+}
+
+pub fn filmstrip_apng(
+    csi_header: &csi::Header,
+    frame_height: u32,
+    unpremultiplied: bool,
+) -> Result<Vec<u8>> {
+    let frames = filmstrip_frames(csi_header, frame_height, unpremultiplied)?;
+    let mut png_data = vec![];
+    {
+        let mut encoder = csi::png_encoder(&mut png_data, csi_header.width, frame_height);
+        encoder.set_animated(frames.len() as u32, 0)?;
+        encoder.set_frame_delay(APNG_FRAME_DELAY.0, APNG_FRAME_DELAY.1)?;
+        let mut writer = encoder.write_header()?;
+        for frame in &frames {
+            writer.write_image_data(frame)?;
+        }
+    }
+    Ok(png_data)
+}
+
+// (file name, data) pairs to write for a filmstrip instead of the whole strip
+fn filmstrip_files(
+    csi_header: &csi::Header,
+    frame_height: u32,
+    filmstrip: Filmstrip,
+    unpremultiplied: bool,
+    name: &str,
+    name_identifier: Option<u16>,
+    used: &mut OutputNames,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    match filmstrip {
+        Filmstrip::Strip => Ok(vec![]),
+        Filmstrip::Apng => Ok(vec![(
+            output_file_name(name, OutputFormat::Png, name_identifier, used),
+            filmstrip_apng(csi_header, frame_height, unpremultiplied)?,
+        )]),
+        Filmstrip::Frames => {
+            // the stem keeps the directory of namespaced assets
+            let stem = match Path::new(name).extension() {
+                Some(extension) => &name[..name.len() - extension.len() - 1],
+                None => name,
+            };
+            filmstrip_frames(csi_header, frame_height, unpremultiplied)?
+                .into_iter()
+                .enumerate()
+                .map(|(index, frame)| {
+                    let file_name = output_file_name(
+                        &format!("{}_frame{:03}.png", stem, index),
+                        OutputFormat::Png,
+                        name_identifier,
+                        used,
+                    );
+                    Ok((
+                        file_name,
+                        csi::encode_png(csi_header.width, frame_height, &frame)?,
+                    ))
+                })
+                .collect()
+        }
+    }
+}
+
+// which kinds of renditions are extracted, by layout. Everything that isn't
+// data or a vector glyph counts as an image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AssetTypes {
+    pub images: bool,
+    pub data: bool,
+    pub vectors: bool,
+}
+
+impl Default for AssetTypes {
+    fn default() -> Self {
+        AssetTypes {
+            images: true,
+            data: true,
+            vectors: true,
+        }
+    }
+}
+
+impl AssetTypes {
+    pub fn contains(&self, layout: rendition::LayoutType32) -> bool {
+        match layout {
+            rendition::LayoutType32::Data => self.data,
+            rendition::LayoutType32::Vector => self.vectors,
+            _ => self.images,
+        }
+    }
+}
+
+// "image,data", the CLI's --types
+impl std::str::FromStr for AssetTypes {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut types = AssetTypes {
+            images: false,
+            data: false,
+            vectors: false,
+        };
+        for name in s.split(',').map(str::trim) {
+            match name {
+                "image" => types.images = true,
+                "data" => types.data = true,
+                "vector" => types.vectors = true,
+                _ => anyhow::bail!(
+                    "unknown asset type {:?}, expected image, data or vector",
+                    name
+                ),
+            }
+        }
+        Ok(types)
+    }
+}
+
+// --colors, the color assets written to one palette file next to the
+// extracted files
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorPalette {
+    // colors.json, {appearance: {name: {Colorspace, Components}}}
+    Json,
+    // colors.css, one custom property per color and appearance
+    Css,
+}
+
+impl ColorPalette {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ColorPalette::Json => "colors.json",
+            ColorPalette::Css => "colors.css",
+        }
+    }
+
+    pub fn render(&self, colors: &[PaletteColor]) -> Result<Vec<u8>> {
+        match self {
+            ColorPalette::Json => {
+                let mut appearances: BTreeMap<&str, BTreeMap<&str, &PaletteColor>> =
+                    BTreeMap::new();
+                for color in colors {
+                    appearances
+                        .entry(&color.appearance)
+                        .or_default()
+                        .insert(&color.name, color);
+                }
+                let mut data = serde_json::to_vec_pretty(&appearances)?;
+                data.push(b'\n');
+                Ok(data)
+            }
+            ColorPalette::Css => {
+                let mut css = ":root {\n".to_string();
+                for color in colors {
+                    css.push_str(&format!(
+                        "  --{}: {};\n",
+                        color.css_property(),
+                        color.css_value()
+                    ));
+                }
+                css.push_str("}\n");
+                Ok(css.into_bytes())
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for ColorPalette {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(ColorPalette::Json),
+            "css" => Ok(ColorPalette::Css),
+            _ => anyhow::bail!("unknown color palette format {:?}, expected json or css", s),
+        }
+    }
+}
+
+// one appearance of a color asset
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PaletteColor {
+    #[serde(skip)]
+    pub name: String,
+    // "any", "dark", ... like the labels of extracted file names
+    #[serde(skip)]
+    pub appearance: String,
+    #[serde(rename = "Colorspace")]
+    pub colorspace: Option<coregraphics::ColorSpace>,
+    #[serde(
+        rename = "Components",
+        serialize_with = "assetutil::serialize_color_components"
+    )]
+    pub components: Option<Vec<f64>>,
+}
+
+impl PaletteColor {
+    // "MyColor", "MyColor-dark"; characters custom property names can't
+    // hold become dashes
+    pub fn css_property(&self) -> String {
+        let name = match self.appearance.as_str() {
+            "any" => self.name.clone(),
+            appearance => format!("{}-{}", self.name, appearance),
+        };
+        name.chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                _ => '-',
+            })
+            .collect()
+    }
+
+    // rgba() with 8 bit channels, color(display-p3 ...) for p3 colors. Gray
+    // colors have a gray and an alpha component
+    pub fn css_value(&self) -> String {
+        let components = self.components.as_deref().unwrap_or_default();
+        let (red, green, blue, alpha) = match components {
+            [gray, alpha] => (*gray, *gray, *gray, *alpha),
+            [red, green, blue, alpha, ..] => (*red, *green, *blue, *alpha),
+            _ => return "transparent".to_string(),
+        };
+        let alpha = alpha.clamp(0.0, 1.0);
+        if self.colorspace == Some(coregraphics::ColorSpace::DisplayP3) {
+            return format!("color(display-p3 {} {} {} / {})", red, green, blue, alpha);
+        }
+        let channel = |value: f64| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        format!(
+            "rgba({}, {}, {}, {})",
+            channel(red),
+            channel(green),
+            channel(blue),
+            alpha
+        )
+    }
+}
+
+// how extracted files are named. Templates can use {facet}, {rendition},
+// {scale}, {idiom} and {appearance}; names without an extension get the one
+// of the format written
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum NamingTemplate {
+    // the rendition name, or the asset name with --best
+    #[default]
+    RenditionName,
+    Template(String),
+}
+
+impl NamingTemplate {
+    const PLACEHOLDERS: [&'static str; 5] = ["facet", "rendition", "scale", "idiom", "appearance"];
+
+    // facet is the facet name as it should appear in the path, with its groups
+    // laid out already
+    fn render(&self, facet: &str, record: &ManifestRecord) -> Option<String> {
+        let NamingTemplate::Template(template) = self else {
+            return None;
+        };
+        Some(
+            template
+                .replace("{facet}", facet)
+                .replace("{rendition}", &record.rendition_name)
+                .replace("{scale}", &record.scale.max(1).to_string())
+                .replace("{idiom}", &record.idiom)
+                .replace("{appearance}", &record.appearance),
+        )
+    }
+}
+
+impl std::str::FromStr for NamingTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .with_context(|| format!("unclosed placeholder in naming template {:?}", s))?;
+            let placeholder = &rest[start + 1..start + end];
+            if !Self::PLACEHOLDERS.contains(&placeholder) {
+                anyhow::bail!(
+                    "unknown placeholder {{{}}} in naming template {:?}, expected one of {}",
+                    placeholder,
+                    s,
+                    Self::PLACEHOLDERS
+                        .map(|name| format!("{{{}}}", name))
+                        .join(", ")
+                );
+            }
+            rest = &rest[start + end + 1..];
+        }
+        if s.trim().is_empty() {
+            anyhow::bail!("the naming template is empty");
+        }
+        Ok(NamingTemplate::Template(s.to_string()))
+    }
+}
+
+// what happened to a rendition, for progress callbacks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExtractOutcome {
+    Written,
+    Skipped,
+    Failed,
+    // left out by the filter, the asset types or --best
+    Excluded,
+    // nothing to write, e.g. colors
+    NotExtracted,
+}
+
+// called after each rendition with (index, total, rendition name, outcome),
+// indices count every rendition of the catalog
+pub type ProgressFn = dyn Fn(usize, usize, &str, ExtractOutcome) + Send + Sync;
+
+#[derive(Clone)]
+pub struct ProgressCallback(pub Arc<ProgressFn>);
+
+impl ProgressCallback {
+    pub fn new(
+        callback: impl Fn(usize, usize, &str, ExtractOutcome) + Send + Sync + 'static,
+    ) -> Self {
+        ProgressCallback(Arc::new(callback))
+    }
+}
+
+impl std::fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+// which renditions extract_all and extract_zip write, and how they lay them out
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    pub filmstrip: Filmstrip,
+    // namespaced assets are written under directories for their groups by default
+    pub flatten_names: common::FlattenNames,
+    // renditions whose key doesn't match are left out, before anything is decoded
+    pub filter: filter::RenditionFilter,
+    // alpha-cropped images are padded back to the size they were authored at
+    pub restore_canvas: bool,
+    // deep color images with extended range components are written as
+    // OpenEXR rather than clamped to a 16 bit png, needs the exr feature
+    pub preserve_extended: bool,
+    // only the rendition of each asset that best matches the query, named
+    // after the asset
+    pub best: Option<coreui::RenditionQuery>,
+    // names differing only in case are different files, true for ext4 and
+    // case-sensitive APFS
+    pub case_sensitive_fs: bool,
+    // renditions whose SizeOnDisk is above this are skipped, on top of the
+    // limit the catalog was parsed with
+    pub max_size: Option<u64>,
+    pub types: AssetTypes,
+    pub naming: NamingTemplate,
+    // color assets aren't written as files, this gathers them into a palette
+    pub colors: Option<ColorPalette>,
+    // renditions whose facet and rendition names both miss the pattern are
+    // left out, before anything is decoded
+    pub name_pattern: Option<filter::NamePattern>,
+    // threads decoding images, 0 for one per core. Files are named and
+    // written in catalog order whatever the number
+    pub jobs: usize,
+    // pngs are written with straight alpha instead of the premultiplied
+    // components CoreUI stores
+    pub unpremultiply: bool,
+    pub on_progress: Option<ProgressCallback>,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            filmstrip: Filmstrip::default(),
+            flatten_names: common::FlattenNames::Keep,
+            filter: filter::RenditionFilter::default(),
+            restore_canvas: false,
+            preserve_extended: false,
+            best: None,
+            case_sensitive_fs: false,
+            max_size: None,
+            types: AssetTypes::default(),
+            naming: NamingTemplate::default(),
+            colors: None,
+            name_pattern: None,
+            jobs: 0,
+            unpremultiply: false,
+            on_progress: None,
+        }
+    }
+}
+
+// what an extraction run did, as the library calls it
+pub type ExtractReport = ExtractSummary;
+
+impl coreui::CarUtilAssetStorage {
+    // extracts into out_dir with everything the extract command can ask for
+    // set in options
+    pub fn extract_with(
+        &self,
+        out_dir: impl AsRef<Path>,
+        options: &ExtractOptions,
+    ) -> Result<ExtractReport> {
+        extract_all(self, out_dir, &coreui::ParseOptions::default(), options)
+    }
+}
+
+pub fn extract_all(
+    car: &coreui::CarUtilAssetStorage,
+    output_path: impl AsRef<Path>,
+    options: &coreui::ParseOptions,
+    extract_options: &ExtractOptions,
+) -> Result<ExtractSummary> {
+    let output_path = output_path.as_ref();
+    extract_each(car, options, extract_options, |file_name, data| {
+        let file_path = output_path.join(file_name);
+        if let Some(directory) = file_path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        Ok(fs::write(file_path, data)?)
+    })
+}
+
+// same files as extract_all, written into a zip archive followed by a
+// manifest.json entry with the summary
+pub fn extract_zip(
+    car: &coreui::CarUtilAssetStorage,
+    zip_path: impl AsRef<Path>,
+    options: &coreui::ParseOptions,
+    extract_options: &ExtractOptions,
+    compression: archive::ZipCompression,
+) -> Result<ExtractSummary> {
+    let zip_path = zip_path.as_ref();
+    let file = fs::File::create(zip_path)
+        .with_context(|| format!("unable to create {}", zip_path.display()))?;
+    let mut zip = archive::ZipWriter::new(BufWriter::new(file), compression);
+    let summary = extract_each(car, options, extract_options, |file_name, data| {
+        zip.add(file_name, data)
+    })?;
+    zip.add(
+        MANIFEST_NAME,
+        serde_json::to_string_pretty(&summary.manifest())?.as_bytes(),
+    )?;
+    zip.finish()?;
+    Ok(summary)
+}
+
+// what --best aims for when nothing else is asked for: the highest scale
+// phones have, universal renditions and P3 where there is one
+pub fn default_best_query() -> coreui::RenditionQuery {
+    coreui::RenditionQuery {
+        scale: Some(3),
+        display_gamut: Some(1),
+        ..Default::default()
+    }
+}
+
+// the facet a rendition belongs to, see CommonAssetStorage::facet_names_for_key
+pub(crate) fn rendition_facet_name(
+    store: &coreui::CommonAssetStorage,
+    rendition_key: &rendition::Key,
+    facet_names: &HashMap<u16, String>,
+    name_identifier_collisions: &BTreeMap<u16, Vec<String>>,
+) -> Option<String> {
+    match name_identifier(store, rendition_key) {
+        Some(name_identifier) if name_identifier_collisions.contains_key(&name_identifier) => {
+            store.facet_names_for_key(rendition_key).into_iter().next()
+        }
+        Some(name_identifier) => facet_names.get(&name_identifier).cloned(),
+        None => None,
+    }
+}
+
+// the key of the rendition to write for each facet, by facet name: the best
+// match for query among the renditions filter lets through, ties go to the
+// lowest SHA256 of the csi header so the pick doesn't depend on key order
+pub fn best_renditions(
+    store: &coreui::CommonAssetStorage,
+    query: &coreui::RenditionQuery,
+    filter: &filter::RenditionFilter,
+) -> BTreeMap<String, rendition::Key> {
+    let facet_names = store.name_identifier_to_facet_name();
+    let name_identifier_collisions = store.name_identifier_collisions();
+    // (score, key) of the renditions each facet has
+    type Candidate<'a> = ((u32, u32), &'a rendition::Key);
+    let mut candidates: BTreeMap<String, Vec<Candidate>> = BTreeMap::new();
+    for rendition_key in store.imagedb.keys() {
+        let key_values = store.key_values(rendition_key);
+        if !filter.matches_key(&key_values) {
+            continue;
+        }
+        let Some(facet_name) = rendition_facet_name(
+            store,
+            rendition_key,
+            &facet_names,
+            &name_identifier_collisions,
+        ) else {
+            continue;
+        };
+        if let Some(score) = query.score(&key_values) {
+            candidates
+                .entry(facet_name)
+                .or_default()
+                .push((score, rendition_key));
+        }
+    }
+    candidates
+        .into_iter()
+        .filter_map(|(facet_name, candidates)| {
+            let best_score = candidates.iter().map(|(score, _)| *score).min()?;
+            let best = candidates
+                .into_iter()
+                .filter(|(score, _)| *score == best_score)
+                .map(|(_, rendition_key)| rendition_key)
+                .min_by_key(|rendition_key| {
+                    let mut writer = Cursor::new(vec![]);
+                    // a header that can't be written sorts last
+                    match store.imagedb[*rendition_key].write(&mut writer) {
+                        Ok(()) => Sha256::digest(writer.into_inner()).to_vec(),
+                        Err(_) => vec![0xff; 33],
+                    }
+                })?;
+            Some((facet_name, *best))
+        })
+        .collect()
+}
+
+// the file name of an asset written by --best: the asset's own name, with the
+// extension of its rendition name when it has one
+fn best_file_name(facet_name: &str, rendition_name: &str) -> String {
+    let base_name = facet_name.rsplit('/').next().unwrap_or(facet_name);
+    match Path::new(rendition_name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some(extension) => format!("{}.{}", base_name, extension),
+        None => base_name.to_string(),
+    }
+}
+
+// whether a rendition is extracted, decided from its key and names before
+// anything is decoded
+enum Selection {
+    // left out by the key filter, --types or --best
+    Excluded,
+    // neither name matches the name pattern
+    NameExcluded(String),
+    Selected {
+        facet_name: Option<String>,
+        rendition_name: String,
+    },
+}
+
+struct Selector<'a> {
+    store: &'a coreui::CommonAssetStorage,
+    extract_options: &'a ExtractOptions,
+    facet_names: HashMap<u16, String>,
+    name_identifier_collisions: BTreeMap<u16, Vec<String>>,
+    appearances: BTreeMap<String, u32>,
+    best: Option<BTreeMap<String, rendition::Key>>,
+}
+
+impl Selector<'_> {
+    fn select(&self, rendition_key: &rendition::Key, csi_header: &csi::Header) -> Selection {
+        let key_values = self.store.key_values(rendition_key);
+        if !self.extract_options.filter.matches_key(&key_values)
+            || !self
+                .extract_options
+                .types
+                .contains(csi_header.csimetadata.layout)
+        {
+            return Selection::Excluded;
+        }
+        let facet_name = rendition_facet_name(
+            self.store,
+            rendition_key,
+            &self.facet_names,
+            &self.name_identifier_collisions,
+        );
+        if let (Some(best), Some(facet_name)) = (&self.best, &facet_name) {
+            if best.get(facet_name) != Some(rendition_key) {
+                return Selection::Excluded;
+            }
+        }
+        let (rendition_name, _) = assetutil::rendition_name(
+            csi_header,
+            facet_name.as_deref(),
+            &key_values,
+            &self.appearances,
+        );
+        if let Some(pattern) = &self.extract_options.name_pattern {
+            if !pattern.matches_names(facet_name.as_deref(), &rendition_name) {
+                return Selection::NameExcluded(rendition_name);
+            }
+        }
+        Selection::Selected {
+            facet_name,
+            rendition_name,
+        }
+    }
+
+    // images written whole are decoded ahead, on the worker threads
+    fn decodes_ahead(&self, rendition_key: &rendition::Key, csi_header: &csi::Header) -> bool {
+        let is_filmstrip = self.extract_options.filmstrip != Filmstrip::Strip
+            && self
+                .store
+                .key_values(rendition_key)
+                .into_iter()
+                .any(|(attribute, value)| {
+                    attribute == rendition::AttributeType::Subtype
+                        && value == rendition::ImageSubtype::AnimationFilmstrip as u16
+                });
+        matches!(
+            csi_header.csimetadata.layout,
+            rendition::LayoutType32::Image
+        ) && !is_filmstrip
+            && matches!(
+                self.select(rendition_key, csi_header),
+                Selection::Selected { .. }
+            )
+    }
+}
+
+// renditions decoded together on the worker threads before their files are
+// named and written in catalog order, which bounds how many decoded images
+// are held at once
+const DECODE_BATCH: usize = 256;
+
+// the image renditions of each size of a multisize set (app icons), in key
+// order. They share the set's name identifier, have the size's idiom, and
+// carry its index as Dimension1
+fn multisize_images<'a>(
+    store: &'a coreui::CommonAssetStorage,
+    by_identifier: &coreui::IdentifierIndex<'a>,
+    rendition_key: &rendition::Key,
+    csi_header: &'a csi::Header,
+) -> Vec<(&'a rendition::MultisizeImageSetEntry, Vec<&'a csi::Header>)> {
+    let Some(rendition::Rendition::MultisizeImageSet { entries, .. }) = &csi_header.rendition_data
+    else {
+        return vec![];
+    };
+    let value = |key_values: &[(rendition::AttributeType, u16)], attribute| {
+        key_values
+            .iter()
+            .find(|(key_attribute, _)| *key_attribute == attribute)
+            .map(|(_, value)| *value)
+    };
+    let images: Vec<(Vec<(rendition::AttributeType, u16)>, &csi::Header)> =
+        name_identifier(store, rendition_key)
+            .and_then(|identifier| by_identifier.get(&identifier))
+            .into_iter()
+            .flatten()
+            .filter(|(_, image)| matches!(image.csimetadata.layout, rendition::LayoutType32::Image))
+            .map(|(image_key, image)| (store.key_values(image_key), *image))
+            .collect();
+    entries
+        .iter()
+        .map(|entry| {
+            let sized = images
+                .iter()
+                .filter(|(key_values, _)| {
+                    value(key_values, rendition::AttributeType::Dimension1) == Some(entry.index)
+                        && value(key_values, rendition::AttributeType::Idiom).unwrap_or(0)
+                            == entry.idiom.value()
+                })
+                .map(|(_, image)| *image)
+                .collect();
+            (entry, sized)
+        })
+        .collect()
+}
+
+// "<name>-<width>x<height>.png" for one size of a multisize set, with the
+// scale when it's above 1x
+fn multisize_file_name(
+    name: &str,
+    entry: &rendition::MultisizeImageSetEntry,
+    image: &csi::Header,
+) -> String {
+    let stem = match Path::new(name).extension() {
+        Some(extension) => &name[..name.len() - extension.len() - 1],
+        None => name,
+    };
+    let scale = match image.scale_factor / 100 {
+        0 | 1 => String::new(),
+        scale => format!("@{}x", scale),
+    };
+    format!("{}-{}x{}{}.png", stem, entry.width, entry.height, scale)
+}
+
+// packed images (atlases) decoded once for all the renditions linked into
+// them, whichever thread gets to one first decodes it
+struct PackedImages<'a> {
+    store: &'a coreui::CommonAssetStorage,
+    decoded: BTreeMap<&'a rendition::Key, OnceLock<Result<csi::DecodedRendition, String>>>,
+}
+
+impl<'a> PackedImages<'a> {
+    fn new(store: &'a coreui::CommonAssetStorage) -> Self {
+        let decoded = store
+            .imagedb
+            .iter()
+            .filter(|(_, csi_header)| {
+                matches!(
+                    csi_header.csimetadata.layout,
+                    rendition::LayoutType32::PackedImage
+                )
+            })
+            .map(|(rendition_key, _)| (rendition_key, OnceLock::new()))
+            .collect();
+        PackedImages { store, decoded }
+    }
+
+    // the decoded rendition, internal links are cropped out of their atlas
+    fn decode(
+        &self,
+        csi_header: &csi::Header,
+        options: &coreui::ParseOptions,
+    ) -> Result<csi::DecodedRendition> {
+        let Some(frame) = csi_header
+            .rendition_data
+            .as_ref()
+            .and_then(|rendition_data| rendition_data.internal_link_frame())
+        else {
+            return csi_header.decode_with_options(options);
+        };
+        let (rendition_key, packed_image) = self
+            .store
+            .packed_image(csi_header)
+            .with_context(|| coreui::missing_packed_image(csi_header))?;
+        let decoded = match self.decoded.get(rendition_key) {
+            Some(decoded) => decoded.get_or_init(|| {
+                packed_image
+                    .decode_with_options(options)
+                    .map_err(|err| format!("{:#}", err))
+            }),
+            // linked to something that isn't a packed image
+            None => return self.store.decode_rendition(csi_header, options),
+        };
+        decoded
+            .as_ref()
+            .map_err(|err| anyhow::anyhow!("{}", err))?
+            .cropped(frame)
+            .with_context(|| format!("Unable to unpack {:?}", csi_header.csimetadata.name()))
+    }
+}
+
+// the file an image rendition is written as, with --restore-canvas and
+// --preserve-extended applied. None for renditions with nothing to write
+// why a rendition whose SizeOnDisk is over the limit is skipped, None for
+// the ones that get decoded
+fn too_large(csi_header: &csi::Header, max_payload_bytes: Option<u64>) -> Option<String> {
+    let size = csi_header.size_on_disk();
+    max_payload_bytes
+        .is_some_and(|limit| size > limit)
+        .then(|| {
+            format!(
+                "{} ({} bytes): too large",
+                csi_header.csimetadata.name(),
+                size
+            )
+        })
+}
+
+fn decoded_image(
+    csi_header: &csi::Header,
+    packed_images: &PackedImages,
+    options: &coreui::ParseOptions,
+    extract_options: &ExtractOptions,
+) -> Result<Option<csi::ExtractedFile>> {
+    if !matches!(
+        csi_header.csimetadata.layout,
+        rendition::LayoutType32::Image
+    ) {
+        return Ok(None);
+    }
+    let mut decoded = packed_images.decode(csi_header, options)?;
+    if extract_options.unpremultiply {
+        decoded = decoded.unpremultiplied();
+    }
+    let extracted_file = decoded.into_extracted_file(&csi_header.csimetadata.name())?;
+    // renditions cut out of an atlas have no pixels of their own to pad
+    let linked = packed_images.store.packed_image(csi_header).is_some();
+    let restored = if extract_options.restore_canvas && !linked {
+        csi_header.restored_canvas_png(extract_options.unpremultiply, options)?
+    } else {
+        None
+    };
+    let extracted_file = match restored {
+        Some(data) => csi::ExtractedFile {
+            format: OutputFormat::Png,
+            data,
+            warning: None,
+        },
+        None => extracted_file,
+    };
+    if extract_options.preserve_extended {
+        if let Some(exr) = extended_exr(csi_header)? {
+            return Ok(Some(exr));
+        }
+    }
+    Ok(Some(extracted_file))
+}
+
+// decodes every rendition in memory and hands each output file to write
+fn extract_each(
+    car: &coreui::CarUtilAssetStorage,
+    options: &coreui::ParseOptions,
+    extract_options: &ExtractOptions,
+    mut write: impl FnMut(&str, &[u8]) -> Result<()>,
+) -> Result<ExtractSummary> {
+    let store = &car.theme_store.store;
+    let filmstrip = extract_options.filmstrip;
+    let selector = Selector {
+        store,
+        extract_options,
+        facet_names: store.name_identifier_to_facet_name(),
+        name_identifier_collisions: store.name_identifier_collisions(),
+        appearances: store.appearance_names().unwrap_or_default(),
+        best: extract_options
+            .best
+            .as_ref()
+            .map(|query| best_renditions(store, query, &extract_options.filter)),
+    };
+    let (appearances, best) = (&selector.appearances, &selector.best);
+    let packed_images = PackedImages::new(store);
+    let mut used = OutputNames::new(extract_options.case_sensitive_fs);
+    // only built once a multisize set needs it
+    let renditions_by_identifier = OnceLock::new();
+    // how many variants of each vector glyph were written so far
+    let mut vector_variants: HashMap<String, usize> = HashMap::new();
+    let options = &coreui::ParseOptions {
+        max_payload_bytes: extract_options.max_size.or(options.max_payload_bytes),
+        ..options.clone()
+    };
+    let total = store.imagedb.len();
+    let progress = |index: usize, rendition_name: &str, outcome: ExtractOutcome| {
+        if let Some(on_progress) = &extract_options.on_progress {
+            (on_progress.0)(index, total, rendition_name, outcome);
+        }
+    };
+    let mut palette = vec![];
+    let mut summary = ExtractSummary {
+        name_filter: extract_options
+            .name_pattern
+            .as_ref()
+            .map(|_| NameFilterCounts::default()),
+        ..ExtractSummary::default()
+    };
+    let renditions: Vec<(&rendition::Key, &csi::Header)> = store.imagedb.iter().collect();
+    for (batch_index, batch) in renditions.chunks(DECODE_BATCH).enumerate() {
+        let decoded = common::parallel_map(
+            batch,
+            extract_options.jobs,
+            |(rendition_key, csi_header)| {
+                selector
+                    .decodes_ahead(rendition_key, csi_header)
+                    .then(|| decoded_image(csi_header, &packed_images, options, extract_options))
+            },
+        );
+        for (batch_offset, ((rendition_key, csi_header), decoded)) in
+            batch.iter().zip(decoded).enumerate()
+        {
+            let (rendition_key, csi_header) = (*rendition_key, *csi_header);
+            let index = batch_index * DECODE_BATCH + batch_offset;
+            let (facet_name, rendition_name) = match selector.select(rendition_key, csi_header) {
+                Selection::Excluded => {
+                    progress(
+                        index,
+                        &csi_header.csimetadata.name(),
+                        ExtractOutcome::Excluded,
+                    );
+                    continue;
+                }
+                Selection::NameExcluded(rendition_name) => {
+                    if let Some(counts) = &mut summary.name_filter {
+                        counts.excluded += 1;
+                    }
+                    progress(index, &rendition_name, ExtractOutcome::Excluded);
+                    continue;
+                }
+                Selection::Selected {
+                    facet_name,
+                    rendition_name,
+                } => {
+                    if let Some(counts) = &mut summary.name_filter {
+                        counts.matched += 1;
+                    }
+                    (facet_name, rendition_name)
+                }
+            };
+            let facet_name = facet_name.as_ref();
+            let prefix = facet_name
+                .map(|facet_name| extract_options.flatten_names.path_prefix(facet_name))
+                .unwrap_or_default();
+            let record = ManifestRecord::new(
+                store,
+                rendition_key,
+                csi_header,
+                facet_name,
+                &rendition_name,
+                appearances,
+            );
+            // the facet as it goes in a path, without a facet the name the
+            // rendition name was made from
+            let path_facet = match facet_name {
+                Some(facet_name) => format!(
+                    "{}{}",
+                    prefix,
+                    facet_name.rsplit('/').next().unwrap_or(facet_name)
+                ),
+                None => assetutil::facet_name_from_rendition_name(&rendition_name)
+                    .unwrap_or_else(|| rendition_name.clone()),
+            };
+            let (name, from_rendition_name) = match (
+                extract_options.naming.render(&path_facet, &record),
+                best,
+                facet_name,
+            ) {
+                (Some(name), _, _) => (name, false),
+                (None, Some(_), Some(facet_name)) => (
+                    format!("{}{}", prefix, best_file_name(facet_name, &rendition_name)),
+                    false,
+                ),
+                _ => (format!("{}{}", prefix, rendition_name), true),
+            };
+            if let (
+                Some(rendition::Rendition::Color {
+                    flags, components, ..
+                }),
+                Some(facet_name),
+            ) = (&csi_header.rendition_data, facet_name)
+            {
+                palette.push(PaletteColor {
+                    name: extract_options.flatten_names.name(facet_name),
+                    appearance: record.appearance.clone(),
+                    // srgb for color spaces the flags don't name
+                    colorspace: Some(
+                        flags
+                            .color_space()
+                            .unwrap_or(coregraphics::ColorSpace::SRGB),
+                    ),
+                    components: Some(components.clone()),
+                });
+            }
+            let name = variant_file_name(&name, &record, &used, from_rendition_name);
+            let (skipped, failed, written) = (
+                summary.skipped.len(),
+                summary.failed.len(),
+                summary.written.len(),
+            );
+            'rendition: {
+                if let Some(reason) = too_large(csi_header, options.max_payload_bytes) {
+                    summary.skipped.push(reason);
+                    break 'rendition;
+                }
+                let is_filmstrip = filmstrip != Filmstrip::Strip
+                    && store
+                        .key_values(rendition_key)
+                        .into_iter()
+                        .any(|(attribute, value)| {
+                            attribute == rendition::AttributeType::Subtype
+                                && value == rendition::ImageSubtype::AnimationFilmstrip as u16
+                        });
+                let frame_height = if is_filmstrip {
+                    csi_header.frame_height()
+                } else {
+                    None
+                };
+                let files = if let Some(frame_height) = frame_height {
+                    let name_identifier = name_identifier(store, rendition_key);
+                    match filmstrip_files(
+                        csi_header,
+                        frame_height,
+                        filmstrip,
+                        extract_options.unpremultiply,
+                        &name,
+                        name_identifier,
+                        &mut used,
+                    ) {
+                        Ok(files) => files,
+                        Err(err) => {
+                            summary.failed.push(err.to_string());
+                            break 'rendition;
+                        }
+                    }
+                } else if matches!(csi_header.csimetadata.layout, rendition::LayoutType32::Data) {
+                    let (name, data) = match data_file(csi_header, &name) {
+                        Ok((name, data, warning)) => {
+                            summary.warnings.extend(warning);
+                            (name, data)
+                        }
+                        Err(err) => {
+                            summary.failed.push(err.to_string());
+                            break 'rendition;
+                        }
+                    };
+                    let file_name = output_file_name(
+                        &name,
+                        OutputFormat::sniff(&data, csi_header.pixel_format),
+                        name_identifier(store, rendition_key),
+                        &mut used,
+                    );
+                    vec![(file_name, data)]
+                } else if matches!(
+                    csi_header.csimetadata.layout,
+                    rendition::LayoutType32::Vector
+                ) {
+                    let index = vector_variants.entry(name.clone()).or_default();
+                    let (file_name, data) = match vector_file(csi_header, &name, *index) {
+                        Ok((file_name, data, warning)) => {
+                            summary.warnings.extend(warning);
+                            (file_name, data)
+                        }
+                        Err(err) => {
+                            summary.failed.push(err.to_string());
+                            break 'rendition;
+                        }
+                    };
+                    *index += 1;
+                    let file_name = output_file_name(
+                        &file_name,
+                        OutputFormat::Data,
+                        name_identifier(store, rendition_key),
+                        &mut used,
+                    );
+                    vec![(file_name, data)]
+                } else if matches!(
+                    csi_header.csimetadata.layout,
+                    rendition::LayoutType32::MultisizeImage
+                ) {
+                    let mut files = vec![];
+                    let by_identifier =
+                        renditions_by_identifier.get_or_init(|| store.renditions_by_identifier());
+                    for (entry, images) in
+                        multisize_images(store, by_identifier, rendition_key, csi_header)
+                    {
+                        if images.is_empty() {
+                            summary.warnings.push(format!(
+                                "{}: no image for the {}x{} size (index {})",
+                                name, entry.width, entry.height, entry.index
+                            ));
+                        }
+                        for image in images {
+                            let extracted_file = match decoded_image(
+                                image,
+                                &packed_images,
+                                options,
+                                extract_options,
+                            ) {
+                                Ok(Some(extracted_file)) => extracted_file,
+                                Ok(None) => continue,
+                                Err(err) => {
+                                    summary.failed.push(err.to_string());
+                                    continue;
+                                }
+                            };
+                            summary.warnings.extend(extracted_file.warning);
+                            let file_name = output_file_name(
+                                &multisize_file_name(&name, entry, image),
+                                extracted_file.format,
+                                name_identifier(store, rendition_key),
+                                &mut used,
+                            );
+                            files.push((file_name, extracted_file.data));
+                        }
+                    }
+                    files
+                } else {
+                    let decoded = decoded.unwrap_or_else(|| {
+                        decoded_image(csi_header, &packed_images, options, extract_options)
+                    });
+                    let extracted_file = match decoded {
+                        Ok(Some(extracted_file)) => extracted_file,
+                        Ok(None) => {
+                            // payloads nothing here understands, like texture
+                            // containers, are only reported
+                            if let Some((tag, payload)) = csi_header
+                                .rendition_data
+                                .as_ref()
+                                .and_then(|rendition_data| rendition_data.unknown_payload())
+                            {
+                                summary.skipped.push(format!(
+                                    "{}: unsupported rendition payload tag {} ({} bytes), extract it with --raw",
+                                    csi_header.csimetadata.name(),
+                                    common::fourcc_label(tag),
+                                    payload.len()
+                                ));
+                            }
+                            break 'rendition;
+                        }
+                        Err(err) => {
+                            // the atlas a linked rendition is cut out of can
+                            // be over the limit too
+                            if let Some(coreui::CarError::PayloadTooLarge { name, size, .. }) =
+                                err.downcast_ref::<coreui::CarError>()
+                            {
+                                summary
+                                    .skipped
+                                    .push(format!("{} ({} bytes): too large", name, size));
+                            } else {
+                                summary.failed.push(err.to_string());
+                            }
+                            break 'rendition;
+                        }
+                    };
+                    summary.warnings.extend(extracted_file.warning);
+                    let file_name = output_file_name(
+                        &name,
+                        extracted_file.format,
+                        name_identifier(store, rendition_key),
+                        &mut used,
+                    );
+                    vec![(file_name, extracted_file.data)]
+                };
+                for (file_name, data) in files {
+                    match write(&file_name, &data) {
+                        Ok(()) => {
+                            summary.records.push(record.written(&file_name, &data));
+                            summary.written.push(file_name);
+                        }
+                        Err(err) => summary.failed.push(format!("{}: {}", file_name, err)),
+                    }
+                }
+            }
+            // renditions that were skipped, or failed to decode or write
+            let not_written: Vec<ManifestRecord> = summary.skipped[skipped..]
+                .iter()
+                .map(|reason| record.not_written(ManifestStatus::Skipped, reason))
+                .chain(
+                    summary.failed[failed..]
+                        .iter()
+                        .map(|reason| record.not_written(ManifestStatus::Failed, reason)),
+                )
+                .collect();
+            summary.records.extend(not_written);
+            let outcome = if summary.failed.len() > failed {
+                ExtractOutcome::Failed
+            } else if summary.skipped.len() > skipped {
+                ExtractOutcome::Skipped
+            } else if summary.written.len() > written {
+                ExtractOutcome::Written
+            } else {
+                ExtractOutcome::NotExtracted
+            };
+            progress(index, &rendition_name, outcome);
+        }
+    }
+    if let Some(format) = extract_options.colors {
+        palette.sort_by(|a, b| (&a.name, &a.appearance).cmp(&(&b.name, &b.appearance)));
+        let file_name = output_file_name(format.file_name(), OutputFormat::Data, None, &mut used);
+        match format
+            .render(&palette)
+            .and_then(|data| write(&file_name, &data))
+        {
+            Ok(()) => summary.written.push(file_name),
+            Err(err) => summary.failed.push(format!("{}: {}", file_name, err)),
+        }
+    }
+    summary.warnings.extend(used.take_warnings());
+    Ok(summary.sort())
+}
+
+// deep color images with components outside 0...1 as OpenEXR, None when a
+// 16 bit png holds them
+fn extended_exr(csi_header: &csi::Header) -> Result<Option<csi::ExtractedFile>> {
+    match csi_header.deep_pixels()? {
+        #[cfg(feature = "exr")]
+        Some(pixels) if pixels.extended_count() > 0 => Ok(Some(csi::ExtractedFile {
+            format: OutputFormat::Exr,
+            data: pixels.encode_exr()?,
+            warning: None,
+        })),
+        #[cfg(not(feature = "exr"))]
+        Some(pixels) if pixels.extended_count() > 0 => {
+            anyhow::bail!(
+                "{:?}: built without the exr feature",
+                csi_header.csimetadata.name()
+            )
+        }
+        _ => Ok(None),
+    }
+}
+
+// file extensions for the UTIs of data assets
+const UTI_EXTENSIONS: [(&str, &str); 14] = [
+    ("public.json", "json"),
+    ("public.plain-text", "txt"),
+    ("public.utf8-plain-text", "txt"),
+    ("public.xml", "xml"),
+    ("com.apple.property-list", "plist"),
+    ("com.apple.coreml.model", "mlmodel"),
+    ("public.png", "png"),
+    ("public.jpeg", "jpg"),
+    ("com.adobe.pdf", "pdf"),
+    ("public.svg-image", "svg"),
+    ("public.truetype-ttf-font", "ttf"),
+    ("public.opentype-font", "otf"),
+    ("public.zip-archive", "zip"),
+    ("com.apple.m4a-audio", "m4a"),
+];
+
+pub fn uti_extension(uti: &str) -> Option<&'static str> {
+    UTI_EXTENSIONS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(uti))
+        .map(|(_, extension)| *extension)
+}
+
+// the decompressed bytes of a data asset with its rendition name. Names
+// without an extension get one from the UTI, the payload's signature or
+// ".bin". Without a codec for its compression the stored bytes are kept and
+// the compression is appended to the name ("config.json.lzfse"), with a
+// warning saying so
+pub(crate) fn data_file(
+    csi_header: &csi::Header,
+    name: &str,
+) -> Result<(String, Vec<u8>, Option<String>)> {
+    if let Some(data) = csi_header.data_payload()? {
+        if Path::new(name).extension().is_some() {
+            return Ok((name.to_string(), data, None));
+        }
+        let extension = csi_header
+            .uti()
+            .and_then(|uti| uti_extension(&uti))
+            .unwrap_or_else(
+                || match OutputFormat::sniff(&data, csi_header.pixel_format) {
+                    OutputFormat::Data => OutputFormat::Bin.extension(),
+                    format => format.extension(),
+                },
+            );
+        return Ok((format!("{}.{}", name, extension), data, None));
+    }
+    let (compression, payload) = raw_payload(csi_header)?;
+    let warning = format!(
+        "{}: no codec for {}, wrote the compressed bytes",
+        name, compression
+    );
+    Ok((format!("{}.{}", name, compression), payload, Some(warning)))
+}
+
+// one variant (weight, size, ...) of a vector glyph as "<name>_<index>.pdf"
+// or ".svg", indexed in key order. Payloads that are neither, like the
+// compiled CoreSVG documents of symbols, are written as they are stored
+// with a warning saying what wasn't understood
+fn vector_file(
+    csi_header: &csi::Header,
+    name: &str,
+    index: usize,
+) -> Result<(String, Vec<u8>, Option<String>)> {
+    let (data_name, data, warning) = data_file(csi_header, name)?;
+    let format = OutputFormat::sniff(&data, csi_header.pixel_format);
+    let stem = [OutputFormat::Pdf, OutputFormat::Svg]
+        .iter()
+        .find_map(|format| name.strip_suffix(&format!(".{}", format.extension())))
+        .unwrap_or(name);
+    if warning.is_some() {
+        // still compressed, keep the compression in the name
+        let compression = &data_name[name.len()..];
+        return Ok((format!("{}_{}{}", stem, index, compression), data, warning));
+    }
+    let warning = (format == OutputFormat::Data).then(|| {
+        format!(
+            "{}: vector payload isn't a pdf or svg (starts with {}), wrote the stored bytes",
+            name,
+            (&data[..data.len().min(8)]).encode_hex::<String>()
+        )
+    });
+    Ok((
+        format!("{}_{}.{}", stem, index, format.extension()),
+        data,
+        warning,
+    ))
+}
+
+// a label for how a rendition's payload is stored, the compression type when
+// there is one ("lzfse", "palette-img", ...)
+pub(crate) fn payload_label(csi_header: &csi::Header) -> String {
+    let label = match &csi_header.rendition_data {
+        Some(rendition_data) => match rendition_data.theme_payload() {
+            Some((compression_type, _)) => return compression_type.name(),
+            None => match rendition_data {
+                rendition::Rendition::RawData { .. } => "raw",
+                rendition::Rendition::Svg { .. } => "svg",
+                rendition::Rendition::Unknown { .. } => "unknown",
+                _ => "rendition",
+            },
+        },
+        None => "empty",
+    };
+    label.to_string()
+}
+
+// the stored payload bytes of a rendition and its payload_label
+pub fn raw_payload(csi_header: &csi::Header) -> Result<(String, Vec<u8>)> {
+    let label = payload_label(csi_header);
+    if let Some((_, raw_data)) = csi_header
+        .rendition_data
+        .as_ref()
+        .and_then(|rendition_data| rendition_data.theme_payload())
+    {
+        return Ok((label, raw_data.to_owned()));
+    }
+    let payload = match &csi_header.rendition_data {
+        Some(rendition::Rendition::RawData { raw_data, .. })
+        | Some(rendition::Rendition::Svg { raw_data, .. })
+        | Some(rendition::Rendition::Unknown { raw_data, .. }) => raw_data.0.to_owned(),
+        // no blob, the payload is the whole rendition region
+        Some(rendition_data) => {
+            let mut writer = Cursor::new(vec![]);
+            rendition_data.write_le(&mut writer)?;
+            writer.into_inner()
+        }
+        None => vec![],
+    };
+    Ok((label, payload))
+}
+
+// csi header fields written next to a raw payload
+pub fn raw_sidecar(csi_header: &csi::Header, compression: &str) -> serde_json::Value {
+    json!({
+        "Name": csi_header.csimetadata.name(),
+        "Version": csi_header.version,
+        "RenditionFlags": csi_header.rendition_flags.0,
+        "Width": csi_header.width,
+        "Height": csi_header.height,
+        "ScaleFactor": csi_header.scale_factor,
+        "PixelFormat": csi_header.pixel_format,
+        "ColorSpace": csi_header.color_space.0,
+        "ModTime": csi_header.csimetadata.mod_time,
+        "Layout": format!("{:?}", csi_header.csimetadata.layout),
+        "TLVLength": csi_header.csibitmaplist.tlv_length,
+        "BitmapCount": csi_header.csibitmaplist.bitmap_count,
+        "BitmapListReserved": csi_header.csibitmaplist.reserved,
+        "RenditionLength": csi_header.csibitmaplist.rendition_length,
+        "Compression": compression,
+        "RenditionPayloadTag": csi_header
+            .rendition_data
+            .as_ref()
+            .and_then(|rendition_data| rendition_data.unknown_payload())
+            .map(|(tag, _)| common::fourcc_label(tag)),
+        "VectorBounds": csi_header.vector_bounds().map(|(width, height)| [width, height]),
+        "TLV": csi_header.tlv_data.0.encode_hex::<String>(),
+    })
+}
+
+// writes every rendition's payload verbatim as <rendition_name>.<compression>.bin
+// with a .json sidecar, no codecs involved
+pub fn extract_raw(
+    car: &coreui::CarUtilAssetStorage,
+    output_path: impl AsRef<Path>,
+    max_payload_bytes: Option<u64>,
+) -> Result<ExtractSummary> {
+    let output_path = output_path.as_ref();
+    let store = &car.theme_store.store;
+    let mut used = OutputNames::default();
+    let mut summary = ExtractSummary::default();
+    for (rendition_key, csi_header) in store.imagedb.iter() {
+        // the parser left the payload of these out
+        if let Some(reason) = too_large(csi_header, max_payload_bytes) {
+            summary.skipped.push(reason);
+            continue;
+        }
+        let (compression, payload) = match raw_payload(csi_header) {
+            Ok(raw_payload) => raw_payload,
+            Err(err) => {
+                summary.failed.push(err.to_string());
+                continue;
+            }
+        };
+        let name = csi_header.csimetadata.name();
+        let stem = Path::new(&name)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(&name)
+            .to_string();
+        let file_name = output_file_name(
+            &format!("{}.{}.bin", stem, compression),
+            OutputFormat::Data,
+            name_identifier(store, rendition_key),
+            &mut used,
+        );
+
+        let file_path = output_path.join(&file_name);
+        let sidecar = raw_sidecar(csi_header, &compression);
+        let result = fs::write(&file_path, &payload).and_then(|_| {
+            fs::write(
+                file_path.with_extension("json"),
+                serde_json::to_string_pretty(&sidecar)?,
+            )
+        });
+        match result {
+            Ok(()) => summary.written.push(file_name),
+            Err(err) => summary.failed.push(format!("{}: {}", file_name, err)),
+        }
+    }
+    summary.warnings.extend(used.take_warnings());
+    Ok(summary.sort())
+}
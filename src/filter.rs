@@ -0,0 +1,294 @@
+use anyhow::Context;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use crate::assetutil;
+use crate::coreui::rendition::AttributeType;
+
+// a single attribute=value constraint on a rendition key
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AttributeRequirement {
+    pub attribute: AttributeType,
+    pub value: u16,
+}
+
+impl AttributeRequirement {
+    // parses "appearance=dark", "idiom=pad", "scale=2x", "displaygamut=p3",
+    // "subtype=570" or "24=1" with a raw attribute id, appearance names are
+    // resolved against the catalog's APPEARANCEKEYS
+    pub fn parse(
+        expression: &str,
+        appearancedb: &BTreeMap<String, u32>,
+    ) -> Result<AttributeRequirement> {
+        let (name, value) = expression
+            .split_once('=')
+            .context(format!("expected attribute=value, got {:?}", expression))?;
+        let attribute = parse_attribute(name.trim())?;
+        let value = value.trim();
+        let value = match attribute {
+            AttributeType::Appearance => parse_appearance(value, appearancedb)?,
+            AttributeType::Idiom => parse_idiom(value)?,
+            AttributeType::Scale => parse_number(value.trim_end_matches('x'))?,
+            AttributeType::DisplayGamut => parse_display_gamut(value)?,
+            _ => parse_number(value)?,
+        };
+        Ok(AttributeRequirement { attribute, value })
+    }
+
+    pub fn matches(&self, key_values: &[(AttributeType, u16)]) -> bool {
+        // attributes missing from the key format are implicitly 0
+        let value = key_values
+            .iter()
+            .find(|(attribute, _)| *attribute == self.attribute)
+            .map(|(_, value)| *value)
+            .unwrap_or(0);
+        value == self.value
+    }
+}
+
+// a name as printed in Key Format (case-insensitive), or the attribute's id so
+// attributes without a dedicated option can be matched too
+fn parse_attribute(name: &str) -> Result<AttributeType> {
+    if let Some(attribute) = AttributeType::from_name(name) {
+        return Ok(attribute);
+    }
+    // ids past the known ones are kept as Unknown, like in the key format
+    if let Ok(id) = name.parse::<u32>() {
+        return Ok(AttributeType::from(id));
+    }
+    None.context(format!(
+        "unknown attribute {:?}, expected an attribute id or one of: {}",
+        name,
+        AttributeType::ALL
+            .iter()
+            .map(|attribute| attribute.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    ))
+}
+
+fn parse_number(value: &str) -> Result<u16> {
+    value
+        .parse::<u16>()
+        .context(format!("expected a number, got {:?}", value))
+}
+
+pub fn parse_idiom(value: &str) -> Result<u16> {
+    match value.to_lowercase().as_str() {
+        "universal" => Ok(0),
+        "phone" | "iphone" => Ok(1),
+        "pad" | "ipad" => Ok(2),
+        "tv" => Ok(3),
+        "car" | "carplay" => Ok(4),
+        "watch" => Ok(5),
+        "marketing" | "ios-marketing" => Ok(6),
+        "mac" => Ok(7),
+        "vision" | "visionos" => Ok(8),
+        _ => parse_number(value),
+    }
+}
+
+fn parse_display_gamut(value: &str) -> Result<u16> {
+    match value.to_lowercase().as_str() {
+        "srgb" => Ok(0),
+        "p3" | "displayp3" => Ok(1),
+        _ => parse_number(value),
+    }
+}
+
+fn parse_appearance(value: &str, appearancedb: &BTreeMap<String, u32>) -> Result<u16> {
+    if value.eq_ignore_ascii_case("any") {
+        return Ok(0);
+    }
+    if let Ok(number) = value.parse::<u16>() {
+        return Ok(number);
+    }
+    appearancedb
+        .iter()
+        .find(|(name, _)| {
+            name.eq_ignore_ascii_case(value)
+                || assetutil::appearance_label(Some(name)).eq_ignore_ascii_case(value)
+        })
+        .map(|(_, identifier)| *identifier as u16)
+        .context(if appearancedb.is_empty() {
+            format!("unknown appearance {:?}, catalog has no appearances", value)
+        } else {
+            format!(
+                "unknown appearance {:?}, catalog has: {}",
+                value,
+                appearancedb
+                    .keys()
+                    .map(|name| name.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            )
+        })
+}
+
+// a constraint on one axis of the appearance: "luminosity=dark" matches the
+// dark and dark high contrast appearances, "contrast=high" every high contrast
+// one. The catalog's appearances on that axis are resolved when parsing
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppearanceAxisRequirement {
+    pub appearances: BTreeSet<u16>,
+}
+
+impl AppearanceAxisRequirement {
+    // None when the expression isn't about luminosity or contrast
+    pub fn parse(
+        expression: &str,
+        appearancedb: &BTreeMap<String, u32>,
+    ) -> Option<Result<AppearanceAxisRequirement>> {
+        let (name, value) = expression.split_once('=')?;
+        let value = value.trim().to_lowercase();
+        let matches: Box<dyn Fn(&assetutil::AppearanceVariant) -> bool> =
+            match name.trim().to_lowercase().as_str() {
+                "luminosity" => {
+                    let luminosity = match value.as_str() {
+                        "any" => assetutil::Luminosity::Any,
+                        "light" => assetutil::Luminosity::Light,
+                        "dark" => assetutil::Luminosity::Dark,
+                        _ => {
+                            return Some(Err(anyhow::anyhow!(
+                                "unknown luminosity {:?}, expected any, light or dark",
+                                value
+                            )))
+                        }
+                    };
+                    Box::new(move |variant| variant.luminosity == luminosity)
+                }
+                "contrast" => {
+                    let high_contrast = match value.as_str() {
+                        "normal" => false,
+                        "high" => true,
+                        _ => {
+                            return Some(Err(anyhow::anyhow!(
+                                "unknown contrast {:?}, expected normal or high",
+                                value
+                            )))
+                        }
+                    };
+                    Box::new(move |variant| variant.high_contrast == high_contrast)
+                }
+                _ => return None,
+            };
+        // renditions without an appearance are any luminosity, normal contrast
+        let mut appearances = BTreeSet::new();
+        if appearancedb.values().all(|identifier| *identifier != 0)
+            && matches(&assetutil::AppearanceVariant::ALL[0])
+        {
+            appearances.insert(0);
+        }
+        appearances.extend(
+            appearancedb
+                .iter()
+                .filter(|(name, _)| {
+                    assetutil::appearance_variant(Some(name))
+                        .is_some_and(|variant| matches(&variant))
+                })
+                .map(|(_, identifier)| *identifier as u16),
+        );
+        Some(Ok(AppearanceAxisRequirement { appearances }))
+    }
+
+    pub fn matches(&self, key_values: &[(AttributeType, u16)]) -> bool {
+        let appearance = key_values
+            .iter()
+            .find(|(attribute, _)| *attribute == AttributeType::Appearance)
+            .map(|(_, value)| *value)
+            .unwrap_or(0);
+        self.appearances.contains(&appearance)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct RenditionFilter {
+    pub attributes: Vec<AttributeRequirement>,
+    pub appearance_axes: Vec<AppearanceAxisRequirement>,
+}
+
+impl RenditionFilter {
+    // attribute=value expressions plus luminosity= and contrast= for the
+    // appearance axes
+    pub fn parse(
+        expressions: &[String],
+        appearancedb: &BTreeMap<String, u32>,
+    ) -> Result<RenditionFilter> {
+        let mut filter = RenditionFilter::default();
+        for expression in expressions {
+            match AppearanceAxisRequirement::parse(expression, appearancedb) {
+                Some(requirement) => filter.appearance_axes.push(requirement?),
+                None => filter
+                    .attributes
+                    .push(AttributeRequirement::parse(expression, appearancedb)?),
+            }
+        }
+        Ok(filter)
+    }
+
+    // only looks at the key, so it can run before any payload is decoded
+    pub fn matches_key(&self, key_values: &[(AttributeType, u16)]) -> bool {
+        self.attributes
+            .iter()
+            .all(|requirement| requirement.matches(key_values))
+            && self
+                .appearance_axes
+                .iter()
+                .all(|requirement| requirement.matches(key_values))
+    }
+}
+
+// a shell-style pattern on facet and rendition names, "*" matches any run of
+// characters (slashes of namespaced names included) and "?" any single one
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamePattern(String);
+
+impl NamePattern {
+    pub fn matches(&self, name: &str) -> bool {
+        let pattern: Vec<char> = self.0.chars().collect();
+        let name: Vec<char> = name.chars().collect();
+        // where the last "*" was and the name position it was tried at
+        let mut star: Option<(usize, usize)> = None;
+        let (mut p, mut n) = (0, 0);
+        while n < name.len() {
+            match pattern.get(p) {
+                Some('*') => {
+                    star = Some((p, n));
+                    p += 1;
+                }
+                Some(&c) if c == '?' || c == name[n] => {
+                    p += 1;
+                    n += 1;
+                }
+                _ => match star {
+                    // let the "*" take one more character
+                    Some((star_p, star_n)) => {
+                        star = Some((star_p, star_n + 1));
+                        p = star_p + 1;
+                        n = star_n + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+        pattern[p..].iter().all(|&c| c == '*')
+    }
+
+    // the facet name or the rendition name, either is enough
+    pub fn matches_names(&self, facet_name: Option<&str>, rendition_name: &str) -> bool {
+        facet_name.is_some_and(|facet_name| self.matches(facet_name))
+            || self.matches(rendition_name)
+    }
+}
+
+impl std::str::FromStr for NamePattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() {
+            anyhow::bail!("the name pattern is empty");
+        }
+        Ok(NamePattern(s.to_string()))
+    }
+}
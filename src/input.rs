@@ -0,0 +1,139 @@
+use anyhow::Context;
+use anyhow::Result;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use crate::common;
+
+// what a path passed as a catalog actually is, so the common mistakes get a
+// useful message instead of a binrw magic mismatch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputKind {
+    Car,
+    Directory,
+    Json,
+    Png,
+    Jpeg,
+    Zip,
+    Other,
+}
+
+pub fn sniff_bytes(data: &[u8]) -> InputKind {
+    if data.starts_with(b"BOMStore") {
+        InputKind::Car
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        InputKind::Png
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        InputKind::Jpeg
+    } else if data.starts_with(b"PK\x03\x04") {
+        InputKind::Zip
+    } else if matches!(
+        data.iter().find(|byte| !byte.is_ascii_whitespace()),
+        Some(b'{') | Some(b'[')
+    ) {
+        InputKind::Json
+    } else {
+        InputKind::Other
+    }
+}
+
+// the catalog path as messages show it, absolute so it doesn't depend on the
+// working directory the command ran in
+pub fn display_path(path: &Path) -> String {
+    match fs::canonicalize(path) {
+        Ok(canonical) => common::strip_verbatim_prefix(&canonical.to_string_lossy()),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+pub fn sniff_input(path: impl AsRef<Path>) -> Result<InputKind> {
+    let path = path.as_ref();
+    let metadata =
+        fs::metadata(path).with_context(|| format!("unable to open {}", path.display()))?;
+    if metadata.is_dir() {
+        return Ok(InputKind::Directory);
+    }
+    let mut data = vec![];
+    fs::File::open(path)
+        .and_then(|file| file.take(64).read_to_end(&mut data))
+        .with_context(|| format!("unable to read {}", display_path(path)))?;
+    Ok(sniff_bytes(&data))
+}
+
+// errors for inputs that are not a compiled catalog, anything with the BOM
+// magic is left for the parser to report
+pub fn check_car_input(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let kind = match sniff_input(path)? {
+        InputKind::Car => return Ok(()),
+        // too short to hold the magic, the truncation check explains these better
+        InputKind::Other if fs::metadata(path)?.len() < 8 => return Ok(()),
+        kind => kind,
+    };
+    let shown = display_path(path);
+    let message = match kind {
+        InputKind::Car => unreachable!(),
+        InputKind::Other => format!(
+            "{} is not a compiled catalog, it doesn't start with a BOMStore header",
+            shown
+        ),
+        InputKind::Directory => format!(
+            "{} is a directory, not a compiled catalog. Compile an .xcassets folder first, e.g. `carutil actool --compile <output_dir> {}`",
+            shown, shown
+        ),
+        InputKind::Json => format!(
+            "{} is a JSON file (a Contents.json?), not a compiled catalog",
+            shown
+        ),
+        InputKind::Png => format!("{} is a PNG image, not a compiled catalog", shown),
+        InputKind::Jpeg => format!("{} is a JPEG image, not a compiled catalog", shown),
+        InputKind::Zip => format!(
+            "{} is a zip archive (an .ipa?), unzip it and pass the Assets.car inside",
+            shown
+        ),
+    };
+    anyhow::bail!(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniff_magic_bytes() {
+        let car = std::fs::read("tests/Assets.car").unwrap();
+        assert_eq!(sniff_bytes(&car), InputKind::Car);
+        assert_eq!(
+            sniff_bytes(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            InputKind::Png
+        );
+        assert_eq!(
+            sniff_bytes(&[0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10]),
+            InputKind::Jpeg
+        );
+        assert_eq!(sniff_bytes(b"PK\x03\x04\x14\0"), InputKind::Zip);
+        assert_eq!(sniff_bytes(b"\n  {\n  \"images\" : ["), InputKind::Json);
+        assert_eq!(sniff_bytes(b"BOMStor"), InputKind::Other);
+        assert_eq!(sniff_bytes(b""), InputKind::Other);
+    }
+
+    #[test]
+    fn sniff_directories_and_files() {
+        let directory = std::env::temp_dir().join(format!("carutil-sniff-{}", std::process::id()));
+        let catalog = directory.join("Media.xcassets");
+        fs::create_dir_all(&catalog).unwrap();
+        let contents = catalog.join("Contents.json");
+        fs::write(&contents, b"{\"info\":{\"version\":1}}").unwrap();
+        let image = directory.join("Icon.png");
+        fs::write(&image, b"\x89PNG\r\n\x1a\n").unwrap();
+
+        assert_eq!(sniff_input(&catalog).unwrap(), InputKind::Directory);
+        assert_eq!(sniff_input(&contents).unwrap(), InputKind::Json);
+        assert_eq!(sniff_input(&image).unwrap(), InputKind::Png);
+        assert_eq!(sniff_input("tests/Assets.car").unwrap(), InputKind::Car);
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(sniff_input("tests/missing.car").is_err());
+    }
+}
@@ -1,5 +1,157 @@
+//! Reads, dumps and writes CoreUI asset catalogs (`Assets.car`).
+//!
+//! The names re-exported here are the supported API. The other public modules
+//! back the `carutil` subcommands (`actool`, `budget`, `stats`, ...), helpers
+//! shared between modules stay inside the crate.
+//!
+//! Open a catalog:
+//! ```
+//! # fn main() -> anyhow::Result<()> {
+//! let car = carutil_lib::CarUtilAssetStorage::from("tests/Assets.car", false)?;
+//! assert!(car.theme_store.store.facet_names().contains(&"MyPNG"));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Dump it the way `assetutil --info` does:
+//! ```
+//! # fn main() -> anyhow::Result<()> {
+//! use carutil_lib::ToAssetUtilHeader;
+//!
+//! let car = carutil_lib::CarUtilAssetStorage::from("tests/Assets.car", false)?;
+//! let header = serde_json::to_value(car.asset_util_header())?;
+//! let mut entries = carutil_lib::AssetUtilEntry::entries_from_asset_storage(&car.theme_store.store);
+//! carutil_lib::assetutil::sort_entries(&mut entries);
+//! let json = carutil_lib::assetutil::to_json_pretty(&header, &entries, 0)?;
+//! assert!(json.contains("Timac@3x.png"));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Extract one asset, without touching the filesystem:
+//! ```
+//! # fn main() -> anyhow::Result<()> {
+//! let car = carutil_lib::CarUtilAssetStorage::from("tests/Assets.car", false)?;
+//! let csi_header = car
+//!     .theme_store
+//!     .best_rendition("MyPNG", &carutil_lib::RenditionQuery::default())
+//!     .expect("no rendition");
+//! let file = csi_header.extracted_file()?.expect("not an image");
+//! assert_eq!(file.format, carutil_lib::csi::OutputFormat::Png);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Or decode it to pixels:
+//! ```
+//! # fn main() -> anyhow::Result<()> {
+//! use carutil_lib::csi::DecodedRendition;
+//!
+//! let car = carutil_lib::CarUtilAssetStorage::from("tests/Assets.car", false)?;
+//! let csi_header = car
+//!     .theme_store
+//!     .best_rendition("MyPNG", &carutil_lib::RenditionQuery::default())
+//!     .expect("no rendition");
+//! match csi_header.decode()? {
+//!     DecodedRendition::Rgba { width, height, pixels } => {
+//!         assert_eq!(pixels.len(), width as usize * height as usize * 4)
+//!     }
+//!     _ => panic!("not rgba pixels"),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Build a catalog with a data asset and write it:
+//! ```
+//! # fn main() -> anyhow::Result<()> {
+//! use carutil_lib::coreui::CarExtendedMetadata;
+//! use carutil_lib::coreui::CarHeader;
+//! use carutil_lib::coreui::StructuredThemeStore;
+//! use carutil_lib::dataset::DataSet;
+//! use carutil_lib::dataset::DataSetFile;
+//!
+//! let header = CarHeader::new(
+//!     802,
+//!     17,
+//!     0,
+//!     0,
+//!     "@(#)PROGRAM:CoreUI  PROJECT:CoreUI-802\n",
+//!     "carutil",
+//!     [0; 16],
+//!     0,
+//!     5,
+//!     0,
+//!     0,
+//! )?;
+//! let extended_metadata = CarExtendedMetadata::new("", "16.0", "ios", "carutil")?;
+//! let mut store = carutil_lib::CommonAssetStorage::new(
+//!     header,
+//!     extended_metadata,
+//!     carutil_lib::rendition::KeyFormat::standard(),
+//! );
+//! let greeting = DataSet {
+//!     name: "greeting".to_string(),
+//!     tags: vec![],
+//!     cursor_hotspot: None,
+//!     files: vec![DataSetFile {
+//!         file_name: "greeting.txt".to_string(),
+//!         idiom: carutil_lib::rendition::Idiom::Universal,
+//!         uti: "public.plain-text".to_string(),
+//!         data: b"hello".to_vec(),
+//!     }],
+//! };
+//! carutil_lib::dataset::add_datasets(&mut store, &[greeting], false, false)?;
+//!
+//! let car = carutil_lib::CarUtilAssetStorage {
+//!     theme_store: StructuredThemeStore { store },
+//! };
+//! let path = std::env::temp_dir().join(format!("carutil-doc-{}.car", std::process::id()));
+//! car.write_data(path.to_str().unwrap())?;
+//! let written = carutil_lib::CarUtilAssetStorage::from(path.to_str().unwrap(), false)?;
+//! std::fs::remove_file(&path)?;
+//! assert_eq!(written.theme_store.store.facet_names(), vec!["greeting"]);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod actool;
+pub mod archive;
 pub mod assetutil;
 pub mod bom;
-pub mod common;
+pub mod budget;
+pub(crate) mod common;
 pub mod coregraphics;
 pub mod coreui;
+pub mod coverage;
+pub mod dataset;
+pub mod decompile;
+pub mod extract;
+pub mod filter;
+pub(crate) mod input;
+pub mod stats;
+pub mod verify;
+pub mod versions;
+
+pub use assetutil::AssetUtilEntry;
+pub use assetutil::AssetUtilHeader;
+pub use assetutil::ToAssetUtilHeader;
+pub use common::FlattenNames;
+pub use common::RawData;
+pub use coreui::csi;
+pub use coreui::rendition;
+pub use coreui::tlv;
+pub use coreui::CarError;
+pub use coreui::CarUtilAssetStorage;
+pub use coreui::CommonAssetStorage;
+pub use coreui::ParseOptions;
+pub use coreui::RenditionQuery;
+pub use extract::AssetTypes;
+pub use extract::ColorPalette;
+pub use extract::ExtractOptions;
+pub use extract::ExtractOutcome;
+pub use extract::ExtractReport;
+pub use extract::ExtractSummary;
+pub use extract::NamingTemplate;
+pub use extract::ProgressCallback;
+pub use filter::NamePattern;
@@ -1,3 +1,4 @@
+use anyhow::Context;
 use anyhow::Result;
 
 use clap::arg;
@@ -5,15 +6,29 @@ use clap::command;
 use clap::CommandFactory;
 use clap::Parser;
 use clap::Subcommand;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
-use assetutil::ToAssetUtilHeader;
+use carutil_lib::actool;
+use carutil_lib::archive;
+use carutil_lib::assetutil;
+use carutil_lib::budget;
+use carutil_lib::coreui;
+use carutil_lib::coverage;
+use carutil_lib::decompile;
+use carutil_lib::extract;
+use carutil_lib::filter;
+use carutil_lib::stats;
+use carutil_lib::verify;
+use carutil_lib::versions;
+use carutil_lib::ToAssetUtilHeader;
 
-mod actool;
-mod assetutil;
-mod bom;
-mod common;
-mod coregraphics;
-mod coreui;
+// deflate needs the zlib feature, builds without it store zip entries
+#[cfg(feature = "zlib")]
+const DEFAULT_ZIP_COMPRESSION: &str = "deflate";
+#[cfg(not(feature = "zlib"))]
+const DEFAULT_ZIP_COMPRESSION: &str = "stored";
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -28,7 +43,52 @@ enum Commands {
     Assetutil {
         /// dumps JSON describing the contents of the .car input file
         #[arg(short = 'I', long, value_name = "inputfile")]
-        info: Option<String>,
+        info: Option<PathBuf>,
+
+        /// adds fields assetutil doesn't print, like the file's own timestamp and
+        /// the idioms, scales and appearances the renditions use
+        #[arg(long)]
+        extended_header: bool,
+
+        /// adds the tlv, payload prefix and csi header bytes of renditions with this name
+        #[arg(long, value_name = "name")]
+        debug_blobs: Option<String>,
+
+        /// adds PixelDigest, a hash of the decoded pixels, to image entries
+        #[arg(long)]
+        pixel_digests: bool,
+
+        /// how group separators in namespaced names are rendered: slash (raw), dash or keep
+        #[arg(long, value_name = "mode", value_parser = ["slash", "dash", "keep"], default_value = "slash")]
+        flatten_names: String,
+
+        /// DumpToolVersion to report, to match the output of a specific assetutil release
+        #[arg(long, value_name = "version")]
+        dump_tool_version: Option<f64>,
+
+        /// threads used to build and serialize entries, 0 for one per core
+        #[arg(long, value_name = "n", default_value_t = 0)]
+        jobs: usize,
+
+        /// attribute=value the rendition key must have, by name or raw attribute id, e.g. idiom=pad or 24=1
+        #[arg(long = "key-filter", value_name = "attribute=value")]
+        key_filters: Vec<String>,
+
+        /// compare names exactly instead of ignoring case like CoreUI
+        #[arg(long)]
+        case_sensitive: bool,
+
+        /// entries of facets sharing a name identifier: report (Name plus NameCandidates), first (Name only) or error
+        #[arg(long, value_name = "mode", value_parser = ["report", "first", "error"], default_value = "report")]
+        name_collisions: String,
+
+        /// only print the platform, versions and entry counts per asset type
+        #[arg(long)]
+        brief: bool,
+
+        /// how --brief output is printed
+        #[arg(long, value_parser = ["text", "json"], default_value = "text", requires = "brief")]
+        format: String,
     },
     /// compatible with actool cli tool
     Actool {
@@ -44,7 +104,7 @@ enum Commands {
         /// option instructs actool to convert an asset catalog to files
         /// optimized for runtime.
         #[arg(long, value_name = "path")]
-        compile: Option<String>,
+        compile: Option<PathBuf>,
 
         /// Specifies the target platform to compile for. This option influences
         /// warnings, validation, and which images are included in the built
@@ -52,54 +112,367 @@ enum Commands {
         #[arg(long, value_name = "platform_name")]
         platform: Option<String>,
 
-        document: String,
+        /// CoreUI version written to the catalog header
+        #[arg(long, value_name = "version")]
+        core_ui_version: Option<u32>,
+
+        /// LZFSE compress the payloads of data assets
+        #[arg(long)]
+        compress: bool,
+
+        /// cut names too long for the catalog's fixed-width fields instead of
+        /// failing
+        #[arg(long)]
+        truncate: bool,
+
+        document: PathBuf,
     },
     /// extract images from Assets.car
     Extract {
         /// path to Assets.car
-        car_path: String,
+        car_path: PathBuf,
 
         /// path to dump images
         #[arg(short = 'o', long, value_name = "inputfile", default_value = ".")]
-        output_path: String,
+        output_path: PathBuf,
+
+        /// skip renditions whose size on disk is above this many bytes
+        #[arg(long, value_name = "bytes")]
+        max_size: Option<u64>,
+
+        /// write the stored payloads without decoding, with a JSON sidecar of the csi header
+        #[arg(long)]
+        raw: bool,
+
+        /// write each frame of animation filmstrips as <name>_frame###.png
+        #[arg(long, conflicts_with = "apng")]
+        split_frames: bool,
+
+        /// write animation filmstrips as animated PNGs
+        #[arg(long)]
+        apng: bool,
+
+        /// write the extracted files into this zip archive instead of output_path
+        #[arg(long, value_name = "path.zip", conflicts_with = "raw")]
+        zip: Option<PathBuf>,
+
+        /// how group separators in namespaced names are rendered: keep (directories), dash or slash (no groups)
+        #[arg(long, value_name = "mode", value_parser = ["slash", "dash", "keep"], default_value = "keep")]
+        flatten_names: String,
+
+        /// how entries of the zip archive are compressed
+        #[arg(long, value_parser = ["stored", "deflate"], default_value = DEFAULT_ZIP_COMPRESSION, requires = "zip")]
+        zip_compression: String,
+
+        /// attribute=value the rendition key must have, by name or raw attribute id, e.g. idiom=pad or 24=1
+        #[arg(
+            long = "key-filter",
+            value_name = "attribute=value",
+            conflicts_with = "raw"
+        )]
+        key_filters: Vec<String>,
+
+        /// write a JSON array describing every written file and every skipped or failed rendition
+        #[arg(long, value_name = "path.json", conflicts_with = "raw")]
+        manifest: Option<PathBuf>,
+
+        /// pad images CoreUI cropped the transparent borders of back to their original size
+        #[arg(long, conflicts_with = "raw")]
+        restore_canvas: bool,
+
+        /// divide color by alpha before writing PNGs, CoreUI stores premultiplied pixels
+        #[arg(long, conflicts_with = "raw")]
+        unpremultiply: bool,
+
+        /// write deep color images with components outside 0...1 as OpenEXR instead of clamping them (needs the exr feature)
+        #[arg(long, conflicts_with = "raw")]
+        preserve_extended: bool,
+
+        /// only write the rendition of each asset CoreUI would pick, named after the asset (3x, universal and P3 unless asked otherwise)
+        #[arg(long, conflicts_with = "raw")]
+        best: bool,
+
+        /// scale factor --best aims for, e.g. 2 or 2x
+        #[arg(long, requires = "best")]
+        scale: Option<String>,
+
+        /// appearance name or label --best aims for, e.g. dark
+        #[arg(long, requires = "best")]
+        appearance: Option<String>,
+
+        /// idiom --best aims for, e.g. phone or pad
+        #[arg(long, requires = "best")]
+        idiom: Option<String>,
+
+        /// display gamut --best aims for, srgb or p3
+        #[arg(long, requires = "best")]
+        gamut: Option<String>,
+
+        /// the output is on a case-sensitive filesystem, don't rename files whose names differ only in case
+        #[arg(long, conflicts_with = "raw")]
+        case_sensitive_fs: bool,
+
+        /// only extract these kinds of renditions, comma separated: image, data, vector
+        #[arg(long, value_name = "types", conflicts_with = "raw")]
+        types: Option<String>,
+
+        /// name files after a template with {facet}, {rendition}, {scale}, {idiom} and {appearance}, e.g. "{facet}@{scale}x~{idiom}"
+        #[arg(long, value_name = "template", conflicts_with = "raw")]
+        naming: Option<String>,
+
+        /// also write the color assets to a palette, json (colors.json) or css (colors.css)
+        #[arg(long, value_name = "format", conflicts_with = "raw")]
+        colors: Option<extract::ColorPalette>,
+
+        /// threads decoding images, 0 for one per core
+        #[arg(long, value_name = "n", default_value_t = 0)]
+        jobs: usize,
+
+        /// only extract renditions whose facet or rendition name matches the glob, e.g. 'AppIcon*' or '*@3x.png'
+        #[arg(long, value_name = "glob", conflicts_with = "raw")]
+        filter: Option<String>,
+    },
+    /// rebuilds an .xcassets folder with an image, color or data set per asset
+    Decompile {
+        /// path to Assets.car
+        car_path: PathBuf,
+
+        /// the .xcassets folder to write
+        #[arg(short = 'o', long, value_name = "path")]
+        output: PathBuf,
+    },
+    /// lists facet names with a summary of their variants
+    List {
+        /// path to Assets.car
+        car_path: PathBuf,
+
+        /// print each rendition on its own line
+        #[arg(long)]
+        long: bool,
     },
+    /// lists facet names containing the search term (case-insensitive unless --case-sensitive)
+    Search {
+        /// path to Assets.car
+        car_path: PathBuf,
+
+        /// text to search for in facet names
+        query: String,
+
+        /// print each rendition on its own line
+        #[arg(long)]
+        long: bool,
+
+        /// compare names exactly instead of ignoring case like CoreUI
+        #[arg(long)]
+        case_sensitive: bool,
+    },
+    /// lists image assets without a rendition matching the required attributes
+    Coverage {
+        /// path to Assets.car
+        car_path: PathBuf,
+
+        /// attribute=value every image asset needs a rendition for, e.g. appearance=dark, or luminosity=dark and contrast=high for one axis of the appearance
+        #[arg(long = "require", value_name = "attribute=value")]
+        requirements: Vec<String>,
+    },
+    /// lists the largest renditions and fails when they exceed a size budget
+    Budget {
+        /// path to Assets.car
+        car_path: PathBuf,
+
+        /// largest SizeOnDisk allowed for one rendition, e.g. 500KB
+        #[arg(long, value_name = "size")]
+        max_asset_size: Option<String>,
+
+        /// largest SizeOnDisk allowed for all renditions together, e.g. 20MB
+        #[arg(long, value_name = "size")]
+        max_total: Option<String>,
+
+        /// JSON object of per-name budgets replacing --max-asset-size, e.g. {"AppIcon": "1MB"}
+        #[arg(long, value_name = "path.json")]
+        config: Option<PathBuf>,
+
+        /// number of largest renditions to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+
+        /// compare names exactly instead of ignoring case like CoreUI
+        #[arg(long)]
+        case_sensitive: bool,
+    },
+    /// prints the rendition CoreUI would pick for an asset
+    Resolve {
+        /// path to Assets.car
+        car_path: PathBuf,
+
+        /// asset (facet) name
+        #[arg(long)]
+        name: String,
+
+        /// scale factor, e.g. 3 or 3x
+        #[arg(long)]
+        scale: Option<String>,
+
+        /// appearance name or label, e.g. dark
+        #[arg(long)]
+        appearance: Option<String>,
+
+        /// idiom, e.g. phone or pad
+        #[arg(long)]
+        idiom: Option<String>,
+
+        /// display gamut, srgb or p3
+        #[arg(long)]
+        gamut: Option<String>,
+
+        /// compare names exactly instead of ignoring case like CoreUI
+        #[arg(long)]
+        case_sensitive: bool,
+    },
+    /// checks for duplicate rendition keys, facets without renditions, unlisted appearances, missing or cyclic key trees and unexpected bitmap lists, and lists shared name identifiers
+    Verify {
+        /// path to Assets.car
+        car_path: PathBuf,
+    },
+    /// replaces the pixels of image renditions with a png
+    SetImage {
+        /// path to Assets.car
+        car_path: PathBuf,
+
+        /// path to the png
+        png_path: PathBuf,
+
+        /// rendition name of the images to replace, e.g. Icon@2x.png
+        #[arg(long, value_name = "name")]
+        rendition: String,
+
+        /// append the new renditions to the file instead of rewriting the whole catalog
+        #[arg(long)]
+        in_place: bool,
+    },
+    /// prints how many bytes each BOM variable takes up and how many no block accounts for
+    Stats {
+        /// path to Assets.car
+        car_path: PathBuf,
+    },
+    /// reports which compression types this build can decode
+    Capabilities,
     /// dumps structs of parsed Assets.car
     Debug {
         /// path to Assets.car
-        car_path: String,
+        car_path: PathBuf,
+
+        /// only dump the key and csi header of renditions with this name
+        #[arg(long, value_name = "name")]
+        rendition: Option<String>,
+
+        /// print every BOM variable with its block id, address and length
+        #[arg(long, conflicts_with_all = ["rendition", "dump_block"])]
+        list_vars: bool,
+
+        /// hexdump a BOM block by id
+        #[arg(long, value_name = "id", conflicts_with = "rendition")]
+        dump_block: Option<u32>,
+
+        /// bytes into the block to start the hexdump at
+        #[arg(long, default_value_t = 0, requires = "dump_block")]
+        offset: u64,
+
+        /// bytes to hexdump, the rest of the block by default
+        #[arg(long, requires = "dump_block")]
+        length: Option<u64>,
     },
 }
 
 fn main() -> Result<()> {
     let args = Cli::parse();
     match args.command {
-        Commands::Assetutil { info } => {
-            if let Some(car_path) = info {
+        Commands::Assetutil {
+            info,
+            extended_header,
+            debug_blobs,
+            pixel_digests,
+            flatten_names,
+            dump_tool_version,
+            jobs,
+            key_filters,
+            case_sensitive,
+            name_collisions,
+            brief,
+            format,
+        } => {
+            if let (Some(car_path), true) = (&info, brief) {
+                let car =
+                    coreui::CarUtilAssetStorage::from_with_options(car_path, &without_digests())?;
+                let brief = assetutil::BriefInfo::from_storage(&car.theme_store.store);
+                if format == "json" {
+                    println!("{}", serde_json::to_string_pretty(&brief)?);
+                } else {
+                    for line in brief.lines() {
+                        println!("{}", line);
+                    }
+                }
+                Ok(())
+            } else if let Some(car_path) = info {
                 let car = coreui::CarUtilAssetStorage::from(&car_path, false)?;
+                let store = &car.theme_store.store;
+                for warning in store
+                    .key_format_warnings()
+                    .into_iter()
+                    .chain(store.facet_name_warnings())
+                {
+                    eprintln!("warning: {}", warning);
+                }
 
-                let asset_util_header = serde_json::to_value(car.asset_util_header())?;
-                let mut result: Vec<serde_json::Value> = vec![asset_util_header];
-
-                let mut entries =
-                    assetutil::AssetUtilEntry::entries_from_asset_storage(&car.theme_store.store);
-                entries.sort_by(|a, b| {
-                    (
-                        a.asset_type.clone(),
-                        a.name.clone(),
-                        a.rendition_name.clone(),
-                    )
-                        .cmp(&(
-                            b.asset_type.clone(),
-                            b.name.clone(),
-                            b.rendition_name.clone(),
-                        ))
-                });
-                for entry in entries {
-                    let value = serde_json::to_value(entry)?;
-                    result.push(value);
+                let mut versions = versions::ToolVersions::default();
+                if let Some(dump_tool_version) = dump_tool_version {
+                    versions = versions.dump_tool_version(dump_tool_version);
+                }
+                let mut asset_util_header = car.asset_util_header_with_versions(&versions);
+                if extended_header {
+                    let store = &car.theme_store.store;
+                    asset_util_header.file_timestamp = store.file_timestamp;
+                    asset_util_header.key_semantics = Some(store.header.key_semantics);
+                    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+                    for warning in store
+                        .timestamp_warnings(now)
+                        .into_iter()
+                        .chain(store.key_semantics_warning())
+                    {
+                        eprintln!("warning: {}", warning);
+                    }
                 }
+                let name_collisions: assetutil::NameCollisions = name_collisions.parse()?;
+                if name_collisions == assetutil::NameCollisions::Error {
+                    if let Some((key, names)) =
+                        assetutil::ambiguous_facet_names(&car.theme_store.store)
+                    {
+                        anyhow::bail!(
+                            "rendition {:?} could belong to any of the facets {:?}",
+                            key,
+                            names
+                        );
+                    }
+                }
+                let mut entries = assetutil::AssetUtilEntry::entries_with_options(
+                    &car.theme_store.store,
+                    &assetutil::EntryOptions {
+                        debug_blobs,
+                        pixel_digests,
+                        flatten_names: flatten_names.parse()?,
+                        jobs,
+                        filter: key_filter(&car.theme_store.store, &key_filters)?,
+                        case_sensitive,
+                        name_collisions,
+                    },
+                );
+                assetutil::sort_entries(&mut entries);
+                if extended_header {
+                    asset_util_header.add_variants_present(&entries);
+                }
+                let asset_util_header = serde_json::to_value(asset_util_header)?;
 
-                let json = serde_json::to_string_pretty(&result)?;
+                let json = assetutil::to_json_pretty(&asset_util_header, &entries, jobs)?;
                 println!("{}", json);
                 Ok(())
             } else {
@@ -111,10 +484,17 @@ fn main() -> Result<()> {
             output_format,
             compile,
             platform,
+            core_ui_version,
+            compress,
+            truncate,
             document,
         } => {
             if let Some(output_path) = compile {
-                actool::compile(&document, &output_path)
+                let mut versions = versions::ToolVersions::default();
+                if let Some(core_ui_version) = core_ui_version {
+                    versions = versions.core_ui_version(core_ui_version);
+                }
+                actool::compile(&document, &output_path, &versions, compress, truncate)
             } else {
                 Ok(())
             }
@@ -122,21 +502,354 @@ fn main() -> Result<()> {
         Commands::Extract {
             car_path,
             output_path,
+            max_size,
+            raw,
+            split_frames,
+            apng,
+            zip,
+            zip_compression,
+            flatten_names,
+            key_filters,
+            manifest,
+            restore_canvas,
+            unpremultiply,
+            preserve_extended,
+            best,
+            scale,
+            appearance,
+            idiom,
+            gamut,
+            case_sensitive_fs,
+            types,
+            naming,
+            colors,
+            jobs,
+            filter,
         } => {
-            let car = coreui::CarUtilAssetStorage::from(&car_path, false)?;
-            let imagedb = car.theme_store.store.imagedb;
-            for (_rendition_key, csi_header) in imagedb.iter() {
-                let result = csi_header.extract(&output_path);
-                if let Err(err) = result {
-                    eprintln!("Unable to extract: {}", err);
-                } else if let Ok(Some(output_path)) = result {
-                    eprintln!("Extracted: {}", output_path);
+            if preserve_extended && !cfg!(feature = "exr") {
+                anyhow::bail!("--preserve-extended needs carutil built with the exr feature");
+            }
+            let options = coreui::ParseOptions {
+                max_payload_bytes: max_size,
+                ..without_digests()
+            };
+            let car = coreui::CarUtilAssetStorage::from_with_options(&car_path, &options)?;
+            let store = &car.theme_store.store;
+            for warning in store
+                .key_format_warnings()
+                .into_iter()
+                .chain(store.facet_name_warnings())
+            {
+                eprintln!("warning: {}", warning);
+            }
+            let summary = if raw {
+                extract::extract_raw(&car, &output_path, max_size)?
+            } else {
+                let filmstrip = if split_frames {
+                    extract::Filmstrip::Frames
+                } else if apng {
+                    extract::Filmstrip::Apng
+                } else {
+                    extract::Filmstrip::Strip
+                };
+                let best = if best {
+                    let store = &car.theme_store.store;
+                    let default = extract::default_best_query();
+                    Some(coreui::RenditionQuery {
+                        scale: query_value(store, "scale", scale)?.or(default.scale),
+                        idiom: query_value(store, "idiom", idiom)?.or(default.idiom),
+                        appearance: query_value(store, "appearance", appearance)?
+                            .or(default.appearance),
+                        display_gamut: query_value(store, "displaygamut", gamut)?
+                            .or(default.display_gamut),
+                        ..default
+                    })
+                } else {
+                    None
+                };
+                let extract_options = extract::ExtractOptions {
+                    filmstrip,
+                    flatten_names: flatten_names.parse()?,
+                    filter: key_filter(&car.theme_store.store, &key_filters)?,
+                    restore_canvas,
+                    unpremultiply,
+                    preserve_extended,
+                    best,
+                    case_sensitive_fs,
+                    max_size,
+                    types: types.as_deref().unwrap_or("image,data,vector").parse()?,
+                    naming: match &naming {
+                        Some(naming) => naming.parse()?,
+                        None => extract::NamingTemplate::default(),
+                    },
+                    colors,
+                    name_pattern: filter.as_deref().map(str::parse).transpose()?,
+                    jobs,
+                    on_progress: None,
+                };
+                if let Some(zip) = &zip {
+                    let compression = if zip_compression == "stored" {
+                        archive::ZipCompression::Stored
+                    } else {
+                        archive::ZipCompression::Deflate
+                    };
+                    extract::extract_zip(&car, zip, &options, &extract_options, compression)?
+                } else {
+                    car.extract_with(&output_path, &extract_options)?
+                }
+            };
+            if let Some(manifest) = &manifest {
+                extract::write_manifest(&summary, manifest)?;
+            }
+            summary.log(zip.as_deref().unwrap_or(&output_path));
+            Ok(())
+        }
+        Commands::Decompile { car_path, output } => {
+            let car =
+                coreui::CarUtilAssetStorage::from_with_options(&car_path, &without_digests())?;
+            let summary = decompile::decompile(&car, &output, &without_digests())?;
+            summary.log(&output);
+            Ok(())
+        }
+        Commands::List { car_path, long } => {
+            let car =
+                coreui::CarUtilAssetStorage::from_with_options(&car_path, &without_digests())?;
+            let entries =
+                assetutil::AssetUtilEntry::entries_from_asset_storage(&car.theme_store.store);
+            print_facets(&entries, long);
+            Ok(())
+        }
+        Commands::Search {
+            car_path,
+            query,
+            long,
+            case_sensitive,
+        } => {
+            let car =
+                coreui::CarUtilAssetStorage::from_with_options(&car_path, &without_digests())?;
+            let entries: Vec<assetutil::AssetUtilEntry> =
+                assetutil::AssetUtilEntry::entries_from_asset_storage(&car.theme_store.store)
+                    .into_iter()
+                    .filter(|entry| {
+                        entry.name.as_ref().is_some_and(|name| {
+                            if case_sensitive {
+                                name.contains(&query)
+                            } else {
+                                coreui::FacetName::new(name).contains(&query)
+                            }
+                        })
+                    })
+                    .collect();
+            print_facets(&entries, long);
+            Ok(())
+        }
+        Commands::Coverage {
+            car_path,
+            requirements,
+        } => {
+            let car =
+                coreui::CarUtilAssetStorage::from_with_options(&car_path, &without_digests())?;
+            let store = &car.theme_store.store;
+            let report = coverage::coverage(store, &key_filter(store, &requirements)?);
+            for name in &report.missing {
+                println!("{}", name);
+            }
+            println!(
+                "{} of {} image assets missing coverage",
+                report.missing.len(),
+                report.checked
+            );
+            if !report.missing.is_empty() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Commands::Resolve {
+            car_path,
+            name,
+            scale,
+            appearance,
+            idiom,
+            gamut,
+            case_sensitive,
+        } => {
+            let car =
+                coreui::CarUtilAssetStorage::from_with_options(&car_path, &without_digests())?;
+            let store = &car.theme_store.store;
+            let query = coreui::RenditionQuery {
+                scale: query_value(store, "scale", scale)?,
+                idiom: query_value(store, "idiom", idiom)?,
+                appearance: query_value(store, "appearance", appearance)?,
+                display_gamut: query_value(store, "displaygamut", gamut)?,
+                case_sensitive,
+            };
+            let csi_header = car
+                .theme_store
+                .best_rendition(&name, &query)
+                .context(format!("no rendition of {:?} matches", name))?;
+            println!("{}", csi_header.csimetadata.name());
+            Ok(())
+        }
+        Commands::Budget {
+            car_path,
+            max_asset_size,
+            max_total,
+            config,
+            top,
+            case_sensitive,
+        } => {
+            let overrides = match config {
+                Some(config) => budget::Budget::overrides_from_json(
+                    &std::fs::read_to_string(&config)
+                        .with_context(|| format!("Unable to read {}", config.display()))?,
+                )?,
+                None => Default::default(),
+            };
+            let budget = budget::Budget {
+                max_asset_size: max_asset_size
+                    .as_deref()
+                    .map(budget::parse_size)
+                    .transpose()?,
+                max_total: max_total.as_deref().map(budget::parse_size).transpose()?,
+                overrides,
+                case_sensitive,
+            };
+            let car =
+                coreui::CarUtilAssetStorage::from_with_options(&car_path, &without_digests())?;
+            let report = budget::check_budget(&car.theme_store.store, &budget);
+            for (name, size) in report.largest.iter().take(top) {
+                println!("{:>12}  {}", size, name);
+            }
+            println!("{:>12}  total", report.total);
+            for line in report.lines() {
+                println!("{}", line);
+            }
+            if !report.is_ok() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Commands::Verify { car_path } => {
+            let report = verify::verify(&car_path)?;
+            for line in report.lines() {
+                println!("{}", line);
+            }
+            if !report.is_ok() {
+                std::process::exit(1);
+            }
+            println!("no problems found");
+            Ok(())
+        }
+        Commands::SetImage {
+            car_path,
+            png_path,
+            rendition,
+            in_place,
+        } => {
+            let mut car = coreui::CarUtilAssetStorage::from_with_options(
+                &car_path,
+                &coreui::ParseOptions {
+                    use_mmap: false,
+                    ..without_digests()
+                },
+            )?;
+            let png = std::fs::read(&png_path)
+                .with_context(|| format!("Unable to read {}", png_path.display()))?;
+            let mut edits = vec![];
+            for (key, csi_header) in &car.theme_store.store.imagedb {
+                if csi_header.csimetadata.name() == rendition {
+                    let mut csi_header = csi_header.clone();
+                    csi_header.set_png(&png)?;
+                    edits.push((*key, csi_header));
                 }
             }
+            if edits.is_empty() {
+                anyhow::bail!("no rendition named {:?}", rendition);
+            }
+            if in_place {
+                eprintln!(
+                    "warning: updating {} in place, the replaced renditions stay in the file unreferenced and an interrupted update can leave it unreadable",
+                    car_path.display()
+                );
+                coreui::CarUtilAssetStorage::update_in_place(&car_path, &edits)?;
+            } else {
+                car.theme_store.store.imagedb.extend(edits.iter().cloned());
+                car.write_data(&car_path)?;
+            }
+            println!("replaced {} renditions", edits.len());
+            Ok(())
+        }
+        Commands::Stats { car_path } => {
+            for line in stats::stats(&car_path)?.lines() {
+                println!("{}", line);
+            }
+            Ok(())
+        }
+        Commands::Capabilities => {
+            let capabilities: Vec<serde_json::Value> = coreui::codec::capabilities()
+                .into_iter()
+                .map(|(compression_type, decodable)| {
+                    serde_json::json!({
+                        "Compression": compression_type,
+                        "Decodable": decodable,
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&capabilities)?);
+            Ok(())
+        }
+        Commands::Debug {
+            car_path,
+            list_vars: true,
+            ..
+        } => {
+            for line in stats::var_block_lines(&stats::var_blocks(&car_path)?) {
+                println!("{}", line);
+            }
+            Ok(())
+        }
+        Commands::Debug {
+            car_path,
+            dump_block: Some(block_id),
+            offset,
+            length,
+            ..
+        } => {
+            let data = stats::block_bytes(&car_path, block_id, offset, length)?;
+            for line in stats::hexdump(&data, offset) {
+                println!("{}", line);
+            }
+            Ok(())
+        }
+        Commands::Debug {
+            car_path,
+            rendition: Some(rendition),
+            ..
+        } => {
+            let car = coreui::CarUtilAssetStorage::from(&car_path, false)?;
+            warn_without_debug_impls();
+            let renditions: Vec<_> = car
+                .theme_store
+                .store
+                .imagedb
+                .iter()
+                .filter(|(_, csi_header)| csi_header.csimetadata.name() == rendition)
+                .collect();
+            if renditions.is_empty() {
+                anyhow::bail!("no rendition named {:?}", rendition);
+            }
+            for (key, csi_header) in renditions {
+                dbg!(key, csi_header);
+            }
             Ok(())
         }
-        Commands::Debug { car_path } => {
+        Commands::Debug {
+            car_path,
+            rendition: None,
+            ..
+        } => {
             let car = coreui::CarUtilAssetStorage::from(&car_path, false)?;
+            warn_without_debug_impls();
             dbg!(car.theme_store.store.header);
             dbg!(car.theme_store.store.extended_metadata);
             dbg!(car.theme_store.store.renditionkeyfmt);
@@ -148,3 +861,55 @@ fn main() -> Result<()> {
         }
     }
 }
+
+// the debug command prints the headers field by field only with debug-impls
+fn warn_without_debug_impls() {
+    if !cfg!(feature = "debug-impls") {
+        eprintln!("warning: built without the debug-impls feature, headers only print their names");
+    }
+}
+
+// for commands that never print SHA1Digest
+fn without_digests() -> coreui::ParseOptions {
+    coreui::ParseOptions {
+        compute_digests: false,
+        ..Default::default()
+    }
+}
+
+// --require and --key-filter expressions, appearance names are looked up in the catalog
+fn key_filter(
+    store: &coreui::CommonAssetStorage,
+    expressions: &[String],
+) -> Result<filter::RenditionFilter> {
+    let appearancedb = store.appearancedb.clone().unwrap_or_default();
+    filter::RenditionFilter::parse(expressions, &appearancedb)
+}
+
+// the key value of a resolve or --best option, appearance names are looked
+// up in the catalog
+fn query_value(
+    store: &coreui::CommonAssetStorage,
+    attribute: &str,
+    value: Option<String>,
+) -> Result<Option<u16>> {
+    let appearancedb = store.appearancedb.clone().unwrap_or_default();
+    value
+        .map(|value| {
+            filter::AttributeRequirement::parse(&format!("{}={}", attribute, value), &appearancedb)
+                .map(|requirement| requirement.value)
+        })
+        .transpose()
+}
+
+fn print_facets(entries: &[assetutil::AssetUtilEntry], long: bool) {
+    if long {
+        for line in assetutil::FacetSummary::long_lines(entries) {
+            println!("{}", line);
+        }
+    } else {
+        for summary in assetutil::FacetSummary::from_entries(entries) {
+            println!("{}", summary);
+        }
+    }
+}
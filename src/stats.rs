@@ -0,0 +1,285 @@
+use anyhow::Context;
+use anyhow::Result;
+use binrw::BinRead;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::bom;
+use crate::coreui;
+use crate::coreui::rendition;
+use crate::input;
+
+// the BOMStore header: magic, version and the block and var table locations
+const BOM_HEADER_SIZE: u64 = 32;
+
+// trees whose keys are numbers rather than block ids
+const NUMBER_KEYED_TREES: [&str; 1] = ["BITMAPKEYS"];
+
+#[derive(Debug, PartialEq)]
+pub struct VarStats {
+    pub name: String,
+    // blocks only counted once, for the first var referencing them
+    pub blocks: usize,
+    pub bytes: u64,
+}
+
+// images Xcode stored in shared atlases rather than on their own
+#[derive(Debug, PartialEq)]
+pub struct PackedStats {
+    // internal link renditions, cropped out of an atlas
+    pub images: usize,
+    // PackedImage renditions and their SizeOnDisk
+    pub atlases: usize,
+    pub atlas_bytes: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StatsReport {
+    pub file_size: u64,
+    // the BOMStore header plus its block and var tables
+    pub bom_tables: u64,
+    // in var storage order
+    pub vars: Vec<VarStats>,
+    // bytes no table or block covers, alignment padding or garbage
+    pub unaccounted: u64,
+    // None when the renditions don't parse
+    pub packed: Option<PackedStats>,
+}
+
+impl StatsReport {
+    pub fn percentage(&self, bytes: u64) -> f64 {
+        if self.file_size == 0 {
+            return 0.0;
+        }
+        bytes as f64 * 100.0 / self.file_size as f64
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let line = |name: &str, bytes: u64| {
+            format!(
+                "{:<20} {:>12} bytes {:>6.2}%",
+                name,
+                bytes,
+                self.percentage(bytes)
+            )
+        };
+        let mut lines: Vec<String> = self
+            .vars
+            .iter()
+            .map(|var| line(&var.name, var.bytes))
+            .collect();
+        lines.push(line("(bom tables)", self.bom_tables));
+        lines.push(line("(unaccounted)", self.unaccounted));
+        lines.push(format!("{:<20} {:>12} bytes", "total", self.file_size));
+        if let Some(packed) = &self.packed {
+            lines.push(format!("{:<20} {:>12}", "packed images", packed.images));
+            lines.push(format!(
+                "{:<20} {:>12} bytes {:>6.2}% in {} atlases",
+                "atlas bytes",
+                packed.atlas_bytes,
+                self.percentage(packed.atlas_bytes),
+                packed.atlases
+            ));
+        }
+        lines
+    }
+}
+
+// a BOM var and the block it points at, address and length are None when
+// the block id is past the end of the block table
+#[derive(Debug, PartialEq)]
+pub struct VarBlock {
+    pub name: String,
+    pub block_id: u32,
+    pub address: Option<u32>,
+    pub length: Option<u32>,
+}
+
+pub fn var_block_lines(var_blocks: &[VarBlock]) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{:<20} {:>8} {:>10} {:>10}",
+        "name", "block", "address", "length"
+    )];
+    lines.extend(var_blocks.iter().map(|var_block| {
+        format!(
+            "{:<20} {:>8} {:>10} {:>10}",
+            var_block.name,
+            var_block.block_id,
+            var_block
+                .address
+                .map_or("-".to_string(), |address| format!("0x{:X}", address)),
+            var_block
+                .length
+                .map_or("-".to_string(), |length| length.to_string())
+        )
+    }));
+    lines
+}
+
+// the file is read through as blocks are looked up, not loaded whole
+fn read_storage(path: &Path) -> Result<(bom::Storage, BufReader<File>)> {
+    input::check_car_input(path)?;
+    let mut reader = BufReader::new(File::open(path)?);
+    let storage = bom::Storage::read(&mut reader)?;
+    Ok((storage, reader))
+}
+
+// every BOM var in var storage order, for catalogs we can't parse further
+pub fn var_blocks(path: impl AsRef<Path>) -> Result<Vec<VarBlock>> {
+    let (storage, _) = read_storage(path.as_ref())?;
+    Ok(storage
+        .var_storage
+        .vars
+        .iter()
+        .map(|var| {
+            let range = storage.block_storage.items.get(var.block_id as usize);
+            VarBlock {
+                name: var.name(),
+                block_id: var.block_id,
+                address: range.map(|range| range.address),
+                length: range.map(|range| range.length),
+            }
+        })
+        .collect())
+}
+
+// up to length bytes of a block starting offset bytes into it, the rest of
+// the block without a length
+pub fn block_bytes(
+    path: impl AsRef<Path>,
+    block_id: u32,
+    offset: u64,
+    length: Option<u64>,
+) -> Result<Vec<u8>> {
+    let (storage, mut reader) = read_storage(path.as_ref())?;
+    let file_size = reader.get_ref().metadata()?.len();
+    let range = storage.block_storage.item(block_id)?;
+    let block_length = range.length as u64;
+    if offset > block_length {
+        anyhow::bail!(
+            "offset {} is past the end of block {} ({} bytes)",
+            offset,
+            block_id,
+            block_length
+        );
+    }
+    let length = length.unwrap_or(block_length).min(block_length - offset);
+    // only the requested window is read, blocks can be large
+    let address = (range.address as u64)
+        .checked_add(offset)
+        .filter(|address| address.saturating_add(length) <= file_size)
+        .and_then(|address| u32::try_from(address).ok())
+        .with_context(|| {
+            format!(
+                "block {} at {} is past the end of the file",
+                block_id, range
+            )
+        })?;
+    let window = bom::BlockRange {
+        address,
+        length: length as u32,
+    };
+    window.read(&mut reader).map_err(|error| {
+        anyhow::anyhow!("unable to read block {} at {}: {}", block_id, range, error)
+    })
+}
+
+// 16 bytes per row: the offset of the row (counting from offset), the bytes in
+// hex and their printable ASCII, "." for the rest. The last row is padded so
+// the ASCII columns line up, no data gives no rows
+pub fn hexdump(data: &[u8], offset: u64) -> Vec<String> {
+    data.chunks(16)
+        .enumerate()
+        .map(|(row, bytes)| {
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            let (first, second) = hex.split_at(hex.len().min(8));
+            let ascii: String = bytes
+                .iter()
+                .map(|b| match b {
+                    0x20..=0x7e => *b as char,
+                    _ => '.',
+                })
+                .collect();
+            format!(
+                "{:08x}  {:<23}  {:<23}  |{}|",
+                offset + row as u64 * 16,
+                first.join(" "),
+                second.join(" "),
+                ascii
+            )
+        })
+        .collect()
+}
+
+// bytes taken by each BOM var: the var's own block and, for trees, every
+// path, key and value block reachable from it
+pub fn stats(path: impl AsRef<Path>) -> Result<StatsReport> {
+    let (storage, mut reader) = read_storage(path.as_ref())?;
+    let file_size = reader.get_ref().metadata()?.len();
+
+    let mut counted = HashSet::new();
+    let mut vars = vec![];
+    for var in &storage.var_storage.vars {
+        let name = var.name();
+        let mut blocks = vec![var.block_id];
+        // vars that aren't trees are a single block
+        if let Ok(tree) = storage.get_named_typed_block::<bom::Tree>(&name, &mut reader, ()) {
+            let keys_are_blocks = !NUMBER_KEYED_TREES.contains(&name.as_str());
+            blocks.extend(tree.blocks(&storage, &mut reader, keys_are_blocks)?);
+        }
+        let mut var_stats = VarStats {
+            name,
+            blocks: 0,
+            bytes: 0,
+        };
+        for block_id in blocks {
+            if !counted.insert(block_id) {
+                continue;
+            }
+            if let Some(range) = storage.block_storage.items.get(block_id as usize) {
+                var_stats.blocks += 1;
+                var_stats.bytes += range.length as u64;
+            }
+        }
+        vars.push(var_stats);
+    }
+
+    let bom_tables =
+        BOM_HEADER_SIZE + storage.block_storage_length as u64 + storage._unknown_len as u64;
+    let referenced = bom_tables + vars.iter().map(|var| var.bytes).sum::<u64>();
+    // the BOM numbers above are still useful for catalogs that stop here
+    let options = coreui::ParseOptions {
+        compute_digests: false,
+        ..Default::default()
+    };
+    let packed = coreui::CarUtilAssetStorage::from_with_options(path.as_ref(), &options)
+        .ok()
+        .map(|car| packed_stats(&car.theme_store.store));
+    Ok(StatsReport {
+        file_size,
+        bom_tables,
+        vars,
+        unaccounted: file_size.saturating_sub(referenced),
+        packed,
+    })
+}
+
+pub fn packed_stats(store: &coreui::CommonAssetStorage) -> PackedStats {
+    let mut packed = PackedStats {
+        images: 0,
+        atlases: 0,
+        atlas_bytes: 0,
+    };
+    for csi_header in store.imagedb.values() {
+        if csi_header.csimetadata.layout == rendition::LayoutType32::PackedImage {
+            packed.atlases += 1;
+            packed.atlas_bytes += csi_header.size_on_disk();
+        }
+        if let Some(rendition::Rendition::InternalLink { .. }) = csi_header.rendition_data {
+            packed.images += 1;
+        }
+    }
+    packed
+}
@@ -0,0 +1,322 @@
+use anyhow::Context;
+use anyhow::Result;
+use binrw::BinRead;
+use hex::ToHex;
+use sha2::Digest;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::assetutil::AssetUtilEntry;
+use crate::assetutil::ToAssetUtilHeader;
+use crate::bom;
+use crate::coreui;
+use crate::coreui::csi;
+use crate::coreui::rendition;
+use crate::coreui::rendition::AttributeType;
+use crate::coreui::rendition::AttributeType16;
+use crate::input;
+
+// renditions sharing one key, CoreUI picks whichever it finds first
+#[derive(Debug, PartialEq)]
+pub struct DuplicateKey {
+    pub key: rendition::Key,
+    // (rendition name, SHA256 of the stored csi header, the SHA1Digest
+    // assetutil shows) in tree order
+    pub renditions: Vec<(String, String)>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct VerifyReport {
+    pub duplicate_keys: Vec<DuplicateKey>,
+    // facet names whose identifier no rendition uses, sorted
+    pub dangling_facets: Vec<String>,
+    // (rendition name, appearance) for entries whose appearance isn't in the header
+    pub unlisted_appearances: Vec<(String, String)>,
+    // BOM trees CoreUI needs for the renditions present, e.g. APPEARANCEKEYS
+    pub missing_trees: Vec<String>,
+    // vars whose tree loops back on itself, nothing else is checked then
+    pub cyclic_trees: Vec<String>,
+    // (rendition name, bitmap count, reserved) of bitmap lists other than 1 and 0
+    pub unexpected_bitmap_lists: Vec<(String, u32, u32)>,
+    // blocks no var or tree points at, e.g. the ones an in-place update
+    // replaced. Harmless, so they don't fail the check
+    pub unreferenced_blocks: Vec<u32>,
+    // (name identifier, facet names) for identifiers more than one facet
+    // uses. Catalogs from Xcode have them, renditions are told apart by the
+    // rest of the facet token, so they don't fail the check either
+    pub name_identifier_collisions: Vec<(u16, Vec<String>)>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.duplicate_keys.is_empty()
+            && self.dangling_facets.is_empty()
+            && self.unlisted_appearances.is_empty()
+            && self.missing_trees.is_empty()
+            && self.cyclic_trees.is_empty()
+            && self.unexpected_bitmap_lists.is_empty()
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![];
+        for duplicate in &self.duplicate_keys {
+            lines.push(format!("duplicate key {:?}:", duplicate.key));
+            for (name, digest) in &duplicate.renditions {
+                lines.push(format!("  {} {}", name, digest));
+            }
+        }
+        for name in &self.dangling_facets {
+            lines.push(format!("dangling facet {:?}: no renditions", name));
+        }
+        for (name, appearance) in &self.unlisted_appearances {
+            lines.push(format!(
+                "{}: appearance {:?} is not in the header's Appearances",
+                name, appearance
+            ));
+        }
+        for name in &self.missing_trees {
+            lines.push(format!("missing {} tree", name));
+        }
+        for name in &self.cyclic_trees {
+            lines.push(format!("{} tree has a cycle", name));
+        }
+        for (name, bitmap_count, reserved) in &self.unexpected_bitmap_lists {
+            lines.push(format!(
+                "{}: bitmap list has bitmap count {} and reserved {}, expected {} and {} (please report this catalog)",
+                name,
+                bitmap_count,
+                reserved,
+                csi::BitmapList::EXPECTED_BITMAP_COUNT,
+                csi::BitmapList::EXPECTED_RESERVED
+            ));
+        }
+        for (identifier, names) in &self.name_identifier_collisions {
+            lines.push(format!(
+                "facets {:?} share name identifier {}",
+                names, identifier
+            ));
+        }
+        if !self.unreferenced_blocks.is_empty() {
+            lines.push(format!(
+                "{} unreferenced blocks (harmless, e.g. left behind by an in-place update)",
+                self.unreferenced_blocks.len()
+            ));
+        }
+        lines
+    }
+}
+
+// keys that appear more than once with different csi headers, given the
+// key and stored bytes of every RENDITIONS entry. imagedb is a map so this
+// has to look at the tree entries before they're collected. Copies of the
+// same bytes are harmless, whichever CoreUI finds it gets the same rendition
+pub fn duplicate_keys<'a>(
+    renditions: impl IntoIterator<Item = (rendition::Key, &'a [u8])>,
+) -> Result<Vec<DuplicateKey>> {
+    let mut seen: BTreeMap<rendition::Key, Vec<(String, String)>> = BTreeMap::new();
+    for (index, (key, value)) in renditions.into_iter().enumerate() {
+        // the name is all that's needed, Some(0) skips the tlvs and payload
+        let csi_header =
+            csi::Header::read_args(&mut Cursor::new(value), (Some(0),)).with_context(|| {
+                format!(
+                    "unable to read the csi header of RENDITIONS entry {}",
+                    index
+                )
+            })?;
+        seen.entry(key).or_default().push((
+            csi_header.csimetadata.name(),
+            Sha256::digest(value).encode_hex_upper(),
+        ));
+    }
+    Ok(seen
+        .into_iter()
+        .filter(|(_, renditions)| {
+            renditions
+                .iter()
+                .any(|(_, digest)| *digest != renditions[0].1)
+        })
+        .map(|(key, renditions)| DuplicateKey { key, renditions })
+        .collect())
+}
+
+pub fn dangling_facets(store: &coreui::CommonAssetStorage) -> Vec<String> {
+    let identifiers: BTreeSet<u16> = store
+        .imagedb
+        .keys()
+        .filter_map(|key| {
+            store
+                .key_values(key)
+                .into_iter()
+                .find(|(attribute, _)| *attribute == AttributeType::Identifier)
+                .map(|(_, identifier)| identifier)
+        })
+        .collect();
+    store
+        .facetkeysdb
+        .iter()
+        .filter(|(_, key_token)| {
+            key_token
+                .find_attribute(AttributeType16::Identifier)
+                .is_some_and(|identifier| !identifiers.contains(&identifier))
+        })
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+// the dump's header and entries must agree on appearance names
+pub fn unlisted_appearances(car: &coreui::CarUtilAssetStorage) -> Vec<(String, String)> {
+    let header = car.asset_util_header();
+    let appearances = header.appearances.unwrap_or_default();
+    AssetUtilEntry::entries_from_asset_storage(&car.theme_store.store)
+        .into_iter()
+        .filter_map(|entry| {
+            let appearance = entry.appearance?;
+            if appearances.contains_key(&appearance) {
+                None
+            } else {
+                Some((entry.rendition_name.unwrap_or_default(), appearance))
+            }
+        })
+        .collect()
+}
+
+// appearance lookup needs APPEARANCEKEYS once a rendition has a nonzero
+// appearance, and images need BITMAPKEYS
+pub fn missing_trees(store: &coreui::CommonAssetStorage) -> Vec<String> {
+    let mut missing_trees = vec![];
+    let has_appearances = store.imagedb.keys().any(|key| {
+        store
+            .key_values(key)
+            .into_iter()
+            .any(|(attribute, value)| attribute == AttributeType::Appearance && value > 0)
+    });
+    if has_appearances && store.appearancedb.is_none() {
+        missing_trees.push("APPEARANCEKEYS".to_string());
+    }
+    let has_images = store.imagedb.values().any(|csi_header| {
+        matches!(
+            csi_header.csimetadata.layout,
+            rendition::LayoutType32::Image
+        )
+    });
+    if has_images && store.bitmapkeydb.is_none() {
+        missing_trees.push("BITMAPKEYS".to_string());
+    }
+    missing_trees
+}
+
+// renditions whose bitmap list fields differ from every catalog seen so far,
+// what they mean is still guesswork
+pub fn unexpected_bitmap_lists(store: &coreui::CommonAssetStorage) -> Vec<(String, u32, u32)> {
+    store
+        .imagedb
+        .values()
+        .filter(|csi_header| !csi_header.csibitmaplist.is_expected())
+        .map(|csi_header| {
+            (
+                csi_header.csimetadata.name(),
+                csi_header.csibitmaplist.bitmap_count,
+                csi_header.csibitmaplist.reserved,
+            )
+        })
+        .collect()
+}
+
+// every var that holds a tree and loops back on itself when walked
+pub fn cyclic_trees(path: impl AsRef<Path>) -> Result<Vec<String>> {
+    let mut reader = Cursor::new(fs::read(path)?);
+    let storage = bom::Storage::read(&mut reader)?;
+    let names: Vec<String> = storage
+        .var_storage
+        .vars
+        .iter()
+        .map(|var| var.name())
+        .collect();
+    Ok(names
+        .into_iter()
+        .filter(|name| {
+            // vars that aren't trees fail to read and are skipped
+            storage
+                .get_named_tree_items(name, &mut reader)
+                .is_err_and(|err| {
+                    matches!(
+                        err.downcast_ref::<coreui::CarError>(),
+                        Some(coreui::CarError::CyclicTree { .. })
+                    )
+                })
+        })
+        .collect())
+}
+
+// blocks with data that no var points at, directly or through its tree
+pub fn unreferenced_blocks(path: impl AsRef<Path>) -> Result<Vec<u32>> {
+    let mut reader = Cursor::new(fs::read(path)?);
+    let storage = bom::Storage::read(&mut reader)?;
+    let mut referenced = BTreeSet::new();
+    for var in &storage.var_storage.vars {
+        referenced.insert(var.block_id);
+        // vars that aren't trees only reference their own block
+        let Ok(tree) = storage.get_named_typed_block::<bom::Tree>(&var.name(), &mut reader, ())
+        else {
+            continue;
+        };
+        // BITMAPKEYS is keyed by name identifiers rather than blocks
+        let keys_are_blocks = var.name() != "BITMAPKEYS";
+        referenced.extend(tree.blocks(&storage, &mut reader, keys_are_blocks)?);
+    }
+    Ok(storage
+        .block_storage
+        .items
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter(|(block_id, range)| range.length > 0 && !referenced.contains(&(*block_id as u32)))
+        .map(|(block_id, _)| block_id as u32)
+        .collect())
+}
+
+pub fn verify(path: impl AsRef<Path>) -> Result<VerifyReport> {
+    let path = path.as_ref();
+    // the same checks open_streaming makes, before reading any trees
+    input::check_car_input(path)?;
+    let data = fs::read(path)?;
+    coreui::CarUtilAssetStorage::check_bounds(&data)?;
+    let cyclic_trees = cyclic_trees(path)?;
+    if !cyclic_trees.is_empty() {
+        return Ok(VerifyReport {
+            cyclic_trees,
+            ..Default::default()
+        });
+    }
+    let bom_storage = bom::Storage::read(&mut Cursor::new(&data))?;
+    let duplicate_keys = duplicate_keys(coreui::CarUtilAssetStorage::rendition_blocks(
+        &bom_storage,
+        &data,
+    )?)?;
+    let car = coreui::CarUtilAssetStorage::from_with_options(
+        path,
+        &coreui::ParseOptions {
+            compute_digests: false,
+            ..Default::default()
+        },
+    )?;
+    Ok(VerifyReport {
+        duplicate_keys,
+        dangling_facets: dangling_facets(&car.theme_store.store),
+        unlisted_appearances: unlisted_appearances(&car),
+        missing_trees: missing_trees(&car.theme_store.store),
+        cyclic_trees,
+        unexpected_bitmap_lists: unexpected_bitmap_lists(&car.theme_store.store),
+        unreferenced_blocks: unreferenced_blocks(path)?,
+        name_identifier_collisions: car
+            .theme_store
+            .store
+            .name_identifier_collisions()
+            .into_iter()
+            .collect(),
+    })
+}
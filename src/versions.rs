@@ -0,0 +1,30 @@
+// version numbers written into our output, the defaults match the releases of
+// assetutil and CoreUI we're compatible with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToolVersions {
+    // DumpToolVersion in assetutil json
+    pub dump_tool_version: f64,
+    // CoreUIVersion of catalogs we write
+    pub core_ui_version: u32,
+}
+
+impl Default for ToolVersions {
+    fn default() -> Self {
+        ToolVersions {
+            dump_tool_version: 804.3,
+            core_ui_version: 802,
+        }
+    }
+}
+
+impl ToolVersions {
+    pub fn dump_tool_version(mut self, dump_tool_version: f64) -> Self {
+        self.dump_tool_version = dump_tool_version;
+        self
+    }
+
+    pub fn core_ui_version(mut self, core_ui_version: u32) -> Self {
+        self.core_ui_version = core_ui_version;
+        self
+    }
+}
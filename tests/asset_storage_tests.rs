@@ -0,0 +1,865 @@
+mod common;
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::rc::Rc;
+
+use carutil_lib::coreui;
+use carutil_lib::coreui::rendition::AttributeType16;
+
+static CAR_PATH: &str = "./tests/Assets.car";
+
+#[test]
+fn facet_names_sorted() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let store = &asset_storage.theme_store.store;
+    assert_eq!(
+        store.facet_names(),
+        vec!["MyColor", "MyJPG", "MyPDF", "MyPNG", "MyText"]
+    );
+}
+
+#[test]
+fn facet_lookup() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let store = &asset_storage.theme_store.store;
+    for name in ["MyColor", "MyPDF", "MyPNG"] {
+        let key_token = store.facet(name).expect("facet not found");
+        let identifier = key_token
+            .find_attribute(AttributeType16::Identifier)
+            .expect("facet has no identifier");
+        assert_eq!(
+            store.name_identifier_to_facet_name().get(&identifier),
+            Some(&name.to_string())
+        );
+    }
+    assert!(store.facet("NotThere").is_none());
+}
+
+#[test]
+fn timestamp_prefers_stored_value() {
+    let mut store = common::storage(vec![], vec![], None);
+    store.file_timestamp = Some(1700000000);
+    assert_eq!(store.timestamp(), 1700000000);
+
+    store.header.storage_timestamp = 1600000000;
+    assert_eq!(store.timestamp(), 1600000000);
+    assert!(store.timestamp_warnings(1700000000).is_empty());
+}
+
+#[test]
+fn timestamp_warnings() {
+    let mut store = common::storage(vec![], vec![], None);
+    let now = 1700000000;
+    assert!(store.timestamp_warnings(now).is_empty());
+
+    store.header.storage_timestamp = 1800000000;
+    assert_eq!(store.timestamp_warnings(now).len(), 1);
+
+    store.header.storage_timestamp = 1000000000;
+    assert_eq!(store.timestamp_warnings(now).len(), 1);
+
+    // past 2038 and in the future
+    store.header.storage_timestamp = 3000000000;
+    assert_eq!(store.timestamp(), 3000000000);
+    assert_eq!(store.timestamp_warnings(now).len(), 2);
+}
+
+#[test]
+fn truncated_files() {
+    let data = std::fs::read(CAR_PATH).expect("Unable to read Assets.car");
+    let directory = common::temp_dir("truncated");
+    let path = directory.join("truncated.car");
+    for length in [0, 7, 31, 32, 1024, data.len() / 2, data.len() - 1] {
+        std::fs::write(&path, &data[..length]).unwrap();
+        let err = coreui::CarUtilAssetStorage::from(path.to_str().unwrap(), false)
+            .err()
+            .expect("truncated file parsed");
+        match err.downcast_ref::<coreui::CarError>() {
+            Some(coreui::CarError::Truncated { actual, .. }) => {
+                assert_eq!(*actual, length as u64)
+            }
+            _ => panic!("expected a truncation error at {}, got {}", length, err),
+        }
+        assert!(err.to_string().contains("incomplete"));
+    }
+
+    assert!(coreui::CarUtilAssetStorage::check_bounds(&data).is_ok());
+}
+
+#[test]
+fn key_semantics() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let store = &asset_storage.theme_store.store;
+    assert_eq!(store.header.key_semantics, 2);
+    assert!(store.key_semantics_warning().is_none());
+
+    let mut store = common::storage(vec![], vec![], None);
+    store.header.key_semantics = 1;
+    assert!(store.key_semantics_warning().is_none());
+    store.header.key_semantics = 3;
+    assert!(store.key_semantics_warning().is_some());
+}
+
+#[test]
+fn supports_appearances() {
+    let image = || common::image("image.png", 1, 1);
+    let store = common::storage(vec![], vec![(common::key(0, 1, 0, 1), image())], None);
+    assert!(!store.supports_appearances());
+
+    let store = common::storage(vec![], vec![(common::key(1, 1, 0, 1), image())], None);
+    assert!(store.supports_appearances());
+
+    let appearances = BTreeMap::from([("UIAppearanceAny".to_string(), 0)]);
+    let store = common::storage(vec![], vec![], Some(appearances));
+    assert!(store.supports_appearances());
+}
+
+#[test]
+fn is_thinned() {
+    let mut store = common::storage(vec![], vec![], None);
+    assert!(!store.is_thinned());
+    store.extended_metadata =
+        coreui::CarExtendedMetadata::new("idiom=phone scale=3", "16.0", "ios", "carutil tests")
+            .unwrap();
+    assert!(store.is_thinned());
+}
+
+#[test]
+fn document_colorspace() {
+    use carutil_lib::coregraphics::ColorSpace;
+
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    assert_eq!(
+        asset_storage.theme_store.store.document_colorspace(),
+        ColorSpace::SRGB
+    );
+
+    // lzfse compressed pixels, only theme payloads get a Colorspace
+    let image = common::csi_header(
+        coreui::rendition::LayoutType32::Image,
+        "image.png",
+        1,
+        1,
+        coreui::csi::PixelFormat::ARGB,
+        vec![],
+        Some(coreui::rendition::Rendition::Theme {
+            version: 1,
+            compression_type: coreui::rendition::CompressionType::LZFSE,
+            _raw_data_length: 0,
+            raw_data: carutil_lib::RawData(vec![]),
+        }),
+    );
+    let mut store = common::storage(vec![], vec![(common::key(0, 1, 0, 1), image)], None);
+    assert_eq!(store.document_colorspace(), ColorSpace::SRGB);
+    store.header.color_space_id = 3;
+    assert_eq!(store.document_colorspace(), ColorSpace::DisplayP3);
+    store.header.color_space_id = 99;
+    assert_eq!(store.document_colorspace(), ColorSpace::SRGB);
+
+    // entries keep the color space of their own color model, what assetutil
+    // prints for other document color spaces hasn't been checked
+    store.header.color_space_id = 3;
+    let entries = carutil_lib::assetutil::AssetUtilEntry::entries_from_asset_storage(&store);
+    assert_eq!(entries[0].colorspace, Some(ColorSpace::SRGB));
+}
+
+// counts the bytes read through it
+struct CountingReader<R> {
+    inner: R,
+    bytes_read: Rc<Cell<usize>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.bytes_read.set(self.bytes_read.get() + count);
+        Ok(count)
+    }
+}
+
+impl<R: Seek> Seek for CountingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[test]
+fn streaming_renditions_are_lazy() {
+    let data = std::fs::read(CAR_PATH).expect("Unable to read Assets.car");
+    let bytes_read = Rc::new(Cell::new(0));
+    let reader = CountingReader {
+        inner: Cursor::new(data),
+        bytes_read: bytes_read.clone(),
+    };
+    let mut streaming =
+        coreui::StreamingAssetStorage::from_reader(reader, None).expect("Unable to parse");
+    assert_eq!(streaming.header.rendition_count, 7);
+    assert_eq!(streaming.renditions.size_hint(), (7, Some(7)));
+
+    let after_header = bytes_read.get();
+    let first: Vec<_> = streaming
+        .renditions
+        .by_ref()
+        .take(5)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(first.len(), 5);
+    let after_five = bytes_read.get();
+    assert!(after_five > after_header);
+
+    let rest: Vec<_> = streaming.renditions.collect::<Result<_, _>>().unwrap();
+    assert_eq!(rest.len(), 2);
+    assert!(bytes_read.get() > after_five);
+
+    // same renditions as the eager path
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let keys: Vec<_> = first.iter().chain(&rest).map(|(key, _)| *key).collect();
+    let mut sorted_keys = keys.clone();
+    sorted_keys.sort();
+    assert_eq!(
+        sorted_keys,
+        asset_storage
+            .theme_store
+            .store
+            .imagedb
+            .keys()
+            .copied()
+            .collect::<Vec<_>>()
+    );
+}
+
+// parses a copy of the fixture with the block of var_name overwritten at its start
+fn parse_corrupted(var_name: &str, name: &str) -> (anyhow::Error, u32) {
+    use binrw::BinRead;
+    use carutil_lib::bom;
+
+    let mut data = std::fs::read(CAR_PATH).expect("Unable to read Assets.car");
+    let storage = bom::Storage::read(&mut Cursor::new(&data)).unwrap();
+    let block = storage.get_named_block(var_name).unwrap();
+    let address = block.address as usize;
+    data[address..address + 4].copy_from_slice(b"XXXX");
+
+    let directory = common::temp_dir(name);
+    let path = directory.join("corrupted.car");
+    std::fs::write(&path, &data).unwrap();
+    let err = coreui::CarUtilAssetStorage::from(path.to_str().unwrap(), false)
+        .err()
+        .expect("corrupted file parsed");
+    (err, block.address)
+}
+
+#[test]
+fn parse_errors_name_the_var() {
+    let (err, address) = parse_corrupted("FACETKEYS", "facetkeys");
+    let message = format!("{:#}", err);
+    assert!(message.contains("FACETKEYS"), "{}", message);
+    assert!(message.contains(&format!("0x{:X}", address)), "{}", message);
+
+    let (err, _) = parse_corrupted("KEYFORMAT", "keyformat");
+    assert!(format!("{:#}", err).contains("KEYFORMAT"));
+}
+
+#[test]
+fn parse_errors_name_the_rendition_entry() {
+    use binrw::BinRead;
+    use carutil_lib::bom;
+
+    let mut data = std::fs::read(CAR_PATH).expect("Unable to read Assets.car");
+    let mut reader = Cursor::new(&data);
+    let storage = bom::Storage::read(&mut reader).unwrap();
+    let tree = storage
+        .get_named_typed_block::<bom::Tree>("RENDITIONS", &mut reader, ())
+        .unwrap();
+    let items = tree.items(&storage, &mut reader).unwrap();
+    // break the csi header magic of the third rendition
+    let (_, value) = items[2];
+    let address = storage.block_storage.items[value as usize].address as usize;
+    data[address..address + 4].copy_from_slice(b"XXXX");
+
+    let directory = common::temp_dir("csi");
+    let path = directory.join("csi.car");
+    std::fs::write(&path, &data).unwrap();
+    let err = coreui::CarUtilAssetStorage::from(path.to_str().unwrap(), false)
+        .err()
+        .expect("corrupted file parsed");
+    let message = format!("{:#}", err);
+    assert!(message.starts_with("RENDITIONS entry 2"), "{}", message);
+    assert!(message.contains(&format!("0x{:X}", address)), "{}", message);
+}
+
+#[test]
+fn from_bytes_matches_from() {
+    let data = std::fs::read(CAR_PATH).expect("Unable to read Assets.car");
+    let from_bytes = coreui::CarUtilAssetStorage::from_bytes(data).unwrap();
+    let from_path = coreui::CarUtilAssetStorage::from(CAR_PATH, false).unwrap();
+    assert_eq!(
+        from_bytes.theme_store.store.imagedb.len(),
+        from_path.theme_store.store.imagedb.len()
+    );
+    assert_eq!(
+        from_bytes.theme_store.store.rendition_sha_digests,
+        from_path.theme_store.store.rendition_sha_digests
+    );
+    assert_eq!(from_bytes.theme_store.store.file_timestamp, None);
+
+    assert!(coreui::CarUtilAssetStorage::from_bytes(vec![]).is_err());
+}
+
+#[test]
+fn tree_items_pointing_at_missing_blocks() {
+    use binrw::BinRead;
+    use carutil_lib::bom;
+
+    let mut data = std::fs::read(CAR_PATH).expect("Unable to read Assets.car");
+    let mut reader = Cursor::new(&data);
+    let storage = bom::Storage::read(&mut reader).unwrap();
+    let tree = storage
+        .get_named_typed_block::<bom::Tree>("FACETKEYS", &mut reader, ())
+        .unwrap();
+    // index0 of the first entry of the leaf, after is_leaf, count, forward
+    // and backward
+    let leaf = storage.block_storage.items[tree.path_block_id as usize].address as usize;
+    data[leaf + 12..leaf + 16].copy_from_slice(&0x00FF_FFFFu32.to_be_bytes());
+
+    let err = coreui::CarUtilAssetStorage::from_bytes(data)
+        .err()
+        .expect("corrupted file parsed");
+    let message = format!("{:#}", err);
+    assert!(
+        message.contains("block 16777215 does not exist"),
+        "{}",
+        message
+    );
+}
+
+// the fixture with the forward pointer of the RENDITIONS leaf pointing at
+// the path block of the tree stored under forward_to
+fn with_renditions_forward(forward_to: &str) -> Vec<u8> {
+    use binrw::BinRead;
+    use carutil_lib::bom;
+
+    let mut data = std::fs::read(CAR_PATH).expect("Unable to read Assets.car");
+    let mut reader = Cursor::new(&data);
+    let storage = bom::Storage::read(&mut reader).unwrap();
+    let path_block_id = |name: &str| {
+        storage
+            .get_named_typed_block::<bom::Tree>(name, &mut Cursor::new(&data), ())
+            .unwrap()
+            .path_block_id
+    };
+    let leaf = storage.block_storage.items[path_block_id("RENDITIONS") as usize].address as usize;
+    let forward = path_block_id(forward_to);
+    // is_leaf and count come before forward
+    data[leaf + 4..leaf + 8].copy_from_slice(&forward.to_be_bytes());
+    data
+}
+
+#[test]
+fn tree_items_follow_forward_pointers() {
+    use binrw::BinRead;
+    use carutil_lib::bom;
+
+    let data = with_renditions_forward("FACETKEYS");
+    let mut reader = Cursor::new(&data);
+    let storage = bom::Storage::read(&mut reader).unwrap();
+    let renditions = storage
+        .get_named_tree_items("RENDITIONS", &mut reader)
+        .unwrap();
+    let facets = storage
+        .get_named_tree_items("FACETKEYS", &mut reader)
+        .unwrap();
+    assert_eq!(renditions.len(), 7 + facets.len());
+    assert_eq!(renditions[7..], facets[..]);
+}
+
+#[test]
+fn cyclic_tree() {
+    use carutil_lib::verify;
+
+    let data = with_renditions_forward("RENDITIONS");
+    let directory = common::temp_dir("cyclic");
+    let path = directory.join("cyclic.car");
+    std::fs::write(&path, &data).unwrap();
+    let err = coreui::CarUtilAssetStorage::from(path.to_str().unwrap(), false)
+        .err()
+        .expect("cyclic file parsed");
+    let report = verify::verify(path.to_str().unwrap());
+
+    assert_eq!(
+        err.downcast_ref::<coreui::CarError>(),
+        Some(&coreui::CarError::CyclicTree {
+            var: "RENDITIONS".to_string()
+        })
+    );
+    let report = report.unwrap();
+    assert_eq!(report.cyclic_trees, vec!["RENDITIONS".to_string()]);
+    assert!(!report.is_ok());
+}
+
+#[test]
+fn tree_with_stale_path_count() {
+    use binrw::BinRead;
+    use carutil_lib::bom;
+
+    // two chained leaves under a tree that claims it has no items
+    let mut data = with_renditions_forward("FACETKEYS");
+    let storage = bom::Storage::read(&mut Cursor::new(&data)).unwrap();
+    let tree = storage.get_named_block("RENDITIONS").unwrap().address as usize;
+    // magic, version, path_block_id and block_size come before path_count
+    data[tree + 16..tree + 20].copy_from_slice(&0u32.to_be_bytes());
+    let mut reader = Cursor::new(&data);
+    let renditions = storage
+        .get_named_tree_items("RENDITIONS", &mut reader)
+        .unwrap();
+    let facets = storage
+        .get_named_tree_items("FACETKEYS", &mut reader)
+        .unwrap();
+    assert_eq!(renditions.len(), 7 + facets.len());
+}
+
+#[test]
+fn rendition_digests_optional() {
+    use carutil_lib::coreui::ParseOptions;
+    use hex::ToHex;
+
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let store = &asset_storage.theme_store.store;
+    assert_eq!(store.rendition_sha_digests.len(), store.imagedb.len());
+    let (key, _) = store
+        .imagedb
+        .iter()
+        .find(|(_, csi_header)| csi_header.csimetadata.name() == "Timac.png")
+        .unwrap();
+    assert_eq!(
+        store.rendition_sha_digests[key].encode_hex_upper::<String>(),
+        "17CBE710BFF9C2A2741AF95BF51E90497A878E91D28DB427D5E3A69A75BE792B"
+    );
+
+    let options = ParseOptions {
+        compute_digests: false,
+        ..Default::default()
+    };
+    let asset_storage = coreui::CarUtilAssetStorage::from_with_options(CAR_PATH, &options)
+        .expect("Unable to parse Assets.car");
+    let store = &asset_storage.theme_store.store;
+    assert!(store.rendition_sha_digests.is_empty());
+    assert_eq!(store.imagedb.len(), 7);
+}
+
+#[test]
+fn missing_renditions_var() {
+    use carutil_lib::assetutil::ToAssetUtilHeader;
+
+    // Assets.car with the RENDITIONS var removed from the var storage
+    let asset_storage = coreui::CarUtilAssetStorage::from("./tests/AssetsNoRenditions.car", true)
+        .expect("Unable to parse AssetsNoRenditions.car");
+    let store = &asset_storage.theme_store.store;
+    assert!(store.imagedb.is_empty());
+    assert!(store.rendition_sha_digests.is_empty());
+    assert_eq!(store.facetkeysdb.len(), 5);
+    assert_eq!(
+        carutil_lib::assetutil::AssetUtilEntry::entries_from_asset_storage(store).len(),
+        0
+    );
+    assert_eq!(asset_storage.asset_util_header().core_ui_version, 498);
+}
+
+#[test]
+fn read_without_mmap() {
+    use carutil_lib::assetutil::ToAssetUtilHeader;
+
+    let dump = |use_mmap| {
+        let options = coreui::ParseOptions {
+            use_mmap,
+            ..Default::default()
+        };
+        let asset_storage = coreui::CarUtilAssetStorage::from_with_options(CAR_PATH, &options)
+            .expect("Unable to parse Assets.car");
+        let store = &asset_storage.theme_store.store;
+        let mut dump = vec![serde_json::to_value(asset_storage.asset_util_header()).unwrap()];
+        dump.extend(
+            carutil_lib::assetutil::AssetUtilEntry::entries_from_asset_storage(store)
+                .iter()
+                .map(|entry| serde_json::to_value(entry).unwrap()),
+        );
+        (store.rendition_sha_digests.clone(), dump)
+    };
+    let (mapped_digests, mapped) = dump(true);
+    let (read_digests, read) = dump(false);
+    assert_eq!(mapped_digests.len(), 7);
+    assert_eq!(mapped_digests, read_digests);
+    assert_eq!(mapped, read);
+
+    let data = coreui::CarData::open(CAR_PATH, false).unwrap();
+    assert!(matches!(data, coreui::CarData::Read(_)));
+    assert_eq!(&*data, std::fs::read(CAR_PATH).unwrap().as_slice());
+    assert!(coreui::CarData::open("./tests/does-not-exist.car", true).is_err());
+}
+
+#[test]
+fn emoji_facet_names() {
+    use carutil_lib::assetutil;
+    use carutil_lib::extract;
+
+    // Assets.car with MyPNG renamed to 🎨 and Timac.png to 🎨.png
+    let asset_storage = coreui::CarUtilAssetStorage::from("./tests/AssetsEmoji.car", false)
+        .expect("Unable to parse AssetsEmoji.car");
+    let store = &asset_storage.theme_store.store;
+    assert!(store.facet_name_warnings().is_empty());
+    assert!(store.facet_names().contains(&"🎨"));
+    let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(store);
+    assert!(entries
+        .iter()
+        .any(|entry| entry.rendition_name.as_deref() == Some("🎨.png")));
+
+    let output_path = common::temp_dir("emoji");
+    let summary = extract::extract_all(
+        &asset_storage,
+        output_path.to_str().unwrap(),
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions::default(),
+    )
+    .unwrap();
+    let exists = output_path.join("🎨.png").exists();
+    assert!(summary.written.contains(&"🎨.png".to_string()));
+    assert!(exists);
+}
+
+#[test]
+fn non_utf8_facet_names() {
+    let raw_name = b"Icon\xFF\xFE%".to_vec();
+    let mut store = common::storage(
+        vec![common::facet("placeholder", 1)],
+        vec![(common::key(0, 1, 0, 1), common::image("icon.png", 1, 1))],
+        None,
+    );
+    let token = store.facetkeysdb.remove("placeholder").unwrap();
+    store.facetkeysdb.insert("Icon%FF%FE%25".to_string(), token);
+    store
+        .raw_facet_names
+        .insert("Icon%FF%FE%25".to_string(), raw_name.clone());
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let directory = common::temp_dir("non-utf8");
+    let path = directory.join("non-utf8.car");
+    car.write_data(path.to_str().unwrap()).unwrap();
+    let written = std::fs::read(&path).unwrap();
+    let read = coreui::CarUtilAssetStorage::from(path.to_str().unwrap(), false);
+
+    // the raw bytes are written back, not the fallback
+    assert!(written
+        .windows(raw_name.len())
+        .any(|window| window == raw_name));
+    let read = read.unwrap();
+    let store = &read.theme_store.store;
+    assert_eq!(store.facet_names(), vec!["Icon%FF%FE%25"]);
+    assert_eq!(
+        store.raw_facet_names,
+        BTreeMap::from([("Icon%FF%FE%25".to_string(), raw_name)])
+    );
+    assert_eq!(
+        store.facet_name_warnings(),
+        vec!["facet name 49636f6efffe25 is not UTF-8, using \"Icon%FF%FE%25\""]
+    );
+}
+
+#[test]
+fn non_utf8_facet_name_collisions() {
+    // a UTF-8 name that spells the fallback of the other one's bytes
+    let raw_name = b"a\xFF".to_vec();
+    let mut store = common::storage(
+        vec![common::facet("a%FF", 1), common::facet("placeholder", 2)],
+        vec![
+            (common::key(0, 1, 0, 1), common::image("a.png", 1, 1)),
+            (common::key(0, 1, 0, 2), common::image("b.png", 1, 1)),
+        ],
+        None,
+    );
+    let token = store.facetkeysdb.remove("placeholder").unwrap();
+    store.facetkeysdb.insert("raw".to_string(), token);
+    store
+        .raw_facet_names
+        .insert("raw".to_string(), raw_name.clone());
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let directory = common::temp_dir("non-utf8-collision");
+    let path = directory.join("non-utf8-collision.car");
+    car.write_data(path.to_str().unwrap()).unwrap();
+    let read = coreui::CarUtilAssetStorage::from(path.to_str().unwrap(), false);
+
+    let read = read.unwrap();
+    let store = &read.theme_store.store;
+    assert_eq!(store.facet_names(), vec!["a%FF", "a%FF-2"]);
+    assert_eq!(
+        store.raw_facet_names,
+        BTreeMap::from([("a%FF-2".to_string(), raw_name)])
+    );
+}
+
+#[test]
+fn cursor_hotspot_round_trips() {
+    use binrw::BinRead;
+    use binrw::BinWrite;
+
+    let attributes = vec![coreui::rendition::Attribute {
+        name: AttributeType16::Identifier,
+        value: 1,
+    }];
+    let key_token = coreui::rendition::KeyToken::with_cursor_hotspot(attributes, (7, 12));
+    let mut bytes = Cursor::new(vec![]);
+    key_token.write(&mut bytes).unwrap();
+    assert_eq!(&bytes.get_ref()[..4], &[7, 0, 12, 0]);
+    bytes.set_position(0);
+    let read = coreui::rendition::KeyToken::read(&mut bytes).unwrap();
+    assert_eq!(read.cursor_hotspot, (7, 12));
+
+    let store = common::storage(
+        vec![("Arrow".to_string(), read)],
+        vec![(common::key(0, 1, 0, 1), common::image("arrow.png", 1, 1))],
+        None,
+    );
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let directory = common::temp_dir("hotspot");
+    let path = directory.join("hotspot.car");
+    car.write_data(path.to_str().unwrap()).unwrap();
+    let read = coreui::CarUtilAssetStorage::from(path.to_str().unwrap(), false);
+
+    let read = read.unwrap();
+    let store = &read.theme_store.store;
+    assert_eq!(store.facetkeysdb["Arrow"].cursor_hotspot, (7, 12));
+    let entries = carutil_lib::assetutil::AssetUtilEntry::entries_from_asset_storage(store);
+    assert_eq!(
+        serde_json::to_value(&entries[0]).unwrap()["CursorHotspot"],
+        serde_json::json!([7, 12])
+    );
+}
+
+#[test]
+fn fixed_width_strings() {
+    let error = coreui::CarExtendedMetadata::new("", "16.0", "ios", &"a".repeat(257)).unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("authoring tool is 257 bytes, at most 256 fit"));
+    // é is two bytes, 127 of them don't fit in 128 bytes
+    let error = coreui::CarHeader::new(802, 17, 0, 0, &"é".repeat(127), "", [0; 16], 0, 5, 0, 2)
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("main version string is 254 bytes, at most 128 fit"));
+}
+
+#[test]
+fn update_in_place() {
+    let directory = common::temp_dir("in-place");
+    let path = directory.join("in-place.car");
+    let path = path.to_str().unwrap();
+    std::fs::copy(CAR_PATH, path).unwrap();
+    let original = std::fs::read(path).unwrap();
+    let before = coreui::CarUtilAssetStorage::from(path, false).unwrap();
+    let store = &before.theme_store.store;
+
+    let (key, csi_header) = store
+        .imagedb
+        .iter()
+        .find(|(_, csi_header)| csi_header.csimetadata.name() == "Timac@3x.png")
+        .expect("No rendition found");
+    let png = carutil_lib::coreui::csi::encode_png(2, 2, &[0x80; 16]).unwrap();
+    let mut replaced = csi_header.clone();
+    replaced.set_png(&png).unwrap();
+    // a rendition for a key the catalog doesn't have yet
+    let mut added_key = *key;
+    added_key.raw[17] = 0xBEEF;
+    coreui::CarUtilAssetStorage::update_in_place(
+        path,
+        &[(*key, replaced), (added_key, csi_header.clone())],
+    )
+    .unwrap();
+
+    let updated = std::fs::read(path).unwrap();
+    let after = coreui::CarUtilAssetStorage::from(path, false).unwrap();
+    let report = carutil_lib::verify::verify(path).unwrap();
+
+    // appended, only the BOM header pointers are rewritten
+    assert!(updated.len() > original.len());
+    assert_eq!(updated[..12], original[..12]);
+    assert_eq!(updated[32..original.len()], original[32..]);
+
+    let edited = &after.theme_store.store;
+    assert_eq!(edited.imagedb.len(), store.imagedb.len() + 1);
+    assert_eq!(edited.header.rendition_count, edited.imagedb.len() as u32);
+    let extracted_file = edited.imagedb[key].extracted_file().unwrap().unwrap();
+    assert_eq!(extracted_file.data, png);
+    assert_eq!(
+        (edited.imagedb[key].width, edited.imagedb[key].height),
+        (2, 2)
+    );
+    assert!(edited.imagedb.contains_key(&added_key));
+    for (rendition_key, digest) in &store.rendition_sha_digests {
+        if rendition_key == key {
+            assert_ne!(&edited.rendition_sha_digests[rendition_key], digest);
+        } else {
+            assert_eq!(&edited.rendition_sha_digests[rendition_key], digest);
+        }
+    }
+
+    // the old tree and csi header are garbage now, which doesn't fail verify
+    assert!(report.is_ok(), "{:?}", report.lines());
+    assert!(!report.unreferenced_blocks.is_empty());
+}
+
+#[test]
+fn update_in_place_splits_leaves() {
+    use binrw::BinRead;
+    use carutil_lib::bom;
+
+    let mut spec = common::fixtures::CatalogSpec::new();
+    for index in 0..100 {
+        spec = spec.images(&format!("Icon{}", index), &[1, 2, 3], &[0], &[0]);
+    }
+    let mut data = std::fs::read(spec.path()).unwrap();
+    // a block_size that holds 6 items per leaf, so the new tree is 4 levels
+    let tree_address = {
+        let storage = bom::Storage::read(&mut Cursor::new(&data)).unwrap();
+        storage.get_named_block("RENDITIONS").unwrap().address as usize
+    };
+    data[tree_address + 12..tree_address + 16].copy_from_slice(&64u32.to_be_bytes());
+    let directory = common::temp_dir("in-place-split");
+    let path = directory.join("in-place-split.car");
+    std::fs::write(&path, &data).unwrap();
+    let before = coreui::CarUtilAssetStorage::from(&path, false).unwrap();
+    let store = &before.theme_store.store;
+    assert_eq!(store.imagedb.len(), 300);
+
+    let (key, csi_header) = store.imagedb.iter().next().unwrap();
+    let mut added_key = *key;
+    added_key.raw[17] = 0xBEEF;
+    coreui::CarUtilAssetStorage::update_in_place(&path, &[(added_key, csi_header.clone())])
+        .unwrap();
+
+    let updated = std::fs::read(&path).unwrap();
+    let after = coreui::CarUtilAssetStorage::from(&path, false).unwrap();
+    let report = carutil_lib::verify::verify(&path).unwrap();
+    assert!(report.is_ok(), "{:?}", report.lines());
+
+    let edited = &after.theme_store.store;
+    assert_eq!(edited.imagedb.len(), 301);
+    assert_eq!(edited.header.rendition_count, 301);
+    for (rendition_key, digest) in &store.rendition_sha_digests {
+        assert_eq!(&edited.rendition_sha_digests[rendition_key], digest);
+    }
+
+    // every leaf fits the block_size and links to its neighbours
+    let mut reader = Cursor::new(&updated);
+    let storage = bom::Storage::read(&mut reader).unwrap();
+    let tree = storage
+        .get_named_typed_block::<bom::Tree>("RENDITIONS", &mut reader, ())
+        .unwrap();
+    assert_eq!(tree.block_size, 64);
+    assert_eq!(tree.path_count, 301);
+    let read_paths = |block_id: u32, reader: &mut Cursor<&Vec<u8>>| {
+        let range = storage.block_storage.items[block_id as usize];
+        assert!(range.length <= 64);
+        reader.seek(SeekFrom::Start(range.address as u64)).unwrap();
+        bom::Paths::read(reader).unwrap()
+    };
+    let mut levels = 1;
+    let mut block_id = tree.path_block_id;
+    let mut paths = read_paths(block_id, &mut reader);
+    while paths.is_leaf == 0 {
+        levels += 1;
+        block_id = paths.indices[0].index0;
+        paths = read_paths(block_id, &mut reader);
+    }
+    assert_eq!(levels, 4);
+    let mut leaves = vec![];
+    let mut previous = 0;
+    loop {
+        assert!(paths.count <= 6);
+        assert_eq!(paths.backward, previous);
+        leaves.push(paths.count);
+        if paths.forward == 0 {
+            break;
+        }
+        previous = block_id;
+        block_id = paths.forward;
+        paths = read_paths(block_id, &mut reader);
+    }
+    assert_eq!(leaves.len(), 51);
+    assert_eq!(
+        leaves.iter().map(|count| *count as usize).sum::<usize>(),
+        301
+    );
+}
+
+#[test]
+fn unknown_layout_type() {
+    use carutil_lib::coreui::rendition;
+
+    // a layout CoreUI added after this was written
+    let future = common::csi_header(
+        rendition::LayoutType32::from(0x3F7),
+        "Future",
+        0,
+        0,
+        coreui::csi::PixelFormat::None,
+        vec![],
+        Some(common::raw_data(b"later")),
+    );
+    let store = common::storage(
+        vec![common::facet("Future", 1), common::facet("Arrow", 2)],
+        vec![
+            (common::key(0, 1, 0, 1), future),
+            (common::key(0, 1, 0, 2), common::image("arrow.png", 1, 1)),
+        ],
+        None,
+    );
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let directory = common::temp_dir("layout");
+    let path = directory.join("layout.car");
+    car.write_data(path.to_str().unwrap()).unwrap();
+    let read = coreui::CarUtilAssetStorage::from(path.to_str().unwrap(), false);
+
+    // the rest of the catalog still parses
+    let read = read.unwrap();
+    let store = &read.theme_store.store;
+    assert_eq!(store.imagedb.len(), 2);
+    let layouts: Vec<rendition::LayoutType32> = store
+        .imagedb
+        .values()
+        .map(|csi_header| csi_header.csimetadata.layout)
+        .collect();
+    assert!(layouts.contains(&rendition::LayoutType32::Unknown(0x3F7)));
+    assert!(layouts.contains(&rendition::LayoutType32::Image));
+    assert_eq!(u32::from(rendition::LayoutType32::Unknown(0x3F7)), 0x3F7);
+    assert_eq!(
+        rendition::LayoutType::from(0x3F1),
+        rendition::LayoutType::Color
+    );
+
+    let entries = carutil_lib::assetutil::AssetUtilEntry::entries_from_asset_storage(store);
+    let asset_types: Vec<Option<&str>> = entries
+        .iter()
+        .map(|entry| entry.asset_type.as_deref())
+        .collect();
+    assert!(asset_types.contains(&Some("Unknown (0x3F7)")));
+    assert!(asset_types.contains(&Some("Image")));
+    let brief = carutil_lib::assetutil::BriefInfo::from_storage(store);
+    assert_eq!(brief.asset_types.get("Unknown (0x3F7)"), Some(&1));
+}
@@ -1,3 +1,7 @@
+mod common;
+
+use std::collections::BTreeMap;
+
 use carutil_lib::assetutil;
 use carutil_lib::assetutil::ToAssetUtilHeader;
 use carutil_lib::coreui;
@@ -54,6 +58,23 @@ fn header_simple() {
     assert_json_eq!(header, expected_header);
 }
 
+#[test]
+fn header_tool_versions() {
+    use carutil_lib::versions::ToolVersions;
+
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let versions = ToolVersions::default()
+        .dump_tool_version(716.2)
+        .core_ui_version(611);
+    assert_eq!(versions.core_ui_version, 611);
+    let header = serde_json::to_value(asset_storage.asset_util_header_with_versions(&versions))
+        .expect("Unable to serialize to JSON value");
+    assert_eq!(header["DumpToolVersion"], json!(716.2));
+    // the catalog's own CoreUI version is reported, not ours
+    assert_eq!(header["CoreUIVersion"], json!(498));
+}
+
 #[test]
 fn color_simple() {
     let expected_color = json!({
@@ -193,3 +214,1129 @@ fn image_simple() {
 
     assert_json_eq!(image, expected_image);
 }
+
+#[test]
+fn facet_summary_fixture() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let entries =
+        assetutil::AssetUtilEntry::entries_from_asset_storage(&asset_storage.theme_store.store);
+    let summaries = assetutil::FacetSummary::from_entries(&entries);
+    let names: Vec<&str> = summaries.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["MyColor", "MyJPG", "MyPDF", "MyPNG", "MyText"]);
+
+    let png = summaries.iter().find(|s| s.name == "MyPNG").unwrap();
+    assert_eq!(png.to_string(), "MyPNG  image  1x,2x,3x  any  universal");
+
+    // appearances, idioms and asset types the real catalog doesn't have
+    let car = common::fixtures::CatalogSpec::new()
+        .images("Icon", &[1, 2, 3], &[0, 2], &[0, 1])
+        .images("Banner", &[2], &[1], &[0])
+        .color(
+            "Tint",
+            &[(0, [1.0, 0.0, 0.0, 1.0]), (1, [0.0, 0.0, 1.0, 1.0])],
+        )
+        .data(
+            "config.json",
+            coreui::rendition::CompressionType::Uncompressed,
+            b"{}",
+        )
+        .open();
+    let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(&car.theme_store.store);
+    let summaries = assetutil::FacetSummary::from_entries(&entries);
+    let lines: Vec<String> = summaries.iter().map(|s| s.to_string()).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "Banner  image  2x  any  iphone",
+            "Icon  image  1x,2x,3x  any+dark  ipad,universal",
+            "Tint  color  1x  any+dark  universal",
+            "config.json  data  1x  any  universal",
+        ]
+    );
+    assert_eq!(assetutil::FacetSummary::long_lines(&entries).len(), 16);
+}
+
+#[test]
+fn facet_summary_four_variants() {
+    let variant = |scale, appearance: Option<&str>, idiom| assetutil::AssetUtilEntry {
+        appearance: appearance.map(|a| a.to_string()),
+        asset_type: Some("Image".to_string()),
+        idiom: Some(idiom),
+        name: Some("BackChevron".to_string()),
+        name_identifier: Some(1234),
+        scale: Some(scale),
+        ..Default::default()
+    };
+    let entries = vec![
+        variant(3, Some("UIAppearanceDark"), coreui::rendition::Idiom::Phone),
+        variant(1, None, coreui::rendition::Idiom::Pad),
+        variant(2, None, coreui::rendition::Idiom::Phone),
+        variant(3, None, coreui::rendition::Idiom::Phone),
+    ];
+
+    let summaries = assetutil::FacetSummary::from_entries(&entries);
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(
+        summaries[0].to_string(),
+        "BackChevron  image  1x,2x,3x  any+dark  ipad,iphone"
+    );
+    assert_eq!(assetutil::FacetSummary::long_lines(&entries).len(), 4);
+}
+
+#[test]
+fn packed_image_entries() {
+    let atlas_key = common::key(0, 2, 0, 600);
+    let atlas = common::csi_header(
+        coreui::rendition::LayoutType32::PackedImage,
+        "ZZZZPackedAsset-2.0.0-gamut0",
+        64,
+        64,
+        coreui::csi::PixelFormat::ARGB,
+        vec![],
+        Some(common::raw_data(&[0u8; 64 * 64 * 4])),
+    );
+    let attribute = |name, value| coreui::rendition::Attribute { name, value };
+    let link = common::csi_header(
+        coreui::rendition::LayoutType32::Image,
+        "Icon@2x.png",
+        16,
+        16,
+        coreui::csi::PixelFormat::ARGB,
+        vec![],
+        Some(coreui::rendition::Rendition::InternalLink {
+            version: 0,
+            x: 16,
+            y: 0,
+            width: 16,
+            height: 16,
+            layout: 0x0C,
+            _key_count: 2,
+            key: vec![
+                attribute(coreui::rendition::AttributeType16::Scale, 2),
+                attribute(coreui::rendition::AttributeType16::Identifier, 600),
+            ],
+        }),
+    );
+    let store = common::storage(
+        vec![common::facet("Icon", 1)],
+        vec![(common::key(0, 2, 0, 1), link), (atlas_key, atlas)],
+        None,
+    );
+
+    let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(&store);
+    let icon = serde_json::to_value(&entries[0]).unwrap();
+    assert_eq!(icon["Name"], json!("Icon"));
+    assert_eq!(icon["Packed"], json!(true));
+    assert_eq!(icon["PackedAsset"], json!("ZZZZPackedAsset-2.0.0-gamut0"));
+    assert!(icon.get("Internal").is_none());
+
+    let atlas = serde_json::to_value(&entries[1]).unwrap();
+    assert_eq!(atlas["AssetType"], json!("PackedImage"));
+    assert_eq!(atlas["Internal"], json!(true));
+    assert!(atlas.get("Packed").is_none());
+
+    let atlas_bytes = store.imagedb[&atlas_key].size_on_disk();
+    assert_eq!(
+        carutil_lib::stats::packed_stats(&store),
+        carutil_lib::stats::PackedStats {
+            images: 1,
+            atlases: 1,
+            atlas_bytes,
+        }
+    );
+}
+
+#[test]
+fn color_component_rounding() {
+    let format = |value: f64| assetutil::color_component(value).to_string();
+    // values as printed by assetutil
+    assert_eq!(format(128.0 / 255.0), "0.501960784313725");
+    assert_eq!(format(1.0 / 255.0), "0.00392156862745098");
+    assert_eq!(format(51.0 / 255.0), "0.2");
+    assert_eq!(format(1.0 / 3.0), "0.333333333333333");
+    assert_eq!(format(0.1 + 0.2), "0.3");
+    assert_eq!(format(0.5), "0.5");
+    assert_eq!(format(1.0), "1");
+    assert_eq!(format(0.0), "0");
+    assert_eq!(format(-0.25), "-0.25");
+}
+
+#[test]
+fn image_subtype_names() {
+    use coreui::rendition::ImageSubtype;
+    use num_traits::FromPrimitive;
+
+    let name = |value: u16| {
+        let subtype: Option<ImageSubtype> = FromPrimitive::from_u16(value);
+        subtype.map(|subtype| serde_json::to_value(subtype).unwrap())
+    };
+    assert_eq!(name(10), Some(json!("one-part fixed size")));
+    assert_eq!(name(21), Some(json!("three-part horizontal scale")));
+    assert_eq!(name(30), Some(json!("nine-part tile")));
+    assert_eq!(name(50), Some(json!("animation filmstrip")));
+    assert_eq!(name(568), None);
+}
+
+#[test]
+fn animation_filmstrip_entry() {
+    // slices tlv: one 32x32 slice of a 32x320 filmstrip
+    let mut tlv = vec![];
+    for value in [0x3E9u32, 20, 1, 0, 0, 32, 32] {
+        tlv.extend(value.to_le_bytes());
+    }
+    let csi_header = common::csi_header(
+        coreui::rendition::LayoutType32::Image,
+        "spinner.png",
+        32,
+        320,
+        coreui::csi::PixelFormat::ARGB,
+        tlv,
+        Some(common::raw_data(&[0u8; 32 * 320 * 4])),
+    );
+    let key_values = vec![
+        (coreui::rendition::AttributeType::Scale, 1),
+        (coreui::rendition::AttributeType::Subtype, 50),
+        (coreui::rendition::AttributeType::Identifier, 7),
+    ];
+    let entry = assetutil::AssetUtilEntry::from_csi_header(
+        &csi_header,
+        Some("spinner".to_string()),
+        key_values,
+        vec![],
+        &BTreeMap::new(),
+    );
+    let entry = serde_json::to_value(entry).unwrap();
+    assert_eq!(entry["Subtype"], json!(50));
+    assert_eq!(entry["SubtypeName"], json!("animation filmstrip"));
+    assert_eq!(entry["FrameCount"], json!(10));
+    assert_eq!(entry["FrameHeight"], json!(32));
+}
+
+#[test]
+fn cbck_palette_image() {
+    let car = coreui::CarUtilAssetStorage::from("tests/Assets.car", false).unwrap();
+    let (key, theme) = car
+        .theme_store
+        .store
+        .imagedb
+        .iter()
+        .find(|(_, csi_header)| csi_header.csimetadata.name() == "Timac@3x.png")
+        .expect("No rendition found");
+    let cbck = match &theme.rendition_data {
+        Some(coreui::rendition::Rendition::Theme {
+            version,
+            compression_type,
+            _raw_data_length,
+            raw_data,
+        }) => {
+            let mut cbck = theme.clone();
+            cbck.rendition_data = Some(coreui::rendition::Rendition::ThemeCBCK {
+                version: *version,
+                compression_type: *compression_type,
+                idk: 0,
+                a: 0,
+                b: 0,
+                c: 0,
+                _raw_data_length: *_raw_data_length,
+                raw_data: raw_data.clone(),
+            });
+            cbck.csibitmaplist.rendition_length += 20;
+            cbck
+        }
+        _ => panic!("expected a CELM rendition"),
+    };
+
+    let key_values = car.theme_store.store.key_values(key);
+    let entry = |csi_header: &coreui::csi::Header| {
+        let entry = assetutil::AssetUtilEntry::from_csi_header(
+            csi_header,
+            Some("Timac".to_string()),
+            key_values.clone(),
+            vec![],
+            &BTreeMap::new(),
+        );
+        serde_json::to_value(entry).unwrap()
+    };
+    assert_eq!(entry(&cbck)["Compression"], json!("palette-img"));
+    assert_eq!(entry(&cbck)["Template Mode"], entry(theme)["Template Mode"]);
+    assert_eq!(entry(&cbck)["Opaque"], entry(theme)["Opaque"]);
+
+    let extracted = cbck.extracted_file().unwrap().unwrap();
+    assert_eq!(extracted.format, coreui::csi::OutputFormat::Png);
+    assert_eq!(
+        extracted.data,
+        theme.extracted_file().unwrap().unwrap().data
+    );
+}
+
+#[test]
+fn debug_blobs_only_for_matching_entries() {
+    let car = coreui::CarUtilAssetStorage::from("tests/Assets.car", false).unwrap();
+    let entries =
+        assetutil::AssetUtilEntry::entries_with_debug_blobs(&car.theme_store.store, Some("MyJPG"));
+    let entries: Vec<serde_json::Value> = entries
+        .into_iter()
+        .map(|entry| serde_json::to_value(entry).unwrap())
+        .collect();
+    for entry in &entries {
+        let has_blobs = entry["Name"] == json!("MyJPG");
+        for field in ["TLVHex", "PayloadHexPrefix", "CSIHeader"] {
+            assert_eq!(
+                entry.get(field).is_some(),
+                has_blobs,
+                "{} in {}",
+                field,
+                entry
+            );
+        }
+    }
+
+    let entry = entries
+        .iter()
+        .find(|entry| entry["Name"] == json!("MyJPG"))
+        .unwrap();
+    // jpeg payloads start with the SOI marker
+    let prefix = entry["PayloadHexPrefix"].as_str().unwrap();
+    assert!(prefix.starts_with("ffd8"));
+    assert_eq!(prefix.len(), 512);
+    assert_eq!(entry["CSIHeader"]["Name"], json!("TimacJPG.jpg"));
+    assert_eq!(entry["CSIHeader"]["TLV"], entry["TLVHex"]);
+
+    let plain = assetutil::AssetUtilEntry::entries_from_asset_storage(&car.theme_store.store);
+    assert!(plain.iter().all(|entry| entry.tlv_hex.is_none()));
+}
+
+#[test]
+fn debug_blobs_ignore_case() {
+    let car = coreui::CarUtilAssetStorage::from("tests/Assets.car", false).unwrap();
+    let with_blobs = |case_sensitive| -> Vec<String> {
+        let options = assetutil::EntryOptions {
+            debug_blobs: Some("myjpg".to_string()),
+            case_sensitive,
+            ..Default::default()
+        };
+        assetutil::AssetUtilEntry::entries_with_options(&car.theme_store.store, &options)
+            .into_iter()
+            .filter(|entry| entry.tlv_hex.is_some())
+            .map(|entry| serde_json::to_value(entry).unwrap()["Name"].to_string())
+            .collect()
+    };
+    // the dump keeps the catalog's spelling
+    assert_eq!(with_blobs(false), vec!["\"MyJPG\""]);
+    assert!(with_blobs(true).is_empty());
+}
+
+#[test]
+fn multisize_image_set_unknown_idiom() {
+    use binrw::BinRead;
+    use binrw::BinWrite;
+
+    let mut data = b"SISM".to_vec();
+    for value in [1u32, 2] {
+        data.extend(value.to_le_bytes());
+    }
+    for (width, height, index, idiom) in [(20u32, 20u32, 1u16, 8u16), (29, 29, 2, 9)] {
+        data.extend(width.to_le_bytes());
+        data.extend(height.to_le_bytes());
+        data.extend(index.to_le_bytes());
+        data.extend(idiom.to_le_bytes());
+    }
+    let rendition_data =
+        coreui::rendition::Rendition::read_le(&mut std::io::Cursor::new(&data)).unwrap();
+    let mut written = std::io::Cursor::new(vec![]);
+    rendition_data.write_le(&mut written).unwrap();
+    assert_eq!(written.into_inner(), data);
+
+    let csi_header = common::csi_header(
+        coreui::rendition::LayoutType32::MultisizeImage,
+        "AppIcon",
+        0,
+        0,
+        coreui::csi::PixelFormat::ARGB,
+        vec![],
+        Some(rendition_data),
+    );
+    let key_values = vec![(coreui::rendition::AttributeType::Idiom, 9)];
+    let entry = assetutil::AssetUtilEntry::from_csi_header(
+        &csi_header,
+        Some("AppIcon".to_string()),
+        key_values,
+        vec![],
+        &BTreeMap::new(),
+    );
+    assert_eq!(assetutil::idiom_label(entry.idiom.as_ref()), "9");
+    let entry = serde_json::to_value(entry).unwrap();
+    assert_eq!(entry["Idiom"], json!(9));
+    assert_eq!(
+        entry["Sizes"],
+        json!(["20x20 index:1 idiom:vision", "29x29 index:2 idiom:9"])
+    );
+    assert_eq!(
+        serde_json::to_value(coreui::rendition::Idiom::Vision).unwrap(),
+        json!("vision")
+    );
+}
+
+#[test]
+fn pixel_digests() {
+    let car = coreui::CarUtilAssetStorage::from(CAR_PATH, false).unwrap();
+    let options = assetutil::EntryOptions {
+        pixel_digests: true,
+        ..Default::default()
+    };
+    let entries: Vec<serde_json::Value> =
+        assetutil::AssetUtilEntry::entries_with_options(&car.theme_store.store, &options)
+            .into_iter()
+            .map(|entry| serde_json::to_value(entry).unwrap())
+            .collect();
+    for entry in &entries {
+        let digest = entry.get("PixelDigest");
+        match entry["RenditionName"].as_str().unwrap_or_default() {
+            // palette-img
+            "Timac.png" | "Timac@2x.png" | "Timac@3x.png" => {
+                assert_eq!(digest.unwrap().as_str().unwrap().len(), 64)
+            }
+            // no jpeg decoder
+            "TimacJPG.jpg" => assert_eq!(digest, Some(&json!(null))),
+            // not images
+            _ => assert_eq!(digest, None),
+        }
+    }
+
+    let plain = assetutil::AssetUtilEntry::entries_from_asset_storage(&car.theme_store.store);
+    assert!(plain.iter().all(|entry| entry.pixel_digest.is_none()));
+}
+
+#[test]
+fn pixel_digest_ignores_compression() {
+    let car = coreui::CarUtilAssetStorage::from(CAR_PATH, false).unwrap();
+    let palette_img = car
+        .theme_store
+        .store
+        .imagedb
+        .values()
+        .find(|csi_header| csi_header.csimetadata.name() == "Timac@2x.png")
+        .unwrap();
+    let rgba = palette_img.rgba_pixels().unwrap();
+    let png = coreui::csi::encode_png(palette_img.width, palette_img.height, &rgba).unwrap();
+    let mut png_image = palette_img.clone();
+    png_image.rendition_data = Some(common::raw_data(&png));
+    assert_eq!(
+        png_image.pixel_digest().unwrap(),
+        palette_img.pixel_digest().unwrap()
+    );
+
+    // same pixels at different dimensions
+    let mut resized = png_image.clone();
+    resized.width *= 2;
+    resized.height /= 2;
+    assert!(resized.pixel_digest().is_err());
+}
+
+#[test]
+fn namespaced_names() {
+    use carutil_lib::FlattenNames;
+
+    let store = common::storage(
+        vec![common::facet("Buttons/Primary/Background", 1)],
+        vec![(
+            common::key(0, 1, 0, 1),
+            common::image("Background.png", 1, 1),
+        )],
+        None,
+    );
+    let name = |flatten_names| {
+        let options = assetutil::EntryOptions {
+            flatten_names,
+            ..Default::default()
+        };
+        assetutil::AssetUtilEntry::entries_with_options(&store, &options)[0]
+            .name
+            .clone()
+            .unwrap()
+    };
+    assert_eq!(name(FlattenNames::Slash), "Buttons/Primary/Background");
+    assert_eq!(name(FlattenNames::Keep), "Buttons/Primary/Background");
+    assert_eq!(name(FlattenNames::Dash), "Buttons-Primary-Background");
+    assert_eq!(
+        assetutil::AssetUtilEntry::entries_from_asset_storage(&store)[0].name,
+        Some("Buttons/Primary/Background".to_string())
+    );
+}
+
+#[test]
+fn compressed_data_length() {
+    use carutil_lib::coreui::rendition::CompressionType;
+
+    let json = br#"{"retries": 3, "timeout": 30, "endpoints": ["a", "b", "c"]}"#;
+    let mut lzfse = vec![];
+    lzfse_rust::encode_bytes(json, &mut lzfse).unwrap();
+    let store = common::storage(
+        vec![common::facet("Config", 1), common::facet("Blob", 2)],
+        vec![
+            (
+                common::key(0, 0, 0, 1),
+                common::compressed_data("config.json", CompressionType::LZFSE, &lzfse),
+            ),
+            (
+                common::key(0, 0, 0, 2),
+                common::compressed_data("blob.bin", CompressionType::RLE, b"rle"),
+            ),
+        ],
+        None,
+    );
+    let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(&store);
+    let entry = |name: &str| {
+        let entry = entries
+            .iter()
+            .find(|entry| entry.name.as_deref() == Some(name))
+            .unwrap();
+        serde_json::to_value(entry).unwrap()
+    };
+    let config = entry("Config");
+    assert_eq!(config["Compression"], "lzfse");
+    assert_eq!(config["Data Length"], json.len());
+    assert_eq!(config["SizeOnDisk"], 184 + 12 + 4 + lzfse.len());
+    // nothing to decompress it with, the stored length is all there is
+    assert_eq!(entry("Blob")["Data Length"], 3);
+    assert!(entry("Blob").get("StoredDataLength").is_none());
+}
+
+#[test]
+fn compressed_data_sizes_with_tlv() {
+    use carutil_lib::coreui::csi;
+    use carutil_lib::coreui::rendition;
+    use carutil_lib::RawData;
+
+    let json = br#"{"retries": 3, "retries": 3, "retries": 3, "retries": 3}"#;
+    let mut lzfse = vec![];
+    lzfse_rust::encode_bytes(json, &mut lzfse).unwrap();
+    let tlv = common::uti_tlv("public.json", 12);
+    let csi_header = common::csi_header(
+        rendition::LayoutType32::Data,
+        "config.json",
+        0,
+        0,
+        csi::PixelFormat::Data,
+        tlv.clone(),
+        Some(rendition::Rendition::Theme {
+            version: 1,
+            compression_type: rendition::CompressionType::LZFSE,
+            _raw_data_length: lzfse.len() as u32,
+            raw_data: RawData(lzfse.clone()),
+        }),
+    );
+    let store = common::storage(
+        vec![common::facet("Config", 1)],
+        vec![(common::key(0, 0, 0, 1), csi_header)],
+        None,
+    );
+    let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(&store);
+    let entry = serde_json::to_value(&entries[0]).unwrap();
+
+    // header, tlvs, and the CELM header (tag, version, compression,
+    // length) with the compressed payload
+    assert_eq!(
+        entry["SizeOnDisk"],
+        csi::Header::HEADER_SIZE as usize + tlv.len() + 16 + lzfse.len()
+    );
+    assert_eq!(entry["Data Length"], json.len());
+    assert_eq!(entry["StoredDataLength"], lzfse.len());
+    assert!(lzfse.len() < json.len());
+    assert_eq!(entry["UTI"], "public.json");
+}
+
+#[test]
+fn parallel_matches_sequential() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from("./tests/Assets.car", true).expect("Unable to parse");
+    let store = &asset_storage.theme_store.store;
+    let header = serde_json::to_value(asset_storage.asset_util_header()).unwrap();
+    let dump = |jobs| {
+        let options = assetutil::EntryOptions {
+            pixel_digests: true,
+            jobs,
+            ..Default::default()
+        };
+        let mut entries = assetutil::AssetUtilEntry::entries_with_options(store, &options);
+        assetutil::sort_entries(&mut entries);
+        assetutil::to_json_pretty(&header, &entries, jobs).unwrap()
+    };
+    let sequential = dump(1);
+    for jobs in [0, 2, 3, 16] {
+        assert_eq!(dump(jobs), sequential, "jobs={}", jobs);
+    }
+
+    // the same text as serializing the whole array in one go
+    let mut entries = assetutil::AssetUtilEntry::entries_with_options(
+        store,
+        &assetutil::EntryOptions {
+            pixel_digests: true,
+            ..Default::default()
+        },
+    );
+    assetutil::sort_entries(&mut entries);
+    let mut values = vec![header.clone()];
+    values.extend(
+        entries
+            .iter()
+            .map(|entry| serde_json::to_value(entry).unwrap()),
+    );
+    assert_eq!(sequential, serde_json::to_string_pretty(&values).unwrap());
+    assert_eq!(
+        assetutil::to_json_pretty(&header, &[], 4).unwrap(),
+        serde_json::to_string_pretty(&vec![header]).unwrap()
+    );
+}
+
+#[test]
+fn key_filter() {
+    use carutil_lib::filter;
+
+    let rendition_names = |car: &coreui::CarUtilAssetStorage, expressions: &[&str]| {
+        let attributes = expressions
+            .iter()
+            .map(|expression| filter::AttributeRequirement::parse(expression, &BTreeMap::new()))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        let options = assetutil::EntryOptions {
+            filter: filter::RenditionFilter {
+                attributes,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut names: Vec<String> =
+            assetutil::AssetUtilEntry::entries_with_options(&car.theme_store.store, &options)
+                .into_iter()
+                .filter_map(|entry| entry.rendition_name)
+                .collect();
+        names.sort();
+        names
+    };
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    assert_eq!(
+        rendition_names(&asset_storage, &["scale=2x"]),
+        ["Timac@2x.png"]
+    );
+    assert_eq!(rendition_names(&asset_storage, &["12=3"]), ["Timac@3x.png"]);
+    assert!(rendition_names(&asset_storage, &["scale=2", "idiom=pad"]).is_empty());
+
+    // a pad idiom to filter on
+    let asset_storage = common::fixtures::CatalogSpec::new()
+        .images("Icon", &[1, 2], &[0, 2], &[0])
+        .open();
+    assert_eq!(
+        rendition_names(&asset_storage, &["scale=2x"]),
+        ["Icon@2x.png", "Icon@2x~ipad.png"]
+    );
+    assert_eq!(
+        rendition_names(&asset_storage, &["12=1"]),
+        ["Icon.png", "Icon~ipad.png"]
+    );
+    assert_eq!(
+        rendition_names(&asset_storage, &["scale=2", "idiom=pad"]),
+        ["Icon@2x~ipad.png"]
+    );
+    assert!(rendition_names(&asset_storage, &["scale=3"]).is_empty());
+    assert_eq!(rendition_names(&asset_storage, &[]).len(), 4);
+}
+
+#[test]
+fn synthesized_rendition_names() {
+    let appearancedb = BTreeMap::from([
+        ("UIAppearanceAny".to_string(), 0),
+        ("UIAppearanceDark".to_string(), 1),
+        ("UIAppearanceHighContrastDark".to_string(), 3),
+    ]);
+    let key_values = |appearance, scale, idiom| {
+        common::KEY_FORMAT
+            .iter()
+            .copied()
+            .zip(common::key(appearance, scale, idiom, 1).raw)
+            .collect::<Vec<_>>()
+    };
+    for (appearance, scale, idiom, expected) in [
+        (0, 1, 0, "MyIcon"),
+        (0, 0, 0, "MyIcon"),
+        (0, 2, 0, "MyIcon@2x"),
+        (0, 3, 1, "MyIcon@3x~iphone"),
+        (0, 2, 2, "MyIcon@2x~ipad"),
+        (0, 1, 7, "MyIcon~mac"),
+        (1, 1, 0, "MyIcon-dark"),
+        (1, 2, 2, "MyIcon-dark@2x~ipad"),
+        (3, 3, 0, "MyIcon-dark-highcontrast@3x"),
+        (2, 1, 0, "MyIcon-UnknownAppearance2"),
+        (0, 1, 42, "MyIcon~42"),
+    ] {
+        assert_eq!(
+            assetutil::synthesized_rendition_name(
+                "MyIcon",
+                &key_values(appearance, scale, idiom),
+                &appearancedb
+            ),
+            expected
+        );
+    }
+    assert_eq!(
+        assetutil::synthesized_rendition_name(
+            "Icons/Tab/MyIcon",
+            &key_values(0, 2, 0),
+            &appearancedb
+        ),
+        "MyIcon@2x"
+    );
+}
+
+#[test]
+fn blank_rendition_names() {
+    let store = common::storage(
+        vec![common::facet("MyIcon", 1)],
+        vec![
+            (common::key(0, 2, 2, 1), common::image("", 1, 1)),
+            (
+                common::key(0, 3, 0, 1),
+                common::image("MyIcon@3x.png", 1, 1),
+            ),
+        ],
+        None,
+    );
+    let mut entries = assetutil::AssetUtilEntry::entries_from_asset_storage(&store);
+    assetutil::sort_entries(&mut entries);
+    let names: Vec<(Option<&str>, Option<bool>)> = entries
+        .iter()
+        .map(|entry| {
+            (
+                entry.rendition_name.as_deref(),
+                entry.synthesized_rendition_name,
+            )
+        })
+        .collect();
+    assert_eq!(
+        names,
+        [
+            (Some("MyIcon@2x~ipad"), Some(true)),
+            (Some("MyIcon@3x.png"), None)
+        ]
+    );
+    let json = serde_json::to_value(&entries[0]).unwrap();
+    assert_eq!(json["SynthesizedRenditionName"], true);
+    assert!(serde_json::to_value(&entries[1])
+        .unwrap()
+        .get("SynthesizedRenditionName")
+        .is_none());
+}
+
+#[test]
+fn vector_pdf_bounds() {
+    use carutil_lib::coreui::csi;
+    use carutil_lib::coreui::rendition;
+    use carutil_lib::extract;
+
+    let pdf = b"%PDF-1.4\n1 0 obj << /Type /Catalog /Pages 2 0 R >> endobj\n\
+        2 0 obj << /Type /Pages /Kids [3 0 R] /Count 1 >> endobj\n\
+        3 0 obj << /Type /Page /Parent 2 0 R /MediaBox [0 0 24.5 18] >> endobj\n\
+        trailer << /Root 1 0 R >>\n%%EOF\n";
+    let vector = common::csi_header(
+        rendition::LayoutType32::Vector,
+        "Arrow.pdf",
+        0,
+        0,
+        csi::PixelFormat::from_fourcc(u32::from_be_bytes(*b"PDF ")),
+        vec![],
+        Some(common::raw_data(pdf)),
+    );
+    let mut preserved = common::image("Check.pdf", 0, 0);
+    preserved.rendition_flags = csi::RenditionFlags(1);
+    preserved.rendition_data = Some(common::raw_data(pdf));
+    let store = common::storage(
+        vec![common::facet("Arrow", 1), common::facet("Check", 2)],
+        vec![
+            (common::key(0, 1, 0, 1), vector.clone()),
+            (common::key(0, 1, 0, 2), preserved),
+        ],
+        None,
+    );
+    let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(&store);
+    for entry in &entries {
+        let json = serde_json::to_value(entry).unwrap();
+        assert_eq!(json["PixelWidth"], 25, "{}", json);
+        assert_eq!(json["PixelHeight"], 18, "{}", json);
+        assert_eq!(json["VectorBased"], true);
+    }
+    assert_eq!(entries.len(), 2);
+    assert_eq!(
+        csi::pdf_media_box(b"/MediaBox[ -10 -5 10.25 5 ]"),
+        Some((20.25, 10.0))
+    );
+    assert_eq!(csi::pdf_media_box(b"/MediaBox 3 0 R"), None);
+    let sidecar = extract::raw_sidecar(&vector, "raw");
+    assert_eq!(sidecar["VectorBounds"], json!([25, 18]));
+}
+
+#[test]
+fn brief_info() {
+    let options = coreui::ParseOptions {
+        compute_digests: false,
+        ..Default::default()
+    };
+    let asset_storage = coreui::CarUtilAssetStorage::from_with_options(CAR_PATH, &options)
+        .expect("Unable to parse Assets.car");
+    let brief = assetutil::BriefInfo::from_storage(&asset_storage.theme_store.store);
+    assert_eq!(
+        brief.lines(),
+        vec![
+            "Platform: ios 12.0",
+            "CoreUI version: 498",
+            "Storage version: 15",
+            "Timestamp: 1539543253 (2018-10-14 18:54:13 UTC)",
+            "Appearances: none",
+            "Key format: 18 attributes",
+            "Entries: 7",
+            "  Color: 1",
+            "  Data: 2",
+            "  Image: 4",
+        ]
+    );
+    assert_json_eq!(
+        serde_json::to_value(&brief).unwrap(),
+        json!({
+            "Platform": "ios",
+            "PlatformVersion": "12.0",
+            "CoreUIVersion": 498,
+            "StorageVersion": 15,
+            "Timestamp": 1539543253,
+            "Appearances": [],
+            "KeyFormatLength": 18,
+            "Entries": 7,
+            "AssetTypes": {"Color": 1, "Data": 2, "Image": 4}
+        })
+    );
+}
+
+#[test]
+fn direction_look_and_presentation_state() {
+    use coreui::rendition::Attribute;
+    use coreui::rendition::AttributeType;
+    use coreui::rendition::AttributeType16;
+
+    let key_format = coreui::rendition::KeyFormat::new(vec![
+        AttributeType::Look,
+        AttributeType::Direction,
+        AttributeType::PresentationState,
+        AttributeType::Identifier,
+    ]);
+    let key = |look, direction, presentation_state| {
+        key_format.key(&[
+            Attribute {
+                name: AttributeType16::Look,
+                value: look,
+            },
+            Attribute {
+                name: AttributeType16::Direction,
+                value: direction,
+            },
+            Attribute {
+                name: AttributeType16::PresentationState,
+                value: presentation_state,
+            },
+            Attribute {
+                name: AttributeType16::Identifier,
+                value: 1,
+            },
+        ])
+    };
+    let mut store = common::storage(
+        vec![common::facet("Arrow", 1)],
+        vec![
+            (key(0, 0, 0), common::image("arrow.png", 1, 1)),
+            (key(0, 5, 0), common::image("arrow-rtl.png", 1, 1)),
+            (key(2, 9, 1), common::image("arrow-flat.png", 1, 1)),
+        ],
+        None,
+    );
+    store.renditionkeyfmt = key_format;
+    let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(&store);
+    let fields: BTreeMap<String, serde_json::Value> = entries
+        .iter()
+        .map(|entry| {
+            let entry = serde_json::to_value(entry).unwrap();
+            (
+                entry["RenditionName"].as_str().unwrap().to_string(),
+                json!([
+                    entry.get("Look"),
+                    entry.get("Direction"),
+                    entry.get("PresentationState")
+                ]),
+            )
+        })
+        .collect();
+    assert_eq!(
+        fields,
+        BTreeMap::from([
+            ("arrow.png".to_string(), json!([null, null, null])),
+            (
+                "arrow-rtl.png".to_string(),
+                json!([null, "rightToLeft", null])
+            ),
+            ("arrow-flat.png".to_string(), json!(["flat", 9, "inactive"])),
+        ])
+    );
+}
+
+#[test]
+fn variants_present_in_header() {
+    let appearances = BTreeMap::from([
+        ("UIAppearanceAny".to_string(), 0),
+        ("UIAppearanceDark".to_string(), 1),
+        ("UIAppearanceLight".to_string(), 2),
+    ]);
+    let image = |name: &str, scale: u32| {
+        let mut csi_header = common::image(name, scale, scale);
+        csi_header.scale_factor = scale * 100;
+        csi_header
+    };
+    let store = common::storage(
+        vec![common::facet("Icon", 1), common::facet("Logo", 2)],
+        vec![
+            (common::key(0, 2, 1, 1), image("Icon@2x~iphone.png", 2)),
+            (common::key(1, 2, 2, 1), image("Icon-dark@2x~ipad.png", 2)),
+            (common::key(0, 1, 0, 2), image("Logo.png", 1)),
+            (common::key(0, 3, 3, 2), image("Logo@3x~tv.png", 3)),
+        ],
+        Some(appearances.clone()),
+    );
+    let mut entries = assetutil::AssetUtilEntry::entries_from_asset_storage(&store);
+    assetutil::sort_entries(&mut entries);
+    let mut header = assetutil::AssetUtilHeader {
+        appearances: Some(appearances),
+        ..Default::default()
+    };
+    header.add_variants_present(&entries);
+    let header = serde_json::to_value(header).unwrap();
+    assert_eq!(
+        header["IdiomsPresent"],
+        json!(["universal", "phone", "pad", "tv"])
+    );
+    assert_eq!(header["ScalesPresent"], json!([1, 2, 3]));
+    // nothing is light
+    assert_eq!(
+        header["AppearancesUsed"],
+        json!({"UIAppearanceAny": 0, "UIAppearanceDark": 1})
+    );
+
+    // only with --extended-header
+    let car = coreui::CarUtilAssetStorage::from(CAR_PATH, false).unwrap();
+    let header = serde_json::to_value(car.asset_util_header()).unwrap();
+    assert!(header.get("IdiomsPresent").is_none());
+    assert!(header.get("AppearancesUsed").is_none());
+}
+
+fn high_contrast_appearances() -> BTreeMap<String, u32> {
+    BTreeMap::from([
+        ("UIAppearanceAny".to_string(), 0),
+        ("UIAppearanceDark".to_string(), 1),
+        ("UIAppearanceHighContrastAny".to_string(), 2),
+        ("UIAppearanceHighContrastDark".to_string(), 3),
+    ])
+}
+
+#[test]
+fn high_contrast_color_variants() {
+    let color = |red| {
+        common::csi_header(
+            coreui::rendition::LayoutType32::Color,
+            "",
+            0,
+            0,
+            coreui::csi::PixelFormat::None,
+            vec![],
+            Some(coreui::rendition::Rendition::Color {
+                version: 1,
+                flags: coreui::rendition::ColorFlags(0),
+                component_count: 4,
+                components: vec![red, 0.0, 0.0, 1.0],
+            }),
+        )
+    };
+    let store = common::storage(
+        vec![common::facet("Tint", 1)],
+        (0..4)
+            .map(|appearance| {
+                (
+                    common::key(appearance, 0, 0, 1),
+                    color(appearance as f64 / 4.0),
+                )
+            })
+            .collect(),
+        Some(high_contrast_appearances()),
+    );
+    let mut entries = assetutil::AssetUtilEntry::entries_from_asset_storage(&store);
+    assetutil::sort_entries(&mut entries);
+    let mut variants: Vec<(String, f64)> = entries
+        .iter()
+        .map(|entry| {
+            (
+                assetutil::appearance_label(entry.appearance.as_deref()),
+                entry.color_components.as_ref().unwrap()[0],
+            )
+        })
+        .collect();
+    variants.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        variants,
+        vec![
+            ("any".to_string(), 0.0),
+            ("dark".to_string(), 0.25),
+            ("dark-highcontrast".to_string(), 0.75),
+            ("highcontrast".to_string(), 0.5),
+        ]
+    );
+
+    // the names a blank rendition name is replaced with
+    let names: Vec<String> = store
+        .imagedb
+        .keys()
+        .map(|key| {
+            assetutil::synthesized_rendition_name(
+                "Tint",
+                &store.key_values(key),
+                &high_contrast_appearances(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            "Tint",
+            "Tint-dark",
+            "Tint-highcontrast",
+            "Tint-dark-highcontrast"
+        ]
+    );
+
+    // normal contrast first, then the high contrast variants
+    let summaries = assetutil::FacetSummary::from_entries(&entries);
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(
+        summaries[0].to_string(),
+        "Tint  color  1x  any+dark+highcontrast+dark-highcontrast  universal"
+    );
+}
+
+#[test]
+fn appearance_labels() {
+    for (appearance, label) in [
+        (None, "any"),
+        (Some("UIAppearanceDark"), "dark"),
+        (Some("NSAppearanceNameAqua"), "light"),
+        (Some("UIAppearanceHighContrastAny"), "highcontrast"),
+        (Some("UIAppearanceHighContrastDark"), "dark-highcontrast"),
+        (
+            Some("NSAppearanceNameAccessibilityHighContrastAqua"),
+            "light-highcontrast",
+        ),
+        (
+            Some("NSAppearanceNameVibrantDark"),
+            "NSAppearanceNameVibrantDark",
+        ),
+    ] {
+        assert_eq!(assetutil::appearance_label(appearance), label);
+    }
+    let variant = assetutil::appearance_variant(Some("UIAppearanceHighContrastDark")).unwrap();
+    assert_eq!(variant.luminosity, assetutil::Luminosity::Dark);
+    assert!(variant.high_contrast);
+    assert!(assetutil::appearance_variant(Some("NSAppearanceNameVibrantDark")).is_none());
+}
+
+#[test]
+fn name_identifier_collisions() {
+    let store = common::colliding_facets_storage();
+    assert_eq!(
+        store.name_identifier_collisions(),
+        BTreeMap::from([(5, vec!["Alpha".to_string(), "Beta".to_string()])])
+    );
+    assert_eq!(
+        store.facet_name_warnings(),
+        vec!["facets [\"Alpha\", \"Beta\"] share name identifier 5"]
+    );
+
+    let names = |options: &assetutil::EntryOptions| -> Vec<serde_json::Value> {
+        let mut entries = assetutil::AssetUtilEntry::entries_with_options(&store, options);
+        assetutil::sort_entries(&mut entries);
+        entries
+            .iter()
+            .map(|entry| {
+                let value = serde_json::to_value(entry).unwrap();
+                json!([
+                    value["RenditionName"],
+                    value["Name"],
+                    value["NameCandidates"]
+                ])
+            })
+            .collect()
+    };
+    // the idiom in the facet tokens settles the iPhone and iPad renditions
+    assert_eq!(
+        names(&assetutil::EntryOptions::default()),
+        vec![
+            json!(["Alpha.png", "Alpha", null]),
+            json!(["Shared.png", "Alpha", ["Alpha", "Beta"]]),
+            json!(["Beta.png", "Beta", null]),
+        ]
+    );
+    assert_eq!(
+        names(&assetutil::EntryOptions {
+            name_collisions: assetutil::NameCollisions::First,
+            ..Default::default()
+        }),
+        vec![
+            json!(["Alpha.png", "Alpha", null]),
+            json!(["Shared.png", "Alpha", null]),
+            json!(["Beta.png", "Beta", null]),
+        ]
+    );
+
+    let (key, candidates) = assetutil::ambiguous_facet_names(&store).unwrap();
+    assert_eq!(key, common::key(0, 1, 0, 5));
+    assert_eq!(candidates, vec!["Alpha", "Beta"]);
+    assert!(assetutil::ambiguous_facet_names(&common::storage(
+        vec![common::facet("Alpha", 5)],
+        vec![(common::key(0, 1, 0, 5), common::image("Alpha.png", 1, 1))],
+        None,
+    ))
+    .is_none());
+}
+
+#[test]
+fn gray_image_entry() {
+    let mut pixels = vec![];
+    lzfse_rust::encode_bytes(&[0x40, 0xFF, 0x80, 0x7F], &mut pixels).unwrap();
+    let car = common::fixtures::CatalogSpec::new()
+        .compressed_image(
+            "Glyph.png",
+            (2, 1),
+            coreui::csi::PixelFormat::Gray,
+            coreui::rendition::CompressionType::LZFSE,
+            &pixels,
+        )
+        .open();
+    let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(&car.theme_store.store);
+    let entry = serde_json::to_value(&entries[0]).unwrap();
+    assert_eq!(entry["ColorModel"], "Monochrome");
+    assert_eq!(entry["Encoding"], "Gray");
+    assert_eq!(entry["Compression"], "lzfse");
+
+    let csi_header = car.theme_store.store.imagedb.values().next().unwrap();
+    let extracted_file = csi_header.extracted_file().unwrap().unwrap();
+    let mut reader = png::Decoder::new(&extracted_file.data[..])
+        .read_info()
+        .unwrap();
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).unwrap();
+    assert_eq!(info.color_type, png::ColorType::GrayscaleAlpha);
+    assert_eq!((info.width, info.height), (2, 1));
+    assert_eq!(buffer, [0x40, 0xFF, 0x80, 0x7F]);
+}
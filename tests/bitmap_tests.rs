@@ -0,0 +1,108 @@
+mod common;
+
+use binrw::BinRead;
+use binrw::BinWrite;
+use std::collections::HashSet;
+use std::io::Cursor;
+
+use carutil_lib::bom;
+use carutil_lib::coreui;
+use carutil_lib::coreui::bitmap;
+use carutil_lib::coreui::rendition;
+
+// deterministic xorshift so failures are reproducible
+fn random_bytes(seed: &mut u64, length: usize) -> Vec<u8> {
+    (0..length)
+        .map(|_| {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            *seed as u8
+        })
+        .collect()
+}
+
+fn write_bytes<T: BinWrite + binrw::meta::WriteEndian>(value: &T) -> Vec<u8>
+where
+    for<'a> T::Args<'a>: Default,
+{
+    let mut writer = Cursor::new(vec![]);
+    value.write(&mut writer).expect("Unable to write key");
+    writer.into_inner()
+}
+
+#[test]
+fn bitmap_key_round_trips() {
+    let mut seed = 0x1692;
+    for _ in 0..256 {
+        let bytes = random_bytes(&mut seed, 22);
+        let key = bitmap::Key::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(write_bytes(&key), bytes);
+        assert_eq!(key.to_hex(), hex::encode(&bytes));
+        assert_eq!(bitmap::Key::from_hex(&key.to_hex()).unwrap(), key);
+    }
+}
+
+#[test]
+fn rendition_key_round_trips() {
+    let mut seed = 0x36;
+    for _ in 0..256 {
+        let bytes = random_bytes(&mut seed, 36);
+        let key = rendition::Key::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(write_bytes(&key), bytes);
+        assert_eq!(rendition::Key::from_hex(&key.to_hex()).unwrap(), key);
+    }
+}
+
+#[test]
+fn keys_hash_by_value() {
+    let a = bitmap::Key::from_hex(&"01".repeat(22)).unwrap();
+    let b = bitmap::Key::from_hex(&"01".repeat(22)).unwrap();
+    assert_eq!(a.raw, [0x0101; 11]);
+    assert_eq!(HashSet::from([a, b]).len(), 1);
+}
+
+#[test]
+fn from_hex_rejects_invalid_strings() {
+    assert!(bitmap::Key::from_hex(&"00".repeat(21)).is_err());
+    assert!(bitmap::Key::from_hex(&"zz".repeat(22)).is_err());
+    assert!(rendition::Key::from_hex(&"00".repeat(22)).is_err());
+}
+
+#[test]
+fn write_data_emits_bitmap_keys() {
+    let car = coreui::CarUtilAssetStorage::from("tests/Assets.car", false).unwrap();
+    let expected = car.theme_store.store.bitmapkeydb.clone().unwrap();
+    assert!(!expected.is_empty());
+
+    let directory = common::temp_dir("bitmapkeys");
+    let path = directory.join("bitmapkeys.car");
+    car.write_data(path.to_str().unwrap()).unwrap();
+    let data = std::fs::read(&path).unwrap();
+
+    let mut reader = Cursor::new(data);
+    let storage = bom::Storage::read(&mut reader).unwrap();
+    let tree = storage
+        .get_named_typed_block::<bom::Tree>("BITMAPKEYS", &mut reader, ())
+        .unwrap();
+    let written: Vec<(u32, bitmap::Key)> = tree
+        .items(&storage, &mut reader)
+        .unwrap()
+        .into_iter()
+        .map(|(name_identifier, value_block_id)| {
+            let range = storage.block_storage.items[value_block_id as usize];
+            reader.set_position(range.address as u64);
+            (name_identifier, bitmap::Key::read(&mut reader).unwrap())
+        })
+        .collect();
+    assert_eq!(written, expected);
+}
+
+#[test]
+fn bitmap_keys_derived_from_scales() {
+    let car = coreui::CarUtilAssetStorage::from("tests/Assets.car", false).unwrap();
+    let mut store = car.theme_store.store;
+    let expected = store.bitmapkeydb.take().unwrap();
+    assert_eq!(store.bitmap_keys(), expected);
+    assert_eq!(bitmap::Key::for_scales([1, 2, 3]).raw[10], 0b1110);
+}
@@ -0,0 +1,143 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use carutil_lib::budget;
+use carutil_lib::budget::Budget;
+use carutil_lib::coreui;
+
+fn store() -> coreui::CommonAssetStorage {
+    common::storage(
+        vec![common::facet("Small", 1), common::facet("Large", 2)],
+        vec![
+            (common::key(0, 1, 0, 1), common::image("Small.png", 4, 4)),
+            (common::key(0, 1, 0, 2), common::image("Large.png", 32, 32)),
+        ],
+        None,
+    )
+}
+
+#[test]
+fn parse_size() {
+    assert_eq!(budget::parse_size("1024").unwrap(), 1024);
+    assert_eq!(budget::parse_size("500KB").unwrap(), 500_000);
+    assert_eq!(budget::parse_size("1.5 MB").unwrap(), 1_500_000);
+    assert_eq!(budget::parse_size("2MiB").unwrap(), 2 << 20);
+    assert_eq!(budget::parse_size("20mb").unwrap(), 20_000_000);
+    assert!(budget::parse_size("12 parsecs").is_err());
+    assert!(budget::parse_size("KB").is_err());
+}
+
+#[test]
+fn largest_first() {
+    let report = budget::check_budget(&store(), &Budget::default());
+    let names: Vec<&str> = report
+        .largest
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(names, ["Large (Large.png)", "Small (Small.png)"]);
+    assert_eq!(
+        report.total,
+        report.largest.iter().map(|(_, size)| size).sum::<u64>()
+    );
+    assert!(report.is_ok());
+}
+
+#[test]
+fn within_budget() {
+    let budget = Budget {
+        max_asset_size: Some(100_000),
+        max_total: Some(100_000),
+        ..Default::default()
+    };
+    let report = budget::check_budget(&store(), &budget);
+    assert!(report.is_ok());
+    assert!(report.lines().is_empty());
+}
+
+#[test]
+fn asset_over_budget() {
+    let budget = Budget {
+        max_asset_size: Some(1000),
+        ..Default::default()
+    };
+    let report = budget::check_budget(&store(), &budget);
+    assert!(!report.is_ok());
+    assert_eq!(report.violations.len(), 1);
+    assert_eq!(report.violations[0].name, "Large (Large.png)");
+    assert_eq!(report.violations[0].limit, 1000);
+    assert_eq!(
+        report.lines(),
+        [format!(
+            "Large (Large.png): {} bytes, over the 1000 byte budget",
+            report.violations[0].size
+        )]
+    );
+}
+
+#[test]
+fn total_over_budget() {
+    let budget = Budget {
+        max_total: Some(1000),
+        ..Default::default()
+    };
+    let report = budget::check_budget(&store(), &budget);
+    assert!(!report.is_ok());
+    assert!(report.violations.is_empty());
+    assert_eq!(report.total_limit_exceeded, Some(1000));
+    assert_eq!(
+        report.lines(),
+        [format!(
+            "total: {} bytes, over the 1000 byte budget",
+            report.total
+        )]
+    );
+}
+
+#[test]
+fn overrides() {
+    let overrides = Budget::overrides_from_json(r#"{"Large": "1MB", "Small.png": 10}"#).unwrap();
+    assert_eq!(
+        overrides,
+        BTreeMap::from([
+            ("Large".to_string(), 1_000_000),
+            ("Small.png".to_string(), 10)
+        ])
+    );
+    let budget = Budget {
+        max_asset_size: Some(1000),
+        overrides,
+        ..Default::default()
+    };
+    let report = budget::check_budget(&store(), &budget);
+    let names: Vec<&str> = report
+        .violations
+        .iter()
+        .map(|violation| violation.name.as_str())
+        .collect();
+    assert_eq!(names, ["Small (Small.png)"]);
+    let budget = Budget {
+        max_asset_size: Some(1000),
+        overrides: BTreeMap::from([("small.PNG".to_string(), 10)]),
+        ..Default::default()
+    };
+    let over_budget = |budget: &Budget| -> Vec<String> {
+        budget::check_budget(&store(), budget)
+            .violations
+            .into_iter()
+            .map(|violation| violation.name)
+            .collect()
+    };
+    assert_eq!(
+        over_budget(&budget),
+        ["Large (Large.png)", "Small (Small.png)"]
+    );
+    let budget = Budget {
+        case_sensitive: true,
+        ..budget
+    };
+    assert_eq!(over_budget(&budget), ["Large (Large.png)"]);
+    assert!(Budget::overrides_from_json(r#"{"Large": true}"#).is_err());
+    assert!(Budget::overrides_from_json("[]").is_err());
+}
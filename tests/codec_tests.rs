@@ -0,0 +1,180 @@
+mod common;
+
+use carutil_lib::coreui::codec;
+use carutil_lib::coreui::csi;
+use carutil_lib::coreui::rendition::CompressionType;
+
+fn lzfse(data: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![];
+    lzfse_rust::encode_bytes(data, &mut encoded).expect("Unable to encode lzfse");
+    encoded
+}
+
+#[test]
+fn codec_uncompressed() {
+    let codec = codec::codec_for(CompressionType::Uncompressed).unwrap();
+    assert_eq!(codec.decode(b"abc", Some(3)).unwrap(), b"abc");
+    assert!(codec.decode(b"abc", Some(4)).is_err());
+}
+
+#[test]
+fn codec_lzfse() {
+    let data = b"hello hello hello hello hello".to_vec();
+    let codec = codec::codec_for(CompressionType::LZFSE).unwrap();
+    assert_eq!(codec.decode(&lzfse(&data), None).unwrap(), data);
+    assert_eq!(codec.decode(&lzfse(&data), Some(data.len())).unwrap(), data);
+    assert!(codec.decode(&lzfse(&data), Some(1)).is_err());
+    assert!(codec.decode(b"garbage", None).is_err());
+    // a size from a hostile header isn't reserved up front
+    assert!(codec.decode(&lzfse(&data), Some(1 << 40)).is_err());
+}
+
+#[test]
+fn codec_lzvn() {
+    // lzfse_rust writes inputs up to 4KiB as an lzvn block, the "bvxn" magic
+    // and then the lzvn stream, which is the container libcompression uses
+    let pixels: Vec<u8> = (0..16 * 16 * 4).map(|i| (i % 5) as u8).collect();
+    let payload = lzfse(&pixels);
+    assert_eq!(&payload[..4], b"bvxn");
+    assert_eq!(&payload[payload.len() - 4..], b"bvx$");
+    let codec = codec::codec_for(CompressionType::LZVN).unwrap();
+    assert_eq!(codec.decode(&payload, Some(pixels.len())).unwrap(), pixels);
+
+    // and the same payload in an LZVN rendition of a catalog
+    let car = common::fixtures::CatalogSpec::new()
+        .compressed_image(
+            "Small",
+            (16, 16),
+            csi::PixelFormat::ARGB,
+            CompressionType::LZVN,
+            &payload,
+        )
+        .open();
+    let csi_header = car.theme_store.store.imagedb.values().next().unwrap();
+    match csi_header.decode().unwrap() {
+        csi::DecodedRendition::Rgba {
+            pixels: decoded, ..
+        } => {
+            assert_eq!(decoded.len(), pixels.len())
+        }
+        _ => panic!("not rgba pixels"),
+    }
+}
+
+#[cfg(feature = "zlib")]
+#[test]
+fn codec_zip() {
+    use std::io::Write;
+
+    let data = b"zip zip zip zip zip".to_vec();
+    let codec = codec::codec_for(CompressionType::ZIP).unwrap();
+
+    let mut encoder =
+        flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&data).unwrap();
+    assert_eq!(
+        codec.decode(&encoder.finish().unwrap(), None).unwrap(),
+        data
+    );
+
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&data).unwrap();
+    assert_eq!(
+        codec.decode(&encoder.finish().unwrap(), None).unwrap(),
+        data
+    );
+}
+
+#[test]
+fn codec_astc() {
+    let data = vec![7u8; 64];
+    let mut payload = vec![0u8; 12];
+    payload.extend(lzfse(&data));
+    let codec = codec::codec_for(CompressionType::ASTC).unwrap();
+    assert_eq!(codec.decode(&payload, None).unwrap(), data);
+    assert!(codec.decode(&[0u8; 4], None).is_err());
+}
+
+#[test]
+fn codec_palette_img() {
+    // 2x2 image, two colors stored as BGRA in a little endian u32
+    let mut quantized = vec![];
+    quantized.extend(0xCAFEF00Du32.to_le_bytes());
+    quantized.extend(1u32.to_le_bytes());
+    quantized.extend(2u16.to_le_bytes());
+    quantized.extend(0x332211FFu32.to_le_bytes());
+    quantized.extend(0x00000000u32.to_le_bytes());
+    quantized.extend(0x0001u16.to_le_bytes());
+    quantized.extend(0x0100u16.to_le_bytes());
+
+    let codec = codec::codec_for(CompressionType::PaletteImg).unwrap();
+    let pixels = codec.decode(&lzfse(&quantized), Some(16)).unwrap();
+    // high byte of each index pair is the first pixel
+    assert_eq!(
+        pixels,
+        vec![0x11, 0x22, 0x33, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0, 0x11, 0x22, 0x33, 0xFF]
+    );
+    assert!(codec.decode(&lzfse(&quantized), None).is_err());
+}
+
+#[test]
+fn codec_palette_img_index_outside_color_table() {
+    // one color, but the second pixel uses index 5
+    let mut quantized = vec![];
+    quantized.extend(0xCAFEF00Du32.to_le_bytes());
+    quantized.extend(1u32.to_le_bytes());
+    quantized.extend(1u16.to_le_bytes());
+    quantized.extend(0x332211FFu32.to_le_bytes());
+    quantized.extend(0x0005u16.to_le_bytes());
+
+    let codec = codec::codec_for(CompressionType::PaletteImg).unwrap();
+    let err = codec.decode(&lzfse(&quantized), Some(8)).unwrap_err();
+    assert!(err.to_string().contains("color index 5"), "{}", err);
+    // a pixel count that isn't even leaves the last pixel out of the pairs
+    assert_eq!(
+        codec.decode(&lzfse(&quantized[..14]), Some(4)).unwrap(),
+        vec![0; 4]
+    );
+}
+
+#[test]
+fn codec_capabilities() {
+    let capabilities = codec::capabilities();
+    assert_eq!(capabilities.len(), CompressionType::ALL.len());
+    assert!(capabilities.contains(&(CompressionType::LZFSE, true)));
+    assert!(capabilities.contains(&(CompressionType::RLE, false)));
+}
+
+#[test]
+fn codec_decode_context_reuse() {
+    let codec = codec::codec_for(CompressionType::LZFSE).unwrap();
+    let mut context = codec::DecodeContext::with_max_retained_bytes(1 << 20);
+    let mut longest = 0;
+    for length in [1000, 10, 3000, 0, 2000] {
+        longest = longest.max(length);
+        let data: Vec<u8> = (0..length).map(|i| (i % 251) as u8).collect();
+        let encoded = lzfse(&data);
+        let decoded = codec
+            .decode_with(&mut context, &encoded, Some(length))
+            .unwrap();
+        assert_eq!(*decoded, data);
+        drop(decoded);
+        assert_eq!(codec.decode(&encoded, Some(length)).unwrap(), data);
+        // capacity is kept for the next rendition
+        assert!(context.retained_capacity() >= longest);
+    }
+
+    // scratch space past the bound is dropped along with the output
+    let data = vec![7u8; 2 << 20];
+    let encoded = lzfse(&data);
+    let decoded = codec.decode_with(&mut context, &encoded, None).unwrap();
+    assert_eq!(*decoded, data);
+    drop(decoded);
+    assert_eq!(context.retained_capacity(), 0);
+    assert!(codec.decode_with(&mut context, b"garbage", None).is_err());
+
+    // uncompressed payloads are handed back without a copy
+    let codec = codec::codec_for(CompressionType::Uncompressed).unwrap();
+    let decoded = codec.decode_with(&mut context, &data, None).unwrap();
+    assert_eq!(decoded.as_ptr(), data.as_ptr());
+}
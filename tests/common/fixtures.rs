@@ -0,0 +1,346 @@
+// catalogs generated from a spec with the write path, for tests that need
+// more than tests/Assets.car has. Each spec is written once under
+// CARGO_TARGET_TMPDIR, named after a hash of the spec, and reused by later
+// tests of the same build
+
+use sha2::Digest;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use carutil_lib::assetutil;
+use carutil_lib::coreui;
+use carutil_lib::coreui::csi;
+use carutil_lib::coreui::rendition;
+
+use super::compressed_data;
+use super::csi_header;
+use super::facet;
+use super::image;
+use super::key;
+use super::storage;
+
+// appearance ids used in keys, the same as the ones Xcode assigns
+pub const APPEARANCES: [(&str, u16); 4] = [
+    ("UIAppearanceAny", 0),
+    ("UIAppearanceDark", 1),
+    ("UIAppearanceHighContrastAny", 2),
+    ("UIAppearanceHighContrastDark", 3),
+];
+
+// (appearance, rgba components)
+pub type ColorVariant = (u16, [f64; 4]);
+
+#[derive(Debug, Clone, Default)]
+pub struct CatalogSpec {
+    images: Vec<ImageSet>,
+    data: Vec<(String, rendition::CompressionType, Vec<u8>)>,
+    compressed_images: Vec<CompressedImage>,
+    colors: Vec<(String, Vec<ColorVariant>)>,
+    icons: Vec<(String, Vec<(u32, u16)>)>,
+    layer_stacks: Vec<LayerStack>,
+    // (offset, bytes) written over the catalog after it's generated
+    patches: Vec<(usize, Vec<u8>)>,
+    truncate: Option<usize>,
+}
+
+// an image rendition whose payload is stored in a CELM container
+#[derive(Debug, Clone)]
+struct CompressedImage {
+    name: String,
+    width: u32,
+    height: u32,
+    pixel_format: csi::PixelFormat,
+    compression_type: rendition::CompressionType,
+    payload: Vec<u8>,
+}
+
+// a layered image (an LSR) and a 1x image set for each of its layers. The
+// layer stack container isn't understood, its payload is written as given
+#[derive(Debug, Clone)]
+struct LayerStack {
+    name: String,
+    width: u32,
+    height: u32,
+    layers: Vec<String>,
+    payload: Vec<u8>,
+}
+
+// one rendition per scale, idiom and appearance, each scale x scale pixels
+#[derive(Debug, Clone)]
+struct ImageSet {
+    name: String,
+    scales: Vec<u16>,
+    idioms: Vec<u16>,
+    appearances: Vec<u16>,
+}
+
+impl CatalogSpec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn images(
+        mut self,
+        name: &str,
+        scales: &[u16],
+        idioms: &[u16],
+        appearances: &[u16],
+    ) -> Self {
+        self.images.push(ImageSet {
+            name: name.to_string(),
+            scales: scales.to_vec(),
+            idioms: idioms.to_vec(),
+            appearances: appearances.to_vec(),
+        });
+        self
+    }
+
+    pub fn data(
+        mut self,
+        name: &str,
+        compression_type: rendition::CompressionType,
+        payload: &[u8],
+    ) -> Self {
+        self.data
+            .push((name.to_string(), compression_type, payload.to_vec()));
+        self
+    }
+
+    pub fn compressed_image(
+        mut self,
+        name: &str,
+        (width, height): (u32, u32),
+        pixel_format: csi::PixelFormat,
+        compression_type: rendition::CompressionType,
+        payload: &[u8],
+    ) -> Self {
+        self.compressed_images.push(CompressedImage {
+            name: name.to_string(),
+            width,
+            height,
+            pixel_format,
+            compression_type,
+            payload: payload.to_vec(),
+        });
+        self
+    }
+
+    pub fn color(mut self, name: &str, variants: &[ColorVariant]) -> Self {
+        self.colors.push((name.to_string(), variants.to_vec()));
+        self
+    }
+
+    // (size in points, idiom) entries of a multisize image set
+    pub fn icon_set(mut self, name: &str, sizes: &[(u32, u16)]) -> Self {
+        self.icons.push((name.to_string(), sizes.to_vec()));
+        self
+    }
+
+    pub fn layer_stack(
+        mut self,
+        name: &str,
+        (width, height): (u32, u32),
+        layers: &[&str],
+        payload: &[u8],
+    ) -> Self {
+        self.layer_stacks.push(LayerStack {
+            name: name.to_string(),
+            width,
+            height,
+            layers: layers.iter().map(|layer| layer.to_string()).collect(),
+            payload: payload.to_vec(),
+        });
+        self
+    }
+
+    pub fn patch(mut self, offset: usize, bytes: &[u8]) -> Self {
+        self.patches.push((offset, bytes.to_vec()));
+        self
+    }
+
+    pub fn truncate(mut self, length: usize) -> Self {
+        self.truncate = Some(length);
+        self
+    }
+
+    // the catalog before it's written, facet identifiers are assigned in the
+    // order the assets were added, starting at 1
+    pub fn storage(&self) -> coreui::CommonAssetStorage {
+        let appearancedb: BTreeMap<String, u32> = APPEARANCES
+            .iter()
+            .map(|(name, id)| (name.to_string(), *id as u32))
+            .collect();
+        let mut facets = vec![];
+        let mut renditions = vec![];
+        let mut identifier = 0;
+        let mut next_facet = |name: &str| {
+            identifier += 1;
+            facets.push(facet(name, identifier));
+            identifier
+        };
+
+        for set in &self.images {
+            let identifier = next_facet(&set.name);
+            for &scale in &set.scales {
+                for &idiom in &set.idioms {
+                    for &appearance in &set.appearances {
+                        let key = key(appearance, scale, idiom, identifier);
+                        let key_values = vec![
+                            (rendition::AttributeType::Appearance, appearance),
+                            (rendition::AttributeType::Scale, scale),
+                            (rendition::AttributeType::Idiom, idiom),
+                        ];
+                        let name = assetutil::synthesized_rendition_name(
+                            &set.name,
+                            &key_values,
+                            &appearancedb,
+                        );
+                        let mut csi_header =
+                            image(&format!("{}.png", name), scale as u32, scale as u32);
+                        csi_header.scale_factor = scale as u32 * 100;
+                        renditions.push((key, csi_header));
+                    }
+                }
+            }
+        }
+        for (name, compression_type, payload) in &self.data {
+            let identifier = next_facet(name);
+            renditions.push((
+                key(0, 0, 0, identifier),
+                compressed_data(name, *compression_type, payload),
+            ));
+        }
+        for image in &self.compressed_images {
+            let identifier = next_facet(&image.name);
+            let mut csi_header =
+                compressed_data(&image.name, image.compression_type, &image.payload);
+            csi_header.csimetadata.layout = rendition::LayoutType32::Image;
+            csi_header.width = image.width;
+            csi_header.height = image.height;
+            csi_header.pixel_format = image.pixel_format;
+            renditions.push((key(0, 1, 0, identifier), csi_header));
+        }
+        for (name, variants) in &self.colors {
+            let identifier = next_facet(name);
+            for (appearance, components) in variants {
+                let color = csi_header(
+                    rendition::LayoutType32::Color,
+                    "",
+                    0,
+                    0,
+                    csi::PixelFormat::None,
+                    vec![],
+                    Some(rendition::Rendition::Color {
+                        version: 1,
+                        flags: rendition::ColorFlags(0),
+                        component_count: 4,
+                        components: components.to_vec(),
+                    }),
+                );
+                renditions.push((key(*appearance, 0, 0, identifier), color));
+            }
+        }
+        for (name, sizes) in &self.icons {
+            let identifier = next_facet(name);
+            renditions.push((key(0, 0, 0, identifier), multisize_image(name, sizes)));
+        }
+        for stack in &self.layer_stacks {
+            let identifier = next_facet(&stack.name);
+            for name in &stack.layers {
+                let layer_identifier = next_facet(name);
+                renditions.push((
+                    key(0, 1, 0, layer_identifier),
+                    image(&format!("{}.png", name), stack.width, stack.height),
+                ));
+            }
+            let layer_stack = csi_header(
+                rendition::LayoutType32::LayerStack,
+                &stack.name,
+                stack.width,
+                stack.height,
+                csi::PixelFormat::None,
+                vec![],
+                Some(rendition::Rendition::Unknown {
+                    tag: u32::from_le_bytes(*b"SRYL"),
+                    version: 1,
+                    _raw_data_length: stack.payload.len() as u32,
+                    raw_data: carutil_lib::RawData(stack.payload.clone()),
+                }),
+            );
+            renditions.push((key(0, 1, 0, identifier), layer_stack));
+        }
+        storage(facets, renditions, Some(appearancedb))
+    }
+
+    // path of the generated catalog, written on first use. The test binary's
+    // build time is part of the hash so a changed write path writes it again
+    pub fn path(&self) -> PathBuf {
+        let built = std::env::current_exe()
+            .and_then(|exe| exe.metadata())
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let digest = hex::encode(Sha256::digest(format!("{:?} {:?}", self, built)));
+        let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("fixtures");
+        let path = directory.join(format!("{}.car", &digest[..16]));
+        if path.exists() {
+            return path;
+        }
+        std::fs::create_dir_all(&directory).unwrap();
+        // tests run in parallel, only a whole catalog is ever renamed into place
+        let partial = directory.join(format!(
+            "{}.{}.{:?}.partial",
+            &digest[..16],
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        let car = coreui::CarUtilAssetStorage {
+            theme_store: coreui::StructuredThemeStore {
+                store: self.storage(),
+            },
+        };
+        car.write_data(&partial).expect("Unable to write fixture");
+        if !self.patches.is_empty() || self.truncate.is_some() {
+            let mut bytes = std::fs::read(&partial).unwrap();
+            for (offset, patch) in &self.patches {
+                bytes[*offset..*offset + patch.len()].copy_from_slice(patch);
+            }
+            if let Some(length) = self.truncate {
+                bytes.truncate(length);
+            }
+            std::fs::write(&partial, bytes).unwrap();
+        }
+        std::fs::rename(&partial, &path).unwrap();
+        path
+    }
+
+    pub fn open(&self) -> coreui::CarUtilAssetStorage {
+        coreui::CarUtilAssetStorage::from(self.path(), false).expect("Unable to parse fixture")
+    }
+}
+
+// SISM rendition listing the sizes of an icon set
+fn multisize_image(name: &str, sizes: &[(u32, u16)]) -> csi::Header {
+    use binrw::BinRead;
+
+    let mut data = b"SISM".to_vec();
+    data.extend(1u32.to_le_bytes());
+    data.extend((sizes.len() as u32).to_le_bytes());
+    for (index, (size, idiom)) in sizes.iter().enumerate() {
+        data.extend(size.to_le_bytes());
+        data.extend(size.to_le_bytes());
+        data.extend((index as u16 + 1).to_le_bytes());
+        data.extend(idiom.to_le_bytes());
+    }
+    let rendition_data = rendition::Rendition::read_le(&mut std::io::Cursor::new(&data))
+        .expect("Unable to read multisize rendition");
+    csi_header(
+        rendition::LayoutType32::MultisizeImage,
+        name,
+        0,
+        0,
+        csi::PixelFormat::ARGB,
+        vec![],
+        Some(rendition_data),
+    )
+}
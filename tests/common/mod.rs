@@ -0,0 +1,289 @@
+// helpers for building synthetic renditions in tests
+#![allow(dead_code)]
+
+use binrw::BinWrite;
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use carutil_lib::coreui;
+use carutil_lib::coreui::csi;
+use carutil_lib::coreui::rendition;
+use carutil_lib::RawData;
+
+pub mod fixtures;
+
+pub fn csi_header(
+    layout: rendition::LayoutType32,
+    name: &str,
+    width: u32,
+    height: u32,
+    pixel_format: csi::PixelFormat,
+    tlv_data: Vec<u8>,
+    rendition_data: Option<rendition::Rendition>,
+) -> csi::Header {
+    let rendition_length = rendition_data
+        .as_ref()
+        .map(|rendition_data| {
+            let mut writer = Cursor::new(vec![]);
+            rendition_data
+                .write_le(&mut writer)
+                .expect("Unable to write rendition");
+            writer.into_inner().len() as u32
+        })
+        .unwrap_or(0);
+    csi::Header {
+        version: 1,
+        rendition_flags: csi::RenditionFlags(0),
+        width,
+        height,
+        scale_factor: 100,
+        pixel_format,
+        color_space: csi::ColorModel(0),
+        csimetadata: csi::Metadata::new(0, layout, name).unwrap(),
+        csibitmaplist: csi::BitmapList {
+            tlv_length: tlv_data.len() as u32,
+            bitmap_count: 1,
+            reserved: 0,
+            rendition_length,
+        },
+        tlv_data: RawData(tlv_data),
+        rendition_data,
+    }
+}
+
+pub fn raw_data(data: &[u8]) -> rendition::Rendition {
+    rendition::Rendition::RawData {
+        version: 1,
+        _raw_data_length: data.len() as u32,
+        raw_data: RawData(data.to_vec()),
+    }
+}
+
+// UTI tlv whose string is NUL padded out to padded_length bytes
+pub fn uti_tlv(uti: &str, padded_length: u32) -> Vec<u8> {
+    let mut string = uti.as_bytes().to_vec();
+    string.resize(padded_length as usize, 0);
+    let mut tlv = vec![];
+    tlv.extend(0x3EDu32.to_le_bytes());
+    tlv.extend((string.len() as u32 + 8).to_le_bytes());
+    tlv.extend(padded_length.to_le_bytes());
+    tlv.extend(0u32.to_le_bytes());
+    tlv.extend(string);
+    tlv
+}
+
+pub const KEY_FORMAT: [rendition::AttributeType; 5] = [
+    rendition::AttributeType::Appearance,
+    rendition::AttributeType::Scale,
+    rendition::AttributeType::Idiom,
+    rendition::AttributeType::Identifier,
+    rendition::AttributeType::State,
+];
+
+// key laid out according to KEY_FORMAT
+pub fn key(appearance: u16, scale: u16, idiom: u16, identifier: u16) -> rendition::Key {
+    let mut raw = [0u16; 18];
+    raw[..4].copy_from_slice(&[appearance, scale, idiom, identifier]);
+    rendition::Key { raw }
+}
+
+pub fn facet(name: &str, identifier: u16) -> (String, rendition::KeyToken) {
+    (
+        name.to_string(),
+        rendition::KeyToken::new(vec![rendition::Attribute {
+            name: rendition::AttributeType16::Identifier,
+            value: identifier,
+        }]),
+    )
+}
+
+pub fn image(name: &str, width: u32, height: u32) -> csi::Header {
+    csi_header(
+        rendition::LayoutType32::Image,
+        name,
+        width,
+        height,
+        csi::PixelFormat::ARGB,
+        vec![],
+        Some(raw_data(&vec![0u8; (width * height * 4) as usize])),
+    )
+}
+
+pub fn storage(
+    facets: Vec<(String, rendition::KeyToken)>,
+    renditions: Vec<(rendition::Key, csi::Header)>,
+    appearances: Option<BTreeMap<String, u32>>,
+) -> coreui::CommonAssetStorage {
+    coreui::CommonAssetStorage {
+        header: coreui::CarHeader::new(
+            802,
+            17,
+            0,
+            renditions.len() as u32,
+            "@(#)PROGRAM:CoreUI  PROJECT:CoreUI-802\n",
+            "Xcode 14.1 (14B47b) via ibtoold",
+            [0; 16],
+            0,
+            5,
+            0,
+            2,
+        )
+        .unwrap(),
+        extended_metadata: coreui::CarExtendedMetadata::new("", "16.0", "ios", "carutil tests")
+            .unwrap(),
+        renditionkeyfmt: rendition::KeyFormat::new(KEY_FORMAT.to_vec()),
+        rendition_sha_digests: BTreeMap::new(),
+        imagedb: renditions.into_iter().collect(),
+        facetkeysdb: facets.into_iter().collect(),
+        raw_facet_names: BTreeMap::new(),
+        bitmapkeydb: None,
+        appearancedb: appearances,
+        file_timestamp: None,
+    }
+}
+
+// data asset whose payload is stored in a CELM container with compression_type
+pub fn compressed_data(
+    name: &str,
+    compression_type: rendition::CompressionType,
+    payload: &[u8],
+) -> csi::Header {
+    csi_header(
+        rendition::LayoutType32::Data,
+        name,
+        0,
+        0,
+        csi::PixelFormat::Data,
+        vec![],
+        Some(rendition::Rendition::Theme {
+            version: 1,
+            compression_type,
+            _raw_data_length: payload.len() as u32,
+            raw_data: RawData(payload.to_vec()),
+        }),
+    )
+}
+
+// key format of symbol catalogs, with the glyph weight and size after the
+// attributes of KEY_FORMAT
+pub const SYMBOL_KEY_FORMAT: [rendition::AttributeType; 7] = [
+    rendition::AttributeType::Appearance,
+    rendition::AttributeType::Scale,
+    rendition::AttributeType::Idiom,
+    rendition::AttributeType::Identifier,
+    rendition::AttributeType::State,
+    rendition::AttributeType::GlyphWeight,
+    rendition::AttributeType::GlyphSize,
+];
+
+pub fn symbol_key(identifier: u16, weight: u16, size: u16) -> rendition::Key {
+    let mut raw = [0u16; 18];
+    raw[3] = identifier;
+    raw[5] = weight;
+    raw[6] = size;
+    rendition::Key { raw }
+}
+
+pub const SYMBOL_SVG: &[u8] =
+    b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>\n";
+
+// a catalog with one symbol, "star.fill": regular and bold svg variants and a
+// black one whose payload is in a format we don't read
+pub fn symbol_storage() -> coreui::CommonAssetStorage {
+    let vector = |name: &str, data: &[u8]| {
+        csi_header(
+            rendition::LayoutType32::Vector,
+            name,
+            0,
+            0,
+            csi::PixelFormat::Data,
+            vec![],
+            Some(raw_data(data)),
+        )
+    };
+    let mut store = storage(
+        vec![facet("star.fill", 7)],
+        vec![
+            (symbol_key(7, 4, 2), vector("star.fill.svg", SYMBOL_SVG)),
+            (symbol_key(7, 7, 2), vector("star.fill.svg", SYMBOL_SVG)),
+            (
+                symbol_key(7, 9, 3),
+                vector("star.fill.svg", b"CSVG\x02\x00\x00\x00"),
+            ),
+        ],
+        None,
+    );
+    store.renditionkeyfmt = rendition::KeyFormat::new(SYMBOL_KEY_FORMAT.to_vec());
+    store
+}
+
+// two facets sharing name identifier 5, told apart by the idiom in their
+// tokens: an iPhone and an iPad rendition, and a universal one that could
+// belong to either
+pub fn colliding_facets_storage() -> coreui::CommonAssetStorage {
+    let facet = |name: &str, idiom: u16| {
+        (
+            name.to_string(),
+            rendition::KeyToken::new(vec![
+                rendition::Attribute {
+                    name: rendition::AttributeType16::Identifier,
+                    value: 5,
+                },
+                rendition::Attribute {
+                    name: rendition::AttributeType16::Idiom,
+                    value: idiom,
+                },
+            ]),
+        )
+    };
+    storage(
+        vec![facet("Alpha", 1), facet("Beta", 2)],
+        vec![
+            (key(0, 1, 1, 5), image("Alpha.png", 1, 1)),
+            (key(0, 1, 2, 5), image("Beta.png", 1, 1)),
+            (key(0, 1, 0, 5), image("Shared.png", 1, 1)),
+        ],
+        None,
+    )
+}
+
+// an empty directory under the system temp dir, removed again when dropped.
+// The name gets the process id and a counter so tests running in parallel
+// never share one
+pub struct TempDir(PathBuf);
+
+pub fn temp_dir(name: &str) -> TempDir {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "carutil-{}-{}-{}",
+        name,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir_all(&path).unwrap();
+    TempDir(path)
+}
+
+impl std::ops::Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
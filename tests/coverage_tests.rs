@@ -0,0 +1,142 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use carutil_lib::coreui::rendition::AttributeType;
+use carutil_lib::coverage;
+use carutil_lib::filter;
+use carutil_lib::filter::AttributeRequirement;
+
+fn appearances() -> BTreeMap<String, u32> {
+    BTreeMap::from([
+        ("UIAppearanceAny".to_string(), 0),
+        ("UIAppearanceDark".to_string(), 1),
+    ])
+}
+
+#[test]
+fn requirement_parse() {
+    let appearancedb = appearances();
+    let parse = |expression| AttributeRequirement::parse(expression, &appearancedb);
+    assert_eq!(
+        parse("appearance=dark").unwrap(),
+        AttributeRequirement {
+            attribute: AttributeType::Appearance,
+            value: 1
+        }
+    );
+    assert_eq!(parse("Idiom=pad").unwrap().value, 2);
+    assert_eq!(parse("idiom=iphone").unwrap().value, 1);
+    assert_eq!(parse("scale=3x").unwrap().value, 3);
+    assert_eq!(parse("NameIdentifier=44959").unwrap().value, 44959);
+    assert!(parse("appearance=sepia").is_err());
+    assert!(parse("colour=red").is_err());
+    assert!(parse("idiom").is_err());
+}
+
+#[test]
+fn coverage_missing_dark_variant() {
+    let store = common::storage(
+        vec![
+            common::facet("Background", 1),
+            common::facet("Chevron", 2),
+            common::facet("Accent", 3),
+        ],
+        vec![
+            (
+                common::key(0, 2, 0, 1),
+                common::image("Background.png", 1, 1),
+            ),
+            (
+                common::key(1, 2, 0, 1),
+                common::image("Background.png", 1, 1),
+            ),
+            (common::key(0, 2, 0, 2), common::image("Chevron.png", 1, 1)),
+            (common::key(0, 3, 0, 2), common::image("Chevron.png", 1, 1)),
+        ],
+        Some(appearances()),
+    );
+
+    let attributes = vec![AttributeRequirement::parse("appearance=dark", &appearances()).unwrap()];
+    let report = coverage::coverage(
+        &store,
+        &filter::RenditionFilter {
+            attributes,
+            ..Default::default()
+        },
+    );
+    assert_eq!(report.checked, 2);
+    assert_eq!(report.missing, vec!["Chevron"]);
+
+    let report = coverage::coverage(&store, &filter::RenditionFilter::default());
+    assert!(report.missing.is_empty());
+}
+
+#[test]
+fn requirement_parse_raw_attributes() {
+    let appearancedb = appearances();
+    let parse = |expression| AttributeRequirement::parse(expression, &appearancedb);
+    // Display names and raw ids name the same attribute
+    assert_eq!(parse("NameIdentifier=3").unwrap(), parse("17=3").unwrap());
+    assert_eq!(parse("scale=2").unwrap(), parse("12=2").unwrap());
+    assert_eq!(
+        parse("13=7").unwrap(),
+        AttributeRequirement {
+            attribute: AttributeType::Unknown13,
+            value: 7
+        }
+    );
+    // raw ids still accept the attribute's spellings
+    assert_eq!(parse("15=pad").unwrap().value, 2);
+
+    let err = parse("bogus=1").unwrap_err().to_string();
+    assert!(err.contains("Idiom, Subtype, NameIdentifier"), "{}", err);
+    // ids newer than this build are matched as Unknown
+    assert_eq!(parse("99=1").unwrap().attribute, AttributeType::Unknown(99));
+    assert!(parse("idiom").is_err());
+    assert!(parse("scale=big").is_err());
+}
+
+#[test]
+fn coverage_appearance_axes() {
+    let appearancedb = BTreeMap::from([
+        ("UIAppearanceAny".to_string(), 0),
+        ("UIAppearanceDark".to_string(), 1),
+        ("UIAppearanceHighContrastAny".to_string(), 2),
+        ("UIAppearanceHighContrastDark".to_string(), 3),
+    ]);
+    let store = common::storage(
+        vec![common::facet("Background", 1), common::facet("Chevron", 2)],
+        vec![
+            (
+                common::key(0, 2, 0, 1),
+                common::image("Background.png", 1, 1),
+            ),
+            (
+                common::key(3, 2, 0, 1),
+                common::image("Background.png", 1, 1),
+            ),
+            (common::key(0, 2, 0, 2), common::image("Chevron.png", 1, 1)),
+            (common::key(1, 2, 0, 2), common::image("Chevron.png", 1, 1)),
+        ],
+        Some(appearancedb.clone()),
+    );
+    let missing = |expressions: &[&str]| {
+        let expressions: Vec<String> = expressions.iter().map(|e| e.to_string()).collect();
+        let filter = filter::RenditionFilter::parse(&expressions, &appearancedb).unwrap();
+        coverage::coverage(&store, &filter).missing
+    };
+    // dark high contrast is dark, but not the dark appearance
+    assert!(missing(&["luminosity=dark"]).is_empty());
+    assert_eq!(missing(&["appearance=dark"]), vec!["Background"]);
+    assert_eq!(missing(&["contrast=high"]), vec!["Chevron"]);
+    assert_eq!(
+        missing(&["luminosity=dark", "contrast=normal"]),
+        vec!["Background"]
+    );
+    assert!(missing(&["luminosity=any", "contrast=normal"]).is_empty());
+    assert_eq!(missing(&["appearance=dark-highcontrast"]), vec!["Chevron"]);
+
+    let expressions = vec!["contrast=extreme".to_string()];
+    assert!(filter::RenditionFilter::parse(&expressions, &appearancedb).is_err());
+}
@@ -0,0 +1,447 @@
+use binrw::BinRead;
+use binrw::BinWrite;
+use std::io::Cursor;
+
+use carutil_lib::coreui;
+use carutil_lib::coreui::csi;
+use carutil_lib::coreui::csi::PixelFormat;
+use carutil_lib::coreui::rendition;
+
+use serde_json::json;
+
+static CAR_PATH: &str = "./tests/Assets.car";
+
+fn write_header(csi_header: &csi::Header) -> Vec<u8> {
+    let mut writer = Cursor::new(vec![]);
+    csi_header
+        .write(&mut writer)
+        .expect("Unable to write csi header");
+    writer.into_inner()
+}
+
+#[test]
+fn pixel_format_known_variants() {
+    assert_eq!(PixelFormat::from_fourcc(0x41524742), PixelFormat::ARGB);
+    assert_eq!(PixelFormat::from_fourcc(0x47413820), PixelFormat::Gray);
+    assert_eq!(
+        serde_json::to_value(PixelFormat::ARGB).unwrap(),
+        json!("ARGB")
+    );
+    assert_eq!(
+        serde_json::to_value(PixelFormat::JPEG).unwrap(),
+        json!("JPEG")
+    );
+    assert_eq!(
+        serde_json::to_value(PixelFormat::Gray).unwrap(),
+        json!("Gray")
+    );
+}
+
+#[test]
+fn pixel_format_printable_fourcc() {
+    let rgb5 = PixelFormat::from_fourcc(u32::from_be_bytes(*b"RGB5"));
+    assert_eq!(rgb5, PixelFormat::Unknown(0x52474235));
+    assert_eq!(serde_json::to_value(rgb5).unwrap(), json!("RGB5"));
+
+    let cmyk = PixelFormat::from_fourcc(u32::from_be_bytes(*b"CMYK"));
+    assert_eq!(serde_json::to_value(cmyk).unwrap(), json!("CMYK"));
+}
+
+#[test]
+fn pixel_format_non_printable_fourcc() {
+    let pixel_format = PixelFormat::from_fourcc(0x00000012);
+    assert_eq!(pixel_format.fourcc_string(), None);
+    assert_eq!(
+        serde_json::to_value(pixel_format).unwrap(),
+        json!("0x00000012")
+    );
+}
+
+#[test]
+fn pixel_format_from_str() {
+    assert_eq!("ARGB".parse::<PixelFormat>().unwrap(), PixelFormat::ARGB);
+    assert_eq!("jpeg".parse::<PixelFormat>().unwrap(), PixelFormat::JPEG);
+    assert_eq!("GA8".parse::<PixelFormat>().unwrap(), PixelFormat::Gray);
+    assert_eq!("GA16".parse::<PixelFormat>().unwrap(), PixelFormat::Gray16);
+    assert_eq!(
+        "RGB5".parse::<PixelFormat>().unwrap(),
+        PixelFormat::Unknown(0x52474235)
+    );
+    assert_eq!(
+        "0x00000012".parse::<PixelFormat>().unwrap(),
+        PixelFormat::Unknown(0x12)
+    );
+    assert!("not a format".parse::<PixelFormat>().is_err());
+    assert!("".parse::<PixelFormat>().is_err());
+}
+
+#[test]
+fn header_round_trip_preserves_tlv() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    for csi_header in asset_storage.theme_store.store.imagedb.values() {
+        let bytes = write_header(csi_header);
+        assert_eq!(
+            bytes.len() as u32,
+            184 + csi_header.csibitmaplist.tlv_length + csi_header.csibitmaplist.rendition_length
+        );
+        let reread = csi::Header::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(reread.tlv_data, csi_header.tlv_data);
+        assert_eq!(reread.rendition_data, csi_header.rendition_data);
+    }
+}
+
+#[test]
+fn header_set_payload() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from(CAR_PATH, false).expect("Unable to parse Assets.car");
+    let original = asset_storage
+        .theme_store
+        .store
+        .imagedb
+        .values()
+        .find(|csi_header| csi_header.csimetadata.name() == "Timac@3x.png")
+        .expect("No rendition found");
+    let payload = match &original.rendition_data {
+        Some(rendition::Rendition::Theme { raw_data, .. }) => raw_data.0.clone(),
+        _ => panic!("expected a CELM rendition"),
+    };
+
+    // same payload, identical bytes
+    let mut csi_header = original.clone();
+    csi_header
+        .set_payload(&payload, rendition::CompressionType::PaletteImg)
+        .unwrap();
+    assert_eq!(write_header(&csi_header), write_header(original));
+
+    // new payload, only the rendition length changes
+    let mut csi_header = original.clone();
+    csi_header
+        .set_payload(b"new payload", rendition::CompressionType::LZFSE)
+        .unwrap();
+    assert_eq!(
+        csi_header.csibitmaplist.rendition_length + payload.len() as u32,
+        original.csibitmaplist.rendition_length + 11
+    );
+    assert_eq!(
+        csi_header.csibitmaplist.tlv_length,
+        original.csibitmaplist.tlv_length
+    );
+
+    let reread = csi::Header::read(&mut Cursor::new(write_header(&csi_header))).unwrap();
+    assert_eq!(reread.tlv_data, original.tlv_data);
+    match reread.rendition_data {
+        Some(rendition::Rendition::Theme {
+            compression_type,
+            raw_data,
+            ..
+        }) => {
+            assert_eq!(compression_type, rendition::CompressionType::LZFSE);
+            assert_eq!(raw_data.0, b"new payload");
+        }
+        _ => panic!("expected a CELM rendition"),
+    }
+}
+
+fn theme_image(
+    width: u32,
+    height: u32,
+    compression_type: rendition::CompressionType,
+    payload: &[u8],
+) -> csi::Header {
+    let mut csi_header = coreui::CarUtilAssetStorage::from(CAR_PATH, false)
+        .unwrap()
+        .theme_store
+        .store
+        .imagedb
+        .into_values()
+        .find(|csi_header| csi_header.csimetadata.name() == "Timac@3x.png")
+        .expect("No rendition found");
+    csi_header.width = width;
+    csi_header.height = height;
+    csi_header.set_payload(payload, compression_type).unwrap();
+    csi_header
+}
+
+#[test]
+fn image_size_overflowing_u32() {
+    let csi_header = theme_image(
+        0x10000,
+        0x10000,
+        rendition::CompressionType::PaletteImg,
+        b"bvx2",
+    );
+    assert!(csi_header.rgba_pixels().is_err());
+    assert!(csi_header.extracted_file().is_err());
+    // opacity falls back to the flags when the palette can't be read
+    assert_eq!(
+        csi_header.is_opaque(),
+        csi_header.rendition_flags.is_opaque()
+    );
+}
+
+#[test]
+fn hevc_images_fail_to_decode() {
+    // short or not, the bitstream isn't written as if it were the image
+    for payload in [
+        &b"hev"[..],
+        b"\x01\x00\x00\x00\x10\x00\x00\x00\x00\x00\x00\x01\x40\x01",
+    ] {
+        let csi_header = theme_image(1, 1, rendition::CompressionType::HEVC, payload);
+        let err = csi_header
+            .extracted_file()
+            .err()
+            .expect("hevc payload extracted");
+        assert_eq!(
+            err.to_string(),
+            "no decoder for hevc compressed image \"Timac@3x.png\", extract it with --raw"
+        );
+    }
+}
+
+#[test]
+fn raw_data_longer_than_input() {
+    // a DWAR claiming almost 4 GiB followed by 4 bytes
+    let mut data = b"DWAR".to_vec();
+    data.extend(1u32.to_le_bytes());
+    data.extend(0xFFFF_FFF0u32.to_le_bytes());
+    data.extend(b"data");
+    let err = rendition::Rendition::read_le(&mut Cursor::new(&data)).unwrap_err();
+    assert!(format!("{:?}", err).contains("only 4 left"), "{:?}", err);
+}
+
+fn lzfse(data: &[u8]) -> Vec<u8> {
+    let mut compressed = vec![];
+    lzfse_rust::encode_bytes(data, &mut compressed).unwrap();
+    compressed
+}
+
+#[test]
+fn lzfse_bitmaps_extracted_as_png() {
+    // two premultiplied BGRA pixels
+    let bgra = [0x10, 0x20, 0x30, 0xFF, 0x00, 0x40, 0x00, 0x80];
+    let csi_header = theme_image(2, 1, rendition::CompressionType::LZFSE, &lzfse(&bgra));
+    assert_eq!(
+        csi_header.rgba_pixels().unwrap(),
+        vec![0x30, 0x20, 0x10, 0xFF, 0x00, 0x40, 0x00, 0x80]
+    );
+    let extracted_file = csi_header.extracted_file().unwrap().unwrap();
+    assert_eq!(extracted_file.format, csi::OutputFormat::Png);
+    assert_eq!(
+        csi::decode_png(&extracted_file.data).unwrap(),
+        (2, 1, csi_header.rgba_pixels().unwrap())
+    );
+
+    // gray and alpha
+    let mut gray = theme_image(
+        2,
+        1,
+        rendition::CompressionType::LZFSE,
+        &lzfse(&[9, 255, 7, 0]),
+    );
+    gray.pixel_format = PixelFormat::Gray;
+    let extracted_file = gray.extracted_file().unwrap().unwrap();
+    let reader = png::Decoder::new(&extracted_file.data[..])
+        .read_info()
+        .unwrap();
+    assert_eq!(reader.info().color_type, png::ColorType::GrayscaleAlpha);
+    assert_eq!(gray.rgba_pixels().unwrap(), vec![9, 9, 9, 255, 7, 7, 7, 0]);
+
+    // one pixel short
+    let short = theme_image(3, 1, rendition::CompressionType::LZFSE, &lzfse(&bgra));
+    let err = short
+        .extracted_file()
+        .err()
+        .expect("short bitmap extracted");
+    assert!(
+        format!("{:#}", err).contains("a 3x1 ARGB image is 12 bytes"),
+        "{:#}",
+        err
+    );
+}
+
+#[test]
+fn deepmap_renditions() {
+    for (compression_type, name) in [
+        (rendition::CompressionType::DeepMapLZFSE, "deepmap-lzfse"),
+        (rendition::CompressionType::DeepMap2, "deepmap2"),
+    ] {
+        let csi_header = theme_image(2, 2, compression_type, b"dmp2");
+        let entry = carutil_lib::assetutil::AssetUtilEntry::from_csi_header(
+            &csi_header,
+            Some("Timac".to_string()),
+            vec![],
+            vec![],
+            &std::collections::BTreeMap::new(),
+        );
+        assert_eq!(serde_json::to_value(entry).unwrap()["Compression"], name);
+        let err = csi_header
+            .extracted_file()
+            .err()
+            .expect("deepmap image extracted");
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "no decoder for {} compressed image \"Timac@3x.png\", extract it with --raw",
+                name
+            )
+        );
+    }
+}
+
+#[test]
+fn raw_bitmaps_extracted_as_png() {
+    let raw = |pixel_format, width, height, data: &[u8]| {
+        let mut csi_header = theme_image(width, height, rendition::CompressionType::LZFSE, b"");
+        csi_header.pixel_format = pixel_format;
+        csi_header.rendition_data = Some(rendition::Rendition::RawData {
+            version: 1,
+            _raw_data_length: data.len() as u32,
+            raw_data: carutil_lib::RawData(data.to_vec()),
+        });
+        csi_header
+    };
+    // 1x2 BGRA, rows padded to 8 bytes
+    let padded = raw(
+        PixelFormat::ARGB,
+        1,
+        2,
+        &[1, 2, 3, 255, 0, 0, 0, 0, 4, 5, 6, 128, 0, 0, 0, 0],
+    );
+    let extracted_file = padded.extracted_file().unwrap().unwrap();
+    assert_eq!(extracted_file.format, csi::OutputFormat::Png);
+    assert_eq!(
+        csi::decode_png(&extracted_file.data).unwrap(),
+        (1, 2, vec![3, 2, 1, 255, 6, 5, 4, 128])
+    );
+    assert_eq!(
+        padded.rgba_pixels().unwrap(),
+        vec![3, 2, 1, 255, 6, 5, 4, 128]
+    );
+
+    let gray = raw(PixelFormat::Gray, 2, 1, &[9, 255, 7, 0]);
+    let extracted_file = gray.extracted_file().unwrap().unwrap();
+    let reader = png::Decoder::new(&extracted_file.data[..])
+        .read_info()
+        .unwrap();
+    assert_eq!(reader.info().color_type, png::ColorType::GrayscaleAlpha);
+
+    // whole files are written as stored
+    let png = extracted_file.data.clone();
+    let embedded = raw(PixelFormat::ARGB, 2, 1, &png);
+    assert_eq!(embedded.extracted_file().unwrap().unwrap().data, png);
+
+    let short = raw(PixelFormat::ARGB, 2, 2, &[0; 12]);
+    let err = short
+        .extracted_file()
+        .err()
+        .expect("short bitmap extracted");
+    assert_eq!(
+        err.to_string(),
+        "\"Timac@3x.png\" is a 2x2 ARGB image but has 12 bytes of pixels"
+    );
+}
+
+#[test]
+fn renditions_decoded_in_memory() {
+    let bgra = [0x10, 0x20, 0x30, 0xFF, 0x00, 0x40, 0x00, 0x80];
+    let csi_header = theme_image(2, 1, rendition::CompressionType::LZFSE, &lzfse(&bgra));
+    match csi_header.decode().unwrap() {
+        csi::DecodedRendition::Rgba {
+            width,
+            height,
+            pixels,
+        } => {
+            assert_eq!((width, height), (2, 1));
+            assert_eq!(pixels, vec![0x30, 0x20, 0x10, 0xFF, 0x00, 0x40, 0x00, 0x80]);
+        }
+        _ => panic!("expected rgba pixels"),
+    }
+
+    let mut gray = theme_image(
+        2,
+        1,
+        rendition::CompressionType::LZFSE,
+        &lzfse(&[9, 255, 7, 0]),
+    );
+    gray.pixel_format = PixelFormat::Gray;
+    assert!(matches!(
+        gray.decode().unwrap(),
+        csi::DecodedRendition::GrayAlpha { pixels, .. } if pixels == [9, 255, 7, 0]
+    ));
+
+    let jpeg = b"\xFF\xD8\xFF\xE0jfif";
+    let csi_header = theme_image(1, 1, rendition::CompressionType::JPEGLZFSE, &lzfse(jpeg));
+    assert!(matches!(
+        csi_header.decode().unwrap(),
+        csi::DecodedRendition::Jpeg(data) if data == jpeg
+    ));
+    let err = csi_header.rgba_pixels().unwrap_err();
+    assert!(err.to_string().contains("no jpeg decoder"), "{}", err);
+
+    let csi_header = theme_image(
+        1,
+        1,
+        rendition::CompressionType::JPEGLZFSE,
+        &lzfse(b"not a jpeg"),
+    );
+    let decoded = csi_header.decode().unwrap();
+    assert!(matches!(&decoded, csi::DecodedRendition::Raw(data) if data == b"not a jpeg"));
+    // extract writes what decode returns
+    let extracted_file = decoded.into_extracted_file("Broken.jpg").unwrap();
+    assert_eq!(extracted_file.format, csi::OutputFormat::Bin);
+    assert_eq!(
+        extracted_file.warning.as_deref(),
+        Some("Broken.jpg: jpeg-lzfse payload isn't a jpeg (starts with 6e6f742061206a70), wrote the decoded bytes")
+    );
+
+    let csi_header = theme_image(1, 1, rendition::CompressionType::HEVC, b"01234567hvc");
+    assert!(csi_header.decode().is_err());
+
+    // the payload limit applies to decode too
+    let options = coreui::ParseOptions {
+        max_payload_bytes: Some(1),
+        ..Default::default()
+    };
+    let err = csi_header
+        .decode_with_options(&options)
+        .err()
+        .expect("payload over the limit decoded");
+    assert!(matches!(
+        err.downcast_ref::<coreui::CarError>(),
+        Some(coreui::CarError::PayloadTooLarge { .. })
+    ));
+}
+
+#[test]
+fn unpremultiplied_pixels() {
+    // half transparent red, transparent with stray color, and opaque
+    let mut rgba = [
+        0x80, 0x00, 0x40, 0x80, 0x12, 0x34, 0x56, 0x00, 0x10, 0x20, 0x30, 0xFF,
+    ];
+    csi::unpremultiply(&mut rgba, 4);
+    assert_eq!(
+        rgba,
+        [0xFF, 0x00, 0x80, 0x80, 0x00, 0x00, 0x00, 0x00, 0x10, 0x20, 0x30, 0xFF]
+    );
+    // components above alpha in a corrupt payload are clamped
+    let mut gray = [0x40, 0x20];
+    csi::unpremultiply(&mut gray, 2);
+    assert_eq!(gray, [0xFF, 0x20]);
+
+    let bgra = [0x00, 0x00, 0x40, 0x80, 0x10, 0x20, 0x30, 0xFF];
+    let csi_header = theme_image(2, 1, rendition::CompressionType::LZFSE, &lzfse(&bgra));
+    assert_eq!(
+        csi_header.unpremultiplied_rgba_pixels().unwrap(),
+        vec![0x80, 0x00, 0x00, 0x80, 0x30, 0x20, 0x10, 0xFF]
+    );
+    assert!(matches!(
+        csi_header.decode().unwrap().unpremultiplied(),
+        csi::DecodedRendition::Rgba { pixels, .. } if pixels == [0x80, 0x00, 0x00, 0x80, 0x30, 0x20, 0x10, 0xFF]
+    ));
+    // premultiplied stays the default
+    assert_eq!(
+        csi_header.rgba_pixels().unwrap(),
+        vec![0x40, 0x00, 0x00, 0x80, 0x30, 0x20, 0x10, 0xFF]
+    );
+}
@@ -0,0 +1,150 @@
+mod common;
+
+use carutil_lib::actool;
+use carutil_lib::assetutil;
+use carutil_lib::coreui;
+use carutil_lib::dataset;
+use carutil_lib::extract;
+use carutil_lib::versions;
+
+const CONFIG: &[u8] = br#"{"retries": 3, "timeout": 30, "endpoints": ["a", "b", "c"]}"#;
+
+// Config.dataset with config.json, tagged for on-demand resources
+fn write_dataset(directory: &std::path::Path) -> std::path::PathBuf {
+    let path = directory.join("Config.dataset");
+    std::fs::create_dir_all(&path).unwrap();
+    std::fs::write(path.join("config.json"), CONFIG).unwrap();
+    std::fs::write(
+        path.join("Contents.json"),
+        r#"{
+  "data" : [
+    {
+      "filename" : "config.json",
+      "idiom" : "universal",
+      "universal-type-identifier" : "public.json"
+    }
+  ],
+  "info" : { "author" : "xcode", "version" : 1 },
+  "properties" : { "on-demand-resource-tags" : [ "level1" ] }
+}"#,
+    )
+    .unwrap();
+    path
+}
+
+#[test]
+fn read_dataset() {
+    let directory = common::temp_dir("dataset");
+    let dataset = dataset::read_dataset(&write_dataset(&directory));
+
+    let dataset = dataset.unwrap();
+    assert_eq!(dataset.name, "Config");
+    assert_eq!(dataset.tags, vec!["level1"]);
+    assert_eq!(dataset.files.len(), 1);
+    assert_eq!(dataset.files[0].file_name, "config.json");
+    assert_eq!(dataset.files[0].uti, "public.json");
+    assert_eq!(dataset.files[0].idiom, coreui::rendition::Idiom::Universal);
+    assert_eq!(dataset.files[0].data, CONFIG);
+}
+
+#[test]
+fn tagged_datasets_fail_to_compile() {
+    let directory = common::temp_dir("dataset-tags");
+    let document = directory.join("Assets.xcassets");
+    write_dataset(&document);
+    std::fs::write(
+        document.join("Contents.json"),
+        r#"{"info" : { "author" : "xcode", "version" : 1 }}"#,
+    )
+    .unwrap();
+    let error = actool::compile(
+        &document,
+        &directory,
+        &versions::ToolVersions::default(),
+        false,
+        false,
+    )
+    .unwrap_err();
+    let written = directory.join("Assets.car").exists();
+    assert_eq!(
+        error.to_string(),
+        "Config.dataset has on-demand-resource tags [\"level1\"], writing them isn't supported"
+    );
+    assert!(!written);
+}
+
+#[test]
+fn compiled_dataset_round_trips() {
+    for compress in [false, true] {
+        let directory = common::temp_dir(&format!("dataset-{}", compress));
+        let dataset = dataset::read_dataset(&write_dataset(&directory)).unwrap();
+        let mut store = common::storage(vec![], vec![], None);
+        store.renditionkeyfmt = coreui::rendition::KeyFormat::standard();
+        dataset::add_datasets(&mut store, &[dataset], compress, false).unwrap();
+        let car = coreui::CarUtilAssetStorage {
+            theme_store: coreui::StructuredThemeStore { store },
+        };
+        let car_path = directory.join("Assets.car");
+        car.write_data(car_path.to_str().unwrap()).unwrap();
+
+        let read = coreui::CarUtilAssetStorage::from(car_path.to_str().unwrap(), false).unwrap();
+        let output_path = directory.join("out");
+        std::fs::create_dir_all(&output_path).unwrap();
+        let summary = extract::extract_all(
+            &read,
+            output_path.to_str().unwrap(),
+            &coreui::ParseOptions::default(),
+            &extract::ExtractOptions::default(),
+        )
+        .unwrap();
+        let extracted = std::fs::read(output_path.join("config.json")).unwrap();
+
+        let store = &read.theme_store.store;
+        let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(store);
+        assert_eq!(entries.len(), 1);
+        let entry = serde_json::to_value(&entries[0]).unwrap();
+        assert_eq!(entry["AssetType"], "Data");
+        assert_eq!(entry["Name"], "Config");
+        assert_eq!(entry["UTI"], "public.json");
+        assert_eq!(entry["Data Length"], CONFIG.len());
+        assert_eq!(
+            entry["Compression"],
+            if compress { "lzfse" } else { "uncompressed" }
+        );
+        let csi_header = store.imagedb.values().next().unwrap();
+        // the on-demand-resource tags aren't written, their tlv isn't known
+        assert!(!csi_header
+            .properties()
+            .into_iter()
+            .any(|property| matches!(property, coreui::tlv::RenditionType::Unknown { .. })));
+        assert_eq!(summary.written, vec!["config.json"]);
+        assert_eq!(extracted, CONFIG);
+    }
+}
+
+#[test]
+fn long_file_names() {
+    let file_name = format!("{}.json", "é".repeat(70));
+    let dataset = dataset::DataSet {
+        name: "Long".to_string(),
+        tags: vec![],
+        cursor_hotspot: None,
+        files: vec![dataset::DataSetFile {
+            file_name: file_name.clone(),
+            idiom: coreui::rendition::Idiom::Universal,
+            uti: "public.json".to_string(),
+            data: CONFIG.to_vec(),
+        }],
+    };
+    let mut store = common::storage(vec![], vec![], None);
+    store.renditionkeyfmt = coreui::rendition::KeyFormat::standard();
+    let error = dataset::add_datasets(&mut store, std::slice::from_ref(&dataset), false, false)
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("rendition name is 145 bytes, at most 128 fit"));
+
+    dataset::add_datasets(&mut store, &[dataset], false, true).unwrap();
+    let csi_header = store.imagedb.values().next().unwrap();
+    assert_eq!(csi_header.csimetadata.name(), "é".repeat(64));
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use common::fixtures::CatalogSpec;
+
+use carutil_lib::actool;
+use carutil_lib::coreui;
+use carutil_lib::coreui::rendition;
+use carutil_lib::decompile;
+use carutil_lib::versions;
+
+fn contents(path: &std::path::Path) -> serde_json::Value {
+    serde_json::from_slice(&std::fs::read(path.join("Contents.json")).unwrap()).unwrap()
+}
+
+#[test]
+fn decompile_sets() {
+    let car = CatalogSpec::new()
+        .images("Icon", &[1, 2], &[0, 2], &[0, 1])
+        .images("Brand/Logo", &[3], &[0], &[3])
+        .data(
+            "config",
+            rendition::CompressionType::Uncompressed,
+            b"{\"retries\": 3}",
+        )
+        .color(
+            "Tint",
+            &[(0, [1.0, 0.0, 0.0, 0.5]), (1, [0.0, 0.0, 1.0, 1.0])],
+        )
+        .icon_set("AppIcon", &[(20, 1)])
+        .open();
+    let directory = common::temp_dir("decompile");
+    let output_path = directory.join("Assets.xcassets");
+    let summary =
+        decompile::decompile(&car, &output_path, &coreui::ParseOptions::default()).unwrap();
+    let root = contents(&output_path);
+    let brand = contents(&output_path.join("Brand"));
+    let icon = contents(&output_path.join("Icon.imageset"));
+    let logo = contents(&output_path.join("Brand/Logo.imageset"));
+    let tint = contents(&output_path.join("Tint.colorset"));
+    let config = contents(&output_path.join("config.dataset"));
+    let config_data = std::fs::read(output_path.join("config.dataset/config.bin")).unwrap();
+
+    assert!(summary.failed.is_empty(), "{:?}", summary.failed);
+    assert!(summary.skipped.is_empty(), "{:?}", summary.skipped);
+    assert_eq!(
+        summary.written,
+        vec![
+            "Brand/Contents.json",
+            "Brand/Logo.imageset/Contents.json",
+            "Brand/Logo.imageset/Logo-dark-highcontrast@3x.png",
+            "Contents.json",
+            "Icon.imageset/Contents.json",
+            "Icon.imageset/Icon-dark.png",
+            "Icon.imageset/Icon-dark@2x.png",
+            "Icon.imageset/Icon-dark@2x~ipad.png",
+            "Icon.imageset/Icon-dark~ipad.png",
+            "Icon.imageset/Icon.png",
+            "Icon.imageset/Icon@2x.png",
+            "Icon.imageset/Icon@2x~ipad.png",
+            "Icon.imageset/Icon~ipad.png",
+            "Tint.colorset/Contents.json",
+            "config.dataset/Contents.json",
+            "config.dataset/config.bin",
+        ]
+    );
+    assert_eq!(
+        root,
+        serde_json::json!({"info": {"author": "xcode", "version": 1}})
+    );
+    assert_eq!(brand["properties"]["provides-namespace"], true);
+    assert_eq!(icon["images"].as_array().unwrap().len(), 8);
+    assert!(icon["images"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!({
+            "filename": "Icon-dark@2x~ipad.png",
+            "appearances": [{"appearance": "luminosity", "value": "dark"}],
+            "idiom": "ipad",
+            "scale": "2x",
+        })));
+    assert_eq!(
+        logo["images"][0]["appearances"],
+        serde_json::json!([
+            {"appearance": "luminosity", "value": "dark"},
+            {"appearance": "contrast", "value": "high"},
+        ])
+    );
+    assert_eq!(
+        tint["colors"],
+        serde_json::json!([
+            {
+                "idiom": "universal",
+                "color": {
+                    "color-space": "srgb",
+                    "components": {"red": 1.0, "green": 0.0, "blue": 0.0, "alpha": 0.5},
+                },
+            },
+            {
+                "appearances": [{"appearance": "luminosity", "value": "dark"}],
+                "idiom": "universal",
+                "color": {
+                    "color-space": "srgb",
+                    "components": {"red": 0.0, "green": 0.0, "blue": 1.0, "alpha": 1.0},
+                },
+            },
+        ])
+    );
+    assert_eq!(config["data"][0]["filename"], "config.bin");
+    assert_eq!(config_data, b"{\"retries\": 3}");
+}
+
+#[test]
+fn decompiled_data_sets_compile() {
+    let car = CatalogSpec::new()
+        .data(
+            "config",
+            rendition::CompressionType::Uncompressed,
+            b"{\"retries\": 3}",
+        )
+        .open();
+    let directory = common::temp_dir("decompile-compile");
+    let document = directory.join("Assets.xcassets");
+    decompile::decompile(&car, &document, &coreui::ParseOptions::default()).unwrap();
+    actool::compile(
+        &document,
+        &directory,
+        &versions::ToolVersions::default(),
+        false,
+        false,
+    )
+    .unwrap();
+    let compiled = coreui::CarUtilAssetStorage::from(directory.join("Assets.car"), false).unwrap();
+    assert_eq!(compiled.theme_store.store.facet_names(), vec!["config"]);
+}
+
+#[test]
+fn decompile_malicious_facet_names() {
+    let car = CatalogSpec::new()
+        .data(
+            "../../escaped/config",
+            rendition::CompressionType::Uncompressed,
+            b"{}",
+        )
+        .data("/rooted", rendition::CompressionType::Uncompressed, b"{}")
+        .open();
+    let parent = common::temp_dir("decompile-escape");
+    let output_path = parent.join("out/Assets.xcassets");
+    let summary =
+        decompile::decompile(&car, &output_path, &coreui::ParseOptions::default()).unwrap();
+    let escaped = parent.join("escaped").exists() || parent.join("out/escaped").exists();
+    let written: Vec<bool> = summary
+        .written
+        .iter()
+        .map(|name| output_path.join(name).exists())
+        .collect();
+
+    assert!(summary.failed.is_empty(), "{:?}", summary.failed);
+    assert!(!escaped);
+    assert!(written.iter().all(|exists| *exists));
+    assert!(summary
+        .written
+        .iter()
+        .all(|name| !name.starts_with('/') && !name.split('/').any(|part| part == "..")));
+    assert!(summary
+        .written
+        .contains(&"__/__/escaped/config.dataset/Contents.json".to_string()));
+    assert!(summary
+        .written
+        .contains(&"_/rooted.dataset/Contents.json".to_string()));
+}
@@ -0,0 +1,249 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use carutil_lib::assetutil;
+use carutil_lib::assetutil::EntryBuilder;
+use carutil_lib::coreui;
+use carutil_lib::coreui::csi;
+use carutil_lib::coreui::csi::OutputFormat;
+use carutil_lib::coreui::csi::PixelFormat;
+use carutil_lib::coreui::rendition;
+use carutil_lib::extract;
+use carutil_lib::RawData;
+
+// 16 bit RGBA gradient, red goes up and blue goes down along the row
+fn gradient(width: u16) -> Vec<u16> {
+    (0..width)
+        .flat_map(|x| {
+            let value = (x as u32 * 65535 / (width as u32 - 1)) as u16;
+            [value, 0x1234, 65535 - value, 65535]
+        })
+        .collect()
+}
+
+fn le_bytes(components: &[u16]) -> Vec<u8> {
+    components
+        .iter()
+        .flat_map(|component| component.to_le_bytes())
+        .collect()
+}
+
+fn deep_image(
+    pixel_format: PixelFormat,
+    width: u32,
+    rendition: rendition::Rendition,
+) -> csi::Header {
+    common::csi_header(
+        rendition::LayoutType32::Image,
+        "Gradient.png",
+        width,
+        1,
+        pixel_format,
+        vec![],
+        Some(rendition),
+    )
+}
+
+// bit depth and big endian samples of a png
+fn png_samples(data: &[u8]) -> (png::BitDepth, Vec<u16>) {
+    let mut reader = png::Decoder::new(data).read_info().unwrap();
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).unwrap();
+    let samples = buffer[..info.buffer_size()]
+        .chunks_exact(2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .collect();
+    (info.bit_depth, samples)
+}
+
+#[test]
+fn pixel_format_bits_per_component() {
+    assert_eq!(PixelFormat::from_fourcc(0x52474257), PixelFormat::ARGB16);
+    assert_eq!(PixelFormat::from_fourcc(0x52474248), PixelFormat::ARGBHalf);
+    assert_eq!(
+        "argb16".parse::<PixelFormat>().unwrap(),
+        PixelFormat::ARGB16
+    );
+    assert_eq!(PixelFormat::ARGB16.bits_per_component(), 16);
+    assert_eq!(PixelFormat::ARGBHalf.bits_per_component(), 16);
+    assert_eq!(PixelFormat::ARGB.bits_per_component(), 8);
+    assert_eq!(PixelFormat::Gray.bits_per_component(), 8);
+}
+
+#[test]
+fn gradient_extracted_as_16_bit_png() {
+    let pixels = gradient(5);
+    let raw = deep_image(PixelFormat::ARGB16, 5, common::raw_data(&le_bytes(&pixels)));
+    let mut lzfse = vec![];
+    lzfse_rust::encode_bytes(&le_bytes(&pixels), &mut lzfse).unwrap();
+    let compressed = deep_image(
+        PixelFormat::ARGB16,
+        5,
+        rendition::Rendition::Theme {
+            version: 1,
+            compression_type: rendition::CompressionType::LZFSE,
+            _raw_data_length: lzfse.len() as u32,
+            raw_data: RawData(lzfse),
+        },
+    );
+    for csi_header in [raw, compressed] {
+        let extracted_file = csi_header.extracted_file().unwrap().unwrap();
+        assert_eq!(extracted_file.format, OutputFormat::Png);
+        assert!(extracted_file.warning.is_none());
+        let (bit_depth, samples) = png_samples(&extracted_file.data);
+        assert_eq!(bit_depth, png::BitDepth::Sixteen);
+        assert_eq!(samples, pixels);
+    }
+
+    // one pixel short
+    let short = deep_image(PixelFormat::ARGB16, 6, common::raw_data(&le_bytes(&pixels)));
+    assert!(short.extracted_file().is_err());
+}
+
+#[test]
+fn bits_per_component_in_entry() {
+    let csi_header = deep_image(PixelFormat::ARGB16, 1, common::raw_data(&[0; 8]));
+    let appearancedb = BTreeMap::new();
+    let context = assetutil::EntryContext {
+        csi_header: &csi_header,
+        facet_key: None,
+        key_values: vec![],
+        sha_digest: vec![],
+        appearancedb: &appearancedb,
+    };
+    let mut entry = context.base_entry();
+    assetutil::ImageEntryBuilder.add_fields(&context, &mut entry);
+    assert_eq!(entry.bits_per_component, Some(16));
+}
+
+#[test]
+fn gray_16_bit() {
+    assert_eq!(PixelFormat::from_fourcc(0x47413136), PixelFormat::Gray16);
+    assert_eq!(PixelFormat::Gray16.bits_per_component(), 16);
+    // a gray ramp, the second pixel half transparent
+    let components = [0x0000u16, 0xFFFF, 0x4000, 0x8000, 0xFFFF, 0xFFFF];
+    let csi_header = deep_image(
+        PixelFormat::Gray16,
+        3,
+        common::raw_data(&le_bytes(&components)),
+    );
+    let extracted_file = csi_header.extracted_file().unwrap().unwrap();
+    let reader = png::Decoder::new(&extracted_file.data[..])
+        .read_info()
+        .unwrap();
+    assert_eq!(reader.info().color_type, png::ColorType::GrayscaleAlpha);
+    assert_eq!(
+        png_samples(&extracted_file.data),
+        (png::BitDepth::Sixteen, components.to_vec())
+    );
+
+    let mut pixels = csi_header.deep_pixels().unwrap().unwrap();
+    pixels.unpremultiply();
+    assert_eq!(
+        pixels.to_u16(),
+        vec![0x0000, 0xFFFF, 0x8000, 0x8000, 0xFFFF, 0xFFFF]
+    );
+
+    let entry = carutil_lib::AssetUtilEntry::from_csi_header(
+        &csi_header,
+        Some("Gradient".to_string()),
+        vec![],
+        vec![],
+        &BTreeMap::new(),
+    );
+    let entry = serde_json::to_value(entry).unwrap();
+    assert_eq!(entry["BitsPerComponent"], 16);
+    assert_eq!(entry["Encoding"], "Gray16");
+
+    // one pixel short
+    let short = deep_image(
+        PixelFormat::Gray16,
+        4,
+        common::raw_data(&le_bytes(&components)),
+    );
+    assert!(short.extracted_file().is_err());
+}
+
+#[test]
+fn half_float_clamped_with_warning() {
+    // -0.5, 0.5, 2.0, 1.0
+    let components = [0xB800u16, 0x3800, 0x4000, 0x3C00];
+    let csi_header = deep_image(
+        PixelFormat::ARGBHalf,
+        1,
+        common::raw_data(&le_bytes(&components)),
+    );
+    let pixels = csi_header.deep_pixels().unwrap().unwrap();
+    assert_eq!(pixels.components, vec![-0.5, 0.5, 2.0, 1.0]);
+    assert_eq!(pixels.extended_count(), 2);
+    assert_eq!(pixels.to_u16(), vec![0, 32768, 65535, 65535]);
+
+    let store = common::storage(
+        vec![common::facet("Gradient", 1)],
+        vec![(common::key(0, 0, 0, 1), csi_header)],
+        None,
+    );
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let output_path = common::temp_dir("deep");
+    let summary = extract::extract_all(
+        &car,
+        output_path.to_str().unwrap(),
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions::default(),
+    )
+    .unwrap();
+    let written = std::fs::read(output_path.join("Gradient.png")).unwrap();
+
+    assert_eq!(summary.written, vec!["Gradient.png"]);
+    assert_eq!(summary.warnings.len(), 1);
+    assert!(
+        summary.warnings[0].starts_with("Gradient.png: clamped 2 extended range components"),
+        "{:?}",
+        summary.warnings
+    );
+    assert_eq!(png_samples(&written).1, vec![0, 32768, 65535, 65535]);
+}
+
+#[cfg(feature = "exr")]
+#[test]
+fn preserve_extended_writes_exr() {
+    let components = [0xB800u16, 0x3800, 0x4000, 0x3C00];
+    let csi_header = deep_image(
+        PixelFormat::ARGBHalf,
+        1,
+        common::raw_data(&le_bytes(&components)),
+    );
+    let store = common::storage(
+        vec![common::facet("Gradient", 1)],
+        vec![(common::key(0, 0, 0, 1), csi_header)],
+        None,
+    );
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let output_path = common::temp_dir("exr");
+    let summary = extract::extract_all(
+        &car,
+        output_path.to_str().unwrap(),
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions {
+            preserve_extended: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let written = std::fs::read(output_path.join("Gradient.exr")).unwrap();
+
+    assert_eq!(summary.written, vec!["Gradient.exr"]);
+    assert!(summary.warnings.is_empty());
+    assert_eq!(written[..4], [0x76, 0x2f, 0x31, 0x01]);
+    // the last scanline holds A B G R as floats, unclamped
+    let floats: Vec<f32> = written[written.len() - 16..]
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+        .collect();
+    assert_eq!(floats, vec![1.0, 2.0, 0.5, -0.5]);
+}
@@ -0,0 +1,364 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use carutil_lib::assetutil;
+use carutil_lib::assetutil::EntryBuilder;
+use carutil_lib::coreui::csi;
+use carutil_lib::coreui::rendition;
+use carutil_lib::RawData;
+use serde_json::json;
+
+fn tlv(values: &[u32]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+fn theme(compression_type: rendition::CompressionType, payload: &[u8]) -> rendition::Rendition {
+    rendition::Rendition::Theme {
+        version: 1,
+        compression_type,
+        _raw_data_length: payload.len() as u32,
+        raw_data: RawData(payload.to_vec()),
+    }
+}
+
+fn context<'a>(
+    csi_header: &'a csi::Header,
+    key_values: Vec<(rendition::AttributeType, u16)>,
+    appearancedb: &'a BTreeMap<String, u32>,
+) -> assetutil::EntryContext<'a> {
+    assetutil::EntryContext {
+        csi_header,
+        facet_key: Some("Facet".to_string()),
+        key_values,
+        sha_digest: vec![0xab, 0x01],
+        appearancedb,
+    }
+}
+
+// what the builder for the header's layout adds to an entry without any fields
+fn added_fields(csi_header: &csi::Header) -> serde_json::Value {
+    let appearancedb = BTreeMap::new();
+    let mut entry = assetutil::AssetUtilEntry::default();
+    let builder = assetutil::entry_builder(csi_header.csimetadata.layout).expect("No builder");
+    builder.add_fields(&context(csi_header, vec![], &appearancedb), &mut entry);
+    serde_json::to_value(entry).unwrap()
+}
+
+#[test]
+fn base_entry_from_key() {
+    let csi_header = common::image("a.png", 1, 1);
+    let appearancedb = BTreeMap::from([
+        ("UIAppearanceAny".to_string(), 0),
+        ("UIAppearanceDark".to_string(), 1),
+    ]);
+    let key_values = vec![
+        (rendition::AttributeType::Appearance, 1),
+        (rendition::AttributeType::Idiom, 2),
+        (rendition::AttributeType::Identifier, 9),
+        (rendition::AttributeType::State, 0),
+        (rendition::AttributeType::Value, 0),
+    ];
+    let entry = context(&csi_header, key_values, &appearancedb).base_entry();
+    assert_eq!(
+        serde_json::to_value(entry).unwrap(),
+        json!({
+            "Appearance": "UIAppearanceDark",
+            "AssetType": "Image",
+            "Idiom": "pad",
+            "Name": "Facet",
+            "NameIdentifier": 9,
+            "Scale": 1,
+            "SHA1Digest": "AB01",
+            "SizeOnDisk": csi_header.size_on_disk(),
+            "State": "Normal",
+            "Value": "Off"
+        })
+    );
+
+    // zero values are defaults, not variants
+    let key_values = vec![
+        (rendition::AttributeType::Appearance, 0),
+        (rendition::AttributeType::Identifier, 0),
+        (rendition::AttributeType::Subtype, 0),
+    ];
+    let mut unscaled = csi_header.clone();
+    unscaled.scale_factor = 0;
+    let entry = context(&unscaled, key_values, &appearancedb).base_entry();
+    assert_eq!(entry.appearance, None);
+    assert_eq!(entry.name_identifier, None);
+    assert_eq!(entry.subtype, None);
+    assert_eq!(entry.idiom, None);
+    assert_eq!(entry.scale, Some(1));
+}
+
+#[test]
+fn base_entry_from_payload() {
+    let appearancedb = BTreeMap::new();
+    let mut csi_header = common::csi_header(
+        rendition::LayoutType32::Vector,
+        "",
+        0,
+        0,
+        csi::PixelFormat::Data,
+        vec![],
+        Some(theme(rendition::CompressionType::LZFSE, b"")),
+    );
+    let entry = context(&csi_header, vec![], &appearancedb).base_entry();
+    assert_eq!(entry.asset_type.as_deref(), Some("Vector"));
+    assert_eq!(
+        serde_json::to_value(&entry).unwrap()["Compression"],
+        json!("lzfse")
+    );
+    assert_eq!(
+        serde_json::to_value(&entry).unwrap()["Colorspace"],
+        json!("srgb")
+    );
+    assert_eq!(entry.vector_based, Some(true));
+
+    csi_header.csimetadata.layout = rendition::LayoutType32::Texture;
+    csi_header.rendition_data = Some(rendition::Rendition::Unknown {
+        tag: u32::from_le_bytes(*b"XTEX"),
+        version: 1,
+        _raw_data_length: 6,
+        raw_data: RawData(vec![0; 6]),
+    });
+    let entry = context(&csi_header, vec![], &appearancedb).base_entry();
+    assert_eq!(entry.compression, None);
+    assert_eq!(entry.colorspace, None);
+    assert_eq!(entry.vector_based, None);
+    assert_eq!(entry.rendition_payload_length, Some(6));
+    assert!(entry.rendition_payload_tag.is_some());
+    assert!(assetutil::entry_builder(rendition::LayoutType32::Texture).is_none());
+}
+
+#[test]
+fn image_builder_required_fields() {
+    let csi_header = common::image("a.png", 3, 2);
+    assert_eq!(
+        added_fields(&csi_header),
+        json!({
+            "BitsPerComponent": 8,
+            "ColorModel": "None",
+            "Encoding": "ARGB",
+            "Opaque": false,
+            "PixelHeight": 2,
+            "PixelWidth": 3,
+            "RenditionName": "a.png"
+        })
+    );
+}
+
+#[test]
+fn image_builder_optional_fields() {
+    // a transparent monochrome palette image, which still has a template mode
+    let mut csi_header = common::csi_header(
+        rendition::LayoutType32::Image,
+        "a.png",
+        4,
+        4,
+        csi::PixelFormat::Gray,
+        // the image was cropped from 10x8, 2 from the left and 1 from the top
+        tlv(&[0x3EB, 28, 0, 2, 1, 4, 3, 8, 10]),
+        Some(theme(rendition::CompressionType::Uncompressed, b"")),
+    );
+    csi_header.color_space = csi::ColorModel(2);
+    csi_header.rendition_flags = csi::RenditionFlags(1 << 5);
+    let mut entry = assetutil::AssetUtilEntry {
+        compression: Some(rendition::CompressionType::PaletteImg),
+        colorspace: Some(carutil_lib::coregraphics::ColorSpace::SRGB),
+        ..Default::default()
+    };
+    let appearancedb = BTreeMap::new();
+    assetutil::ImageEntryBuilder
+        .add_fields(&context(&csi_header, vec![], &appearancedb), &mut entry);
+    let fields = serde_json::to_value(&entry).unwrap();
+    assert_eq!(fields["ColorModel"], json!("Monochrome"));
+    assert_eq!(fields["Colorspace"], json!("gray gamma 22"));
+    assert_eq!(fields["OriginalSize"], json!([10, 8]));
+    assert_eq!(fields["AlphaCroppedFrame"], json!([2, 1, 4, 4]));
+    assert_eq!(fields["Template Mode"], json!("original"));
+
+    // not opaque and not a palette image: no template mode
+    let mut transparent = common::image("a.png", 4, 4);
+    transparent.rendition_flags = csi::RenditionFlags(1 << 5);
+    assert!(added_fields(&transparent).get("Template Mode").is_none());
+    let mut opaque = transparent.clone();
+    opaque.rendition_flags = csi::RenditionFlags(1 << 5 | 16);
+    assert_eq!(added_fields(&opaque)["Template Mode"], json!("original"));
+
+    // no size in the header, the slices have it
+    let sliced = common::csi_header(
+        rendition::LayoutType32::Image,
+        "a.png",
+        0,
+        0,
+        csi::PixelFormat::ARGB,
+        tlv(&[0x3E9, 20, 1, 0, 0, 6, 5]),
+        None,
+    );
+    let fields = added_fields(&sliced);
+    assert_eq!(fields["PixelWidth"], json!(5));
+    assert_eq!(fields["PixelHeight"], json!(6));
+    assert!(fields.get("AlphaCroppedFrame").is_none());
+}
+
+#[test]
+fn image_builder_packed_image() {
+    let mut csi_header = common::image("ZZZZPackedAsset-1.0.0-gamut0", 2, 2);
+    csi_header.csimetadata.layout = rendition::LayoutType32::PackedImage;
+    csi_header.rendition_flags = csi::RenditionFlags(1 << 5 | 16);
+    let fields = added_fields(&csi_header);
+    assert_eq!(fields["Internal"], json!(true));
+    assert!(fields.get("Template Mode").is_none());
+    assert_eq!(
+        fields["RenditionName"],
+        json!("ZZZZPackedAsset-1.0.0-gamut0")
+    );
+}
+
+#[test]
+fn color_builder() {
+    let color = common::csi_header(
+        rendition::LayoutType32::Color,
+        "",
+        0,
+        0,
+        csi::PixelFormat::None,
+        vec![],
+        Some(rendition::Rendition::Color {
+            version: 1,
+            flags: rendition::ColorFlags(0),
+            component_count: 2,
+            components: vec![0.5, 1.0],
+        }),
+    );
+    assert_eq!(
+        added_fields(&color),
+        json!({"Color components": [0.5, 1], "Colorspace": "srgb"})
+    );
+
+    let mut empty = color.clone();
+    empty.rendition_data = None;
+    assert_eq!(added_fields(&empty), json!({}));
+}
+
+#[test]
+fn data_builder() {
+    let raw = common::csi_header(
+        rendition::LayoutType32::Data,
+        "",
+        0,
+        0,
+        csi::PixelFormat::Data,
+        common::uti_tlv("public.json", 12),
+        Some(common::raw_data(b"{}")),
+    );
+    assert_eq!(
+        added_fields(&raw),
+        json!({"Compression": "uncompressed", "Data Length": 2, "UTI": "public.json"})
+    );
+
+    // the compression comes from the base entry, the length is decompressed
+    let compressed = common::compressed_data("", rendition::CompressionType::LZFSE, b"bvx-");
+    let fields = added_fields(&compressed);
+    assert!(fields.get("Compression").is_none());
+    assert_eq!(fields["Data Length"], json!(4));
+    assert_eq!(fields["UTI"], json!("UTI-Unknown"));
+
+    // data is never reported as vector based
+    let mut vector = raw.clone();
+    vector.rendition_flags = csi::RenditionFlags(1);
+    let appearancedb = BTreeMap::new();
+    let context = context(&vector, vec![], &appearancedb);
+    let mut entry = context.base_entry();
+    assert_eq!(entry.vector_based, Some(true));
+    assetutil::DataEntryBuilder.add_fields(&context, &mut entry);
+    assert_eq!(entry.vector_based, None);
+
+    let mut empty = raw.clone();
+    empty.rendition_data = None;
+    assert!(added_fields(&empty).get("Data Length").is_none());
+}
+
+#[test]
+fn multisize_builder() {
+    let csi_header = common::csi_header(
+        rendition::LayoutType32::MultisizeImage,
+        "AppIcon",
+        0,
+        0,
+        csi::PixelFormat::ARGB,
+        vec![],
+        Some(rendition::Rendition::MultisizeImageSet {
+            version: 1,
+            sizes_count: 1,
+            entries: vec![rendition::MultisizeImageSetEntry {
+                width: 20,
+                height: 20,
+                index: 1,
+                idiom: rendition::Idiom::Phone,
+            }],
+        }),
+    );
+    assert_eq!(
+        added_fields(&csi_header),
+        json!({"Sizes": ["20x20 index:1 idiom:phone"]})
+    );
+
+    let mut empty = csi_header.clone();
+    empty.rendition_data = None;
+    assert_eq!(added_fields(&empty), json!({}));
+}
+
+#[test]
+fn png_file_in_raw_data() {
+    // an RGB png, the decoder has to add the alpha channel
+    let mut png_data = vec![];
+    {
+        let mut encoder = png::Encoder::new(&mut png_data, 2, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[255, 0, 0, 0, 0, 255]).unwrap();
+    }
+    let mut csi_header = common::image("wrapped.png", 2, 1);
+    csi_header.rendition_data = Some(common::raw_data(&png_data));
+    assert_eq!(csi_header.pixel_format, csi::PixelFormat::ARGB);
+
+    assert_eq!(csi_header.container_format(), Some(csi::OutputFormat::Png));
+    let extracted_file = csi_header.extracted_file().unwrap().unwrap();
+    assert_eq!(extracted_file.format, csi::OutputFormat::Png);
+    // written as stored, not decoded and encoded again
+    assert_eq!(extracted_file.data, png_data);
+    assert_eq!(
+        csi_header.rgba_pixels().unwrap(),
+        vec![255, 0, 0, 255, 0, 0, 255, 255]
+    );
+    assert!(csi_header.pixel_digest().is_ok());
+    assert_eq!(added_fields(&csi_header)["Encoding"], json!("PNG"));
+
+    // raw pixels keep the pixel format
+    assert_eq!(
+        added_fields(&common::image("pixels.png", 1, 1))["Encoding"],
+        json!("ARGB")
+    );
+}
+
+#[test]
+fn jpeg_file_in_raw_data() {
+    let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10, b'J', b'F', b'I', b'F'];
+    let mut csi_header = common::image("wrapped.jpg", 1, 1);
+    csi_header.rendition_data = Some(common::raw_data(&jpeg));
+
+    assert_eq!(csi_header.container_format(), Some(csi::OutputFormat::Jpeg));
+    let extracted_file = csi_header.extracted_file().unwrap().unwrap();
+    assert_eq!(extracted_file.format, csi::OutputFormat::Jpeg);
+    assert_eq!(extracted_file.data, jpeg);
+    // passed through, but there is nothing to decode it with
+    assert!(csi_header.rgba_pixels().is_err());
+    assert_eq!(added_fields(&csi_header)["Encoding"], json!("JPEG"));
+}
@@ -0,0 +1,1797 @@
+mod common;
+
+use carutil_lib::extract::OutputNames;
+use std::io::Cursor;
+
+use carutil_lib::archive;
+use carutil_lib::coreui;
+use carutil_lib::coreui::csi;
+use carutil_lib::coreui::csi::OutputFormat;
+use carutil_lib::coreui::csi::PixelFormat;
+use carutil_lib::coreui::rendition;
+use carutil_lib::extract;
+use carutil_lib::extract::output_file_name;
+
+#[test]
+fn output_file_name_extensionless() {
+    let mut used = OutputNames::default();
+    assert_eq!(
+        output_file_name("gradient", OutputFormat::Png, Some(44959), &mut used),
+        "gradient.png"
+    );
+    assert_eq!(
+        output_file_name("MyText", OutputFormat::Data, None, &mut used),
+        "MyText.dat"
+    );
+}
+
+#[test]
+fn output_file_name_already_suffixed() {
+    let mut used = OutputNames::default();
+    assert_eq!(
+        output_file_name("Timac@2x.png", OutputFormat::Png, Some(1), &mut used),
+        "Timac@2x.png"
+    );
+    assert_eq!(
+        output_file_name("photo.jpeg", OutputFormat::Jpeg, Some(2), &mut used),
+        "photo.jpeg"
+    );
+}
+
+#[test]
+fn output_file_name_colliding() {
+    let mut used = OutputNames::default();
+    assert_eq!(
+        output_file_name("gradient", OutputFormat::Png, Some(1), &mut used),
+        "gradient.png"
+    );
+    assert_eq!(
+        output_file_name("gradient", OutputFormat::Png, Some(44959), &mut used),
+        "gradient-44959.png"
+    );
+    assert_eq!(
+        output_file_name("gradient.png", OutputFormat::Png, Some(44959), &mut used),
+        "gradient-44959-2.png"
+    );
+    assert_eq!(
+        output_file_name("gradient", OutputFormat::Png, None, &mut used),
+        "gradient-2.png"
+    );
+}
+
+#[test]
+fn output_file_name_case_collision() {
+    let mut used = OutputNames::default();
+    assert_eq!(
+        output_file_name("Icon.png", OutputFormat::Png, Some(1), &mut used),
+        "Icon.png"
+    );
+    assert_eq!(
+        output_file_name("icon.png", OutputFormat::Png, Some(2), &mut used),
+        "icon-2.png"
+    );
+    assert_eq!(
+        output_file_name("ICON", OutputFormat::Png, None, &mut used),
+        "ICON-3.png"
+    );
+    // the same name twice is the usual collision, not a case one
+    assert_eq!(
+        output_file_name("Icon.png", OutputFormat::Png, None, &mut used),
+        "Icon-4.png"
+    );
+    assert_eq!(
+        used.take_warnings(),
+        vec![
+            "Icon.png and icon.png differ only in case, wrote icon-2.png instead",
+            "Icon.png and ICON.png differ only in case, wrote ICON-3.png instead",
+        ]
+    );
+    assert!(used.take_warnings().is_empty());
+
+    let mut used = OutputNames::new(true);
+    assert_eq!(
+        output_file_name("Icon.png", OutputFormat::Png, Some(1), &mut used),
+        "Icon.png"
+    );
+    assert_eq!(
+        output_file_name("icon.png", OutputFormat::Png, Some(2), &mut used),
+        "icon.png"
+    );
+    assert!(used.take_warnings().is_empty());
+}
+
+#[test]
+fn output_format_sniff() {
+    assert_eq!(
+        OutputFormat::sniff(b"\x89PNG\r\n\x1a\n....", PixelFormat::ARGB),
+        OutputFormat::Png
+    );
+    assert_eq!(
+        OutputFormat::sniff(&[0xFF, 0xD8, 0xFF, 0xE0], PixelFormat::Data),
+        OutputFormat::Jpeg
+    );
+    assert_eq!(
+        OutputFormat::sniff(b"%PDF-1.3", PixelFormat::Data),
+        OutputFormat::Pdf
+    );
+    assert_eq!(
+        OutputFormat::sniff(b"abc", PixelFormat::JPEG),
+        OutputFormat::Jpeg
+    );
+    assert_eq!(
+        OutputFormat::sniff(b"abc", PixelFormat::Data),
+        OutputFormat::Data
+    );
+}
+
+#[test]
+fn extracted_file_max_payload_bytes() {
+    let mut csi_header = common::image("huge.png", 1, 1);
+    // claims to be 3 GB without holding the bytes
+    csi_header.csibitmaplist.rendition_length = 3_000_000_000;
+
+    let options = coreui::ParseOptions {
+        max_payload_bytes: Some(1024 * 1024),
+        ..Default::default()
+    };
+    let err = csi_header
+        .extracted_file_with_options(&options)
+        .err()
+        .expect("expected a size error");
+    assert_eq!(
+        err.downcast_ref::<coreui::CarError>(),
+        Some(&coreui::CarError::PayloadTooLarge {
+            name: "huge.png".to_string(),
+            size: 3_000_000_184 + csi_header.csibitmaplist.tlv_length as u64,
+            limit: 1024 * 1024,
+        })
+    );
+
+    // no limit by default
+    assert!(csi_header.extracted_file().unwrap().is_some());
+}
+
+#[test]
+fn payloads_over_the_limit_are_not_read() {
+    let options = coreui::ParseOptions {
+        max_payload_bytes: Some(2000),
+        ..Default::default()
+    };
+    let limited = coreui::CarUtilAssetStorage::from_with_options("./tests/Assets.car", &options)
+        .expect("Unable to parse");
+    let full = coreui::CarUtilAssetStorage::from("./tests/Assets.car", false).unwrap();
+    let full = &full.theme_store.store.imagedb;
+    let mut over = 0;
+    for (key, csi_header) in &limited.theme_store.store.imagedb {
+        let size = csi_header.size_on_disk();
+        assert_eq!(size, full[key].size_on_disk());
+        if size <= 2000 {
+            assert_eq!(csi_header.tlv_data, full[key].tlv_data);
+            continue;
+        }
+        over += 1;
+        assert!(csi_header.rendition_data.is_none());
+        assert!(csi_header.tlv_data.0.is_empty());
+        let err = csi_header.decode_with_options(&options).err().unwrap();
+        assert!(matches!(
+            err.downcast_ref::<coreui::CarError>(),
+            Some(coreui::CarError::PayloadTooLarge { .. })
+        ));
+    }
+    assert!(over > 0);
+
+    let directory = common::temp_dir("limit");
+    let summary = extract::extract_raw(&limited, &directory, Some(2000)).unwrap();
+    assert_eq!(summary.skipped.len(), over);
+    assert!(summary
+        .skipped
+        .iter()
+        .all(|skip| skip.ends_with("too large")));
+}
+
+#[test]
+fn raw_payload_fixture() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from("./tests/Assets.car", false).expect("Unable to parse");
+    let csi_header = asset_storage
+        .theme_store
+        .store
+        .imagedb
+        .values()
+        .find(|csi_header| csi_header.csimetadata.name() == "Timac@2x.png")
+        .expect("No rendition found");
+    let raw_data_length = match &csi_header.rendition_data {
+        Some(coreui::rendition::Rendition::Theme {
+            _raw_data_length, ..
+        }) => *_raw_data_length,
+        _ => panic!("expected a CELM rendition"),
+    };
+
+    let (compression, payload) = extract::raw_payload(csi_header).unwrap();
+    assert_eq!(compression, "palette-img");
+    assert_eq!(payload.len() as u32, raw_data_length);
+
+    let sidecar = extract::raw_sidecar(csi_header, &compression);
+    assert_eq!(sidecar["Name"], "Timac@2x.png");
+    assert_eq!(
+        sidecar["TLV"].as_str().unwrap().len() as u32,
+        csi_header.csibitmaplist.tlv_length * 2
+    );
+}
+
+#[test]
+fn extract_all_is_deterministic() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from("./tests/Assets.car", false).expect("Unable to parse");
+    let run = |name: &str| {
+        let output_path = common::temp_dir(name);
+        let summary = extract::extract_all(
+            &asset_storage,
+            output_path.to_str().unwrap(),
+            &coreui::ParseOptions::default(),
+            &extract::ExtractOptions::default(),
+        )
+        .unwrap();
+        let mut files: Vec<String> = std::fs::read_dir(&output_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        (summary, files)
+    };
+
+    let (first, first_files) = run("first");
+    let (second, second_files) = run("second");
+    assert_eq!(first, second);
+    assert_eq!(first.summary_line(), second.summary_line());
+    assert_eq!(first.written, first_files);
+    assert_eq!(first_files, second_files);
+    assert_eq!(
+        first.written,
+        vec![
+            "CoreStructuredImage.pdf",
+            "Timac.png",
+            "Timac@2x.png",
+            "Timac@3x.png",
+            "TimacJPG.jpg",
+            "text.txt"
+        ]
+    );
+}
+
+// 2 pixel wide filmstrip of 4 one pixel high frames, frame n is filled with color n
+fn filmstrip() -> (csi::Header, Vec<[u8; 4]>) {
+    let colors: Vec<[u8; 4]> = (0..4u8).map(|n| [n * 10, n * 20, n * 30, 0xFF]).collect();
+    let mut quantized = vec![];
+    quantized.extend(0xCAFEF00Du32.to_le_bytes());
+    quantized.extend(1u32.to_le_bytes());
+    quantized.extend((colors.len() as u16).to_le_bytes());
+    for [r, g, b, a] in &colors {
+        quantized.extend([*a, *r, *g, *b]);
+    }
+    for n in 0..4u16 {
+        // two pixels per index pair
+        quantized.extend((n << 8 | n).to_le_bytes());
+    }
+    let mut payload = vec![];
+    lzfse_rust::encode_bytes(&quantized, &mut payload).expect("Unable to encode lzfse");
+
+    let mut slices = vec![];
+    for value in [0x3E9u32, 20, 1, 0, 0, 1, 2] {
+        slices.extend(value.to_le_bytes());
+    }
+    let csi_header = common::csi_header(
+        rendition::LayoutType32::Image,
+        "spinner.png",
+        2,
+        4,
+        csi::PixelFormat::ARGB,
+        slices,
+        Some(rendition::Rendition::Theme {
+            version: 1,
+            compression_type: rendition::CompressionType::PaletteImg,
+            _raw_data_length: payload.len() as u32,
+            raw_data: carutil_lib::RawData(payload),
+        }),
+    );
+    (csi_header, colors)
+}
+
+#[test]
+fn filmstrip_frames() {
+    let (csi_header, colors) = filmstrip();
+    assert_eq!(csi_header.frame_height(), Some(1));
+    let frames = extract::filmstrip_frames(&csi_header, 1, false).unwrap();
+    assert_eq!(frames.len(), 4);
+    for (frame, color) in frames.iter().zip(&colors) {
+        assert_eq!(frame, &[color.as_slice(), color.as_slice()].concat());
+    }
+
+    let apng = extract::filmstrip_apng(&csi_header, 1, false).unwrap();
+    let reader = png::Decoder::new(Cursor::new(apng)).read_info().unwrap();
+    let info = reader.info();
+    assert_eq!((info.width, info.height), (2, 1));
+    assert_eq!(info.animation_control.unwrap().num_frames, 4);
+}
+
+#[test]
+fn extract_split_frames() {
+    let (csi_header, _) = filmstrip();
+    let mut store = common::storage(vec![common::facet("spinner", 7)], vec![], None);
+    store.renditionkeyfmt = rendition::KeyFormat::new(vec![
+        rendition::AttributeType::Identifier,
+        rendition::AttributeType::Subtype,
+    ]);
+    let mut raw = [0u16; 18];
+    raw[..2].copy_from_slice(&[7, 50]);
+    store.imagedb.insert(rendition::Key { raw }, csi_header);
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+
+    let output_path = common::temp_dir("frames");
+    let run = |filmstrip| {
+        extract::extract_all(
+            &car,
+            output_path.to_str().unwrap(),
+            &coreui::ParseOptions::default(),
+            &extract::ExtractOptions {
+                filmstrip,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .written
+    };
+    assert_eq!(
+        run(extract::Filmstrip::Frames),
+        vec![
+            "spinner_frame000.png",
+            "spinner_frame001.png",
+            "spinner_frame002.png",
+            "spinner_frame003.png"
+        ]
+    );
+    assert_eq!(run(extract::Filmstrip::Apng), vec!["spinner.png"]);
+    assert_eq!(run(extract::Filmstrip::Strip), vec!["spinner.png"]);
+}
+
+// (name, method, data) of each local file header, the crc check assumes stored entries
+fn zip_entries(zip: &[u8]) -> Vec<(String, u16, Vec<u8>)> {
+    let u16_at = |offset: usize| u16::from_le_bytes([zip[offset], zip[offset + 1]]);
+    let u32_at = |offset: usize| u32::from_le_bytes(zip[offset..offset + 4].try_into().unwrap());
+    let mut entries = vec![];
+    let mut offset = 0;
+    while u32_at(offset) == 0x04034b50 {
+        let method = u16_at(offset + 8);
+        let compressed_size = u32_at(offset + 18) as usize;
+        let name_length = u16_at(offset + 26) as usize;
+        let extra_length = u16_at(offset + 28) as usize;
+        let name_start = offset + 30;
+        let data_start = name_start + name_length + extra_length;
+        let name = String::from_utf8(zip[name_start..name_start + name_length].to_vec()).unwrap();
+        let data = zip[data_start..data_start + compressed_size].to_vec();
+        assert_eq!(u32_at(offset + 14), crc32fast::hash(&data));
+        entries.push((name, method, data));
+        offset = data_start + compressed_size;
+    }
+    // central directory follows the last entry
+    assert_eq!(u32_at(offset), 0x02014b50);
+    entries
+}
+
+#[test]
+fn extract_zip_stored() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from("./tests/Assets.car", false).expect("Unable to parse");
+    let directory = common::temp_dir("zip");
+    let zip_path = directory.join("Assets.zip");
+    let summary = extract::extract_zip(
+        &asset_storage,
+        zip_path.to_str().unwrap(),
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions::default(),
+        archive::ZipCompression::Stored,
+    )
+    .unwrap();
+    let zip = std::fs::read(&zip_path).unwrap();
+
+    let entries = zip_entries(&zip);
+    let mut names: Vec<&str> = entries.iter().map(|(name, _, _)| name.as_str()).collect();
+    assert_eq!(names.pop(), Some(extract::MANIFEST_NAME));
+    names.sort();
+    assert_eq!(names, summary.written);
+    assert!(entries.iter().all(|(_, method, _)| *method == 0));
+
+    let (_, _, jpg) = entries
+        .iter()
+        .find(|(name, _, _)| name == "TimacJPG.jpg")
+        .unwrap();
+    let (_, csi_header) = asset_storage
+        .theme_store
+        .store
+        .imagedb
+        .iter()
+        .find(|(_, csi_header)| csi_header.csimetadata.name() == "TimacJPG.jpg")
+        .unwrap();
+    assert_eq!(jpg, &csi_header.extracted_file().unwrap().unwrap().data);
+
+    let (_, _, manifest) = entries.last().unwrap();
+    let manifest: serde_json::Value = serde_json::from_slice(manifest).unwrap();
+    assert_eq!(manifest, summary.manifest());
+}
+
+#[test]
+fn extract_namespaced_names() {
+    use carutil_lib::FlattenNames;
+
+    let store = common::storage(
+        vec![common::facet("Buttons/Primary/Background", 1)],
+        vec![(
+            common::key(0, 1, 0, 1),
+            common::image("Background.png", 1, 1),
+        )],
+        None,
+    );
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let output_path = common::temp_dir("namespaced");
+    let run = |flatten_names| {
+        extract::extract_all(
+            &car,
+            output_path.to_str().unwrap(),
+            &coreui::ParseOptions::default(),
+            &extract::ExtractOptions {
+                flatten_names,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .written
+    };
+    assert_eq!(
+        run(FlattenNames::Keep),
+        vec!["Buttons/Primary/Background.png"]
+    );
+    assert!(output_path.join("Buttons/Primary/Background.png").is_file());
+    assert_eq!(
+        run(FlattenNames::Dash),
+        vec!["Buttons-Primary-Background.png"]
+    );
+    assert_eq!(run(FlattenNames::Slash), vec!["Background.png"]);
+
+    assert_eq!(FlattenNames::Keep.path_prefix("../a//./b/Name"), "a/b/");
+    assert_eq!(FlattenNames::Keep.path_prefix("Name"), "");
+}
+
+#[test]
+fn extract_compressed_data() {
+    let json = br#"{"endpoint": "https://example.com", "retries": 3}"#;
+    let mut lzfse = vec![];
+    lzfse_rust::encode_bytes(json, &mut lzfse).unwrap();
+    let store = common::storage(
+        vec![common::facet("Config", 1), common::facet("Blob", 2)],
+        vec![
+            (
+                common::key(0, 0, 0, 1),
+                common::compressed_data("config.json", rendition::CompressionType::LZFSE, &lzfse),
+            ),
+            // no codec for rle, the stored bytes are written as they are
+            (
+                common::key(0, 0, 0, 2),
+                common::compressed_data("blob.bin", rendition::CompressionType::RLE, b"rle"),
+            ),
+        ],
+        None,
+    );
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let output_path = common::temp_dir("data");
+    let summary = extract::extract_all(
+        &car,
+        output_path.to_str().unwrap(),
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions::default(),
+    )
+    .unwrap();
+    let written = std::fs::read(output_path.join("config.json")).unwrap();
+    let fallback = std::fs::read(output_path.join("blob.bin.rle")).unwrap();
+
+    assert_eq!(summary.written, vec!["blob.bin.rle", "config.json"]);
+    assert!(summary.failed.is_empty());
+    assert_eq!(
+        summary.warnings,
+        vec!["blob.bin: no codec for rle, wrote the compressed bytes"]
+    );
+    let value: serde_json::Value = serde_json::from_slice(&written).unwrap();
+    assert_eq!(value["retries"], 3);
+    assert_eq!(fallback, b"rle");
+}
+
+#[test]
+fn extract_data_extensions() {
+    let data = |name: &str, uti: Option<&str>, payload: &[u8]| {
+        let tlv = uti.map(|uti| common::uti_tlv(uti, 32)).unwrap_or_default();
+        let mut csi_header = common::csi_header(
+            rendition::LayoutType32::Data,
+            name,
+            0,
+            0,
+            PixelFormat::Data,
+            tlv,
+            Some(common::raw_data(payload)),
+        );
+        csi_header.csibitmaplist.tlv_length = csi_header.tlv_data.0.len() as u32;
+        csi_header
+    };
+    let store = common::storage(
+        vec![
+            common::facet("Model", 1),
+            common::facet("Notes", 2),
+            common::facet("Logo", 3),
+            common::facet("Config", 4),
+        ],
+        vec![
+            (
+                common::key(0, 0, 0, 1),
+                data("Model", Some("com.apple.coreml.model"), b"model"),
+            ),
+            (common::key(0, 0, 0, 2), data("Notes", None, b"notes")),
+            (
+                common::key(0, 0, 0, 3),
+                data("Logo", Some("public.data"), b"%PDF-1.4"),
+            ),
+            // names with an extension keep it
+            (
+                common::key(0, 0, 0, 4),
+                data("config.txt", Some("public.json"), b"{}"),
+            ),
+        ],
+        None,
+    );
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let output_path = common::temp_dir("data-names");
+    let summary = extract::extract_all(
+        &car,
+        &output_path,
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions::default(),
+    )
+    .unwrap();
+    let model = std::fs::read(output_path.join("Model.mlmodel")).unwrap();
+
+    assert_eq!(
+        summary.written,
+        vec!["Logo.pdf", "Model.mlmodel", "Notes.bin", "config.txt"]
+    );
+    assert_eq!(model, b"model");
+    assert_eq!(extract::uti_extension("public.JSON"), Some("json"));
+    assert_eq!(extract::uti_extension("public.data"), None);
+}
+
+#[test]
+fn extract_key_filter() {
+    let written = |asset_storage: &coreui::CarUtilAssetStorage, expressions: &[&str]| {
+        let output_path = common::temp_dir("key-filter");
+        let requirements = expressions
+            .iter()
+            .map(|expression| {
+                carutil_lib::filter::AttributeRequirement::parse(
+                    expression,
+                    &std::collections::BTreeMap::new(),
+                )
+                .unwrap()
+            })
+            .collect();
+        let summary = extract::extract_all(
+            asset_storage,
+            output_path.to_str().unwrap(),
+            &coreui::ParseOptions::default(),
+            &extract::ExtractOptions {
+                filter: carutil_lib::filter::RenditionFilter {
+                    attributes: requirements,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        summary.written
+    };
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from("./tests/Assets.car", false).expect("Unable to parse");
+    assert_eq!(written(&asset_storage, &["scale=3"]), vec!["Timac@3x.png"]);
+    let asset_storage = common::fixtures::CatalogSpec::new()
+        .images("Icon", &[1, 2], &[0, 2], &[0])
+        .open();
+    assert_eq!(
+        written(&asset_storage, &["scale=2", "idiom=pad"]),
+        vec!["Icon@2x~ipad.png"]
+    );
+}
+
+#[test]
+fn extract_blank_rendition_names() {
+    let store = common::storage(
+        vec![common::facet("MyIcon", 1)],
+        vec![
+            (common::key(0, 1, 0, 1), common::image("", 1, 1)),
+            (common::key(0, 2, 0, 1), common::image("", 1, 1)),
+        ],
+        None,
+    );
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let output_path = common::temp_dir("blank");
+    let summary = extract::extract_all(
+        &car,
+        output_path.to_str().unwrap(),
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions::default(),
+    )
+    .unwrap();
+    // the uncompressed pixels are written as pngs
+    assert_eq!(summary.written, vec!["MyIcon.png", "MyIcon@2x.png"]);
+}
+
+#[test]
+fn extract_manifest_matches_files() {
+    use hex::ToHex;
+    use sha2::Digest;
+
+    let options = coreui::ParseOptions {
+        max_payload_bytes: Some(2000),
+        ..Default::default()
+    };
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from_with_options("./tests/Assets.car", &options).unwrap();
+    let output_path = common::temp_dir("manifest");
+    let summary = extract::extract_all(
+        &asset_storage,
+        output_path.to_str().unwrap(),
+        &options,
+        &extract::ExtractOptions::default(),
+    )
+    .unwrap();
+    let manifest_path = output_path.join("manifest.json");
+    extract::write_manifest(&summary, manifest_path.to_str().unwrap()).unwrap();
+    let manifest: Vec<serde_json::Value> =
+        serde_json::from_slice(&std::fs::read(&manifest_path).unwrap()).unwrap();
+
+    assert!(!summary.skipped.is_empty());
+    assert_eq!(
+        manifest.len(),
+        summary.written.len() + summary.skipped.len() + summary.failed.len()
+    );
+    let mut written = vec![];
+    for record in &manifest {
+        match record["Status"].as_str().unwrap() {
+            "written" => {
+                let path = record["Path"].as_str().unwrap();
+                let data = std::fs::read(output_path.join(path)).unwrap();
+                assert_eq!(
+                    record["SHA256Digest"].as_str().unwrap(),
+                    sha2::Sha256::digest(&data).encode_hex::<String>()
+                );
+                assert!(record["Reason"].is_null());
+                written.push(path.to_string());
+            }
+            "skipped" => {
+                assert!(record["Path"].is_null());
+                assert!(record["SHA256Digest"].is_null());
+                assert!(record["Reason"].as_str().unwrap().ends_with("too large"));
+            }
+            status => panic!("unexpected status {:?}", status),
+        }
+        assert!(record["FacetName"].is_string());
+        assert_eq!(record["Idiom"], "universal");
+        assert_eq!(record["Appearance"], "any");
+    }
+    written.sort();
+    assert_eq!(written, summary.written);
+}
+
+#[test]
+fn unknown_rendition_payload() {
+    let payload = b"\x01\x02\x03\x04new format".to_vec();
+    let unknown = rendition::Rendition::Unknown {
+        tag: u32::from_be_bytes(*b"ABCD"),
+        version: 1,
+        _raw_data_length: payload.len() as u32,
+        raw_data: carutil_lib::RawData(payload.clone()),
+    };
+    let store = common::storage(
+        vec![common::facet("Future", 1)],
+        vec![(
+            common::key(0, 1, 0, 1),
+            common::csi_header(
+                rendition::LayoutType32::Image,
+                "future.png",
+                4,
+                4,
+                PixelFormat::ARGB,
+                vec![],
+                Some(unknown),
+            ),
+        )],
+        None,
+    );
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let directory = common::temp_dir("unknown");
+    let car_path = directory.join("Assets.car");
+    car.write_data(car_path.to_str().unwrap()).unwrap();
+    let read = coreui::CarUtilAssetStorage::from(car_path.to_str().unwrap(), false).unwrap();
+    let summary = extract::extract_all(
+        &read,
+        directory.to_str().unwrap(),
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions::default(),
+    )
+    .unwrap();
+    let raw_summary = extract::extract_raw(&read, directory.to_str().unwrap(), None).unwrap();
+    let raw = std::fs::read(directory.join("future.unknown.bin")).unwrap();
+
+    // the payload is read back whole, the tag and length are reported
+    let store = &read.theme_store.store;
+    let csi_header = store.imagedb.values().next().unwrap();
+    let (tag, read_payload) = csi_header
+        .rendition_data
+        .as_ref()
+        .and_then(|rendition_data| rendition_data.unknown_payload())
+        .unwrap();
+    assert_eq!(tag.to_be_bytes(), *b"ABCD");
+    assert_eq!(read_payload, payload);
+    let entries = carutil_lib::assetutil::AssetUtilEntry::entries_from_asset_storage(store);
+    let entry = serde_json::to_value(&entries[0]).unwrap();
+    assert_eq!(entry["RenditionPayloadTag"], "ABCD");
+    assert_eq!(entry["RenditionPayloadLength"], payload.len());
+    assert_eq!(
+        extract::raw_sidecar(csi_header, "unknown")["RenditionPayloadTag"],
+        "ABCD"
+    );
+
+    assert!(summary.written.is_empty());
+    assert_eq!(
+        summary.failed,
+        vec!["unsupported rendition payload tag ABCD (14 bytes) for image \"future.png\", extract it with --raw"]
+    );
+    assert_eq!(raw_summary.written, vec!["future.unknown.bin"]);
+    assert_eq!(raw, payload);
+}
+
+#[test]
+fn core_svg_rendition() {
+    let document = b"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"8\" height=\"8\"/>".to_vec();
+    let svg = rendition::Rendition::Svg {
+        version: 1,
+        _raw_data_length: document.len() as u32,
+        raw_data: carutil_lib::RawData(document.clone()),
+    };
+    let store = common::storage(
+        vec![common::facet("Badge", 1)],
+        vec![(
+            common::key(0, 1, 0, 1),
+            common::csi_header(
+                rendition::LayoutType32::Image,
+                "Badge.svg",
+                8,
+                8,
+                PixelFormat::None,
+                vec![],
+                Some(svg),
+            ),
+        )],
+        None,
+    );
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let directory = common::temp_dir("svg");
+    let car_path = directory.join("Assets.car");
+    car.write_data(car_path.to_str().unwrap()).unwrap();
+    let read = coreui::CarUtilAssetStorage::from(car_path.to_str().unwrap(), false).unwrap();
+    let summary = extract::extract_all(
+        &read,
+        directory.to_str().unwrap(),
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions::default(),
+    )
+    .unwrap();
+    let written = std::fs::read(directory.join("Badge.svg")).unwrap();
+
+    // the CSVG container is read back as an svg, not an unknown payload
+    let store = &read.theme_store.store;
+    let csi_header = store.imagedb.values().next().unwrap();
+    let rendition_data = csi_header.rendition_data.as_ref().unwrap();
+    assert_eq!(rendition_data.svg_document(), Some(&document[..]));
+    assert!(rendition_data.unknown_payload().is_none());
+    assert_eq!(csi_header.uti().as_deref(), Some("public.svg-image"));
+    let entries = carutil_lib::assetutil::AssetUtilEntry::entries_from_asset_storage(store);
+    let entry = serde_json::to_value(&entries[0]).unwrap();
+    assert_eq!(entry["UTI"], "public.svg-image");
+    assert!(entry.get("RenditionPayloadTag").is_none());
+
+    assert_eq!(summary.written, vec!["Badge.svg"]);
+    assert!(summary.failed.is_empty(), "{:?}", summary.failed);
+    assert_eq!(written, document);
+    assert_eq!(
+        extract::raw_payload(csi_header).unwrap(),
+        ("svg".to_string(), document)
+    );
+}
+
+#[test]
+fn extract_packed_images() {
+    // a 4x2 BGRA atlas, the left half red and the right half blue
+    let mut bgra = vec![];
+    for _ in 0..2 {
+        bgra.extend([
+            0, 0, 255, 255, 0, 0, 255, 255, 255, 0, 0, 255, 255, 0, 0, 128,
+        ]);
+    }
+    let atlas = common::csi_header(
+        rendition::LayoutType32::PackedImage,
+        "ZZZZPackedAsset-1.0.0-gamut0",
+        4,
+        2,
+        PixelFormat::ARGB,
+        vec![],
+        Some(common::raw_data(&bgra)),
+    );
+    let link = |name: &str, x, atlas_identifier| {
+        let attribute = |name, value| rendition::Attribute { name, value };
+        common::csi_header(
+            rendition::LayoutType32::Image,
+            name,
+            0,
+            0,
+            PixelFormat::ARGB,
+            vec![],
+            Some(rendition::Rendition::InternalLink {
+                version: 0,
+                x,
+                y: 0,
+                width: 2,
+                height: 2,
+                layout: 0x0C,
+                _key_count: 2,
+                key: vec![
+                    attribute(rendition::AttributeType16::Scale, 1),
+                    attribute(rendition::AttributeType16::Identifier, atlas_identifier),
+                ],
+            }),
+        )
+    };
+    let store = common::storage(
+        vec![
+            common::facet("Red", 1),
+            common::facet("Blue", 2),
+            common::facet("Lost", 3),
+        ],
+        vec![
+            (common::key(0, 1, 0, 1), link("Red.png", 0, 600)),
+            (common::key(0, 1, 0, 2), link("Blue.png", 2, 600)),
+            (common::key(0, 1, 0, 3), link("Lost.png", 0, 700)),
+            (common::key(0, 1, 0, 600), atlas),
+        ],
+        None,
+    );
+
+    let entries = carutil_lib::assetutil::AssetUtilEntry::entries_from_asset_storage(&store);
+    let blue = entries
+        .iter()
+        .find(|entry| entry.name.as_deref() == Some("Blue"))
+        .unwrap();
+    assert_eq!((blue.pixel_width, blue.pixel_height), (Some(2), Some(2)));
+
+    let (_, red) = store.imagedb.iter().next().unwrap();
+    match store
+        .decode_rendition(red, &coreui::ParseOptions::default())
+        .unwrap()
+    {
+        csi::DecodedRendition::Rgba {
+            width,
+            height,
+            pixels,
+        } => {
+            assert_eq!((width, height), (2, 2));
+            assert_eq!(pixels, [255, 0, 0, 255].repeat(4));
+        }
+        _ => panic!("expected rgba pixels"),
+    }
+
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let output_path = common::temp_dir("packed");
+    let summary = extract::extract_all(
+        &car,
+        output_path.to_str().unwrap(),
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions {
+            jobs: 2,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let read = |name: &str| csi::decode_png(&std::fs::read(output_path.join(name)).unwrap());
+    let (red, blue) = (read("Red.png").unwrap(), read("Blue.png").unwrap());
+
+    // the atlas itself isn't written
+    assert_eq!(summary.written, vec!["Blue.png", "Red.png"]);
+    assert_eq!(red, (2, 2, [255, 0, 0, 255].repeat(4)));
+    assert_eq!(blue, (2, 2, [0, 0, 255, 255, 0, 0, 255, 128].repeat(2)));
+    assert_eq!(
+        summary.failed,
+        vec!["\"Lost.png\" links to a packed image that isn't in the catalog"]
+    );
+}
+
+#[test]
+fn extract_multisize_sizes() {
+    // keys are appearance, scale, idiom, identifier and dimension1
+    let key = |scale, idiom, identifier, index| {
+        let mut rendition_key = common::key(0, scale, idiom, identifier);
+        rendition_key.raw[4] = index;
+        rendition_key
+    };
+    let entry = |width, index, idiom| rendition::MultisizeImageSetEntry {
+        width,
+        height: width,
+        index,
+        idiom,
+    };
+    let icon_set = common::csi_header(
+        rendition::LayoutType32::MultisizeImage,
+        "AppIcon",
+        0,
+        0,
+        PixelFormat::ARGB,
+        vec![],
+        Some(rendition::Rendition::MultisizeImageSet {
+            version: 1,
+            sizes_count: 3,
+            entries: vec![
+                entry(2, 1, rendition::Idiom::Phone),
+                entry(3, 2, rendition::Idiom::Pad),
+                entry(4, 3, rendition::Idiom::Phone),
+            ],
+        }),
+    );
+    let image = |name: &str, size: u32, scale: u32| {
+        let pixels = size * scale;
+        let mut csi_header = common::image(name, pixels, pixels);
+        csi_header.scale_factor = scale * 100;
+        csi_header
+    };
+    let mut store = common::storage(
+        vec![common::facet("AppIcon", 1)],
+        vec![
+            (key(0, 0, 1, 0), icon_set),
+            (key(1, 1, 1, 1), image("AppIcon-2.png", 2, 1)),
+            (key(2, 1, 1, 1), image("AppIcon-2@2x.png", 2, 2)),
+            (key(1, 2, 1, 2), image("AppIcon-3~ipad.png", 3, 1)),
+        ],
+        None,
+    );
+    store.renditionkeyfmt = rendition::KeyFormat::new(vec![
+        rendition::AttributeType::Appearance,
+        rendition::AttributeType::Scale,
+        rendition::AttributeType::Idiom,
+        rendition::AttributeType::Identifier,
+        rendition::AttributeType::Dimension1,
+    ]);
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let output_path = common::temp_dir("multisize");
+    let summary = extract::extract_all(
+        &car,
+        output_path.to_str().unwrap(),
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions::default(),
+    )
+    .unwrap();
+    let size = |name: &str| {
+        let (width, height, _) =
+            csi::decode_png(&std::fs::read(output_path.join(name)).unwrap()).unwrap();
+        (width, height)
+    };
+    let sizes = [
+        size("AppIcon-2x2.png"),
+        size("AppIcon-2x2@2x.png"),
+        size("AppIcon-3x3.png"),
+    ];
+
+    assert_eq!(sizes, [(2, 2), (4, 4), (3, 3)]);
+    // the images are written under their own names too
+    assert_eq!(
+        summary.written,
+        vec![
+            "AppIcon-2.png",
+            "AppIcon-2@2x.png",
+            "AppIcon-2x2.png",
+            "AppIcon-2x2@2x.png",
+            "AppIcon-3x3.png",
+            "AppIcon-3~ipad.png",
+        ]
+    );
+    assert_eq!(
+        summary.warnings,
+        vec!["AppIcon: no image for the 4x4 size (index 3)"]
+    );
+}
+
+#[test]
+fn restore_canvas() {
+    // a 2x1 image cropped out of a 4x3 canvas, one pixel in from the top left
+    let pixels = [255, 0, 0, 255, 0, 255, 0, 255];
+    let png = csi::encode_png(2, 1, &pixels).unwrap();
+    let mut metrics = vec![];
+    for value in [0x3EB, 28, 1, 1, 1, 1, 1, 3, 4] {
+        metrics.extend(u32::to_le_bytes(value));
+    }
+    let store = common::storage(
+        vec![common::facet("Cropped", 1)],
+        vec![(
+            common::key(0, 1, 0, 1),
+            common::csi_header(
+                rendition::LayoutType32::Image,
+                "cropped.png",
+                2,
+                1,
+                PixelFormat::ARGB,
+                metrics,
+                Some(common::raw_data(&png)),
+            ),
+        )],
+        None,
+    );
+    let entries = carutil_lib::assetutil::AssetUtilEntry::entries_from_asset_storage(&store);
+    let entry = serde_json::to_value(&entries[0]).unwrap();
+    assert_eq!(entry["OriginalSize"], serde_json::json!([4, 3]));
+    assert_eq!(entry["AlphaCroppedFrame"], serde_json::json!([1, 1, 2, 1]));
+
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let output_path = common::temp_dir("canvas");
+    let extract = |restore_canvas| {
+        extract::extract_all(
+            &car,
+            output_path.to_str().unwrap(),
+            &coreui::ParseOptions::default(),
+            &extract::ExtractOptions {
+                restore_canvas,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        csi::decode_png(&std::fs::read(output_path.join("cropped.png")).unwrap()).unwrap()
+    };
+    let cropped = extract(false);
+    let restored = extract(true);
+
+    assert_eq!((cropped.0, cropped.1), (2, 1));
+    assert_eq!((restored.0, restored.1), (4, 3));
+    let mut expected = vec![0; 4 * 3 * 4];
+    expected[(4 + 1) * 4..(4 + 3) * 4].copy_from_slice(&pixels);
+    assert_eq!(restored.2, expected);
+}
+
+#[test]
+fn restore_canvas_hostile_metrics() {
+    let png = csi::encode_png(2, 1, &[0; 8]).unwrap();
+    let cropped = |x: u32, height: u32, width: u32| {
+        let mut metrics = vec![];
+        for value in [0x3EB, 28, 1, x, 0, 0, 0, height, width] {
+            metrics.extend(u32::to_le_bytes(value));
+        }
+        common::csi_header(
+            rendition::LayoutType32::Image,
+            "cropped.png",
+            2,
+            1,
+            PixelFormat::ARGB,
+            metrics,
+            Some(common::raw_data(&png)),
+        )
+    };
+    let options = coreui::ParseOptions::default();
+    // the frame's right edge overflows u32 instead of landing on the canvas
+    let error = cropped(u32::MAX, 1, 4)
+        .restored_canvas_png(false, &options)
+        .unwrap_err();
+    assert!(
+        error.to_string().contains("outside its 4x1 canvas"),
+        "{}",
+        error
+    );
+
+    // a canvas the size of the address space is held to the payload limit
+    let limited = coreui::ParseOptions {
+        max_payload_bytes: Some(1 << 20),
+        ..Default::default()
+    };
+    let error = cropped(0, u32::MAX, u32::MAX)
+        .restored_canvas_png(false, &limited)
+        .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<coreui::CarError>(),
+        Some(coreui::CarError::PayloadTooLarge { .. })
+    ));
+}
+
+#[test]
+fn extract_high_contrast_variants() {
+    let appearances = std::collections::BTreeMap::from([
+        ("UIAppearanceAny".to_string(), 0),
+        ("UIAppearanceDark".to_string(), 1),
+        ("UIAppearanceHighContrastAny".to_string(), 2),
+        ("UIAppearanceHighContrastDark".to_string(), 3),
+    ]);
+    // Xcode gives every appearance of an image the same rendition name
+    let store = common::storage(
+        vec![common::facet("Tint", 1)],
+        (0..4)
+            .map(|appearance| {
+                (
+                    common::key(appearance, 1, 0, 1),
+                    common::image("Tint.png", 1, 1),
+                )
+            })
+            .collect(),
+        Some(appearances),
+    );
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let output_path = common::temp_dir("high-contrast");
+    let summary = extract::extract_all(
+        &car,
+        output_path.to_str().unwrap(),
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions::default(),
+    )
+    .unwrap();
+    assert!(summary.failed.is_empty());
+    assert_eq!(
+        summary.written,
+        vec![
+            "Tint.png",
+            "Tint~dark-highcontrast.png",
+            "Tint~dark.png",
+            "Tint~highcontrast.png"
+        ]
+    );
+}
+
+#[test]
+fn extract_appearance_without_collision() {
+    // variants with no any-appearance sibling still get their label
+    let store = common::storage(
+        vec![common::facet("Moon", 1), common::facet("Sun", 2)],
+        vec![
+            (common::key(1, 2, 0, 1), common::image("Moon@2x.png", 1, 1)),
+            (common::key(2, 1, 0, 2), common::image("Sun.png", 1, 1)),
+        ],
+        Some(std::collections::BTreeMap::from([
+            ("UIAppearanceAny".to_string(), 0),
+            ("UIAppearanceDark".to_string(), 1),
+            ("UIAppearanceHighContrastAny".to_string(), 2),
+        ])),
+    );
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let output_path = common::temp_dir("appearance-label");
+    let summary = extract::extract_all(
+        &car,
+        &output_path,
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions::default(),
+    )
+    .unwrap();
+    assert!(summary.failed.is_empty());
+    assert_eq!(
+        summary.written,
+        vec!["Moon~dark@2x.png", "Sun~highcontrast.png"]
+    );
+}
+
+#[test]
+fn extract_shared_rendition_names() {
+    // every variant stored as "Icon.png", the way some tools write catalogs
+    let mut keys = vec![];
+    for scale in [1, 2, 3] {
+        for appearance in [0, 1] {
+            keys.push(common::key(appearance, scale, 0, 1));
+        }
+    }
+    keys.push(common::key(1, 2, 2, 1));
+    let store = common::storage(
+        vec![common::facet("Icon", 1)],
+        keys.into_iter()
+            .map(|key| (key, common::image("Icon.png", 1, 1)))
+            .chain([(common::key(0, 2, 0, 2), common::image("Logo@2x.png", 1, 1))])
+            .chain([(common::key(1, 2, 0, 2), common::image("Logo@2x.png", 1, 1))])
+            .collect(),
+        Some(std::collections::BTreeMap::from([
+            ("UIAppearanceAny".to_string(), 0),
+            ("UIAppearanceDark".to_string(), 1),
+        ])),
+    );
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let output_path = common::temp_dir("shared-names");
+    let summary = extract::extract_all(
+        &car,
+        &output_path,
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions::default(),
+    )
+    .unwrap();
+    assert!(summary.failed.is_empty());
+    assert_eq!(
+        summary.written,
+        vec![
+            "Icon.png",
+            "Icon@2x.png",
+            "Icon@3x.png",
+            "Icon~dark.png",
+            "Icon~dark@2x.png",
+            "Icon~dark@2x~ipad.png",
+            "Icon~dark@3x.png",
+            "Logo@2x.png",
+            "Logo~dark@2x.png",
+        ]
+    );
+}
+
+#[test]
+fn extract_best_writes_one_file_per_asset() {
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from("./tests/Assets.car", false).expect("Unable to parse");
+    let run = |name: &str, best: coreui::RenditionQuery| {
+        let output_path = common::temp_dir(&format!("best-{}", name));
+        let summary = extract::extract_all(
+            &asset_storage,
+            output_path.to_str().unwrap(),
+            &coreui::ParseOptions::default(),
+            &extract::ExtractOptions {
+                best: Some(best),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let png = std::fs::read(output_path.join("MyPNG.png")).unwrap();
+        (summary, png)
+    };
+    let width = |png: &[u8]| png::Decoder::new(png).read_info().unwrap().info().width;
+
+    let (summary, png) = run("default", extract::default_best_query());
+    assert_eq!(
+        summary.written,
+        vec!["MyJPG.jpg", "MyPDF.pdf", "MyPNG.png", "MyText.txt"]
+    );
+    let timac = |name: &str| {
+        asset_storage
+            .theme_store
+            .store
+            .imagedb
+            .values()
+            .find(|csi_header| csi_header.csimetadata.name() == name)
+            .unwrap()
+            .width
+    };
+    assert_eq!(width(&png), timac("Timac@3x.png"));
+
+    let (summary, png) = run(
+        "2x",
+        coreui::RenditionQuery {
+            scale: Some(2),
+            ..Default::default()
+        },
+    );
+    assert_eq!(summary.written.len(), 4);
+    assert_eq!(width(&png), timac("Timac@2x.png"));
+}
+
+#[test]
+fn best_rendition_ties_broken_by_digest() {
+    // the same key apart from the state, which the query doesn't look at
+    let mut states = vec![];
+    for state in [1, 2] {
+        let mut key = common::key(0, 3, 0, 1);
+        key.raw[4] = state;
+        states.push(key);
+    }
+    let mut other = common::image("b.png", 1, 1);
+    other.rendition_data = Some(common::raw_data(&[1, 2, 3, 4]));
+    let store = |first: csi::Header, second: csi::Header| {
+        common::storage(
+            vec![common::facet("Icon", 1)],
+            vec![
+                (common::key(0, 1, 0, 1), common::image("a@1x.png", 1, 1)),
+                (states[0], first),
+                (states[1], second),
+            ],
+            None,
+        )
+    };
+    let query = extract::default_best_query();
+    let filter = Default::default();
+    let forward = extract::best_renditions(
+        &store(common::image("a.png", 1, 1), other.clone()),
+        &query,
+        &filter,
+    );
+    let backward =
+        extract::best_renditions(&store(other, common::image("a.png", 1, 1)), &query, &filter);
+    assert_eq!(forward.len(), 1);
+    // the same rendition wins wherever it is
+    assert_ne!(forward["Icon"], backward["Icon"]);
+    assert!(states.contains(&forward["Icon"]));
+}
+
+#[test]
+fn extract_with_library_options() {
+    use std::sync::Mutex;
+
+    use carutil_lib::filter;
+
+    let asset_storage =
+        coreui::CarUtilAssetStorage::from("./tests/Assets.car", false).expect("Unable to parse");
+    let output_path = common::temp_dir("extract-with");
+    let outcomes = std::sync::Arc::new(Mutex::new(vec![]));
+    let progress = outcomes.clone();
+    let report = asset_storage
+        .extract_with(
+            &output_path,
+            &extract::ExtractOptions {
+                types: "image".parse().unwrap(),
+                naming: "{facet}@{scale}x~{idiom}".parse().unwrap(),
+                on_progress: Some(extract::ProgressCallback::new(
+                    move |index, total, name, outcome| {
+                        progress
+                            .lock()
+                            .unwrap()
+                            .push((index, total, name.to_string(), outcome));
+                    },
+                )),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let written_png = output_path.join("MyPNG@2x~universal.png").exists();
+
+    assert_eq!(
+        report.written,
+        vec![
+            "MyJPG@1x~universal.jpg",
+            "MyPNG@1x~universal.png",
+            "MyPNG@2x~universal.png",
+            "MyPNG@3x~universal.png",
+        ]
+    );
+    assert!(written_png);
+    let outcomes = outcomes.lock().unwrap();
+    assert_eq!(outcomes.len(), 7);
+    assert!(outcomes
+        .iter()
+        .enumerate()
+        .all(|(index, (called_index, total, _, _))| *called_index == index && *total == 7));
+    let count = |outcome| outcomes.iter().filter(|(_, _, _, o)| *o == outcome).count();
+    assert_eq!(count(extract::ExtractOutcome::Written), 4);
+    // the two data assets, the color has nothing to write
+    assert_eq!(count(extract::ExtractOutcome::Excluded), 2);
+    assert_eq!(count(extract::ExtractOutcome::NotExtracted), 1);
+    assert!(outcomes
+        .iter()
+        .any(|(_, _, name, outcome)| name == "Timac@2x.png"
+            && *outcome == extract::ExtractOutcome::Written));
+
+    // only 2x, with a size cap every rendition is above
+    let report = asset_storage
+        .extract_with(
+            common::temp_dir("extract-capped"),
+            &extract::ExtractOptions {
+                filter: filter::RenditionFilter::parse(
+                    &["scale=2".to_string()],
+                    &Default::default(),
+                )
+                .unwrap(),
+                max_size: Some(1),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(report.written.is_empty());
+    assert_eq!(report.skipped.len(), 1);
+    assert!(report.skipped[0].starts_with("Timac@2x.png"));
+
+    assert!("image,sound".parse::<extract::AssetTypes>().is_err());
+    assert!("{facet}-{size}".parse::<extract::NamingTemplate>().is_err());
+    assert!("{facet".parse::<extract::NamingTemplate>().is_err());
+}
+
+#[test]
+fn extract_unpremultiplied() {
+    // a half transparent gray pixel stored premultiplied, and a transparent one
+    let bgra = [0x40, 0x40, 0x40, 0x80, 0x00, 0x00, 0x00, 0x00];
+    let mut payload = vec![];
+    lzfse_rust::encode_bytes(&bgra, &mut payload).unwrap();
+    let car = common::fixtures::CatalogSpec::new()
+        .compressed_image(
+            "Edge.png",
+            (2, 1),
+            PixelFormat::ARGB,
+            rendition::CompressionType::LZFSE,
+            &payload,
+        )
+        .open();
+    let output_path = common::temp_dir("unpremultiply");
+    let extract = |unpremultiply| {
+        extract::extract_all(
+            &car,
+            &output_path,
+            &coreui::ParseOptions::default(),
+            &extract::ExtractOptions {
+                unpremultiply,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        csi::decode_png(&std::fs::read(output_path.join("Edge.png")).unwrap()).unwrap()
+    };
+    let premultiplied = extract(false);
+    let unpremultiplied = extract(true);
+
+    assert_eq!(premultiplied.2, vec![0x40, 0x40, 0x40, 0x80, 0, 0, 0, 0]);
+    assert_eq!(unpremultiplied.2, vec![0x80, 0x80, 0x80, 0x80, 0, 0, 0, 0]);
+}
+
+#[test]
+fn extract_jpeg_lzfse() {
+    let jpeg = b"\xFF\xD8\xFF\xE0\x00\x10JFIF\x00\xFF\xD9";
+    let lzfse = |data: &[u8]| {
+        let mut compressed = vec![];
+        lzfse_rust::encode_bytes(data, &mut compressed).unwrap();
+        compressed
+    };
+    let car = common::fixtures::CatalogSpec::new()
+        .compressed_image(
+            "Photo.jpg",
+            (1, 1),
+            PixelFormat::JPEG,
+            rendition::CompressionType::JPEGLZFSE,
+            &lzfse(jpeg),
+        )
+        .compressed_image(
+            "Broken.jpg",
+            (1, 1),
+            PixelFormat::JPEG,
+            rendition::CompressionType::JPEGLZFSE,
+            &lzfse(b"not a jpeg"),
+        )
+        .open();
+    let output_path = common::temp_dir("jpeg");
+    let summary = extract::extract_all(
+        &car,
+        &output_path,
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions::default(),
+    )
+    .unwrap();
+    let photo = std::fs::read(output_path.join("Photo.jpg")).unwrap();
+    let broken = std::fs::read(output_path.join("Broken.bin")).unwrap();
+
+    assert_eq!(summary.written, vec!["Broken.bin", "Photo.jpg"]);
+    assert_eq!(photo, jpeg);
+    assert_eq!(broken, b"not a jpeg");
+    assert_eq!(
+        summary.warnings,
+        vec!["Broken.jpg: jpeg-lzfse payload isn't a jpeg (starts with 6e6f742061206a70), wrote the decoded bytes"]
+    );
+}
+
+#[test]
+fn extract_color_palettes() {
+    let car = common::fixtures::CatalogSpec::new()
+        .images("Icon", &[1], &[0], &[0])
+        .color(
+            "Tint",
+            &[(0, [1.0, 0.0, 0.0, 0.5]), (1, [0.0, 0.0, 1.0, 1.0])],
+        )
+        .color("Brand/Primary", &[(0, [0.2, 0.4, 0.6, 1.0])])
+        .open();
+    let output_path = common::temp_dir("palette");
+    let extract = |colors: &str| {
+        extract::extract_all(
+            &car,
+            &output_path,
+            &coreui::ParseOptions::default(),
+            &extract::ExtractOptions {
+                colors: Some(colors.parse().unwrap()),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let json_summary = extract("json");
+    let css_summary = extract("css");
+    let json = std::fs::read(output_path.join("colors.json")).unwrap();
+    let css = std::fs::read_to_string(output_path.join("colors.css")).unwrap();
+
+    assert_eq!(json_summary.written, vec!["Icon.png", "colors.json"]);
+    assert_eq!(css_summary.written, vec!["Icon.png", "colors.css"]);
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "any": {
+                "Brand/Primary": {"Colorspace": "srgb", "Components": [0.2, 0.4, 0.6, 1]},
+                "Tint": {"Colorspace": "srgb", "Components": [1, 0, 0, 0.5]},
+            },
+            "dark": {
+                "Tint": {"Colorspace": "srgb", "Components": [0, 0, 1, 1]},
+            },
+        })
+    );
+    assert_eq!(
+        css,
+        ":root {\n  --Brand-Primary: rgba(51, 102, 153, 1);\n  --Tint: rgba(255, 0, 0, 0.5);\n  --Tint-dark: rgba(0, 0, 255, 1);\n}\n"
+    );
+    assert!("scss".parse::<extract::ColorPalette>().is_err());
+}
+
+#[test]
+fn extract_color_palette_color_spaces() {
+    let color = |flags| {
+        common::csi_header(
+            rendition::LayoutType32::Color,
+            "",
+            0,
+            0,
+            PixelFormat::None,
+            vec![],
+            Some(rendition::Rendition::Color {
+                version: 1,
+                flags: rendition::ColorFlags(flags),
+                component_count: 4,
+                components: vec![1.0, 0.5, 0.0, 1.0],
+            }),
+        )
+    };
+    let store = common::storage(
+        vec![common::facet("Wide", 1), common::facet("Odd", 2)],
+        vec![
+            (common::key(0, 0, 0, 1), color(2)),
+            (common::key(0, 0, 0, 2), color(0xff)),
+        ],
+        None,
+    );
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let output_path = common::temp_dir("palette-spaces");
+    let extract = |colors: &str| {
+        extract::extract_all(
+            &car,
+            &output_path,
+            &coreui::ParseOptions::default(),
+            &extract::ExtractOptions {
+                colors: Some(colors.parse().unwrap()),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    extract("json");
+    extract("css");
+    let json = std::fs::read(output_path.join("colors.json")).unwrap();
+    let css = std::fs::read_to_string(output_path.join("colors.css")).unwrap();
+
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(json["any"]["Wide"]["Colorspace"], "p3");
+    assert_eq!(json["any"]["Odd"]["Colorspace"], "srgb");
+    assert_eq!(
+        css,
+        ":root {\n  --Odd: rgba(255, 128, 0, 1);\n  --Wide: color(display-p3 1 0.5 0 / 1);\n}\n"
+    );
+}
+
+#[test]
+fn name_patterns() {
+    let pattern = |glob: &str| glob.parse::<carutil_lib::NamePattern>().unwrap();
+    assert!(pattern("AppIcon*").matches("AppIcon"));
+    assert!(pattern("*@3x.png").matches("Icon@3x.png"));
+    assert!(!pattern("*@3x.png").matches("Icon@2x.png"));
+    assert!(pattern("Brand/*").matches("Brand/Buttons/Primary"));
+    assert!(pattern("Icon?").matches("Icon2"));
+    assert!(!pattern("Icon?").matches("Icon"));
+    assert!(pattern("*a*b*").matches("xaxxbx"));
+    assert!(!pattern("appicon*").matches("AppIcon"));
+    assert!(pattern("Icon").matches_names(Some("Icon"), "Icon@2x.png"));
+    assert!("".parse::<carutil_lib::NamePattern>().is_err());
+}
+
+#[test]
+fn extract_name_filter() {
+    let car = common::fixtures::CatalogSpec::new()
+        .images("AppIcon", &[1, 2, 3], &[0], &[0])
+        .images("Banner", &[2, 3], &[0], &[0])
+        .open();
+    let output_path = common::temp_dir("name-filter");
+    let extract = |glob: &str| {
+        extract::extract_all(
+            &car,
+            &output_path,
+            &coreui::ParseOptions::default(),
+            &extract::ExtractOptions {
+                name_pattern: Some(glob.parse().unwrap()),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let by_facet = extract("AppIcon*");
+    let by_rendition = extract("*@3x.png");
+    let unfiltered = extract::extract_all(
+        &car,
+        &output_path,
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions::default(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        by_facet.written,
+        vec!["AppIcon.png", "AppIcon@2x.png", "AppIcon@3x.png"]
+    );
+    assert_eq!(
+        by_facet.name_filter,
+        Some(extract::NameFilterCounts {
+            matched: 3,
+            excluded: 2
+        })
+    );
+    assert_eq!(
+        by_rendition.written,
+        vec!["AppIcon@3x.png", "Banner@3x.png"]
+    );
+    assert_eq!(
+        by_rendition.summary_line(),
+        "2 renditions matched the filter, 3 excluded, 2 files written, 0 skipped, 0 failed"
+    );
+    assert_eq!(unfiltered.name_filter, None);
+    assert_eq!(
+        unfiltered.summary_line(),
+        "5 files written, 0 skipped, 0 failed"
+    );
+}
+
+#[test]
+fn extract_jobs_report_identically() {
+    // more renditions than are decoded in one batch, and some that fail
+    let lzfse = |data: &[u8]| {
+        let mut compressed = vec![];
+        lzfse_rust::encode_bytes(data, &mut compressed).unwrap();
+        compressed
+    };
+    let car = common::fixtures::CatalogSpec::new()
+        .images("Icon", &[1, 2, 3], &[0, 1, 2, 3, 4, 5, 6, 7], &[0, 1, 2, 3])
+        .images(
+            "Banner",
+            &[1, 2, 3],
+            &[0, 1, 2, 3, 4, 5, 6, 7],
+            &[0, 1, 2, 3],
+        )
+        .images("Logo", &[1, 2, 3], &[0, 1, 2, 3, 4, 5, 6, 7], &[0, 1, 2, 3])
+        .compressed_image(
+            "Broken.png",
+            (4, 4),
+            PixelFormat::ARGB,
+            rendition::CompressionType::LZFSE,
+            &lzfse(b"too short"),
+        )
+        .compressed_image(
+            "Corrupt.png",
+            (4, 4),
+            PixelFormat::ARGB,
+            rendition::CompressionType::LZFSE,
+            &lzfse(&[0; 65]),
+        )
+        .open();
+    let extract = |jobs: usize| {
+        let output_path = common::temp_dir(&format!("jobs-{}", jobs));
+        extract::extract_all(
+            &car,
+            &output_path,
+            &coreui::ParseOptions::default(),
+            &extract::ExtractOptions {
+                jobs,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let sequential = extract(1);
+    assert_eq!(sequential.written.len(), 288);
+    assert_eq!(sequential.failed.len(), 2, "{:?}", sequential.failed);
+    assert_eq!(extract(4), sequential);
+    assert_eq!(extract(0), sequential);
+}
+
+#[test]
+fn texture_renditions_are_unknown() {
+    // the texture container hasn't been checked against a texture set Xcode
+    // compiled, so it's kept as an unknown payload
+    let data: Vec<u8> = (0..44).collect();
+    let texture = rendition::Rendition::Unknown {
+        tag: u32::from_le_bytes(*b"RTXT"),
+        version: 1,
+        _raw_data_length: data.len() as u32,
+        raw_data: carutil_lib::RawData(data.clone()),
+    };
+    let store = common::storage(
+        vec![common::facet("Noise", 1)],
+        vec![(
+            common::key(0, 1, 0, 1),
+            common::csi_header(
+                rendition::LayoutType32::TextureImage,
+                "Noise",
+                4,
+                2,
+                PixelFormat::None,
+                vec![],
+                Some(texture),
+            ),
+        )],
+        None,
+    );
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+    let directory = common::temp_dir("texture");
+    let car_path = directory.join("Assets.car");
+    car.write_data(car_path.to_str().unwrap()).unwrap();
+    let read = coreui::CarUtilAssetStorage::from(car_path.to_str().unwrap(), false).unwrap();
+    let output_path = directory.join("out");
+    std::fs::create_dir_all(&output_path).unwrap();
+    let summary = extract::extract_all(
+        &read,
+        output_path.to_str().unwrap(),
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions::default(),
+    )
+    .unwrap();
+
+    assert!(summary.failed.is_empty(), "{:?}", summary.failed);
+    assert!(summary.written.is_empty());
+    assert_eq!(
+        summary.skipped,
+        vec!["Noise: unsupported rendition payload tag TXTR (44 bytes), extract it with --raw"]
+    );
+
+    let store = &read.theme_store.store;
+    let entries = carutil_lib::assetutil::AssetUtilEntry::entries_from_asset_storage(store);
+    let entry = serde_json::to_value(&entries[0]).unwrap();
+    assert_eq!(entry["AssetType"], "Texture Image");
+    assert_eq!(entry["RenditionPayloadTag"], "TXTR");
+    assert_eq!(entry["RenditionPayloadLength"], 44);
+    assert!(entry.get("TextureFormat").is_none());
+    let csi_header = store.imagedb.values().next().unwrap();
+    assert_eq!(
+        extract::raw_payload(csi_header).unwrap(),
+        ("unknown".to_string(), data)
+    );
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use common::fixtures::CatalogSpec;
+
+use carutil_lib::assetutil;
+use carutil_lib::coreui;
+use carutil_lib::coreui::rendition;
+use carutil_lib::verify;
+
+fn everything() -> CatalogSpec {
+    CatalogSpec::new()
+        .images("Icon", &[1, 2, 3], &[0, 2], &[0, 1])
+        .images("Banner", &[2], &[1], &[0])
+        .data(
+            "config.json",
+            rendition::CompressionType::Uncompressed,
+            b"{\"retries\": 3}",
+        )
+        .color(
+            "Tint",
+            &[(0, [1.0, 0.0, 0.0, 1.0]), (1, [0.0, 0.0, 1.0, 1.0])],
+        )
+        .icon_set("AppIcon", &[(20, 1), (29, 2)])
+}
+
+#[test]
+fn generated_catalog_passes_verify() {
+    let path = everything().path();
+    let report = verify::verify(&path).unwrap();
+    assert!(report.is_ok(), "{:?}", report.lines());
+    // the same spec is written once
+    assert_eq!(everything().path(), path);
+    assert_ne!(
+        CatalogSpec::new().images("Icon", &[1], &[0], &[0]).path(),
+        path
+    );
+}
+
+#[test]
+fn generated_catalog_entries() {
+    let car = everything().open();
+    let store = &car.theme_store.store;
+    assert_eq!(
+        store.facet_names(),
+        vec!["AppIcon", "Banner", "Icon", "Tint", "config.json"]
+    );
+    let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(store);
+    let count = |asset_type: &str| {
+        entries
+            .iter()
+            .filter(|entry| entry.asset_type.as_deref() == Some(asset_type))
+            .count()
+    };
+    assert_eq!(count("Image"), 13);
+    assert_eq!(count("Color"), 2);
+    assert_eq!(count("Data"), 1);
+    assert_eq!(count("MultiSized Image"), 1);
+
+    let mut names: Vec<String> = entries
+        .iter()
+        .filter(|entry| entry.name.as_deref() == Some("Icon"))
+        .filter_map(|entry| entry.rendition_name.clone())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names[..3],
+        ["Icon-dark.png", "Icon-dark@2x.png", "Icon-dark@2x~ipad.png"]
+    );
+}
+
+#[test]
+fn layered_image() {
+    // the layer stack container isn't parsed, it's read as an unknown payload
+    let spec = CatalogSpec::new().layer_stack(
+        "TopShelf",
+        (400, 240),
+        &["TopShelf-Back", "TopShelf-Front"],
+        &[7; 40],
+    );
+    let report = verify::verify(spec.path()).unwrap();
+    assert!(report.is_ok(), "{:?}", report.lines());
+    let car = spec.open();
+    let store = &car.theme_store.store;
+    let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(store);
+    let stack = entries
+        .iter()
+        .find(|entry| entry.asset_type.as_deref() == Some("Layer Stack"))
+        .expect("no layer stack entry");
+    assert_eq!(stack.name.as_deref(), Some("TopShelf"));
+    assert_eq!(stack.rendition_payload_tag.as_deref(), Some("LYRS"));
+    assert_eq!(stack.rendition_payload_length, Some(40));
+    // the layers' own images are plain image entries
+    assert_eq!(
+        entries
+            .iter()
+            .filter(|entry| entry.asset_type.as_deref() == Some("Image"))
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn corrupted_catalogs() {
+    let spec = CatalogSpec::new().images("Icon", &[1], &[0], &[0]);
+    assert!(
+        coreui::CarUtilAssetStorage::from(spec.clone().patch(0, b"XOMStore").path(), false)
+            .is_err()
+    );
+    let length = std::fs::metadata(spec.path()).unwrap().len() as usize;
+    let truncated = spec.truncate(length / 2).path();
+    assert!(coreui::CarUtilAssetStorage::from(&truncated, false).is_err());
+    assert!(verify::verify(&truncated).is_err());
+}
@@ -0,0 +1,37 @@
+use carutil_lib::assetutil;
+use carutil_lib::coreui;
+use std::path::PathBuf;
+
+// crash inputs found by the fuzz targets, minimized down to the bytes that
+// differ from the catalog they were mutated from. Each one is run through the
+// same code as its target and has to come back without a panic or an abort
+fn inputs(target: &str) -> Vec<(PathBuf, Vec<u8>)> {
+    let directory = PathBuf::from("tests/fuzz_regressions").join(target);
+    let mut inputs: Vec<(PathBuf, Vec<u8>)> = std::fs::read_dir(&directory)
+        .expect("Unable to read the fuzz regressions")
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let data = std::fs::read(&path).unwrap();
+            (path, data)
+        })
+        .collect();
+    inputs.sort();
+    inputs
+}
+
+#[test]
+fn asset_storage_regressions() {
+    let inputs = inputs("asset_storage");
+    assert!(!inputs.is_empty());
+    for (path, data) in inputs {
+        println!("{}", path.display());
+        let Ok(car) = coreui::CarUtilAssetStorage::from_bytes(data) else {
+            continue;
+        };
+        let store = &car.theme_store.store;
+        let _ = assetutil::AssetUtilEntry::entries_from_asset_storage(store);
+        for csi_header in store.imagedb.values() {
+            let _ = csi_header.extracted_file();
+        }
+    }
+}
@@ -0,0 +1,22 @@
+mod common;
+
+use carutil_lib::coreui;
+
+#[test]
+fn sniff_directories_and_files() {
+    let directory = common::temp_dir("input");
+    let catalog = directory.join("Media.xcassets");
+    std::fs::create_dir_all(&catalog).unwrap();
+    std::fs::write(catalog.join("Contents.json"), b"{\"info\":{\"version\":1}}").unwrap();
+    let image = directory.join("Icon.png");
+    std::fs::write(&image, b"\x89PNG\r\n\x1a\n").unwrap();
+
+    let err = coreui::CarUtilAssetStorage::from(catalog.to_str().unwrap(), false)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("actool --compile"), "{}", err);
+    let err = coreui::CarUtilAssetStorage::from(image.to_str().unwrap(), false)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("is a PNG image"), "{}", err);
+}
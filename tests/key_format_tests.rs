@@ -0,0 +1,288 @@
+mod common;
+
+use carutil_lib::coreui::rendition::AttributeType;
+use carutil_lib::coreui::rendition::Key;
+use carutil_lib::coreui::rendition::KeyFormat;
+
+// deterministic xorshift so failures are reproducible
+fn next(seed: &mut u64) -> u64 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 7;
+    *seed ^= *seed << 17;
+    *seed
+}
+
+#[test]
+fn reencode_moves_values_by_attribute() {
+    let source = KeyFormat::new(common::KEY_FORMAT.to_vec());
+    let target = KeyFormat::new(vec![
+        AttributeType::Identifier,
+        AttributeType::DisplayGamut,
+        AttributeType::Scale,
+        AttributeType::Appearance,
+        AttributeType::Idiom,
+        AttributeType::State,
+    ]);
+    let key = common::key(1, 2, 3, 44959);
+    let reencoded = source.reencode(&key, &target).unwrap();
+    assert_eq!(reencoded.raw[..6], [44959, 0, 2, 1, 3, 0]);
+    assert!(reencoded.raw[6..].iter().all(|value| *value == 0));
+}
+
+#[test]
+fn reencode_refuses_to_drop_values() {
+    let source = KeyFormat::new(common::KEY_FORMAT.to_vec());
+    let target = KeyFormat::new(vec![AttributeType::Identifier, AttributeType::Scale]);
+    // appearance 0 and idiom 0 can be dropped
+    assert!(source.reencode(&common::key(0, 2, 0, 7), &target).is_ok());
+    let err = source
+        .reencode(&common::key(1, 2, 0, 7), &target)
+        .unwrap_err();
+    assert!(err.to_string().contains("Appearance"), "{}", err);
+}
+
+#[test]
+fn reencode_round_trips_through_a_superset() {
+    let all: Vec<AttributeType> = common::KEY_FORMAT
+        .iter()
+        .copied()
+        .chain([
+            AttributeType::Subtype,
+            AttributeType::DisplayGamut,
+            AttributeType::Direction,
+            AttributeType::SizeClassHorizontal,
+            AttributeType::SizeClassVertical,
+            AttributeType::MemoryClass,
+            AttributeType::GraphicsClass,
+            AttributeType::DeploymentTarget,
+        ])
+        .collect();
+    let mut seed = 0x1701;
+    for _ in 0..256 {
+        // A is a prefix of a shuffled list, B is A plus more attributes in a new order
+        let mut shuffled = all.clone();
+        for i in (1..shuffled.len()).rev() {
+            shuffled.swap(i, next(&mut seed) as usize % (i + 1));
+        }
+        let a_count = 1 + next(&mut seed) as usize % shuffled.len();
+        let a = KeyFormat::new(shuffled[..a_count].to_vec());
+        let mut b_types = shuffled.clone();
+        b_types.swap(0, shuffled.len() - 1);
+        let b = KeyFormat::new(b_types);
+
+        let mut raw = [0u16; 18];
+        for value in raw.iter_mut().take(a_count) {
+            *value = next(&mut seed) as u16;
+        }
+        let key = Key { raw };
+        let there = a.reencode(&key, &b).unwrap();
+        assert_eq!(b.reencode(&there, &a).unwrap(), key);
+    }
+}
+
+// downstream tools parse these, changing them is a breaking change
+#[test]
+fn display_and_debug_formats() {
+    use carutil_lib::bom::BlockRange;
+    use carutil_lib::coreui::bitmap;
+    use carutil_lib::coreui::rendition::Attribute;
+    use carutil_lib::coreui::rendition::AttributeType16;
+    use carutil_lib::coreui::rendition::KeyToken;
+
+    let key_format = KeyFormat::new(common::KEY_FORMAT.to_vec());
+    let key = common::key(1, 2, 2, 32625);
+    assert_eq!(
+        key.display_with(&key_format).to_string(),
+        "appearance=1 scale=2 idiom=pad identifier=32625"
+    );
+    assert_eq!(
+        common::key(0, 1, 12, 3)
+            .display_with(&key_format)
+            .to_string(),
+        "scale=1 idiom=12 identifier=3"
+    );
+    assert_eq!(key.to_string(), key.to_hex());
+    assert_eq!(
+        format!("{:?}", key),
+        "RenditionKey { 1, 2, 2, 32625, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0 }"
+    );
+    let map = key.to_map(&key_format);
+    assert_eq!(
+        map.keys().collect::<Vec<_>>(),
+        vec!["appearance", "identifier", "idiom", "scale", "state"]
+    );
+    assert_eq!(map["identifier"], 32625);
+    assert_eq!(map["state"], 0);
+
+    let key_token = KeyToken::new(vec![
+        Attribute {
+            name: AttributeType16::Element,
+            value: 85,
+        },
+        Attribute {
+            name: AttributeType16::Identifier,
+            value: 0,
+        },
+    ]);
+    assert_eq!(key_token.to_string(), "element=85 identifier=0");
+    assert_eq!(
+        format!("{:?}", key_token),
+        "KeyToken { cursor_hotspot: (0, 0),  attributes: [Attribute { name: Element, value: 85 }, Attribute { name: Identifier, value: 0 }] }"
+    );
+
+    let bitmap_key = bitmap::Key::for_scales([1]);
+    assert_eq!(bitmap_key.to_string(), bitmap_key.to_hex());
+    assert_eq!(
+        format!("{:?}", bitmap_key),
+        "BitmapKey { 1, 0, 0, 0, 76, 0, 18, 0, 65535, 65535, 2 }"
+    );
+
+    let range = BlockRange {
+        address: 0x200,
+        length: 16,
+    };
+    assert_eq!(range.to_string(), "0x200..0x210");
+    assert_eq!(
+        format!("{:?}", range),
+        "BlockRange { address: 0x200, length: 16 }"
+    );
+}
+
+fn three_values(first: u16, second: u16, third: u16) -> Key {
+    let mut raw = [0u16; 18];
+    raw[..3].copy_from_slice(&[first, second, third]);
+    Key { raw }
+}
+
+#[test]
+fn three_attribute_key_format_falls_back() {
+    use carutil_lib::assetutil;
+    use carutil_lib::coreui::csi::PixelFormat;
+    use carutil_lib::coreui::rendition::LayoutType32;
+
+    let mut store = common::storage(
+        vec![],
+        vec![
+            (
+                three_values(1, 2, 2),
+                common::csi_header(
+                    LayoutType32::Image,
+                    "Icon@2x~ipad.png",
+                    1,
+                    1,
+                    PixelFormat::ARGB,
+                    vec![],
+                    Some(common::raw_data(&[0; 4])),
+                ),
+            ),
+            (
+                three_values(1, 1, 0),
+                common::csi_header(
+                    LayoutType32::Image,
+                    "Badge.png",
+                    1,
+                    1,
+                    PixelFormat::ARGB,
+                    vec![],
+                    Some(common::raw_data(&[0; 4])),
+                ),
+            ),
+        ],
+        None,
+    );
+    store.renditionkeyfmt = KeyFormat::new(vec![
+        AttributeType::Element,
+        AttributeType::Scale,
+        AttributeType::Idiom,
+    ]);
+    assert_eq!(
+        store.key_format_warnings(),
+        vec!["key format has no Identifier attribute; names will be derived from rendition names"]
+    );
+    store.renditionkeyfmt = KeyFormat::new(vec![
+        AttributeType::Element,
+        AttributeType::Part,
+        AttributeType::State,
+    ]);
+    assert_eq!(
+        store.key_format_warnings(),
+        vec![
+            "key format has no Identifier attribute; names will be derived from rendition names",
+            "key format has no Scale attribute; scales are taken from the csi headers",
+            "key format has no Idiom attribute; entries will have no Idiom",
+        ]
+    );
+
+    let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(&store);
+    let fields: Vec<serde_json::Value> = entries
+        .iter()
+        .map(|entry| {
+            let value = serde_json::to_value(entry).unwrap();
+            serde_json::json!([value["Name"], value["Scale"], value["Idiom"]])
+        })
+        .collect();
+    assert_eq!(
+        fields,
+        vec![
+            serde_json::json!(["Badge", 1, null]),
+            serde_json::json!(["Icon", 1, null]),
+        ]
+    );
+    assert_eq!(
+        assetutil::facet_name_from_rendition_name("Icon@3x.png").as_deref(),
+        Some("Icon")
+    );
+    assert_eq!(
+        assetutil::facet_name_from_rendition_name("user@host.txt").as_deref(),
+        Some("user@host")
+    );
+    assert_eq!(assetutil::facet_name_from_rendition_name("  "), None);
+}
+
+#[test]
+fn unknown_attribute_ids() {
+    use binrw::{BinRead, BinWrite};
+    use carutil_lib::coreui::rendition::AttributeType16;
+    use carutil_lib::filter::AttributeRequirement;
+    use std::collections::BTreeMap;
+
+    // a key format from a newer CoreUI with an attribute id we don't know
+    let mut data = b"tmfk".to_vec();
+    for value in [1u32, 3, 12, 40, 17] {
+        data.extend(value.to_le_bytes());
+    }
+    let key_format = KeyFormat::read(&mut std::io::Cursor::new(&data)).unwrap();
+    assert_eq!(
+        key_format.attribute_types,
+        [
+            AttributeType::Scale,
+            AttributeType::Unknown(40),
+            AttributeType::Identifier
+        ]
+    );
+    let mut written = std::io::Cursor::new(vec![]);
+    key_format.write(&mut written).unwrap();
+    assert_eq!(written.into_inner(), data);
+
+    assert_eq!(AttributeType::Unknown(40).to_string(), "Unknown40");
+    assert_eq!(
+        serde_json::to_value(AttributeType::Unknown(40)).unwrap(),
+        "kCRThemeUnknown40Name"
+    );
+    assert_eq!(AttributeType16::from(40), AttributeType16::Unknown(40));
+    assert_eq!(
+        AttributeType::from(AttributeType16::Unknown(40)),
+        AttributeType::Unknown(40)
+    );
+
+    let key = Key {
+        raw: [2, 5, 7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    };
+    assert_eq!(
+        key.display_with(&key_format).to_string(),
+        "scale=2 unknown40=5 identifier=7"
+    );
+    let requirement = AttributeRequirement::parse("40=5", &BTreeMap::new()).unwrap();
+    assert_eq!(requirement.attribute, AttributeType::Unknown(40));
+    assert!(requirement.matches(&key_format.map(&key)));
+}
@@ -0,0 +1,17 @@
+use carutil_lib::coreui::csi::OutputFormat;
+use carutil_lib::extract;
+
+#[test]
+fn output_file_name_is_portable() {
+    let mut used = extract::OutputNames::default();
+    assert_eq!(
+        extract::output_file_name("Clock 12:30", OutputFormat::Png, Some(1), &mut used),
+        "Clock 12_30.png"
+    );
+    // the same file once it's portable
+    assert_eq!(
+        extract::output_file_name("Clock 12?30", OutputFormat::Png, Some(2), &mut used),
+        "Clock 12_30-2.png"
+    );
+    assert!(used.contains("Clock 12:30.png"));
+}
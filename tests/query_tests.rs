@@ -0,0 +1,175 @@
+mod common;
+
+use carutil_lib::coreui;
+
+fn theme_store() -> coreui::StructuredThemeStore {
+    let renditions = vec![
+        (common::key(0, 1, 0, 1), common::image("Icon.png", 1, 1)),
+        (common::key(0, 2, 0, 1), common::image("Icon@2x.png", 2, 2)),
+        (common::key(0, 3, 0, 1), common::image("Icon@3x.png", 3, 3)),
+        (
+            common::key(1, 2, 0, 1),
+            common::image("Icon-dark@2x.png", 2, 2),
+        ),
+        (
+            common::key(0, 2, 2, 1),
+            common::image("Icon~ipad@2x.png", 2, 2),
+        ),
+        (common::key(0, 2, 0, 2), common::image("Badge@2x.png", 2, 2)),
+    ];
+    let appearances = [("UIAppearanceAny", 0), ("UIAppearanceDark", 1)]
+        .into_iter()
+        .map(|(name, identifier)| (name.to_string(), identifier))
+        .collect();
+    coreui::StructuredThemeStore {
+        store: common::storage(
+            vec![common::facet("Icon", 1), common::facet("Badge", 2)],
+            renditions,
+            Some(appearances),
+        ),
+    }
+}
+
+fn resolve(
+    theme_store: &coreui::StructuredThemeStore,
+    name: &str,
+    query: coreui::RenditionQuery,
+) -> Option<String> {
+    theme_store
+        .best_rendition(name, &query)
+        .map(|csi_header| csi_header.csimetadata.name())
+}
+
+#[test]
+fn best_rendition_exact_scale() {
+    let theme_store = theme_store();
+    let query = |scale| coreui::RenditionQuery {
+        scale: Some(scale),
+        ..Default::default()
+    };
+    assert_eq!(
+        resolve(&theme_store, "Icon", query(1)).as_deref(),
+        Some("Icon.png")
+    );
+    assert_eq!(
+        resolve(&theme_store, "Icon", query(2)).as_deref(),
+        Some("Icon@2x.png")
+    );
+    assert_eq!(
+        resolve(&theme_store, "Icon", query(3)).as_deref(),
+        Some("Icon@3x.png")
+    );
+}
+
+#[test]
+fn best_rendition_scale_fallback() {
+    let theme_store = theme_store();
+    let query = |scale| coreui::RenditionQuery {
+        scale: Some(scale),
+        ..Default::default()
+    };
+    // downward first
+    assert_eq!(
+        resolve(&theme_store, "Icon", query(4)).as_deref(),
+        Some("Icon@3x.png")
+    );
+    // then upward
+    assert_eq!(
+        resolve(&theme_store, "Badge", query(1)).as_deref(),
+        Some("Badge@2x.png")
+    );
+}
+
+#[test]
+fn best_rendition_attributes() {
+    let theme_store = theme_store();
+    let dark = coreui::RenditionQuery {
+        scale: Some(3),
+        appearance: Some(1),
+        ..Default::default()
+    };
+    // the dark variant wins over the closer scale
+    assert_eq!(
+        resolve(&theme_store, "Icon", dark).as_deref(),
+        Some("Icon-dark@2x.png")
+    );
+
+    let pad = coreui::RenditionQuery {
+        scale: Some(2),
+        idiom: Some(2),
+        ..Default::default()
+    };
+    assert_eq!(
+        resolve(&theme_store, "Icon", pad).as_deref(),
+        Some("Icon~ipad@2x.png")
+    );
+
+    // universal renditions stand in for other idioms
+    let phone = coreui::RenditionQuery {
+        scale: Some(2),
+        idiom: Some(1),
+        ..Default::default()
+    };
+    assert_eq!(
+        resolve(&theme_store, "Icon", phone).as_deref(),
+        Some("Icon@2x.png")
+    );
+
+    // unknown names resolve to nothing
+    let tv = coreui::RenditionQuery {
+        scale: Some(2),
+        idiom: Some(3),
+        ..Default::default()
+    };
+    assert_eq!(
+        resolve(&theme_store, "Badge", tv.clone()).as_deref(),
+        Some("Badge@2x.png")
+    );
+    assert_eq!(resolve(&theme_store, "Missing", tv), None);
+}
+
+#[test]
+fn best_rendition_ignores_case() {
+    let theme_store = theme_store();
+    let query = |case_sensitive| coreui::RenditionQuery {
+        scale: Some(2),
+        case_sensitive,
+        ..Default::default()
+    };
+    assert_eq!(
+        resolve(&theme_store, "icon", query(false)).as_deref(),
+        Some("Icon@2x.png")
+    );
+    assert_eq!(
+        resolve(&theme_store, "BADGE", query(false)).as_deref(),
+        Some("Badge@2x.png")
+    );
+    assert_eq!(resolve(&theme_store, "icon", query(true)), None);
+    assert_eq!(
+        resolve(&theme_store, "Icon", query(true)).as_deref(),
+        Some("Icon@2x.png")
+    );
+}
+
+#[test]
+fn facet_names_compare_ignoring_case() {
+    use std::collections::HashSet;
+
+    let name = coreui::FacetName::new("AppIcon");
+    assert_eq!(name, coreui::FacetName::new("appicon"));
+    assert_ne!(name, coreui::FacetName::new("AppIcon2"));
+    assert!(name.matches("APPICON"));
+    assert!(name.contains("pic"));
+    assert_eq!(name.to_string(), "AppIcon");
+    assert_eq!(
+        coreui::FacetName::new("ÉCLAIR"),
+        coreui::FacetName::new("éclair")
+    );
+    let names: HashSet<coreui::FacetName> = ["AppIcon", "appIcon", "APPICON"]
+        .into_iter()
+        .map(coreui::FacetName::from)
+        .collect();
+    assert_eq!(names.len(), 1);
+    assert!(coreui::names_match("AppIcon", "appicon", false));
+    assert!(!coreui::names_match("AppIcon", "appicon", true));
+}
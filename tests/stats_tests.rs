@@ -0,0 +1,133 @@
+mod common;
+
+use carutil_lib::assetutil;
+use carutil_lib::coreui;
+use carutil_lib::stats;
+
+static CAR_PATH: &str = "./tests/Assets.car";
+
+#[test]
+fn fixture_stats() {
+    let report = stats::stats(CAR_PATH).unwrap();
+    let vars: Vec<(&str, u64)> = report
+        .vars
+        .iter()
+        .map(|var| (var.name.as_str(), var.bytes))
+        .collect();
+    assert_eq!(
+        vars,
+        [
+            ("CARHEADER", 436),
+            ("RENDITIONS", 24517),
+            ("FACETKEYS", 4235),
+            ("KEYFORMAT", 84),
+            ("EXTENDED_METADATA", 1028),
+            ("BITMAPKEYS", 1485),
+        ]
+    );
+    assert_eq!(report.file_size, 38248);
+    assert_eq!(report.bom_tables, 5610);
+    assert_eq!(report.unaccounted, 853);
+    assert_eq!(
+        report.bom_tables + report.unaccounted + vars.iter().map(|(_, bytes)| bytes).sum::<u64>(),
+        report.file_size
+    );
+    assert_eq!(
+        report.lines()[1],
+        "RENDITIONS                  24517 bytes  64.10%"
+    );
+    // nothing in the fixture is packed
+    assert_eq!(
+        report.packed,
+        Some(stats::PackedStats {
+            images: 0,
+            atlases: 0,
+            atlas_bytes: 0,
+        })
+    );
+    assert_eq!(
+        report.lines()[report.lines().len() - 2..],
+        [
+            "packed images                   0",
+            "atlas bytes                     0 bytes   0.00% in 0 atlases",
+        ]
+    );
+
+    // the renditions' csi headers are in there along with their keys and paths
+    let car = coreui::CarUtilAssetStorage::from(CAR_PATH, false).unwrap();
+    let payloads: u64 =
+        assetutil::AssetUtilEntry::entries_from_asset_storage(&car.theme_store.store)
+            .iter()
+            .filter_map(|entry| entry.size_on_disk)
+            .map(u64::from)
+            .sum();
+    let renditions = &report.vars[1];
+    assert!(renditions.bytes > payloads);
+    // tree block, path block, and a key and value block per rendition
+    assert_eq!(renditions.blocks, 2 + 2 * 7);
+}
+
+#[test]
+fn fixture_var_blocks() {
+    let var_blocks = stats::var_blocks(CAR_PATH).unwrap();
+    assert_eq!(
+        var_blocks[0],
+        stats::VarBlock {
+            name: "CARHEADER".to_string(),
+            block_id: 1,
+            address: Some(0x200),
+            length: Some(436),
+        }
+    );
+    let lines = stats::var_block_lines(&var_blocks);
+    assert_eq!(lines.len(), var_blocks.len() + 1);
+    assert_eq!(
+        lines[1],
+        "CARHEADER                   1      0x200        436"
+    );
+
+    // the CARHEADER block starts with the "RATC" magic, little endian
+    let header = stats::block_bytes(CAR_PATH, 1, 0, Some(4)).unwrap();
+    assert_eq!(header, b"RATC");
+    // paging stops at the end of the block
+    let tail = stats::block_bytes(CAR_PATH, 1, 430, Some(100)).unwrap();
+    assert_eq!(tail.len(), 6);
+    assert!(stats::block_bytes(CAR_PATH, 1, 436, None)
+        .unwrap()
+        .is_empty());
+    assert!(stats::block_bytes(CAR_PATH, 1, 437, None).is_err());
+    assert!(stats::block_bytes(CAR_PATH, 9999, 0, None).is_err());
+
+    // a block range pointing past the end of the file, near u32::MAX
+    let mut data = std::fs::read(CAR_PATH).unwrap();
+    let index_offset = u32::from_be_bytes(data[16..20].try_into().unwrap()) as usize;
+    let entry = index_offset + 4 + 8;
+    data[entry..entry + 4].copy_from_slice(&0xFFFF_FFF0u32.to_be_bytes());
+    let directory = common::temp_dir("block");
+    let path = directory.join("block.car");
+    std::fs::write(&path, &data).unwrap();
+    let err = stats::block_bytes(&path, 1, 0x20, Some(4)).unwrap_err();
+    assert!(
+        err.to_string().contains("past the end of the file"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn hexdump_rows() {
+    assert!(stats::hexdump(&[], 0).is_empty());
+    let data: Vec<u8> = (0x40..0x40 + 20).collect();
+    assert_eq!(
+        stats::hexdump(&data, 0x20),
+        vec![
+            "00000020  40 41 42 43 44 45 46 47  48 49 4a 4b 4c 4d 4e 4f  |@ABCDEFGHIJKLMNO|",
+            // partial row, padded so the ascii column lines up
+            "00000030  50 51 52 53                                       |PQRS|",
+        ]
+    );
+    assert_eq!(
+        stats::hexdump(b"\x00a\x7f\xff", 0),
+        vec!["00000000  00 61 7f ff                                       |.a..|"]
+    );
+}
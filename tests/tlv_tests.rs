@@ -0,0 +1,78 @@
+mod common;
+
+use carutil_lib::assetutil;
+use carutil_lib::coreui::csi;
+use carutil_lib::coreui::rendition;
+use carutil_lib::coreui::tlv;
+
+fn data_header(tlv_data: Vec<u8>) -> csi::Header {
+    common::csi_header(
+        rendition::LayoutType32::Data,
+        "CoreStructuredImage",
+        0,
+        0,
+        csi::PixelFormat::Data,
+        tlv_data,
+        Some(common::raw_data(b"{}")),
+    )
+}
+
+fn entry_uti(csi_header: &csi::Header) -> Option<String> {
+    assetutil::AssetUtilEntry::from_csi_header(
+        csi_header,
+        Some("MyData".to_string()),
+        vec![],
+        vec![],
+        &Default::default(),
+    )
+    .uti
+}
+
+#[test]
+fn uti_padded_beyond_string() {
+    // string_length covers 16 bytes of which only 11 are the UTI
+    let mut tlv_data = common::uti_tlv("public.json", 16);
+    // a tlv following the UTI must still be found
+    tlv_data.extend(common::uti_tlv("second.uti", 12));
+
+    let csi_header = data_header(tlv_data);
+    let utis: Vec<String> = csi_header
+        .properties()
+        .iter()
+        .filter_map(tlv::RenditionType::uti)
+        .collect();
+    assert_eq!(utis, vec!["public.json", "second.uti"]);
+    assert_eq!(entry_uti(&csi_header), Some("public.json".to_string()));
+    #[cfg(feature = "debug-impls")]
+    assert_eq!(
+        format!("{:?}", csi_header.properties()[0]),
+        "UTI { string: public.json }"
+    );
+}
+
+#[test]
+fn uti_string_region_longer_than_string_length() {
+    // string_length excludes the trailing padding in the tlv
+    let mut tlv_data = common::uti_tlv("public.json", 16);
+    tlv_data[8..12].copy_from_slice(&11u32.to_le_bytes());
+    tlv_data.extend(common::uti_tlv("second.uti", 12));
+
+    let utis: Vec<String> = data_header(tlv_data)
+        .properties()
+        .iter()
+        .filter_map(tlv::RenditionType::uti)
+        .collect();
+    assert_eq!(utis, vec!["public.json", "second.uti"]);
+}
+
+#[test]
+fn uti_fallback_only_when_absent() {
+    assert_eq!(
+        entry_uti(&data_header(vec![])),
+        Some("UTI-Unknown".to_string())
+    );
+    assert_eq!(
+        entry_uti(&data_header(common::uti_tlv("", 4))),
+        Some("".to_string())
+    );
+}
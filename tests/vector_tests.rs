@@ -0,0 +1,118 @@
+mod common;
+
+use carutil_lib::assetutil;
+use carutil_lib::coreui;
+use carutil_lib::coreui::csi::OutputFormat;
+use carutil_lib::coreui::csi::PixelFormat;
+use carutil_lib::coreui::rendition;
+use carutil_lib::extract;
+
+fn symbol_car() -> coreui::CarUtilAssetStorage {
+    coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore {
+            store: common::symbol_storage(),
+        },
+    }
+}
+
+#[test]
+fn svg_sniffed() {
+    assert_eq!(
+        OutputFormat::sniff(common::SYMBOL_SVG, PixelFormat::Data),
+        OutputFormat::Svg
+    );
+    assert_eq!(
+        OutputFormat::sniff(b"\xEF\xBB\xBF  <svg/>", PixelFormat::Data),
+        OutputFormat::Svg
+    );
+    // xml, but not an svg
+    assert_eq!(
+        OutputFormat::sniff(b"<?xml version=\"1.0\"?><plist/>", PixelFormat::Data),
+        OutputFormat::Data
+    );
+    assert_eq!(OutputFormat::Svg.file_name("star"), "star.svg");
+}
+
+#[test]
+fn symbol_catalog_round_trip() {
+    let directory = common::temp_dir("symbols");
+    let path = directory.join("symbols.car");
+    symbol_car().write_data(path.to_str().unwrap()).unwrap();
+    let car = coreui::CarUtilAssetStorage::from(path.to_str().unwrap(), false).unwrap();
+    let store = &car.theme_store.store;
+
+    assert_eq!(
+        store.renditionkeyfmt.attribute_types,
+        common::SYMBOL_KEY_FORMAT.to_vec()
+    );
+    let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(store);
+    let fields: Vec<serde_json::Value> = entries
+        .iter()
+        .map(|entry| {
+            let value = serde_json::to_value(entry).unwrap();
+            serde_json::json!([
+                value["AssetType"],
+                value["Name"],
+                value["GlyphWeight"],
+                value["GlyphSize"],
+                value["VectorFormat"],
+            ])
+        })
+        .collect();
+    assert_eq!(
+        fields,
+        vec![
+            serde_json::json!(["Vector", "star.fill", "regular", "medium", "svg"]),
+            serde_json::json!(["Vector", "star.fill", "bold", "medium", "svg"]),
+            serde_json::json!(["Vector", "star.fill", "black", "large", "unknown"]),
+        ]
+    );
+}
+
+#[test]
+fn symbol_summary_lists_weights() {
+    let mut store = common::symbol_storage();
+    // a weight we have no name for
+    let csi_header = store.imagedb.values().next().unwrap().clone();
+    store
+        .imagedb
+        .insert(common::symbol_key(7, 12, 1), csi_header);
+    let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(&store);
+    let summaries = assetutil::FacetSummary::from_entries(&entries);
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(
+        summaries[0].to_string(),
+        "star.fill  vector  1x  any  universal  weights:regular,bold,black,12  4 variants"
+    );
+    assert_eq!(
+        assetutil::asset_type_name(rendition::LayoutType32::Vector),
+        Some("Vector")
+    );
+}
+
+#[test]
+fn extract_symbol_variants() {
+    let output_path = common::temp_dir("symbol-extract");
+    let summary = extract::extract_all(
+        &symbol_car(),
+        output_path.to_str().unwrap(),
+        &coreui::ParseOptions::default(),
+        &extract::ExtractOptions::default(),
+    )
+    .unwrap();
+    let svg = std::fs::read(output_path.join("star.fill_1.svg")).unwrap();
+    let unknown = std::fs::read(output_path.join("star.fill_2.dat")).unwrap();
+
+    assert_eq!(
+        summary.written,
+        vec!["star.fill_0.svg", "star.fill_1.svg", "star.fill_2.dat"]
+    );
+    assert_eq!(svg, common::SYMBOL_SVG);
+    assert_eq!(unknown, b"CSVG\x02\x00\x00\x00");
+    assert_eq!(
+        summary.warnings,
+        vec![
+            "star.fill.svg: vector payload isn't a pdf or svg (starts with 4353564702000000), wrote the stored bytes"
+        ]
+    );
+}
@@ -0,0 +1,250 @@
+mod common;
+
+use carutil_lib::verify;
+
+#[test]
+fn duplicate_keys() {
+    use binrw::BinWrite;
+    use sha2::Digest;
+
+    let stored = |csi_header: carutil_lib::csi::Header| {
+        let mut writer = std::io::Cursor::new(vec![]);
+        csi_header.write(&mut writer).unwrap();
+        writer.into_inner()
+    };
+    let mut other = common::image("b.png", 1, 1);
+    other.rendition_data = Some(common::raw_data(&[1, 2, 3, 4]));
+    let renditions = [
+        (
+            common::key(0, 1, 0, 1),
+            stored(common::image("a.png", 1, 1)),
+        ),
+        (
+            common::key(0, 2, 0, 1),
+            stored(common::image("a@2x.png", 2, 2)),
+        ),
+        (common::key(0, 1, 0, 1), stored(other)),
+        // the same bytes twice aren't a conflict
+        (
+            common::key(0, 3, 0, 1),
+            stored(common::image("a@3x.png", 3, 3)),
+        ),
+        (
+            common::key(0, 3, 0, 1),
+            stored(common::image("a@3x.png", 3, 3)),
+        ),
+    ];
+    let duplicates = verify::duplicate_keys(
+        renditions
+            .iter()
+            .map(|(key, value)| (*key, value.as_slice())),
+    )
+    .unwrap();
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].key, common::key(0, 1, 0, 1));
+    let names: Vec<&str> = duplicates[0]
+        .renditions
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(names, vec!["a.png", "b.png"]);
+    // digests of the stored bytes, what assetutil shows as SHA1Digest
+    assert_eq!(
+        duplicates[0].renditions[0].1,
+        hex::encode_upper(sha2::Sha256::digest(&renditions[0].1))
+    );
+    assert_ne!(duplicates[0].renditions[0].1, duplicates[0].renditions[1].1);
+
+    let report = verify::VerifyReport {
+        duplicate_keys: duplicates,
+        dangling_facets: vec![],
+        unlisted_appearances: vec![],
+        missing_trees: vec![],
+        cyclic_trees: vec![],
+        unexpected_bitmap_lists: vec![],
+        unreferenced_blocks: vec![],
+        name_identifier_collisions: vec![],
+    };
+    assert!(!report.is_ok());
+    assert_eq!(report.lines().len(), 3);
+}
+
+#[test]
+fn dangling_facets() {
+    let store = common::storage(
+        vec![
+            common::facet("used", 1),
+            common::facet("unused", 2),
+            common::facet("also-unused", 3),
+        ],
+        vec![(common::key(0, 1, 0, 1), common::image("used.png", 1, 1))],
+        None,
+    );
+    assert_eq!(
+        verify::dangling_facets(&store),
+        vec!["also-unused".to_string(), "unused".to_string()]
+    );
+}
+
+#[test]
+fn verify_fixture() {
+    let generated = common::fixtures::CatalogSpec::new()
+        .images("Icon", &[1, 2, 3], &[0, 1, 2], &[0, 1, 2, 3])
+        .color("Tint", &[(0, [0.0, 0.0, 0.0, 1.0])])
+        .path();
+    for path in [std::path::PathBuf::from("tests/Assets.car"), generated] {
+        let report = verify::verify(&path).unwrap();
+        assert!(report.is_ok(), "{}: {:?}", path.display(), report.lines());
+    }
+}
+
+#[test]
+fn orphan_appearance_ids() {
+    use carutil_lib::assetutil;
+    use carutil_lib::assetutil::ToAssetUtilHeader;
+    use carutil_lib::coreui;
+    use std::collections::BTreeMap;
+
+    let appearances: BTreeMap<String, u32> = [("UIAppearanceAny", 0), ("UIAppearanceDark", 1)]
+        .into_iter()
+        .map(|(name, id)| (name.to_string(), id))
+        .collect();
+    let store = common::storage(
+        vec![common::facet("Icon", 1)],
+        vec![
+            (common::key(0, 1, 0, 1), common::image("Icon.png", 1, 1)),
+            (
+                common::key(1, 1, 0, 1),
+                common::image("Icon-dark.png", 1, 1),
+            ),
+            (common::key(5, 1, 0, 1), common::image("Icon-5.png", 1, 1)),
+        ],
+        Some(appearances),
+    );
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore { store },
+    };
+
+    let header = serde_json::to_value(car.asset_util_header()).unwrap();
+    assert_eq!(
+        header["Appearances"],
+        serde_json::json!({
+            "UIAppearanceAny": 0,
+            "UIAppearanceDark": 1,
+            "UnknownAppearance5": 5,
+        })
+    );
+    let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(&car.theme_store.store);
+    let orphan = entries
+        .iter()
+        .find(|entry| entry.rendition_name.as_deref() == Some("Icon-5.png"))
+        .unwrap();
+    assert_eq!(orphan.appearance.as_deref(), Some("UnknownAppearance5"));
+    assert!(verify::unlisted_appearances(&car).is_empty());
+}
+
+fn dark_variant_store() -> carutil_lib::coreui::CommonAssetStorage {
+    let appearances = [("UIAppearanceAny", 0), ("UIAppearanceDark", 1)]
+        .into_iter()
+        .map(|(name, id)| (name.to_string(), id))
+        .collect();
+    common::storage(
+        vec![common::facet("Icon", 1)],
+        vec![
+            (common::key(0, 1, 0, 1), common::image("Icon.png", 1, 1)),
+            (
+                common::key(1, 1, 0, 1),
+                common::image("Icon-dark.png", 1, 1),
+            ),
+        ],
+        Some(appearances),
+    )
+}
+
+#[test]
+fn missing_trees() {
+    let mut store = dark_variant_store();
+    assert_eq!(
+        verify::missing_trees(&store),
+        vec!["BITMAPKEYS".to_string()]
+    );
+    store.appearancedb = None;
+    store.bitmapkeydb = Some(store.bitmap_keys());
+    assert_eq!(
+        verify::missing_trees(&store),
+        vec!["APPEARANCEKEYS".to_string()]
+    );
+}
+
+#[test]
+fn written_dark_variant_round_trips() {
+    use carutil_lib::assetutil;
+    use carutil_lib::coreui;
+
+    let car = coreui::CarUtilAssetStorage {
+        theme_store: coreui::StructuredThemeStore {
+            store: dark_variant_store(),
+        },
+    };
+    let directory = common::temp_dir("dark");
+    let path = directory.join("dark.car");
+    car.write_data(path.to_str().unwrap()).unwrap();
+    let report = verify::verify(path.to_str().unwrap());
+    let written = coreui::CarUtilAssetStorage::from(path.to_str().unwrap(), false);
+
+    let report = report.unwrap();
+    assert!(report.is_ok(), "{:?}", report.lines());
+    let written = written.unwrap();
+    let store = &written.theme_store.store;
+    assert_eq!(store.appearancedb, car.theme_store.store.appearancedb);
+    assert_eq!(store.facet_names(), vec!["Icon"]);
+    let entries = assetutil::AssetUtilEntry::entries_from_asset_storage(store);
+    let dark = entries
+        .iter()
+        .find(|entry| entry.rendition_name.as_deref() == Some("Icon-dark.png"))
+        .unwrap();
+    assert_eq!(dark.appearance.as_deref(), Some("UIAppearanceDark"));
+}
+
+#[test]
+fn unexpected_bitmap_lists() {
+    let mut odd = common::image("odd.png", 1, 1);
+    odd.csibitmaplist.bitmap_count = 2;
+    odd.csibitmaplist.reserved = 7;
+    let store = common::storage(
+        vec![common::facet("normal", 1), common::facet("odd", 2)],
+        vec![
+            (common::key(0, 1, 0, 1), common::image("normal.png", 1, 1)),
+            (common::key(0, 1, 0, 2), odd),
+        ],
+        None,
+    );
+    assert_eq!(
+        verify::unexpected_bitmap_lists(&store),
+        vec![("odd.png".to_string(), 2, 7)]
+    );
+}
+
+#[test]
+fn name_identifier_collisions_listed() {
+    let car = carutil_lib::coreui::CarUtilAssetStorage {
+        theme_store: carutil_lib::coreui::StructuredThemeStore {
+            store: common::colliding_facets_storage(),
+        },
+    };
+    let directory = common::temp_dir("collision");
+    let path = directory.join("collision.car");
+    car.write_data(path.to_str().unwrap()).unwrap();
+    let report = verify::verify(path.to_str().unwrap());
+
+    let report = report.unwrap();
+    assert!(report.is_ok(), "{:?}", report.lines());
+    assert_eq!(
+        report.name_identifier_collisions,
+        vec![(5, vec!["Alpha".to_string(), "Beta".to_string()])]
+    );
+    assert_eq!(
+        report.lines(),
+        vec!["facets [\"Alpha\", \"Beta\"] share name identifier 5"]
+    );
+}